@@ -0,0 +1,18 @@
+use std::process::Command;
+
+// Embeds the git commit for /api/version. Builds outside a checkout, or
+// with GIT_COMMIT already set, keep whatever the environment provides.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    if std::env::var("GIT_COMMIT").is_ok() {
+        return;
+    }
+    let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output();
+    if let Ok(output) = output {
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !commit.is_empty() {
+            println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+        }
+    }
+}
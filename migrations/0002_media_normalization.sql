@@ -0,0 +1,33 @@
+-- Metadata filled in by the media normalization job (and by uploads going forward).
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS mime_type TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS size_bytes BIGINT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS width INT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS height INT;
+
+-- Progress of resumable data migrations, one row per migration.
+CREATE TABLE IF NOT EXISTS migration_state (
+    name TEXT PRIMARY KEY,
+    last_id INT NOT NULL DEFAULT 0,
+    processed BIGINT NOT NULL DEFAULT 0,
+    failed BIGINT NOT NULL DEFAULT 0,
+    rename_files BOOLEAN NOT NULL DEFAULT FALSE,
+    started_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    finished_at BIGINT
+);
+
+-- Per-row failures reported by data migrations.
+CREATE TABLE IF NOT EXISTS migration_failures (
+    id SERIAL PRIMARY KEY,
+    migration TEXT NOT NULL,
+    media_id INT NOT NULL,
+    error TEXT NOT NULL,
+    occurred_at BIGINT NOT NULL
+);
+
+-- Old upload URLs that were renamed, so links embedded elsewhere keep resolving.
+CREATE TABLE IF NOT EXISTS media_redirects (
+    old_path TEXT PRIMARY KEY,
+    new_path TEXT NOT NULL
+);
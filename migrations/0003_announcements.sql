@@ -0,0 +1,10 @@
+-- Site-wide (board IS NULL) or board-scoped banners shown during their time window.
+CREATE TABLE IF NOT EXISTS announcements (
+    id SERIAL PRIMARY KEY,
+    message TEXT NOT NULL,
+    starts_at BIGINT,
+    ends_at BIGINT,
+    board TEXT,
+    dismissible BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at BIGINT NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Per-article activity timeline, written in the same transaction as the change it records.
+CREATE TABLE IF NOT EXISTS article_events (
+    id SERIAL PRIMARY KEY,
+    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    detail TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS article_events_article_id_idx ON article_events (article_id, id);
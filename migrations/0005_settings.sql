@@ -0,0 +1,5 @@
+-- Admin-editable site settings; missing keys fall back to the defaults in code.
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Stored full-text vectors (title weighted A, body weighted B) maintained by the
+-- application. Existing rows start with a NULL vector and are backfilled in
+-- batches by the search consistency job, which also repairs any row whose
+-- updated_at is newer than its search_indexed_at.
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS updated_at BIGINT;
+UPDATE articles SET updated_at = bump_time WHERE updated_at IS NULL;
+ALTER TABLE articles ALTER COLUMN updated_at SET NOT NULL;
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS search_vector tsvector;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS search_indexed_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS articles_search_vector_idx ON articles USING GIN (search_vector);
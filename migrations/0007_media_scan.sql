@@ -0,0 +1,3 @@
+-- Virus scan outcome per upload: clean, unscanned (scanner down, failing open) or skipped (no scanner).
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS scan_status TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS scan_duration_ms BIGINT;
@@ -0,0 +1,12 @@
+-- `#123` references from article bodies (source_comment_id IS NULL) and comments.
+-- Targets have no foreign key: references to missing articles are kept and render as plain text.
+CREATE TABLE IF NOT EXISTS article_links (
+    id SERIAL PRIMARY KEY,
+    source_article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    source_comment_id INT REFERENCES comments(id) ON DELETE CASCADE,
+    target_article_id INT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS article_links_unique_idx
+    ON article_links (source_article_id, COALESCE(source_comment_id, 0), target_article_id);
+CREATE INDEX IF NOT EXISTS article_links_target_idx ON article_links (target_article_id);
@@ -0,0 +1,13 @@
+-- Comment text kept server-side after a rejected submission, so the article
+-- page can pre-fill the form. Keyed by a random token stored in a cookie.
+CREATE TABLE IF NOT EXISTS comment_drafts (
+    token TEXT PRIMARY KEY,
+    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    client_ip TEXT NOT NULL,
+    comment TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS comment_drafts_client_ip_idx ON comment_drafts (client_ip, created_at);
+CREATE INDEX IF NOT EXISTS comment_drafts_expires_at_idx ON comment_drafts (expires_at);
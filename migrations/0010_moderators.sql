@@ -0,0 +1,8 @@
+-- Individual staff accounts. `permissions` holds admin::Permission bit flags.
+CREATE TABLE IF NOT EXISTS moderators (
+    id SERIAL PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    permissions INT NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL
+);
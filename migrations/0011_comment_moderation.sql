@@ -0,0 +1,12 @@
+-- Metadata for the admin comment browser. Comments posted before this
+-- migration have no created_at or ip_representation.
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS created_at BIGINT;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS ip_representation TEXT;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS hidden BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Trigram index so ILIKE '%text%' searches over comment bodies stay indexed
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS comments_comment_trgm_idx ON comments USING GIN (comment gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS comments_article_id_idx ON comments (article_id);
+CREATE INDEX IF NOT EXISTS comments_ip_representation_idx ON comments (ip_representation);
+CREATE INDEX IF NOT EXISTS comments_created_at_idx ON comments (created_at);
@@ -0,0 +1,2 @@
+-- Deleted comments kept as "[deleted]" placeholders when tombstoning is on
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS deleted BOOLEAN NOT NULL DEFAULT FALSE;
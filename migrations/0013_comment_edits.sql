@@ -0,0 +1,2 @@
+-- Set when a commenter edits their own comment within the edit window
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS edited_at BIGINT;
@@ -0,0 +1,3 @@
+-- Alt text for screen readers and an optional visible caption, editable after publication
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS alt_text TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS caption TEXT;
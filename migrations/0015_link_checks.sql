@@ -0,0 +1,17 @@
+-- Outcome of the most recent check of each outbound link in an article body.
+-- status is the HTTP status code, or NULL when the request failed outright.
+CREATE TABLE IF NOT EXISTS link_checks (
+    id SERIAL PRIMARY KEY,
+    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    status INT,
+    error TEXT,
+    checked_at BIGINT NOT NULL,
+    UNIQUE (article_id, url)
+);
+
+CREATE INDEX IF NOT EXISTS link_checks_broken_idx ON link_checks (article_id)
+    WHERE status IS NULL OR status >= 400;
+
+-- When the article's links were last checked, compared against updated_at
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS links_checked_at BIGINT;
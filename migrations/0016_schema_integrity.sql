@@ -0,0 +1,45 @@
+-- Databases created by reset.sh before migrations existed may have the base
+-- tables without their foreign keys. Sweep rows pointing at missing articles
+-- first so adding the constraints can't fail on old data.
+DELETE FROM comments WHERE article_id IS NULL OR article_id NOT IN (SELECT id FROM articles);
+DELETE FROM article_media WHERE article_id IS NULL OR article_id NOT IN (SELECT id FROM articles);
+
+ALTER TABLE comments ALTER COLUMN article_id SET NOT NULL;
+ALTER TABLE article_media ALTER COLUMN article_id SET NOT NULL;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'comments'::regclass AND contype = 'f' AND confrelid = 'articles'::regclass
+    ) THEN
+        ALTER TABLE comments ADD CONSTRAINT comments_article_id_fkey
+            FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE;
+    END IF;
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'article_media'::regclass AND contype = 'f' AND confrelid = 'articles'::regclass
+    ) THEN
+        ALTER TABLE article_media ADD CONSTRAINT article_media_article_id_fkey
+            FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE;
+    END IF;
+END $$;
+
+-- Optional timestamps that were never set properly mean "unknown"
+UPDATE comments SET created_at = NULL WHERE created_at <= 0;
+UPDATE comments SET edited_at = NULL WHERE edited_at <= 0;
+
+-- Epoch-second timestamps must be positive. Required columns are checked
+-- NOT VALID: new and updated rows are held to it, existing rows are left alone.
+ALTER TABLE comments DROP CONSTRAINT IF EXISTS comments_created_at_positive;
+ALTER TABLE comments ADD CONSTRAINT comments_created_at_positive CHECK (created_at > 0);
+ALTER TABLE comments DROP CONSTRAINT IF EXISTS comments_edited_at_positive;
+ALTER TABLE comments ADD CONSTRAINT comments_edited_at_positive CHECK (edited_at > 0);
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_bump_time_positive;
+ALTER TABLE articles ADD CONSTRAINT articles_bump_time_positive CHECK (bump_time > 0) NOT VALID;
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_updated_at_positive;
+ALTER TABLE articles ADD CONSTRAINT articles_updated_at_positive CHECK (updated_at > 0) NOT VALID;
+ALTER TABLE article_events DROP CONSTRAINT IF EXISTS article_events_created_at_positive;
+ALTER TABLE article_events ADD CONSTRAINT article_events_created_at_positive CHECK (created_at > 0) NOT VALID;
+ALTER TABLE announcements DROP CONSTRAINT IF EXISTS announcements_created_at_positive;
+ALTER TABLE announcements ADD CONSTRAINT announcements_created_at_positive CHECK (created_at > 0) NOT VALID;
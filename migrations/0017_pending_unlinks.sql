@@ -0,0 +1,8 @@
+-- Media files to delete from disk. Rows are queued in the same transaction
+-- that stops referencing the file and drained by a background job, so a
+-- crash can neither leak the file nor leave a row pointing at a missing one.
+CREATE TABLE IF NOT EXISTS pending_unlinks (
+    id SERIAL PRIMARY KEY,
+    media_path TEXT NOT NULL,
+    queued_at BIGINT NOT NULL
+);
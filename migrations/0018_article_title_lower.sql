@@ -0,0 +1,2 @@
+-- Case-insensitive title lookups for the duplicate-title warning on submit
+CREATE INDEX IF NOT EXISTS articles_lower_title_idx ON articles (lower(title));
@@ -0,0 +1,3 @@
+-- Name of the file as uploaded, offered when downloading. Stored names are
+-- sanitized or content hashes; rows uploaded before this have none.
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS original_filename TEXT;
@@ -0,0 +1,12 @@
+-- When an article was first posted. Backfilled from the "created" activity
+-- event where one exists; older articles stay NULL.
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS created_at BIGINT;
+
+UPDATE articles a SET created_at = e.created_at
+FROM (
+    SELECT article_id, MIN(created_at) AS created_at FROM article_events
+    WHERE kind = 'created' GROUP BY article_id
+) e
+WHERE e.article_id = a.id AND a.created_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS articles_created_at_idx ON articles (created_at);
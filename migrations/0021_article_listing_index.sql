@@ -0,0 +1,3 @@
+-- Covering index for the article list's keyset walk, which filters and sorts
+-- on (bump_time, id) descending and reads only the title besides those
+CREATE INDEX IF NOT EXISTS articles_listing_idx ON articles (bump_time DESC, id DESC) INCLUDE (title);
@@ -0,0 +1,3 @@
+-- Comments posted by the site operator, shown with a badge. Only set by the
+-- server after checking the admin session or password.
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE;
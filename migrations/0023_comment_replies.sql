@@ -0,0 +1,17 @@
+-- `>>id` replies between comments on the same article, recorded when a
+-- comment is saved. Existing comments are backfilled from their text.
+CREATE TABLE IF NOT EXISTS comment_replies (
+    source_comment_id INT NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    target_comment_id INT NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    PRIMARY KEY (source_comment_id, target_comment_id)
+);
+
+CREATE INDEX IF NOT EXISTS comment_replies_target_idx ON comment_replies (target_comment_id);
+
+INSERT INTO comment_replies (source_comment_id, target_comment_id)
+SELECT DISTINCT c.id, t.id
+FROM comments c
+CROSS JOIN LATERAL regexp_matches(c.comment, '(?:^|\s)>>([0-9]{1,9})(?![0-9A-Za-z_])', 'g') AS m(ids)
+JOIN comments t ON t.id = m.ids[1]::int AND t.article_id = c.article_id AND t.id <> c.id
+WHERE NOT c.deleted
+ON CONFLICT DO NOTHING;
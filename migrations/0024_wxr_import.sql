@@ -0,0 +1,12 @@
+-- Items brought in by `import-wxr`, keyed by their GUID in the source
+-- export so a re-run skips what it already imported
+CREATE TABLE IF NOT EXISTS import_sources (
+    source_guid TEXT NOT NULL,
+    kind TEXT NOT NULL, -- 'article' or 'comment'
+    target_id INT NOT NULL,
+    imported_at BIGINT NOT NULL,
+    PRIMARY KEY (source_guid, kind)
+);
+
+-- Imported comments keep the name they were posted under
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS author_name TEXT;
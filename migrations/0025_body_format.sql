@@ -0,0 +1,13 @@
+-- How each article body is written: markdown, plain or html. Bodies from
+-- before this were shown as raw HTML, so they become `html` and pass
+-- through the sanitizer from now on; new articles default to plain.
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS body_format TEXT NOT NULL DEFAULT 'html';
+ALTER TABLE articles ALTER COLUMN body_format SET DEFAULT 'plain';
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'articles_body_format_check') THEN
+        ALTER TABLE articles ADD CONSTRAINT articles_body_format_check
+            CHECK (body_format IN ('markdown', 'plain', 'html'));
+    END IF;
+END $$;
@@ -0,0 +1,10 @@
+-- IMAP messages the mail gateway has handled, so polling never posts one twice
+CREATE TABLE IF NOT EXISTS mail_gateway_messages (
+    mailbox TEXT NOT NULL,
+    uid_validity BIGINT NOT NULL,
+    uid BIGINT NOT NULL,
+    outcome TEXT NOT NULL,
+    comment_id INTEGER REFERENCES comments(id) ON DELETE SET NULL,
+    processed_at BIGINT NOT NULL,
+    PRIMARY KEY (mailbox, uid_validity, uid)
+);
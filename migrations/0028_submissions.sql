@@ -0,0 +1,24 @@
+-- Uploads from article submissions that haven't been published yet, so a
+-- retried submission can reuse them instead of uploading and scanning again
+CREATE TABLE IF NOT EXISTS submission_uploads (
+    submission_id TEXT NOT NULL,
+    original_filename TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    media_path TEXT NOT NULL,
+    scan_status TEXT NOT NULL,
+    scan_duration_ms BIGINT,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (submission_id, original_filename, size_bytes)
+);
+
+CREATE INDEX IF NOT EXISTS submission_uploads_created_at_idx ON submission_uploads (created_at);
+
+-- Published submissions, so a retry of one that already went through
+-- doesn't post the article twice
+CREATE TABLE IF NOT EXISTS submissions (
+    submission_id TEXT PRIMARY KEY,
+    article_id INTEGER NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS submissions_created_at_idx ON submissions (created_at);
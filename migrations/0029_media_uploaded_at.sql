@@ -0,0 +1,14 @@
+-- When each attachment was uploaded, for the gallery. Existing rows take
+-- their article's creation time, or its last bump where that's unknown.
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS uploaded_at BIGINT;
+
+UPDATE article_media m SET uploaded_at = COALESCE(a.created_at, a.bump_time)
+FROM articles a
+WHERE a.id = m.article_id AND m.uploaded_at IS NULL;
+
+ALTER TABLE article_media ALTER COLUMN uploaded_at SET DEFAULT EXTRACT(EPOCH FROM now())::BIGINT;
+ALTER TABLE article_media ALTER COLUMN uploaded_at SET NOT NULL;
+
+-- The gallery walks images newest first, keyed on (uploaded_at, id)
+CREATE INDEX IF NOT EXISTS article_media_gallery_idx ON article_media (uploaded_at DESC, id DESC)
+    WHERE mime_type LIKE 'image/%';
@@ -0,0 +1,16 @@
+-- What moved an article, kept apart from bump_time (the listing's sort key):
+-- its newest comment and its last edit. Backfilled from comments and from
+-- "edited" activity events; articles without either stay NULL.
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS last_comment_at BIGINT;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS last_edited_at BIGINT;
+
+UPDATE articles a SET last_comment_at = c.created_at
+FROM (SELECT article_id, MAX(created_at) AS created_at FROM comments GROUP BY article_id) c
+WHERE c.article_id = a.id AND a.last_comment_at IS NULL;
+
+UPDATE articles a SET last_edited_at = e.created_at
+FROM (
+    SELECT article_id, MAX(created_at) AS created_at FROM article_events
+    WHERE kind = 'edited' GROUP BY article_id
+) e
+WHERE e.article_id = a.id AND a.last_edited_at IS NULL;
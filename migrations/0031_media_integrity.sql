@@ -0,0 +1,7 @@
+-- Result of the media integrity scan: NULL when the file was found as recorded,
+-- otherwise 'missing' or 'size_mismatch'. Cleared again when a later scan finds it intact.
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS media_integrity TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS integrity_checked_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS article_media_integrity_idx ON article_media (article_id)
+    WHERE media_integrity IS NOT NULL;
@@ -0,0 +1,15 @@
+-- Comments a reader can see on each article (shown and not tombstones),
+-- recounted in the same transaction as every comment write so pages and the
+-- API read it instead of counting per request.
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS comment_count BIGINT NOT NULL DEFAULT 0;
+
+UPDATE articles a SET comment_count = c.n
+FROM (
+    SELECT article_id, COUNT(*) AS n FROM comments
+    WHERE NOT hidden AND NOT deleted GROUP BY article_id
+) c
+WHERE c.article_id = a.id;
+
+-- The listing shows the count as a badge; keep its walk index-only
+DROP INDEX IF EXISTS articles_listing_idx;
+CREATE INDEX IF NOT EXISTS articles_listing_idx ON articles (bump_time DESC, id DESC) INCLUDE (title, comment_count);
@@ -0,0 +1,11 @@
+-- When each client last posted, per action ('article' or 'comment'), for the
+-- board-style cooldown between posts. ip_hash is a SHA-256 of the client
+-- address, never the address itself.
+CREATE TABLE IF NOT EXISTS post_cooldowns (
+    ip_hash TEXT NOT NULL,
+    action TEXT NOT NULL,
+    last_post_at BIGINT NOT NULL,
+    PRIMARY KEY (ip_hash, action)
+);
+
+CREATE INDEX IF NOT EXISTS post_cooldowns_last_post_idx ON post_cooldowns (last_post_at);
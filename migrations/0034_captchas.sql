@@ -0,0 +1,8 @@
+-- Captcha nonces already answered (rightly or wrongly), so a challenge can be
+-- tried only once. Rows are pruned once their challenge has expired anyway.
+CREATE TABLE IF NOT EXISTS captchas (
+    nonce TEXT PRIMARY KEY,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS captchas_expires_idx ON captchas (expires_at);
@@ -0,0 +1,9 @@
+-- Smaller re-encodings of JPEG and PNG attachments, made in the background.
+-- The paths are NULL until made, and stay NULL when the encoder failed or the
+-- result was no smaller; derivatives_at says the row has been tried.
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS webp_path TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS avif_path TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS derivatives_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS article_media_derivatives_pending_idx ON article_media (id)
+    WHERE derivatives_at IS NULL AND mime_type IN ('image/jpeg', 'image/png');
@@ -0,0 +1,12 @@
+-- Words, phrases and patterns refused or masked in new articles, edits and
+-- comments. A plain term matches as a whole word or phrase, ignoring case; a
+-- regex term is used as written. action is 'reject' (the post is refused) or
+-- 'replace' (the match is masked with asterisks).
+CREATE TABLE IF NOT EXISTS blocked_terms (
+    id SERIAL PRIMARY KEY,
+    pattern TEXT NOT NULL,
+    is_regex BOOLEAN NOT NULL DEFAULT FALSE,
+    action TEXT NOT NULL CHECK (action IN ('reject', 'replace')),
+    created_at BIGINT NOT NULL,
+    UNIQUE (pattern, is_regex)
+);
@@ -0,0 +1,14 @@
+-- Clients barred from posting, commenting and editing. ip_hash is an HMAC of
+-- the address keyed with the server's SECRET_KEY, never the address itself.
+-- expires_at is NULL for a permanent ban; an expired row simply stops
+-- matching and is pruned later.
+CREATE TABLE IF NOT EXISTS bans (
+    id SERIAL PRIMARY KEY,
+    ip_hash TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    expires_at BIGINT,
+    created_at BIGINT NOT NULL,
+    created_by TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS bans_ip_hash_idx ON bans (ip_hash);
@@ -0,0 +1,10 @@
+-- Clicks on external links in article bodies, counted through /out. Only the
+-- target domain, the article and the time are kept, never who clicked.
+CREATE TABLE IF NOT EXISTS link_clicks (
+    id BIGSERIAL PRIMARY KEY,
+    article_id INTEGER REFERENCES articles(id) ON DELETE SET NULL,
+    domain TEXT NOT NULL,
+    clicked_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS link_clicks_clicked_at_idx ON link_clicks (clicked_at);
@@ -0,0 +1,21 @@
+-- Visitor reports of articles and comments, for the moderation queue at
+-- /admin/reports. Repeat reports of one target from one client (ip_hash, an
+-- HMAC of the address keyed with SECRET_KEY) add to report_count on the open
+-- row instead of adding rows. A report is resolved when staff dismiss it or
+-- when its target is deleted.
+CREATE TABLE IF NOT EXISTS reports (
+    id SERIAL PRIMARY KEY,
+    target_type TEXT NOT NULL CHECK (target_type IN ('article', 'comment')),
+    target_id INTEGER NOT NULL,
+    ip_hash TEXT NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    report_count INTEGER NOT NULL DEFAULT 1,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    resolved_at BIGINT,
+    resolution TEXT CHECK (resolution IN ('dismissed', 'deleted')),
+    resolved_by TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS reports_open_unique_idx ON reports (target_type, target_id, ip_hash)
+    WHERE resolved_at IS NULL;
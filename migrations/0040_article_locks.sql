@@ -0,0 +1,3 @@
+-- Locked articles take no new comments, whatever their age. Set and cleared
+-- with `admin lock`; NULL means open.
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS locked_at BIGINT;
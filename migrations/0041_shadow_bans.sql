@@ -0,0 +1,16 @@
+-- Shadow bans. Posts from a shadow-banned address are stored with
+-- shadow_hidden set and shown only to that address (shadow_viewer holds its
+-- keyed hash, as in bans.ip_hash) and to staff, until staff release them.
+ALTER TABLE bans ADD COLUMN IF NOT EXISTS shadow BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS shadow_hidden BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS shadow_viewer TEXT;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS shadow_hidden BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS shadow_viewer TEXT;
+
+CREATE INDEX IF NOT EXISTS comments_shadow_idx ON comments (article_id, shadow_viewer) WHERE shadow_hidden;
+CREATE INDEX IF NOT EXISTS articles_shadow_idx ON articles (shadow_viewer) WHERE shadow_hidden;
+
+-- The listing filters on both; keep its walk index-only
+DROP INDEX IF EXISTS articles_listing_idx;
+CREATE INDEX IF NOT EXISTS articles_listing_idx ON articles (bump_time DESC, id DESC)
+    INCLUDE (title, comment_count, shadow_hidden, shadow_viewer);
@@ -0,0 +1,11 @@
+-- Replacement files stored for an article edit's preview, until the edit is
+-- saved or abandoned; abandoned ones expire after an hour
+CREATE TABLE IF NOT EXISTS staged_uploads (
+    article_id INTEGER NOT NULL,
+    media_path TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (article_id, media_path)
+);
+
+CREATE INDEX IF NOT EXISTS staged_uploads_media_path_idx ON staged_uploads (media_path);
+CREATE INDEX IF NOT EXISTS staged_uploads_created_at_idx ON staged_uploads (created_at);
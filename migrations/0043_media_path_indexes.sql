@@ -0,0 +1,6 @@
+-- /uploads and the unlink queue look files up by path, as an attachment's
+-- file or one of its derivatives, or as an upload held for a retry
+CREATE INDEX IF NOT EXISTS article_media_media_path_idx ON article_media (media_path);
+CREATE INDEX IF NOT EXISTS article_media_webp_path_idx ON article_media (webp_path) WHERE webp_path IS NOT NULL;
+CREATE INDEX IF NOT EXISTS article_media_avif_path_idx ON article_media (avif_path) WHERE avif_path IS NOT NULL;
+CREATE INDEX IF NOT EXISTS submission_uploads_media_path_idx ON submission_uploads (media_path);
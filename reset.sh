@@ -8,50 +8,54 @@ DB_HOST="localhost"
 
 export PGPASSWORD="$DB_PASSWORD"
 
-psql -h "$DB_HOST" -U "$DB_USER" -d "$DB_NAME" <<EOF
+psql -h "$DB_HOST" -U "$DB_USER" -d "$DB_NAME" <<EOF2
 -- Drop existing tables if they exist
+DROP TABLE IF EXISTS staged_uploads;
+DROP TABLE IF EXISTS reports;
+DROP TABLE IF EXISTS link_clicks;
+DROP TABLE IF EXISTS bans;
+DROP TABLE IF EXISTS blocked_terms;
+DROP TABLE IF EXISTS captchas;
+DROP TABLE IF EXISTS post_cooldowns;
+DROP TABLE IF EXISTS submissions;
+DROP TABLE IF EXISTS submission_uploads;
+DROP TABLE IF EXISTS mail_gateway_messages;
+DROP TABLE IF EXISTS admin_actions;
+DROP TABLE IF EXISTS import_sources;
+DROP TABLE IF EXISTS comment_replies;
+DROP TABLE IF EXISTS pending_unlinks;
+DROP TABLE IF EXISTS link_checks;
+DROP TABLE IF EXISTS moderators;
+DROP TABLE IF EXISTS comment_drafts;
+DROP TABLE IF EXISTS article_links;
+DROP TABLE IF EXISTS settings;
+DROP TABLE IF EXISTS article_events;
+DROP TABLE IF EXISTS announcements;
+DROP TABLE IF EXISTS media_redirects;
+DROP TABLE IF EXISTS migration_failures;
+DROP TABLE IF EXISTS migration_state;
 DROP TABLE IF EXISTS article_media;
 DROP TABLE IF EXISTS comments;
 DROP TABLE IF EXISTS articles;
 DROP TABLE IF EXISTS admins;
+DROP TABLE IF EXISTS _sqlx_migrations;
+EOF2
 
--- Create articles table
-CREATE TABLE articles (
-    id SERIAL PRIMARY KEY,
-    title TEXT NOT NULL,
-    body TEXT NOT NULL,
-    bump_time BIGINT NOT NULL
-);
-
--- Create table for associated media
-CREATE TABLE article_media (
-    id SERIAL PRIMARY KEY,
-    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
-    media_path TEXT NOT NULL
-);
-
--- Create table for comments
-CREATE TABLE comments (
-    id SERIAL PRIMARY KEY,
-    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
-    comment TEXT NOT NULL
-);
-
--- Create admins table
-CREATE TABLE admins (
-    username TEXT PRIMARY KEY,
-    password_hash TEXT NOT NULL
-);
+# Create the schema from the same migrations the server applies at startup
+for migration in "$(dirname "$0")"/migrations/*.sql; do
+    psql -h "$DB_HOST" -U "$DB_USER" -d "$DB_NAME" -f "$migration"
+done
 
+psql -h "$DB_HOST" -U "$DB_USER" -d "$DB_NAME" <<EOF2
 -- Insert a sample admin
 INSERT INTO admins (username, password_hash) VALUES ('admin', 'plaintextpassword');
 
 -- Optionally insert a sample article and data
-INSERT INTO articles (title, body, bump_time) VALUES ('Sample Article', 'This is a test article body.', EXTRACT(EPOCH FROM now())::BIGINT);
+INSERT INTO articles (title, body, bump_time, updated_at) VALUES ('Sample Article', 'This is a test article body.', EXTRACT(EPOCH FROM now())::BIGINT, EXTRACT(EPOCH FROM now())::BIGINT);
 INSERT INTO article_media (article_id, media_path)
     SELECT id, '/uploads/sample_image.jpg' FROM articles WHERE title='Sample Article';
 INSERT INTO comments (article_id, comment)
     SELECT id, 'This is a sample comment.' FROM articles WHERE title='Sample Article';
-EOF
+EOF2
 
 echo "Database tables created and sample data inserted."
@@ -0,0 +1,174 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, USER_AGENT};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use chrono::Utc;
+use rand::Rng;
+use serde_json::json;
+use std::env;
+use std::io::Write;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::client;
+
+// One JSON object per request on stdout (LOG_FORMAT=json), for log shippers.
+// Errors logged while handling a request carry the same request id.
+
+static JSON_LOGS: OnceLock<bool> = OnceLock::new();
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+pub fn init_from_env() {
+    let enabled = env::var("LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json"));
+    let _ = JSON_LOGS.set(enabled);
+}
+
+pub fn json_enabled() -> bool {
+    JSON_LOGS.get().copied().unwrap_or(false)
+}
+
+// Id of the request being handled, when JSON logs are assigning them
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+pub fn new_request_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+fn emit(line: serde_json::Value) {
+    #[cfg(test)]
+    tests::CAPTURED.with(|captured| captured.borrow_mut().push(line.to_string()));
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{}", line);
+}
+
+// Mirrors an error message as a JSON event tied to the current request, if any
+pub fn emit_error(message: &str) {
+    if !json_enabled() {
+        return;
+    }
+    let request_id = request_id();
+    emit(json!({
+        "timestamp": Utc::now().to_rfc3339(),
+        "level": "error",
+        "message": message,
+        "request_id": request_id,
+    }));
+}
+
+// Route pattern rather than the raw path, so `/articles/123` logs as
+// `/articles/{id}` and the number of distinct values stays bounded
+fn path_template(req: &ServiceRequest) -> String {
+    if let Some(pattern) = req.match_pattern() {
+        return pattern;
+    }
+    let path = req.path();
+    if path.starts_with("/static/") {
+        "/static/*".to_string()
+    } else if path.starts_with("/uploads/") {
+        "/uploads/*".to_string()
+    } else {
+        "<unmatched>".to_string()
+    }
+}
+
+pub async fn json_access_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if !json_enabled() {
+        return next.call(req).await;
+    }
+
+    let request_id = new_request_id();
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let path = path_template(&req);
+    let client_ip = client::ip_representation(req.request());
+    let user_agent = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let result = REQUEST_ID.scope(request_id.clone(), next.call(req)).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let (status, bytes_out) = match &result {
+        Ok(res) => {
+            let bytes = match res.response().body().size() {
+                BodySize::Sized(n) => Some(n),
+                _ => None,
+            };
+            (res.status().as_u16(), bytes)
+        }
+        Err(e) => (e.as_response_error().status_code().as_u16(), None),
+    };
+
+    emit(json!({
+        "timestamp": Utc::now().to_rfc3339(),
+        "method": method,
+        "path": path,
+        "status": status,
+        "latency_ms": latency_ms,
+        "bytes_out": bytes_out,
+        "client_ip": client_ip,
+        "request_id": request_id,
+        "user_agent": user_agent,
+    }));
+
+    let mut res = result?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+    use std::cell::RefCell;
+
+    thread_local! {
+        // Lines emitted on this thread, instead of only going to stdout
+        pub static CAPTURED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[actix_web::test]
+    async fn access_line_is_json_with_the_route_template() {
+        let _ = JSON_LOGS.set(true);
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(json_access_log))
+                .route("/articles/{id}", web::get().to(|| async { HttpResponse::Ok().body("hello") })),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/articles/123")
+            .insert_header((USER_AGENT, "tester/1.0"))
+            .peer_addr("198.51.100.1:1000".parse().unwrap())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let header_id = res.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+
+        let lines = CAPTURED.with(|captured| captured.borrow().clone());
+        let line: serde_json::Value = serde_json::from_str(lines.last().expect("nothing logged")).unwrap();
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["path"], "/articles/{id}");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["bytes_out"], 5);
+        assert_eq!(line["user_agent"], "tester/1.0");
+        assert_eq!(line["request_id"], header_id.as_str());
+        assert!(line["client_ip"].as_str().unwrap().starts_with("ip:"));
+        assert!(line["latency_ms"].is_f64());
+        assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+    }
+}
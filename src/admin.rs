@@ -0,0 +1,669 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::dev::Payload;
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use chrono::Utc;
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use argon2::password_hash::{PasswordHash, PasswordVerifier};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::env;
+use std::io::{self, BufRead};
+use std::sync::OnceLock;
+
+use crate::events::{ADMIN_ACTOR, MOD_PASSWORD_ACTOR};
+use crate::login_limits::LoginLimiter;
+use crate::{csrf, log_error, media_api, moderators, paths, security_headers, tokens};
+
+const SESSION_COOKIE: &str = "admin_session";
+const SESSION_PURPOSE: &str = "admin-session";
+const DEFAULT_SESSION_HOURS: i64 = 12;
+// How long a login lasts, from ADMIN_SESSION_HOURS
+static SESSION_TTL_SECS: OnceLock<i64> = OnceLock::new();
+const EDIT_TOKEN_PURPOSE: &str = "edit-article";
+const EDIT_TOKEN_TTL_SECS: i64 = 15 * 60;
+
+// The super-admin password's argon2 hash, from ADMIN_PASSWORD_HASH
+static ADMIN_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+// The password shipped as the default before it came from the environment
+const DEFAULT_ADMIN_PASSWORD: &str = "changeme";
+// The optional moderation password's argon2 hash, from MOD_PASSWORD_HASH. It
+// only carries the comment permissions in MOD_PASSWORD_PERMISSIONS.
+static MOD_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+const MOD_PASSWORD_PERMISSIONS: [Permission; 1] = [Permission::DeleteComments];
+
+// Session payloads: the super-admin (logged in with the admin password), the
+// moderation password, or a moderator by id
+const SUPER_ADMIN_SESSION: &str = "admin";
+const MOD_PASSWORD_SESSION: &str = "mod-password";
+const MODERATOR_SESSION_PREFIX: &str = "mod:";
+
+// Loads ADMIN_PASSWORD_HASH, MOD_PASSWORD_HASH and ADMIN_SESSION_HOURS.
+// Startup stops without a usable admin hash, on an unusable moderation hash,
+// and on a hash of the old default password unless ALLOW_DEFAULT_ADMIN_PASSWORD=1.
+pub fn init_from_env() -> Result<(), String> {
+    let session_hours = match env::var("ADMIN_SESSION_HOURS") {
+        Ok(v) => v
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|h| (1..=24 * 365).contains(h))
+            .ok_or_else(|| format!("ADMIN_SESSION_HOURS must be a whole number of hours, got {:?}", v))?,
+        Err(_) => DEFAULT_SESSION_HOURS,
+    };
+    let _ = SESSION_TTL_SECS.set(session_hours * 60 * 60);
+
+    let allow_default = env::var("ALLOW_DEFAULT_ADMIN_PASSWORD").is_ok_and(|v| v.trim() == "1");
+    let hash = admin_hash(env::var("ADMIN_PASSWORD_HASH").ok(), allow_default)?;
+    let _ = ADMIN_PASSWORD_HASH.set(hash);
+
+    if let Some(mod_hash) = env::var("MOD_PASSWORD_HASH")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+    {
+        PasswordHash::new(&mod_hash).map_err(|e| format!("MOD_PASSWORD_HASH is not a valid argon2 hash: {}", e))?;
+        let _ = MOD_PASSWORD_HASH.set(mod_hash);
+    }
+    Ok(())
+}
+
+// The admin hash to start with, or why startup should stop
+fn admin_hash(value: Option<String>, allow_default: bool) -> Result<String, String> {
+    let hash = value
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .ok_or("ADMIN_PASSWORD_HASH is not set; generate one with `articles1 --hash-password`")?;
+    let parsed = PasswordHash::new(&hash)
+        .map_err(|e| format!("ADMIN_PASSWORD_HASH is not a valid argon2 hash: {}", e))?;
+
+    let is_default = Argon2::default()
+        .verify_password(DEFAULT_ADMIN_PASSWORD.as_bytes(), &parsed)
+        .is_ok();
+    if is_default {
+        if !allow_default {
+            return Err(format!(
+                "ADMIN_PASSWORD_HASH is the hash of \"{}\"; choose another password, or set \
+                 ALLOW_DEFAULT_ADMIN_PASSWORD=1 to start anyway",
+                DEFAULT_ADMIN_PASSWORD
+            ));
+        }
+        log_error("Starting with the default admin password because ALLOW_DEFAULT_ADMIN_PASSWORD=1");
+    }
+    Ok(hash)
+}
+
+// Which of the configured passwords was given
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PasswordRole {
+    Admin,
+    Moderator,
+    None,
+}
+
+fn matches_hash(password: &str, hash: Option<&String>) -> bool {
+    let hash = match hash {
+        Some(h) => h,
+        None => return false,
+    };
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+        Err(_) => false,
+    }
+}
+
+pub fn password_role(password: &str) -> PasswordRole {
+    if password.is_empty() {
+        PasswordRole::None
+    } else if matches_hash(password, ADMIN_PASSWORD_HASH.get()) {
+        PasswordRole::Admin
+    } else if matches_hash(password, MOD_PASSWORD_HASH.get()) {
+        PasswordRole::Moderator
+    } else {
+        PasswordRole::None
+    }
+}
+
+// `articles1 --hash-password`: reads a password from stdin and prints the
+// value to use for ADMIN_PASSWORD_HASH
+pub fn print_password_hash() -> io::Result<()> {
+    eprintln!("Password (read from stdin):");
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty password"));
+    }
+    let hash = moderators::hash_password(password).map_err(io::Error::other)?;
+    println!("{}", hash);
+    Ok(())
+}
+
+// What a moderator account may do. Stored as bit flags on the moderators row.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    DeleteComments,
+    DeleteArticles,
+    EditArticles,
+    ManageBans,
+    ManageSettings,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [
+        Permission::DeleteComments,
+        Permission::DeleteArticles,
+        Permission::EditArticles,
+        Permission::ManageBans,
+        Permission::ManageSettings,
+    ];
+
+    pub fn bit(self) -> i32 {
+        match self {
+            Permission::DeleteComments => 1,
+            Permission::DeleteArticles => 1 << 1,
+            Permission::EditArticles => 1 << 2,
+            Permission::ManageBans => 1 << 3,
+            Permission::ManageSettings => 1 << 4,
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Permission::DeleteComments => "delete_comments",
+            Permission::DeleteArticles => "delete_articles",
+            Permission::EditArticles => "edit_articles",
+            Permission::ManageBans => "manage_bans",
+            Permission::ManageSettings => "manage_settings",
+        }
+    }
+}
+
+// How a staff member proved who they are, recorded with admin actions
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    Session,
+    ApiToken,
+    Password,
+    EditToken,
+}
+
+impl AuthMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            AuthMethod::Session => "session",
+            AuthMethod::ApiToken => "api token",
+            AuthMethod::Password => "password",
+            AuthMethod::EditToken => "edit token",
+        }
+    }
+}
+
+// Whoever is acting through an admin page
+pub struct Staff {
+    pub moderator_id: Option<i32>, // None for the admin and moderation passwords
+    pub username: String,
+    pub method: AuthMethod,
+    role: PasswordRole,
+    permissions: i32,
+}
+
+impl Staff {
+    fn super_admin() -> Self {
+        Staff {
+            moderator_id: None,
+            username: ADMIN_ACTOR.to_string(),
+            method: AuthMethod::Session,
+            role: PasswordRole::Admin,
+            permissions: 0,
+        }
+    }
+
+    fn moderation_password() -> Self {
+        Staff {
+            moderator_id: None,
+            username: MOD_PASSWORD_ACTOR.to_string(),
+            method: AuthMethod::Session,
+            role: PasswordRole::Moderator,
+            permissions: MOD_PASSWORD_PERMISSIONS.iter().fold(0, |bits, p| bits | p.bit()),
+        }
+    }
+
+    // Automation holding API_TOKEN, with the super-admin's rights
+    fn api() -> Self {
+        Staff {
+            moderator_id: None,
+            username: media_api::API_ACTOR.to_string(),
+            method: AuthMethod::ApiToken,
+            role: PasswordRole::Admin,
+            permissions: 0,
+        }
+    }
+
+    pub fn is_super_admin(&self) -> bool {
+        self.role == PasswordRole::Admin
+    }
+
+    // What to put in a session or token to stand for this staff member
+    fn holder(&self) -> String {
+        match (self.moderator_id, self.role) {
+            (Some(id), _) => format!("{}{}", MODERATOR_SESSION_PREFIX, id),
+            (None, PasswordRole::Admin) => SUPER_ADMIN_SESSION.to_string(),
+            (None, _) => MOD_PASSWORD_SESSION.to_string(),
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        self.is_super_admin() || self.permissions & permission.bit() != 0
+    }
+}
+
+#[derive(FromRow)]
+struct ModeratorRow {
+    username: String,
+    permissions: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginForm {
+    #[serde(default)]
+    username: String,
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutForm {
+    #[serde(default)]
+    csrf_token: String,
+}
+
+// True when the request carries a valid, unexpired staff session cookie.
+// Only checks the signature; use `current_staff` for permissions.
+pub fn is_admin(req: &HttpRequest) -> bool {
+    session_payload(req).is_some()
+}
+
+fn session_payload(req: &HttpRequest) -> Option<String> {
+    req.cookie(SESSION_COOKIE)
+        .and_then(|c| tokens::verify_expiring(SESSION_PURPOSE, c.value()))
+}
+
+// The session's staff member. Moderator permissions are re-read on every
+// request so revoking one takes effect without waiting for the session to end.
+pub async fn current_staff(req: &HttpRequest, pool: &PgPool) -> Option<Staff> {
+    staff_for_payload(pool, &session_payload(req)?).await
+}
+
+async fn staff_for_payload(pool: &PgPool, payload: &str) -> Option<Staff> {
+    if payload == SUPER_ADMIN_SESSION {
+        return Some(Staff::super_admin());
+    }
+    // Unsetting MOD_PASSWORD_HASH ends these sessions too
+    if payload == MOD_PASSWORD_SESSION {
+        return MOD_PASSWORD_HASH.get().map(|_| Staff::moderation_password());
+    }
+
+    let moderator_id: i32 = payload.strip_prefix(MODERATOR_SESSION_PREFIX)?.parse().ok()?;
+    match sqlx::query_as::<_, ModeratorRow>("SELECT username, permissions FROM moderators WHERE id = $1")
+        .bind(moderator_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(row) => row.map(|m| Staff {
+            moderator_id: Some(moderator_id),
+            username: m.username,
+            method: AuthMethod::Session,
+            role: PasswordRole::Moderator,
+            permissions: m.permissions,
+        }),
+        Err(e) => {
+            log_error(&format!("Failed to load moderator: {}", e));
+            None
+        }
+    }
+}
+
+// Token carried by the article edit form in place of the password that
+// opened it. It names the article and the staff member, and expires.
+pub fn edit_token(staff: &Staff, article_id: i32) -> String {
+    tokens::sign_expiring(
+        EDIT_TOKEN_PURPOSE,
+        &format!("{}|{}", article_id, staff.holder()),
+        EDIT_TOKEN_TTL_SECS,
+    )
+}
+
+// Like `authorize`, for a form carrying an `edit_token` for `article_id`
+pub async fn authorize_edit_token(
+    pool: &PgPool,
+    token: &str,
+    article_id: i32,
+    permission: Permission,
+    action: &str,
+) -> Result<Staff, HttpResponse> {
+    let holder = tokens::verify_expiring(EDIT_TOKEN_PURPOSE, token).and_then(|data| {
+        let (id, holder) = data.split_once('|')?;
+        (id.parse::<i32>().ok()? == article_id).then(|| holder.to_string())
+    });
+    let staff = match holder {
+        Some(holder) => staff_for_payload(pool, &holder).await,
+        None => None,
+    };
+    match staff {
+        Some(mut staff) if staff.can(permission) => {
+            staff.method = AuthMethod::EditToken;
+            Ok(staff)
+        }
+        Some(_) => Err(missing_permission(action, permission.key())),
+        None => {
+            log_error(&format!("Invalid or expired token for {}", action));
+            Err(HttpResponse::Forbidden().body("This form has expired or is for another article, please start again"))
+        }
+    }
+}
+
+// Password input for admin forms, left out when a staff session will be used instead
+pub fn password_field(req: &HttpRequest) -> &'static str {
+    if is_admin(req) {
+        ""
+    } else {
+        r#"<input type="password" name="password" placeholder="Password" required>"#
+    }
+}
+
+fn missing_permission(action: &str, missing: &str) -> HttpResponse {
+    let base = paths::base();
+    log_error(&format!("Missing permission {} for {}", missing, action));
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Forbidden</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Forbidden</h2>
+        <p>Missing permission {}</p>
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        missing
+    );
+    HttpResponse::Forbidden().content_type("text/html").body(html)
+}
+
+// Credentials an admin request carries outside its body: the staff session
+// cookie and an `Authorization: Bearer` API token. The legacy password sits
+// in each handler's form, so handlers pass it to `authorize` themselves.
+pub struct AdminAuth {
+    session: Option<String>,
+    api_token: bool,
+}
+
+impl FromRequest for AdminAuth {
+    type Error = Infallible;
+    type Future = Ready<Result<AdminAuth, Infallible>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(AdminAuth {
+            session: session_payload(req),
+            api_token: media_api::has_valid_token(req),
+        }))
+    }
+}
+
+impl AdminAuth {
+    // CSRF check for an admin form. A request authenticated by the password
+    // field alone is let through: another site can't know the password, and
+    // with no session cookie there is nothing for it to ride on. Anything
+    // else must carry the token, as every other form does.
+    pub fn check_csrf(&self, req: &HttpRequest, submitted: &str, password: &str) -> Result<(), csrf::Rejected> {
+        if !password.is_empty() && req.cookie(SESSION_COOKIE).is_none() {
+            return Ok(());
+        }
+        csrf::check(req, submitted)
+    }
+
+    // Checks an admin action. A valid session wins, then the API token, then
+    // the legacy password field: the admin password works as super-admin and
+    // the moderation password for the comment permissions. Staff must hold
+    // `permission` (None: any staff member). `action` names the action in the
+    // error log and the audit log.
+    pub async fn authorize(
+        &self,
+        req: &HttpRequest,
+        pool: &PgPool,
+        password: &str,
+        permission: Option<Permission>,
+        action: &str,
+    ) -> Result<Staff, HttpResponse> {
+        let staff = self.identify(req, pool, password, action).await?;
+        if let Some(p) = permission {
+            if !staff.can(p) {
+                return Err(missing_permission(action, p.key()));
+            }
+        }
+        if req.method() == Method::POST {
+            record_action(pool, &staff, action).await;
+        }
+        Ok(staff)
+    }
+
+    // Like `authorize`, but only the super-admin passes (moderator management)
+    pub async fn authorize_super_admin(
+        &self,
+        req: &HttpRequest,
+        pool: &PgPool,
+        password: &str,
+        action: &str,
+    ) -> Result<Staff, HttpResponse> {
+        let staff = self.authorize(req, pool, password, None, action).await?;
+        if !staff.is_super_admin() {
+            return Err(missing_permission(action, "super_admin"));
+        }
+        Ok(staff)
+    }
+
+    async fn identify(&self, req: &HttpRequest, pool: &PgPool, password: &str, action: &str) -> Result<Staff, HttpResponse> {
+        // A session for a removed moderator falls through to the others
+        if let Some(payload) = &self.session {
+            if let Some(staff) = staff_for_payload(pool, payload).await {
+                return Ok(staff);
+            }
+        }
+        if self.api_token {
+            return Ok(Staff::api());
+        }
+        if password.is_empty() {
+            log_error(&format!("Not logged in for {}", action));
+            return Err(HttpResponse::Unauthorized().body("Incorrect password"));
+        }
+
+        let limiter = req.app_data::<web::Data<LoginLimiter>>();
+        if let Some(limiter) = limiter {
+            limiter.check(req)?;
+        }
+        let mut staff = match password_role(password) {
+            PasswordRole::Admin => Staff::super_admin(),
+            PasswordRole::Moderator => Staff::moderation_password(),
+            PasswordRole::None => {
+                if let Some(limiter) = limiter {
+                    limiter.record_failure(req);
+                }
+                log_error(&format!("Incorrect password for {}", action));
+                return Err(HttpResponse::Unauthorized().body("Incorrect password"));
+            }
+        };
+        if let Some(limiter) = limiter {
+            limiter.record_success(req);
+        }
+        log_error(&format!(
+            "Deprecated: {} authorized by a password field; sign in at /admin/login or send API_TOKEN as a Bearer token",
+            action
+        ));
+        staff.method = AuthMethod::Password;
+        Ok(staff)
+    }
+}
+
+// Audit row for an authorized admin POST, naming how the actor got in
+async fn record_action(pool: &PgPool, staff: &Staff, action: &str) {
+    if let Err(e) = sqlx::query("INSERT INTO admin_actions (actor, action, detail, created_at) VALUES ($1, $2, $3, $4)")
+        .bind(&staff.username)
+        .bind(action)
+        .bind(format!("via {}", staff.method.name()))
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await
+    {
+        log_error(&format!("Failed to record admin action: {}", e));
+    }
+}
+
+pub async fn login_form(req: HttpRequest) -> HttpResponse {
+    let base = paths::base();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Admin Login</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Admin Login</h2>
+        <form action="{base}/admin/login" method="POST">
+            {}
+            <input type="text" name="username" placeholder="Username (blank for the admin or moderation password)">
+            <input type="password" name="password" placeholder="Password" required>
+            <input type="submit" value="Log In">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        csrf::field(&req)
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn login(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    limiter: web::Data<LoginLimiter>,
+    form: web::Form<LoginForm>,
+) -> HttpResponse {
+    if let Err(res) = csrf::check(&req, &form.csrf_token) {
+        return res.into();
+    }
+    if let Err(res) = limiter.check(&req) {
+        return res.into();
+    }
+    let username = form.username.trim();
+    let session = if username.is_empty() {
+        match password_role(&form.password) {
+            PasswordRole::Admin => Staff::super_admin().holder(),
+            PasswordRole::Moderator => Staff::moderation_password().holder(),
+            PasswordRole::None => {
+                limiter.record_failure(&req);
+                log_error("Incorrect password for admin login");
+                return HttpResponse::Unauthorized().body("Incorrect password");
+            }
+        }
+    } else {
+        match moderators::verify_login(pool.get_ref(), username, &form.password).await {
+            Some(id) => format!("{}{}", MODERATOR_SESSION_PREFIX, id),
+            None => {
+                limiter.record_failure(&req);
+                log_error(&format!("Failed moderator login for {}", username));
+                return HttpResponse::Unauthorized().body("Incorrect username or password");
+            }
+        }
+    };
+    limiter.record_success(&req);
+
+    let ttl = SESSION_TTL_SECS.get().copied().unwrap_or(DEFAULT_SESSION_HOURS * 60 * 60);
+    let cookie = Cookie::build(SESSION_COOKIE, tokens::sign_expiring(SESSION_PURPOSE, &session, ttl))
+        .path(paths::url("/"))
+        .http_only(true)
+        .secure(security_headers::behind_tls())
+        .same_site(SameSite::Lax)
+        .max_age(CookieDuration::seconds(ttl))
+        .finish();
+
+    HttpResponse::Found()
+        .cookie(cookie)
+        .append_header(("Location", paths::url("/articles")))
+        .finish()
+}
+
+pub async fn logout_form(req: HttpRequest) -> HttpResponse {
+    let base = paths::base();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Admin Logout</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Admin Logout</h2>
+        <form action="{base}/admin/logout" method="POST">
+            {}
+            <input type="submit" value="Log Out">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        csrf::field(&req)
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// POST only, so another site can't log staff out with a link or an image
+pub async fn logout(req: HttpRequest, form: web::Form<LogoutForm>) -> HttpResponse {
+    if let Err(res) = csrf::check(&req, &form.csrf_token) {
+        return res.into();
+    }
+    let mut cookie = Cookie::build(SESSION_COOKIE, "")
+        .path(paths::url("/"))
+        .http_only(true)
+        .secure(security_headers::behind_tls())
+        .same_site(SameSite::Lax)
+        .finish();
+    cookie.make_removal();
+
+    HttpResponse::Found()
+        .cookie(cookie)
+        .append_header(("Location", paths::url("/articles")))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_hash_refuses_missing_invalid_and_default() {
+        let missing = admin_hash(None, false).unwrap_err();
+        assert!(missing.contains("--hash-password"), "{}", missing);
+        assert!(admin_hash(Some("  ".to_string()), false).is_err());
+        assert!(admin_hash(Some("changeme".to_string()), false).unwrap_err().contains("not a valid argon2 hash"));
+
+        let default = moderators::hash_password(DEFAULT_ADMIN_PASSWORD).unwrap();
+        let refused = admin_hash(Some(default.clone()), false).unwrap_err();
+        assert!(refused.contains("ALLOW_DEFAULT_ADMIN_PASSWORD=1"), "{}", refused);
+        assert_eq!(admin_hash(Some(format!(" {}\n", default)), true).unwrap(), default);
+
+        let chosen = moderators::hash_password("a better password").unwrap();
+        assert_eq!(admin_hash(Some(chosen.clone()), false).unwrap(), chosen);
+        assert!(matches_hash("a better password", Some(&chosen)));
+        assert!(!matches_hash(DEFAULT_ADMIN_PASSWORD, Some(&chosen)));
+    }
+}
@@ -0,0 +1,221 @@
+use chrono::Utc;
+use serde_json::json;
+use sqlx::PgPool;
+use std::net::IpAddr;
+
+use crate::settings::SettingsCache;
+use crate::{bans, delete_article_rows, remove_comments, set_article_locked, unlinks};
+
+// `admin`: moderation from the command line, for when the web UI is down or
+// in scripts. Uses DATABASE_URL like the server and the same deletion
+// functions as the web handlers, so media cleanup and the activity timeline
+// come out the same. Deleting needs --yes; --json prints a JSON object
+// instead of a sentence.
+//     articles1 admin delete-article <id> --yes [--json]
+//     articles1 admin delete-comment <id> --yes [--json]
+//     articles1 admin lock <id> [--unlock] [--json]
+//     articles1 admin ban-ip <addr> [--days N] [--reason TEXT] [--shadow] [--json]
+//     articles1 admin list-reports [--json]
+// Comment deletions, locks and bans are recorded under this actor
+const CLI_ACTOR: &str = "cli";
+
+struct Options {
+    id: i32,
+    yes: bool,
+    json: bool,
+    unlock: bool,
+}
+
+fn parse_options(command: &str, args: &[String]) -> Result<Options, String> {
+    let mut id = None;
+    let mut yes = false;
+    let mut json = false;
+    let mut unlock = false;
+    for arg in args {
+        match arg.as_str() {
+            "--yes" => yes = true,
+            "--json" => json = true,
+            "--unlock" if command == "lock" => unlock = true,
+            value if id.is_none() && !value.starts_with("--") => {
+                id = Some(value.parse().map_err(|_| format!("{} needs a numeric id, not {}", command, value))?)
+            }
+            other => return Err(format!("Unknown {} option: {}", command, other)),
+        }
+    }
+    let id = id.ok_or_else(|| format!("{} needs an id", command))?;
+    Ok(Options { id, yes, json, unlock })
+}
+
+fn confirmed(command: &str, options: &Options) -> Result<(), String> {
+    if options.yes {
+        Ok(())
+    } else {
+        Err(format!("{} deletes data; add --yes to go ahead", command))
+    }
+}
+
+fn report(options: &Options, kind: &str, message: String) {
+    if options.json {
+        println!("{}", json!({ "deleted": kind, "id": options.id }));
+    } else {
+        println!("{}", message);
+    }
+}
+
+async fn delete_article(pool: &PgPool, options: &Options) -> Result<(), String> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)")
+        .bind(options.id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to look up article: {}", e))?;
+    if !exists {
+        return Err(format!("Article {} not found", options.id));
+    }
+    delete_article_rows(pool, options.id)
+        .await
+        .map_err(|e| format!("Failed to delete article: {}", e))?;
+    // The server's background job would get to the files too; this process
+    // is about to exit, so remove them now
+    unlinks::drain(pool).await?;
+    report(options, "article", format!("Deleted article {}", options.id));
+    Ok(())
+}
+
+async fn delete_comment(pool: &PgPool, options: &Options) -> Result<(), String> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM comments WHERE id = $1)")
+        .bind(options.id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to look up comment: {}", e))?;
+    if !exists {
+        return Err(format!("Comment {} not found", options.id));
+    }
+    let tombstone = SettingsCache::default().get(pool).await.tombstone_deleted_comments;
+    remove_comments(pool, &[options.id], tombstone, true, CLI_ACTOR)
+        .await
+        .map_err(|e| format!("Failed to delete comment: {}", e))?;
+    report(options, "comment", format!("Deleted comment {}", options.id));
+    Ok(())
+}
+
+async fn lock(pool: &PgPool, options: &Options) -> Result<(), String> {
+    let locked = !options.unlock;
+    let found = set_article_locked(pool, options.id, locked, CLI_ACTOR)
+        .await
+        .map_err(|e| format!("Failed to lock article: {}", e))?;
+    if !found {
+        return Err(format!("Article {} not found", options.id));
+    }
+    if options.json {
+        println!("{}", json!({ "article": options.id, "locked": locked }));
+    } else if locked {
+        println!("Locked comments on article {}", options.id);
+    } else {
+        println!("Unlocked comments on article {}", options.id);
+    }
+    Ok(())
+}
+
+async fn ban_ip(pool: &PgPool, args: &[String]) -> Result<(), String> {
+    let mut ip = None;
+    let mut days: Option<i64> = None;
+    let mut reason = String::new();
+    let mut shadow = false;
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--days" => {
+                days = Some(
+                    args.next()
+                        .and_then(|d| d.parse().ok())
+                        .filter(|d| *d >= 0)
+                        .ok_or("--days needs a whole number")?,
+                )
+            }
+            "--reason" => reason = args.next().cloned().ok_or("--reason needs a text")?,
+            "--shadow" => shadow = true,
+            "--json" => json = true,
+            value if ip.is_none() && !value.starts_with("--") => ip = Some(value.to_string()),
+            other => return Err(format!("Unknown ban-ip option: {}", other)),
+        }
+    }
+    let ip = ip.ok_or("ban-ip needs an address")?;
+    if ip.parse::<IpAddr>().is_err() {
+        return Err(format!("{} is not a valid IP address", ip));
+    }
+    let reason = if reason.trim().is_empty() { "Banned by an administrator".to_string() } else { reason.trim().to_string() };
+    let now = Utc::now().timestamp();
+    // Same as the admin form: no days, or 0, is permanent
+    let expires_at = days.filter(|d| *d > 0).map(|d| now + d.min(36_500) * 86_400);
+
+    let id = bans::insert_ban(pool, &ip, &reason, expires_at, shadow, CLI_ACTOR)
+        .await
+        .map_err(|e| format!("Failed to store ban: {}", e))?;
+
+    if json {
+        println!("{}", json!({ "banned": id, "expires_at": expires_at, "shadow": shadow }));
+    } else {
+        let banned = if shadow { "Shadow banned" } else { "Banned" };
+        match expires_at {
+            Some(_) => println!("{} {} for {} days (ban {})", banned, ip, days.unwrap_or_default(), id),
+            None => println!("{} {} permanently (ban {})", banned, ip, id),
+        }
+    }
+    Ok(())
+}
+
+async fn list_reports(pool: &PgPool, args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            other => return Err(format!("Unknown list-reports option: {}", other)),
+        }
+    }
+    let rows: Vec<(String, i32, i64, i64)> = sqlx::query_as(
+        "SELECT target_type, target_id, SUM(report_count)::BIGINT, MAX(updated_at) FROM reports
+         WHERE resolved_at IS NULL GROUP BY target_type, target_id ORDER BY 3 DESC, 4 DESC",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch reports: {}", e))?;
+
+    if json {
+        let reports: Vec<_> = rows
+            .iter()
+            .map(|(target_type, target_id, count, last)| {
+                json!({ "target_type": target_type, "target_id": target_id, "reports": count, "last_reported_at": last })
+            })
+            .collect();
+        println!("{}", json!({ "open": reports }));
+    } else if rows.is_empty() {
+        println!("No open reports");
+    } else {
+        for (target_type, target_id, count, _) in &rows {
+            println!("{} {}: {} reports", target_type, target_id, count);
+        }
+    }
+    Ok(())
+}
+
+pub async fn run(pool: &PgPool, args: &[String]) -> Result<(), String> {
+    let command = args.first().map(String::as_str).unwrap_or("");
+    match command {
+        "delete-article" => {
+            let options = parse_options(command, &args[1..])?;
+            confirmed(command, &options)?;
+            delete_article(pool, &options).await
+        }
+        "delete-comment" => {
+            let options = parse_options(command, &args[1..])?;
+            confirmed(command, &options)?;
+            delete_comment(pool, &options).await
+        }
+        "lock" => lock(pool, &parse_options(command, &args[1..])?).await,
+        "ban-ip" => ban_ip(pool, &args[1..]).await,
+        "list-reports" => list_reports(pool, &args[1..]).await,
+        "" => Err("admin needs a command: delete-article, delete-comment, lock, ban-ip or list-reports".to_string()),
+        other => Err(format!("Unknown admin command: {}", other)),
+    }
+}
@@ -0,0 +1,509 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::sync::RwLock;
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::caches::{Invalidate, Target};
+use crate::consent;
+use crate::csrf;
+use crate::log_error;
+use crate::paths;
+use crate::text::render_markdown;
+
+const DATETIME_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+#[derive(Clone, FromRow)]
+struct Announcement {
+    id: i32,
+    message: String,
+    starts_at: Option<i64>,
+    ends_at: Option<i64>,
+    board: Option<String>,
+    dismissible: bool,
+}
+
+#[derive(Clone)]
+struct ActiveAnnouncement {
+    id: i32,
+    html: String,
+    starts_at: Option<i64>,
+    ends_at: Option<i64>,
+    board: Option<String>,
+    dismissible: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnnouncementForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+    message: String,
+    starts_at: String,
+    ends_at: String,
+    board: String,
+    dismissible: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteAnnouncementForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+// Announcements that haven't expired yet, with their markdown pre-rendered.
+// Shared by all workers and cleared whenever an admin changes an announcement.
+#[derive(Default)]
+pub struct AnnouncementCache {
+    active: RwLock<Option<Vec<ActiveAnnouncement>>>,
+}
+
+impl AnnouncementCache {
+    pub fn invalidate(&self) {
+        if let Ok(mut active) = self.active.write() {
+            *active = None;
+        }
+    }
+
+    async fn current(&self, pool: &PgPool) -> Vec<ActiveAnnouncement> {
+        if let Ok(active) = self.active.read() {
+            if let Some(list) = active.as_ref() {
+                return list.clone();
+            }
+        }
+
+        let rows = match sqlx::query_as::<_, Announcement>(
+            "SELECT id, message, starts_at, ends_at, board, dismissible FROM announcements
+             WHERE ends_at IS NULL OR ends_at > $1 ORDER BY id DESC",
+        )
+        .bind(Utc::now().timestamp())
+        .fetch_all(pool)
+        .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                log_error(&format!("Failed to fetch announcements: {}", e));
+                return Vec::new();
+            }
+        };
+
+        let list: Vec<ActiveAnnouncement> = rows
+            .into_iter()
+            .map(|a| ActiveAnnouncement {
+                id: a.id,
+                html: render_markdown(&a.message),
+                starts_at: a.starts_at,
+                ends_at: a.ends_at,
+                board: a.board,
+                dismissible: a.dismissible,
+            })
+            .collect();
+
+        if let Ok(mut active) = self.active.write() {
+            *active = Some(list.clone());
+        }
+        list
+    }
+}
+
+impl Invalidate for AnnouncementCache {
+    fn invalidate(&self, target: Target) -> Vec<String> {
+        if !matches!(target, Target::All | Target::Announcements) {
+            return Vec::new();
+        }
+        AnnouncementCache::invalidate(self);
+        vec!["active announcements".to_string()]
+    }
+}
+
+fn dismiss_cookie_name(id: i32) -> String {
+    format!("dismissed_announcement_{}", id)
+}
+
+// Banner markup for the top of a public page. Site-wide announcements show
+// everywhere; board-scoped ones only when `board` matches.
+pub async fn banner_html(
+    req: &HttpRequest,
+    pool: &PgPool,
+    cache: &AnnouncementCache,
+    board: Option<&str>,
+) -> String {
+    let base = paths::base();
+    let now = Utc::now().timestamp();
+    let mut html = consent::banner_html(req);
+
+    for a in cache.current(pool).await {
+        let in_window = a.starts_at.is_none_or(|s| s <= now) && a.ends_at.is_none_or(|e| e > now);
+        let in_scope = a.board.is_none() || a.board.as_deref() == board;
+        let dismissed = a.dismissible && req.cookie(&dismiss_cookie_name(a.id)).is_some();
+        if !in_window || !in_scope || dismissed {
+            continue;
+        }
+
+        html.push_str(r#"<div class="announcement">"#);
+        html.push_str(&a.html);
+        if a.dismissible {
+            html.push_str(&format!(
+                r#"<a href="{base}/announcements/{}/dismiss" class="dismiss-link">[dismiss]</a>"#,
+                a.id
+            ));
+        }
+        html.push_str("</div>");
+    }
+
+    html
+}
+
+pub async fn dismiss_announcement(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    let announcement_id = path.into_inner();
+
+    // Send the visitor back to the page they were on, but never off-site
+    let location = paths::referer_path(&req).unwrap_or_else(|| paths::url("/articles"));
+
+    let cookie = Cookie::build(dismiss_cookie_name(announcement_id), "1")
+        .path(paths::url("/"))
+        .max_age(CookieDuration::days(365))
+        .http_only(true)
+        .finish();
+
+    // Without consent the banner comes back on the next page
+    let mut response = HttpResponse::Found();
+    consent::add_cookie(&req, &mut response, cookie);
+    response.append_header(("Location", location)).finish()
+}
+
+fn parse_datetime_input(value: &str) -> Result<Option<i64>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    NaiveDateTime::parse_from_str(value, DATETIME_INPUT_FORMAT)
+        .map(|dt| Some(Utc.from_utc_datetime(&dt).timestamp()))
+        .map_err(|_| format!("Invalid date/time: {}", value))
+}
+
+fn format_datetime_input(ts: Option<i64>) -> String {
+    ts.and_then(|t| Utc.timestamp_opt(t, 0).single())
+        .map(|dt| dt.format(DATETIME_INPUT_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+// `auth_fields`: the CSRF token and, without a session, the password input
+fn announcement_form_html(
+    auth_fields: &str,
+    action: &str,
+    submit_label: &str,
+    a: Option<&Announcement>,
+) -> String {
+    format!(
+        r#"
+        <form action="{}{}" method="POST">
+            {}
+            <textarea name="message" rows="4" placeholder="Message (markdown)" required>{}</textarea><br>
+            Starts (UTC, optional): <input type="datetime-local" name="starts_at" value="{}"><br>
+            Ends (UTC, optional): <input type="datetime-local" name="ends_at" value="{}"><br>
+            <input type="text" name="board" placeholder="Board (blank for site-wide)" value="{}"><br>
+            <label><input type="checkbox" name="dismissible" value="1"{}> Dismissible</label><br><br>
+            <input type="submit" value="{}">
+        </form>
+        "#,
+        paths::base(),
+        action,
+        auth_fields,
+        a.map(|a| html_escape::encode_text(&a.message).into_owned()).unwrap_or_default(),
+        format_datetime_input(a.and_then(|a| a.starts_at)),
+        format_datetime_input(a.and_then(|a| a.ends_at)),
+        a.and_then(|a| a.board.as_deref())
+            .map(|b| html_escape::encode_double_quoted_attribute(b).into_owned())
+            .unwrap_or_default(),
+        if a.is_none_or(|a| a.dismissible) { " checked" } else { "" },
+        submit_label
+    )
+}
+
+struct ValidatedAnnouncement {
+    message: String,
+    starts_at: Option<i64>,
+    ends_at: Option<i64>,
+    board: Option<String>,
+    dismissible: bool,
+}
+
+async fn validate_form(
+    req: &HttpRequest,
+    auth: &AdminAuth,
+    pool: &PgPool,
+    form: &AnnouncementForm,
+) -> Result<ValidatedAnnouncement, HttpResponse> {
+    auth.check_csrf(req, &form.csrf_token, &form.password)?;
+    auth.authorize(
+        req,
+        pool,
+        &form.password,
+        Some(Permission::ManageSettings),
+        "announcement change",
+    )
+    .await?;
+
+    let message = form.message.trim().to_string();
+    if message.is_empty() {
+        return Err(HttpResponse::BadRequest().body("Message is required"));
+    }
+
+    let starts_at = parse_datetime_input(&form.starts_at).map_err(|e| HttpResponse::BadRequest().body(e))?;
+    let ends_at = parse_datetime_input(&form.ends_at).map_err(|e| HttpResponse::BadRequest().body(e))?;
+    if let (Some(s), Some(e)) = (starts_at, ends_at) {
+        if e <= s {
+            return Err(HttpResponse::BadRequest().body("End time must be after start time"));
+        }
+    }
+
+    let board = form.board.trim();
+    Ok(ValidatedAnnouncement {
+        message,
+        starts_at,
+        ends_at,
+        board: if board.is_empty() { None } else { Some(board.to_string()) },
+        dismissible: form.dismissible.is_some(),
+    })
+}
+
+pub async fn list_announcements(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Lists scheduled announcements that aren't public yet
+    if let Err(res) =
+        auth.authorize(&req, pool.get_ref(), "", Some(Permission::ManageSettings), "announcement list").await
+    {
+        return res;
+    }
+    let announcements = match sqlx::query_as::<_, Announcement>(
+        "SELECT id, message, starts_at, ends_at, board, dismissible FROM announcements ORDER BY id DESC",
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(a) => a,
+        Err(e) => {
+            log_error(&format!("Failed to fetch announcements: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load announcements");
+        }
+    };
+
+    let auth_fields = format!("{}{}", csrf::field(&req), admin::password_field(&req));
+
+    let mut rows_html = String::new();
+    for a in &announcements {
+        rows_html.push_str(&format!(
+            r#"<div class="comment"><p>{}</p><p>Starts: {} / Ends: {} / Board: {} / Dismissible: {}</p>
+            <a href="{base}/admin/announcements/{}/edit" class="edit-link">[+]</a>
+            <form action="{base}/admin/announcements/{}/delete" method="POST">
+                {}
+                <input type="submit" value="Delete">
+            </form></div>"#,
+            html_escape::encode_text(&a.message),
+            a.starts_at.map_or("-".to_string(), |t| format_datetime_input(Some(t))),
+            a.ends_at.map_or("-".to_string(), |t| format_datetime_input(Some(t))),
+            html_escape::encode_text(a.board.as_deref().unwrap_or("site-wide")),
+            if a.dismissible { "yes" } else { "no" },
+            a.id,
+            a.id,
+            auth_fields
+        ));
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Announcements</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>New Announcement</h2>
+        {}
+        </div>
+        <h2>Announcements</h2>
+        {}
+        </body>
+        </html>
+        "#,
+        announcement_form_html(&auth_fields, "/admin/announcements", "Create Announcement", None),
+        rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn create_announcement(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    cache: web::Data<AnnouncementCache>,
+    form: web::Form<AnnouncementForm>,
+) -> HttpResponse {
+    let a = match validate_form(&req, &auth, pool.get_ref(), &form).await {
+        Ok(a) => a,
+        Err(res) => return res,
+    };
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO announcements (message, starts_at, ends_at, board, dismissible, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(&a.message)
+    .bind(a.starts_at)
+    .bind(a.ends_at)
+    .bind(&a.board)
+    .bind(a.dismissible)
+    .bind(Utc::now().timestamp())
+    .execute(pool.get_ref())
+    .await
+    {
+        log_error(&format!("Failed to store announcement: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to store announcement.");
+    }
+
+    cache.invalidate();
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/announcements")))
+        .finish()
+}
+
+pub async fn edit_announcement_form(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let base = paths::base();
+    let announcement_id = path.into_inner();
+    if let Err(res) =
+        auth.authorize(&req, pool.get_ref(), "", Some(Permission::ManageSettings), "announcement edit form").await
+    {
+        return res;
+    }
+
+    let announcement = match sqlx::query_as::<_, Announcement>(
+        "SELECT id, message, starts_at, ends_at, board, dismissible FROM announcements WHERE id = $1",
+    )
+    .bind(announcement_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(Some(a)) => a,
+        Ok(None) => return HttpResponse::NotFound().body("Announcement not found"),
+        Err(e) => {
+            log_error(&format!("Failed to fetch announcement: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load announcement");
+        }
+    };
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Edit Announcement</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Edit Announcement</h2>
+        {}
+        </div>
+        </body>
+        </html>
+        "#,
+        announcement_form_html(
+            &format!("{}{}", csrf::field(&req), admin::password_field(&req)),
+            &format!("/admin/announcements/{}/edit", announcement_id),
+            "Save Changes",
+            Some(&announcement)
+        )
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn edit_announcement(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    cache: web::Data<AnnouncementCache>,
+    path: web::Path<i32>,
+    form: web::Form<AnnouncementForm>,
+) -> HttpResponse {
+    let announcement_id = path.into_inner();
+    let a = match validate_form(&req, &auth, pool.get_ref(), &form).await {
+        Ok(a) => a,
+        Err(res) => return res,
+    };
+
+    if let Err(e) = sqlx::query(
+        "UPDATE announcements SET message = $1, starts_at = $2, ends_at = $3, board = $4, dismissible = $5
+         WHERE id = $6",
+    )
+    .bind(&a.message)
+    .bind(a.starts_at)
+    .bind(a.ends_at)
+    .bind(&a.board)
+    .bind(a.dismissible)
+    .bind(announcement_id)
+    .execute(pool.get_ref())
+    .await
+    {
+        log_error(&format!("Failed to update announcement: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to update announcement.");
+    }
+
+    cache.invalidate();
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/announcements")))
+        .finish()
+}
+
+pub async fn delete_announcement(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    cache: web::Data<AnnouncementCache>,
+    path: web::Path<i32>,
+    form: web::Form<DeleteAnnouncementForm>,
+) -> HttpResponse {
+    let announcement_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageSettings),
+        "announcement deletion",
+    )
+    .await
+    {
+        return res;
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM announcements WHERE id = $1")
+        .bind(announcement_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        log_error(&format!("Failed to delete announcement: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to delete announcement.");
+    }
+
+    cache.invalidate();
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/announcements")))
+        .finish()
+}
@@ -0,0 +1,373 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool};
+use std::net::IpAddr;
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::{client, csrf, log_error, paths, tokens};
+
+// IP bans, managed at /admin/bans. A banned client can still read everything
+// but can't submit articles, comment or edit. A shadow ban instead lets the
+// posts through looking as usual, but stores them shadow-hidden: shown only
+// to that address and to staff (see visibility) until staff release them.
+// Addresses are stored as a keyed hash (SECRET_KEY), so the table can't be
+// read back into a list of IPs. With no SECRET_KEY set the key changes on
+// restart, so new bans are refused.
+const HASH_PURPOSE: &str = "ip-ban";
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+#[derive(FromRow)]
+struct Ban {
+    id: i32,
+    reason: String,
+    expires_at: Option<i64>,
+    created_at: i64,
+    created_by: String,
+    shadow: bool,
+}
+
+// Set on a request from a shadow-banned client by `guard_writes`; holds the
+// client's address hash, which its posts are stored with as shadow_viewer
+#[derive(Clone)]
+pub struct Shadowed(pub String);
+
+impl Shadowed {
+    pub fn of(req: &HttpRequest) -> Option<String> {
+        req.extensions().get::<Shadowed>().map(|shadowed| shadowed.0.clone())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BanForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+    ip: String,
+    reason: String,
+    days: String, // blank or 0 for a permanent ban
+    #[serde(default)]
+    shadow: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LiftBanForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+// The same address written differently (e.g. IPv6 case or zero runs) hashes
+// the same
+pub fn ip_hash(ip: &str) -> String {
+    let ip = ip.trim();
+    let canonical = ip.parse::<IpAddr>().map(|addr| addr.to_string()).unwrap_or_else(|_| ip.to_string());
+    tokens::keyed_hash(HASH_PURPOSE, &canonical)
+}
+
+fn format_time(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0)
+        .single()
+        .map(|dt| dt.format(DATE_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+fn format_expiry(expires_at: Option<i64>) -> String {
+    expires_at.map_or("never".to_string(), format_time)
+}
+
+// The ban in force for this client: an outright one before a shadow one,
+// then the latest end, permanent first
+async fn active_ban(pool: &PgPool, ip_hash: &str) -> Result<Option<Ban>, sqlx::Error> {
+    sqlx::query_as::<_, Ban>(
+        "SELECT id, reason, expires_at, created_at, created_by, shadow FROM bans
+         WHERE ip_hash = $1 AND (expires_at IS NULL OR expires_at > $2)
+         ORDER BY shadow, expires_at DESC NULLS FIRST LIMIT 1",
+    )
+    .bind(ip_hash)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await
+}
+
+// Submitting, commenting and the article and comment edit forms
+fn is_guarded(path: &str) -> bool {
+    path == "/submit" || (path.starts_with("/articles/") && (path.ends_with("/comment") || path.ends_with("/edit")))
+}
+
+fn banned_page(ban: &Ban) -> HttpResponse {
+    let base = paths::base();
+    let until = match ban.expires_at {
+        Some(expires_at) => format!("until {}", format_time(expires_at)),
+        None => "permanently".to_string(),
+    };
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Banned</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Banned</h2>
+        <p>You are banned from posting {}. Nothing you sent was saved.</p>
+        <p>Reason: {}</p>
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        until,
+        html_escape::encode_text(&ban.reason)
+    );
+    HttpResponse::Forbidden().content_type("text/html").body(html)
+}
+
+// Middleware turning away posts from banned clients before their handlers
+// read the body, and marking those from shadow-banned ones `Shadowed` for the
+// handlers to store as such. Staff sessions are never turned away.
+pub async fn guard_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.method() != Method::POST || !is_guarded(req.path()) {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+    let pool = match req.app_data::<web::Data<PgPool>>() {
+        Some(pool) => pool.clone(),
+        None => return next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+    };
+    // The same lookup as AdminAuth, so a removed moderator's session is banned like anyone
+    if admin::current_staff(req.request(), pool.get_ref()).await.is_some() {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    // A failed lookup lets the post through rather than blocking everyone
+    let hash = ip_hash(&client::client_ip(req.request()));
+    let ban = active_ban(pool.get_ref(), &hash).await.unwrap_or_else(|e| {
+        log_error(&format!("Failed to check bans: {}", e));
+        None
+    });
+    match ban {
+        Some(ban) if ban.shadow => {
+            req.extensions_mut().insert(Shadowed(hash));
+            next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+        }
+        Some(ban) => Ok(req.into_response(banned_page(&ban))),
+        None => next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+    }
+}
+
+// GET /admin/bans
+pub async fn list_bans(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", Some(Permission::ManageBans), "ban list").await {
+        return res;
+    }
+
+    let bans = match sqlx::query_as::<_, Ban>(
+        "SELECT id, reason, expires_at, created_at, created_by, shadow FROM bans
+         WHERE expires_at IS NULL OR expires_at > $1 ORDER BY id DESC",
+    )
+    .bind(Utc::now().timestamp())
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(bans) => bans,
+        Err(e) => {
+            log_error(&format!("Failed to fetch bans: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load bans");
+        }
+    };
+
+    let auth_fields = format!("{}{}", csrf::field(&req), admin::password_field(&req));
+    let key_warning = if tokens::key_is_persistent() {
+        ""
+    } else {
+        r#"<div class="thread-age-notice"><strong>SECRET_KEY is not set.</strong> Addresses are hashed with a key that changes on every restart, so bans would stop matching; new bans are refused until it is set.</div>"#
+    };
+
+    let mut rows_html = String::new();
+    for ban in &bans {
+        rows_html.push_str(&format!(
+            r#"<div class="comment"><p>{}</p><p>{} {} by {} / Expires: {}</p>
+            <form action="{base}/admin/bans/{}/lift" method="POST">
+                {}
+                <input type="submit" value="Lift Ban">
+            </form></div>"#,
+            html_escape::encode_text(&ban.reason),
+            if ban.shadow { "Shadow banned" } else { "Banned" },
+            format_time(ban.created_at),
+            html_escape::encode_text(&ban.created_by),
+            format_expiry(ban.expires_at),
+            ban.id,
+            auth_fields
+        ));
+    }
+    if bans.is_empty() {
+        rows_html.push_str("<p>No active bans.</p>");
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Bans</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Ban an IP Address</h2>
+        {}
+        <p>A banned address can still read the site but can't post, comment or edit. A shadow-banned one can, but only it and staff see what it posts, until released from the comment browser or the article page. The address is stored only as a keyed hash and isn't shown again.</p>
+        <form action="{base}/admin/bans" method="POST">
+            {}
+            <input type="text" name="ip" placeholder="IP address" required><br>
+            <input type="text" name="reason" placeholder="Reason (shown to them)" required><br>
+            <input type="number" name="days" min="0" placeholder="Days (blank for permanent)"><br>
+            <label><input type="checkbox" name="shadow" value="1"> Shadow ban</label><br><br>
+            <input type="submit" value="Ban">
+        </form>
+        </div>
+        <h2>Active Bans</h2>
+        {}
+        </body>
+        </html>
+        "#,
+        key_warning, auth_fields, rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// Stores a ban on `ip` and returns its id; `expires_at` None is permanent
+pub async fn insert_ban(
+    pool: &PgPool,
+    ip: &str,
+    reason: &str,
+    expires_at: Option<i64>,
+    shadow: bool,
+    created_by: &str,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO bans (ip_hash, reason, expires_at, created_at, created_by, shadow)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(ip_hash(ip))
+    .bind(reason)
+    .bind(expires_at)
+    .bind(Utc::now().timestamp())
+    .bind(created_by)
+    .bind(shadow)
+    .fetch_one(pool)
+    .await
+}
+
+// POST /admin/bans
+pub async fn create_ban(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    form: web::Form<BanForm>,
+) -> HttpResponse {
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageBans),
+        "ban creation",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    // Hashed with a random key the ban would silently stop matching at the next restart
+    if !tokens::key_is_persistent() {
+        log_error("Refused to store a ban because SECRET_KEY is not set");
+        return HttpResponse::ServiceUnavailable().body("Set SECRET_KEY before adding bans; without it they stop matching on restart.");
+    }
+    let ip = form.ip.trim();
+    if ip.parse::<IpAddr>().is_err() {
+        return HttpResponse::BadRequest().body("Not a valid IP address");
+    }
+    let reason = form.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().body("A reason is required");
+    }
+    let now = Utc::now().timestamp();
+    let expires_at = match form.days.trim() {
+        "" | "0" => None,
+        days => match days.parse::<i64>() {
+            Ok(days) if days > 0 => Some(now + days.min(36_500) * 86_400),
+            _ => return HttpResponse::BadRequest().body("Days must be a whole number"),
+        },
+    };
+
+    let shadow = form.shadow.is_some();
+    if let Err(e) = insert_ban(pool.get_ref(), ip, reason, expires_at, shadow, &staff.username).await {
+        log_error(&format!("Failed to store ban: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to store ban.");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/bans")))
+        .finish()
+}
+
+// POST /admin/bans/{id}/lift
+pub async fn lift_ban(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    path: web::Path<i32>,
+    form: web::Form<LiftBanForm>,
+) -> HttpResponse {
+    let ban_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageBans),
+        "ban lifting",
+    )
+    .await
+    {
+        return res;
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM bans WHERE id = $1")
+        .bind(ban_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        log_error(&format!("Failed to lift ban: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to lift ban.");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/bans")))
+        .finish()
+}
+
+// Drops bans that have run out; they already stopped matching
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM bans WHERE expires_at <= $1")
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
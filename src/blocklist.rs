@@ -0,0 +1,346 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use regex::Regex;
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool};
+use std::sync::{Arc, RwLock};
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::caches::{Invalidate, Target};
+use crate::{csrf, log_error, paths};
+
+// Terms refused or masked in new articles, article edits and comments,
+// managed at /admin/blocklist. Patterns are compiled once when the list is
+// loaded and kept until an admin changes it.
+
+#[derive(FromRow)]
+struct BlockedTerm {
+    id: i32,
+    pattern: String,
+    is_regex: bool,
+    action: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Reject,
+    Replace,
+}
+
+impl Action {
+    fn parse(value: &str) -> Option<Action> {
+        match value.trim() {
+            "reject" => Some(Action::Reject),
+            "replace" => Some(Action::Replace),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Action::Reject => "reject",
+            Action::Replace => "replace",
+        }
+    }
+}
+
+struct CompiledTerm {
+    matcher: Regex,
+    action: Action,
+}
+
+// A plain term matches as a whole word or phrase, ignoring case. Word
+// boundaries are only added at ends that are word characters, so "c++" or
+// "#tag" still match.
+fn compile(pattern: &str, is_regex: bool) -> Result<Regex, regex::Error> {
+    if is_regex {
+        return Regex::new(pattern);
+    }
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(pattern.chars().next()) { r"\b" } else { "" };
+    let end = if is_word(pattern.chars().last()) { r"\b" } else { "" };
+    Regex::new(&format!("(?i){}{}{}", start, regex::escape(pattern), end))
+}
+
+#[derive(Default)]
+pub struct Blocklist {
+    terms: RwLock<Option<Arc<Vec<CompiledTerm>>>>,
+}
+
+impl Blocklist {
+    pub fn invalidate(&self) {
+        if let Ok(mut terms) = self.terms.write() {
+            *terms = None;
+        }
+    }
+
+    async fn current(&self, pool: &PgPool) -> Arc<Vec<CompiledTerm>> {
+        if let Ok(terms) = self.terms.read() {
+            if let Some(terms) = terms.as_ref() {
+                return terms.clone();
+            }
+        }
+
+        // Posting goes on unchecked while the list can't be read
+        let rows = match sqlx::query_as::<_, BlockedTerm>("SELECT id, pattern, is_regex, action FROM blocked_terms ORDER BY id")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                log_error(&format!("Failed to fetch blocked terms: {}", e));
+                return Arc::new(Vec::new());
+            }
+        };
+
+        let mut compiled = Vec::with_capacity(rows.len());
+        for row in rows {
+            let action = match Action::parse(&row.action) {
+                Some(action) => action,
+                None => continue,
+            };
+            match compile(&row.pattern, row.is_regex) {
+                Ok(matcher) => compiled.push(CompiledTerm { matcher, action }),
+                // Checked when added, so only a term written to the table by hand
+                Err(e) => log_error(&format!("Skipping blocked term {}: {}", row.id, e)),
+            }
+        }
+        let compiled = Arc::new(compiled);
+
+        if let Ok(mut terms) = self.terms.write() {
+            *terms = Some(compiled.clone());
+        }
+        compiled
+    }
+
+    // The text with "replace" terms masked, or Err with the message to show
+    // when it uses a "reject" term. `what` names the text in that message.
+    pub async fn screen(&self, pool: &PgPool, what: &str, text: String) -> Result<String, String> {
+        let terms = self.current(pool).await;
+        if terms.iter().any(|term| term.action == Action::Reject && term.matcher.is_match(&text)) {
+            return Err(format!(
+                "Your {} uses a word or phrase that isn't allowed here. Please reword it and try again.",
+                what
+            ));
+        }
+        let mut text = text;
+        for term in terms.iter().filter(|term| term.action == Action::Replace) {
+            // One asterisk per character, so length limits still hold
+            if term.matcher.is_match(&text) {
+                text = term
+                    .matcher
+                    .replace_all(&text, |caps: &regex::Captures| "*".repeat(caps[0].chars().count()))
+                    .into_owned();
+            }
+        }
+        Ok(text)
+    }
+
+    pub async fn screen_article(&self, pool: &PgPool, title: String, body: String) -> Result<(String, String), String> {
+        let title = self.screen(pool, "title", title).await?;
+        let body = self.screen(pool, "article", body).await?;
+        Ok((title, body))
+    }
+}
+
+impl Invalidate for Blocklist {
+    fn invalidate(&self, target: Target) -> Vec<String> {
+        if !matches!(target, Target::All | Target::Blocklist) {
+            return Vec::new();
+        }
+        Blocklist::invalidate(self);
+        vec!["blocked terms".to_string()]
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TermForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+    pattern: String,
+    is_regex: Option<String>,
+    action: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteTermForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn back_to_list() -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/blocklist")))
+        .finish()
+}
+
+// GET /admin/blocklist
+pub async fn list_terms(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Any staff member may read the list; changing it takes ManageSettings
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", None, "blocklist").await {
+        return res;
+    }
+
+    let terms = match sqlx::query_as::<_, BlockedTerm>("SELECT id, pattern, is_regex, action FROM blocked_terms ORDER BY id")
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(terms) => terms,
+        Err(e) => {
+            log_error(&format!("Failed to fetch blocked terms: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load blocklist");
+        }
+    };
+
+    let auth_fields = format!("{}{}", csrf::field(&req), admin::password_field(&req));
+
+    let mut rows_html = String::new();
+    for term in &terms {
+        rows_html.push_str(&format!(
+            r#"<div class="comment"><p><code>{}</code></p><p>{} / {}</p>
+            <form action="{base}/admin/blocklist/{}/delete" method="POST">
+                {}
+                <input type="submit" value="Remove">
+            </form></div>"#,
+            html_escape::encode_text(&term.pattern),
+            if term.is_regex { "regex" } else { "word or phrase" },
+            term.action,
+            term.id,
+            auth_fields
+        ));
+    }
+    if terms.is_empty() {
+        rows_html.push_str("<p>No blocked terms.</p>");
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Blocklist</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Block a Term</h2>
+        <p>Checked against new articles, article edits and comments. A word or phrase matches whole words, ignoring case; a regex is used as written (add <code>(?i)</code> to ignore case).</p>
+        <form action="{base}/admin/blocklist" method="POST">
+            {}
+            <input type="text" name="pattern" placeholder="Word, phrase or regex" required><br>
+            <label><input type="checkbox" name="is_regex" value="1"> Regex</label><br>
+            <select name="action">
+                <option value="reject">Reject the post</option>
+                <option value="replace">Replace with asterisks</option>
+            </select><br><br>
+            <input type="submit" value="Add Term">
+        </form>
+        </div>
+        <h2>Blocked Terms</h2>
+        {}
+        </body>
+        </html>
+        "#,
+        auth_fields, rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// POST /admin/blocklist
+pub async fn add_term(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    blocklist: web::Data<Blocklist>,
+    form: web::Form<TermForm>,
+) -> HttpResponse {
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageSettings),
+        "blocklist change",
+    )
+    .await
+    {
+        return res;
+    }
+
+    let pattern = form.pattern.trim();
+    if pattern.is_empty() {
+        return HttpResponse::BadRequest().body("A term is required");
+    }
+    let action = match Action::parse(&form.action) {
+        Some(action) => action,
+        None => return HttpResponse::BadRequest().body("Unknown action"),
+    };
+    let is_regex = form.is_regex.is_some();
+    if let Err(e) = compile(pattern, is_regex) {
+        return HttpResponse::BadRequest().body(format!("Invalid regex: {}", e));
+    }
+
+    // Adding a term again updates its action
+    if let Err(e) = sqlx::query(
+        "INSERT INTO blocked_terms (pattern, is_regex, action, created_at) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (pattern, is_regex) DO UPDATE SET action = EXCLUDED.action",
+    )
+    .bind(pattern)
+    .bind(is_regex)
+    .bind(action.name())
+    .bind(Utc::now().timestamp())
+    .execute(pool.get_ref())
+    .await
+    {
+        log_error(&format!("Failed to store blocked term: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to store blocked term.");
+    }
+
+    blocklist.invalidate();
+    back_to_list()
+}
+
+// POST /admin/blocklist/{id}/delete
+pub async fn delete_term(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    blocklist: web::Data<Blocklist>,
+    path: web::Path<i32>,
+    form: web::Form<DeleteTermForm>,
+) -> HttpResponse {
+    let term_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageSettings),
+        "blocklist change",
+    )
+    .await
+    {
+        return res;
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM blocked_terms WHERE id = $1")
+        .bind(term_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        log_error(&format!("Failed to delete blocked term: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to delete blocked term.");
+    }
+
+    blocklist.invalidate();
+    back_to_list()
+}
@@ -0,0 +1,176 @@
+use ammonia::Builder;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::{outbound, paths, text};
+
+// How an article body is written. Bodies are stored as typed and rendered
+// on every view, so sanitizer changes apply to old articles too.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Markdown,
+    Plain,
+    Html,
+}
+
+const ALL: [BodyFormat; 3] = [BodyFormat::Plain, BodyFormat::Markdown, BodyFormat::Html];
+
+impl BodyFormat {
+    pub fn parse(name: &str) -> Option<BodyFormat> {
+        match name.trim() {
+            "markdown" => Some(BodyFormat::Markdown),
+            "plain" => Some(BodyFormat::Plain),
+            "html" => Some(BodyFormat::Html),
+            _ => None,
+        }
+    }
+
+    // Stored in articles.body_format
+    pub fn name(self) -> &'static str {
+        match self {
+            BodyFormat::Markdown => "markdown",
+            BodyFormat::Plain => "plain",
+            BodyFormat::Html => "html",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            BodyFormat::Markdown => "Markdown",
+            BodyFormat::Plain => "Plain text",
+            BodyFormat::Html => "HTML (basic tags only)",
+        }
+    }
+}
+
+// Stored names that aren't known any more render as plain text
+pub fn from_column(name: &str) -> BodyFormat {
+    BodyFormat::parse(name).unwrap_or(BodyFormat::Plain)
+}
+
+// <select> for the submit and edit forms
+pub fn select_html(selected: BodyFormat) -> String {
+    let mut html = String::from(r#"<label>Body format <select name="body_format">"#);
+    for format in ALL {
+        html.push_str(&format!(
+            r#"<option value="{}"{}>{}</option>"#,
+            format.name(),
+            if format == selected { " selected" } else { "" },
+            format.label()
+        ));
+    }
+    html.push_str("</select></label><br>");
+    html
+}
+
+// Body as HTML safe to put on a page, with `#123` references linked where
+// the format allows it. With `outbound_for`, external links go through /out
+// signed for that article.
+pub fn render(format: BodyFormat, body: &str, ref_titles: &HashMap<i32, String>, outbound_for: Option<i32>) -> String {
+    match format {
+        BodyFormat::Plain => {
+            let linked = text::link_references(&html_escape::encode_text(body), ref_titles);
+            // Left unwrapped so long bodies can fold anywhere, not just between blocks
+            linked.trim().replace("\r\n", "\n").replace('\n', "<br>\n")
+        }
+        BodyFormat::Markdown => text::render_markdown_linked(body, ref_titles, outbound_for),
+        // References aren't linked here: the sanitized markup has attributes
+        // a bare `#123` could sit in, and authors of HTML bodies write their own links
+        BodyFormat::Html => match outbound_for {
+            Some(article_id) => sanitizer_for(Some(article_id)).clean(body).to_string(),
+            None => sanitize_html(body),
+        },
+    }
+}
+
+// Conservative allowlist for `html` bodies. Links get rel="nofollow noopener
+// noreferrer" and images may only show files uploaded here.
+fn sanitizer() -> &'static Builder<'static> {
+    static SANITIZER: OnceLock<Builder<'static>> = OnceLock::new();
+    SANITIZER.get_or_init(|| sanitizer_for(None))
+}
+
+// The same rules, with external links sent through /out for `outbound_for`.
+// Built per page when wrapping, as the signature depends on the article.
+fn sanitizer_for(outbound_for: Option<i32>) -> Builder<'static> {
+    let mut builder = Builder::empty();
+    builder
+        .tags(HashSet::from([
+            "p", "br", "a", "em", "strong", "ul", "ol", "li", "blockquote", "img", "code", "pre",
+        ]))
+        .tag_attributes(HashMap::from([
+            ("a", HashSet::from(["href"])),
+            ("img", HashSet::from(["src", "alt"])),
+        ]))
+        .clean_content_tags(HashSet::from(["script", "style"]))
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some(outbound::REL))
+        .attribute_filter(move |element, attribute, value| match (element, attribute) {
+            ("img", "src") if is_upload_path(value) => Some(Cow::Borrowed(value)),
+            ("img", "src") => None,
+            ("a", "href") if outbound::is_external(value) => match outbound_for {
+                Some(article_id) => Some(Cow::Owned(outbound::wrap(article_id, value.trim()))),
+                None => Some(Cow::Borrowed(value)),
+            },
+            _ => Some(Cow::Borrowed(value)),
+        });
+    builder
+}
+
+pub fn sanitize_html(html: &str) -> String {
+    sanitizer().clean(html).to_string()
+}
+
+// Same-origin /uploads/ paths, with or without the configured base path
+fn is_upload_path(src: &str) -> bool {
+    let uploads = paths::url("/uploads/");
+    (src.starts_with(&uploads) || src.starts_with("/uploads/")) && !src.contains("..") && !src.contains("//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_event_handlers() {
+        let clean = sanitize_html(r#"<p onclick="steal()">Hi <img src="/uploads/a.png" onerror="steal()"></p>"#);
+        assert!(!clean.contains("onclick"), "{}", clean);
+        assert!(!clean.contains("onerror"), "{}", clean);
+        assert!(!clean.contains("steal"), "{}", clean);
+        assert!(clean.contains(r#"<img src="/uploads/a.png">"#), "{}", clean);
+    }
+
+    #[test]
+    fn strips_javascript_urls() {
+        for href in ["javascript:alert(1)", "JavaScript:alert(1)", " javascript:alert(1)", "java\tscript:alert(1)"] {
+            let clean = sanitize_html(&format!(r#"<a href="{}">x</a>"#, href));
+            assert!(!clean.to_lowercase().contains("script:"), "{}", clean);
+            assert!(!clean.contains("href"), "{}", clean);
+        }
+    }
+
+    #[test]
+    fn strips_style_attributes_and_elements() {
+        let clean = sanitize_html(
+            r#"<p style="position:fixed;top:0">Hi</p><style>body { display: none }</style><script>steal()</script>"#,
+        );
+        assert_eq!(clean, "<p>Hi</p>");
+    }
+
+    #[test]
+    fn keeps_allowed_markup() {
+        let clean = sanitize_html(r#"<p><a href="https://example.com/">link</a> <em>and</em> <code>x</code></p>"#);
+        assert_eq!(
+            clean,
+            format!(r#"<p><a href="https://example.com/" rel="{}">link</a> <em>and</em> <code>x</code></p>"#, outbound::REL)
+        );
+    }
+
+    #[test]
+    fn only_shows_uploaded_images() {
+        assert!(!sanitize_html(r#"<img src="https://evil.example/track.gif">"#).contains("src"));
+        assert!(!sanitize_html(r#"<img src="/uploads/../admin">"#).contains("src"));
+        assert!(!sanitize_html(r#"<img src="/uploads//evil.example/x.png">"#).contains("src"));
+    }
+}
@@ -0,0 +1,121 @@
+use sqlx::PgConnection;
+
+// How activity moves an article in the listing. bump_time is only the sort
+// key; what happened is kept in last_comment_at and last_edited_at, and
+// `policy` is the one table of rules turning activity into a bump. New
+// rules go there rather than into the handlers.
+#[derive(Clone, Copy, Debug)]
+pub enum Activity {
+    // A comment posted on the site or by email
+    Commented,
+    // A comment carried over by an import, at its original time
+    ImportedComment,
+    // Staff saved changes to the article
+    Edited,
+    // Staff made a shadow-hidden article public, as if posted then
+    Released,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bump {
+    // Sort by the activity's time
+    To,
+    // Only ever move later, so old activity can't sink a thread
+    Forward,
+}
+
+fn policy(activity: Activity) -> Bump {
+    match activity {
+        Activity::Commented | Activity::Edited | Activity::Released => Bump::To,
+        Activity::ImportedComment => Bump::Forward,
+    }
+}
+
+// The UPDATE recording `activity`, taking its time as $1 and the article as $2
+fn update_sql(activity: Activity) -> String {
+    let column = match activity {
+        Activity::Commented | Activity::ImportedComment => Some("last_comment_at"),
+        Activity::Edited => Some("last_edited_at"),
+        // Neither a comment nor an edit, so only the sort key moves
+        Activity::Released => None,
+    };
+    let bump_time = match policy(activity) {
+        Bump::To => "$1",
+        Bump::Forward => "GREATEST(bump_time, $1)",
+    };
+    match column {
+        Some(column) => format!(
+            "UPDATE articles SET {column} = GREATEST(COALESCE({column}, 0), $1), bump_time = {bump_time} WHERE id = $2"
+        ),
+        None => format!("UPDATE articles SET bump_time = {bump_time} WHERE id = $2"),
+    }
+}
+
+// Records `activity` at `at` and bumps the article as the policy says. Pass
+// the transaction making the change so both commit together.
+pub async fn record(conn: &mut PgConnection, article_id: i32, activity: Activity, at: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(&update_sql(activity))
+        .bind(at)
+        .bind(article_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Activity; 4] = [Activity::Commented, Activity::ImportedComment, Activity::Edited, Activity::Released];
+
+    #[test]
+    fn policy_matrix() {
+        for (activity, expected) in [
+            (Activity::Commented, Bump::To),
+            (Activity::ImportedComment, Bump::Forward),
+            (Activity::Edited, Bump::To),
+            (Activity::Released, Bump::To),
+        ] {
+            assert_eq!(policy(activity), expected, "{:?}", activity);
+        }
+    }
+
+    #[test]
+    fn update_follows_the_policy() {
+        for activity in ALL {
+            let sql = update_sql(activity);
+            match policy(activity) {
+                Bump::To => assert!(sql.contains("bump_time = $1 "), "{}", sql),
+                Bump::Forward => assert!(sql.contains("bump_time = GREATEST(bump_time, $1)"), "{}", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn activity_lands_in_its_column() {
+        for (activity, column) in [
+            (Activity::Commented, "last_comment_at"),
+            (Activity::ImportedComment, "last_comment_at"),
+            (Activity::Edited, "last_edited_at"),
+        ] {
+            let sql = update_sql(activity);
+            assert!(
+                sql.contains(&format!("{column} = GREATEST(COALESCE({column}, 0), $1)")),
+                "{}",
+                sql
+            );
+            let other = if column == "last_comment_at" {
+                "last_edited_at"
+            } else {
+                "last_comment_at"
+            };
+            assert!(!sql.contains(other), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn release_only_moves_the_sort_key() {
+        let sql = update_sql(Activity::Released);
+        assert!(!sql.contains("last_comment_at") && !sql.contains("last_edited_at"), "{}", sql);
+    }
+}
@@ -0,0 +1,154 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::{Arc, RwLock};
+
+use crate::admin::{AdminAuth, Permission};
+use crate::log_error;
+
+// In-memory caches register here at startup so POST /admin/invalidate can
+// clear them without knowing each one. A cache ignores targets that aren't
+// its concern.
+#[derive(Clone, Copy)]
+pub enum Target {
+    All,
+    Listing,
+    Article(i32),
+    Feeds,
+    Settings,
+    Announcements,
+    Blocklist,
+    // Not a cache: the media row is queued for the derivatives job again
+    Derivatives(i32),
+}
+
+impl Target {
+    fn parse(value: &str) -> Option<Target> {
+        let value = value.trim();
+        if let Some(id) = value.strip_prefix("article:") {
+            return id.parse().ok().map(Target::Article);
+        }
+        if let Some(id) = value.strip_prefix("derivatives:") {
+            return id.parse().ok().map(Target::Derivatives);
+        }
+        match value {
+            "all" => Some(Target::All),
+            "listing" => Some(Target::Listing),
+            "feeds" => Some(Target::Feeds),
+            "settings" => Some(Target::Settings),
+            "announcements" => Some(Target::Announcements),
+            "blocklist" => Some(Target::Blocklist),
+            _ => None,
+        }
+    }
+}
+
+pub trait Invalidate: Send + Sync {
+    // Clears whatever `target` covers and describes each thing cleared
+    fn invalidate(&self, target: Target) -> Vec<String>;
+}
+
+#[derive(Default)]
+pub struct CacheRegistry {
+    caches: RwLock<Vec<Arc<dyn Invalidate>>>,
+}
+
+impl CacheRegistry {
+    pub fn register(&self, cache: Arc<dyn Invalidate>) {
+        if let Ok(mut caches) = self.caches.write() {
+            caches.push(cache);
+        }
+    }
+
+    fn invalidate(&self, target: Target) -> Vec<String> {
+        match self.caches.read() {
+            Ok(caches) => caches.iter().flat_map(|cache| cache.invalidate(target)).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct InvalidateForm {
+    target: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[derive(Serialize)]
+struct InvalidateJson<'a> {
+    target: &'a str,
+    invalidated: Vec<String>,
+}
+
+// POST /admin/invalidate
+pub async fn invalidate(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    registry: web::Data<CacheRegistry>,
+    form: web::Form<InvalidateForm>,
+) -> HttpResponse {
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageSettings),
+        "cache invalidation",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    let target = match Target::parse(&form.target) {
+        Some(t) => t,
+        None => {
+            return HttpResponse::BadRequest().body(
+                "target must be all, listing, article:{id}, feeds, settings, announcements, blocklist or derivatives:{media_id}",
+            )
+        }
+    };
+
+    let mut invalidated = registry.invalidate(target);
+    if let Target::Derivatives(media_id) = target {
+        // Paths are cleared too so pages serve the original until the job
+        // has made fresh copies
+        match sqlx::query(
+            "UPDATE article_media SET derivatives_at = NULL, webp_path = NULL, avif_path = NULL WHERE id = $1",
+        )
+        .bind(media_id)
+        .execute(pool.get_ref())
+        .await
+        {
+            Ok(done) if done.rows_affected() > 0 => invalidated.push(format!("derivatives of media {}", media_id)),
+            Ok(_) => {}
+            Err(e) => {
+                log_error(&format!("Failed to queue derivatives of media {}: {}", media_id, e));
+                return HttpResponse::InternalServerError().body("Failed to queue derivatives");
+            }
+        }
+    }
+    if let Err(e) = sqlx::query("INSERT INTO admin_actions (actor, action, detail, created_at) VALUES ($1, $2, $3, $4)")
+        .bind(&staff.username)
+        .bind("invalidate")
+        .bind(format!("{}: {}", form.target.trim(), invalidated.join(", ")))
+        .bind(Utc::now().timestamp())
+        .execute(pool.get_ref())
+        .await
+    {
+        log_error(&format!("Failed to record cache invalidation: {}", e));
+    }
+
+    HttpResponse::Ok().json(InvalidateJson {
+        target: form.target.trim(),
+        invalidated,
+    })
+}
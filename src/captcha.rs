@@ -0,0 +1,112 @@
+use actix_web::HttpRequest;
+use chrono::Utc;
+use rand::Rng;
+use sqlx::PgPool;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::{admin, log_error, tokens};
+
+// Optional arithmetic challenge on the submit and comment forms, answered
+// without any outside service. The form carries a signed, expiring token
+// holding a random nonce and a keyed hash of the answer, so nothing is
+// stored until someone answers. Answering consumes the nonce in the
+// captchas table, rightly or wrongly, so one challenge can't be replayed or
+// guessed at repeatedly. CAPTCHA=1 turns it on; staff sessions skip it.
+pub const TOKEN_FIELD: &str = "captcha_token";
+pub const ANSWER_FIELD: &str = "captcha_answer";
+
+const PURPOSE: &str = "captcha";
+const ANSWER_PURPOSE: &str = "captcha-answer";
+const TTL_SECS: i64 = 30 * 60;
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+pub fn init_from_env() {
+    let _ = ENABLED.set(env::var("CAPTCHA").is_ok_and(|v| v.trim() == "1"));
+}
+
+fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+fn required(req: &HttpRequest) -> bool {
+    enabled() && !admin::is_admin(req)
+}
+
+fn answer_hash(nonce: &str, answer: i64) -> String {
+    tokens::sign_short(ANSWER_PURPOSE, &format!("{}|{}", nonce, answer))
+}
+
+// A fresh challenge for inclusion in a form, or "" when none is needed
+pub fn field(req: &HttpRequest) -> String {
+    if !required(req) {
+        return String::new();
+    }
+    let mut rng = rand::thread_rng();
+    let nonce: String = (0..16).map(|_| format!("{:02x}", rng.gen::<u8>())).collect();
+    let a: i64 = rng.gen_range(2..=12);
+    let b: i64 = rng.gen_range(1..=9);
+    let (question, answer) = if rng.gen_bool(0.5) {
+        (format!("{} + {}", a, b), a + b)
+    } else {
+        // Never negative
+        (format!("{} − {}", a.max(b), a.min(b)), a.max(b) - a.min(b))
+    };
+    let token = tokens::sign_expiring(PURPOSE, &format!("{}|{}", nonce, answer_hash(&nonce, answer)), TTL_SECS);
+    format!(
+        r#"<input type="hidden" name="{}" value="{}">
+                <label class="captcha">What is {}? <input type="text" name="{}" inputmode="numeric" autocomplete="off" required></label><br>"#,
+        TOKEN_FIELD,
+        html_escape::encode_double_quoted_attribute(&token),
+        question,
+        ANSWER_FIELD
+    )
+}
+
+// Ok when no challenge is needed or it was answered right. Err carries the
+// message to show above a form with a new challenge.
+pub async fn check(req: &HttpRequest, pool: &PgPool, token: &str, answer: &str) -> Result<(), String> {
+    if !required(req) {
+        return Ok(());
+    }
+    let expired = "The challenge expired. Please answer the new one below.".to_string();
+    let data = tokens::verify_expiring(PURPOSE, token.trim()).ok_or_else(|| expired.clone())?;
+    let (nonce, hash) = data.split_once('|').ok_or_else(|| expired.clone())?;
+
+    // Tokens carry their own expiry; the row only needs to outlive it
+    let consumed = sqlx::query("INSERT INTO captchas (nonce, expires_at) VALUES ($1, $2) ON CONFLICT (nonce) DO NOTHING")
+        .bind(nonce)
+        .bind(Utc::now().timestamp() + TTL_SECS)
+        .execute(pool)
+        .await;
+    match consumed {
+        Ok(result) if result.rows_affected() == 0 => {
+            return Err("That challenge was already used. Please answer the new one below.".to_string())
+        }
+        Ok(_) => {}
+        Err(e) => {
+            log_error(&format!("Failed to consume captcha: {}", e));
+            return Err("The answer couldn't be checked. Please try the new challenge below.".to_string());
+        }
+    }
+
+    let right = answer
+        .trim()
+        .parse::<i64>()
+        .is_ok_and(|answer| tokens::verify_short(ANSWER_PURPOSE, &format!("{}|{}", nonce, answer), hash));
+    if right {
+        Ok(())
+    } else {
+        Err("Wrong answer to the challenge. Please try the new one below.".to_string())
+    }
+}
+
+// Drops nonces whose challenges have expired
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM captchas WHERE expires_at < $1")
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
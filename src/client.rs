@@ -0,0 +1,43 @@
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::OnceLock;
+
+// Header a trusted reverse proxy puts the client address in, from
+// TRUSTED_PROXY_HEADER (e.g. X-Real-IP behind nginx). Unset, the address is
+// the peer's: Forwarded/X-Forwarded-For are set by the client and prove nothing.
+static TRUSTED_PROXY_HEADER: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn init_from_env() {
+    let header = env::var("TRUSTED_PROXY_HEADER")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty());
+    let _ = TRUSTED_PROXY_HEADER.set(header);
+}
+
+// Best-effort address of the client that sent the request
+pub fn client_ip(req: &HttpRequest) -> String {
+    if let Some(header) = TRUSTED_PROXY_HEADER.get().and_then(Option::as_deref) {
+        // A list (X-Forwarded-For) ends with the address our proxy saw
+        let from_proxy = req
+            .headers()
+            .get(header)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+        if let Some(ip) = from_proxy {
+            return ip.to_string();
+        }
+    }
+    req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_else(|| "unknown".to_string())
+}
+
+// Short, non-reversible form of the client address for storing alongside
+// actions, so moderators can tell clients apart without keeping raw IPs
+pub fn ip_representation(req: &HttpRequest) -> String {
+    let digest = Sha256::digest(client_ip(req).as_bytes());
+    let hex: String = digest.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    format!("ip:{}", hex)
+}
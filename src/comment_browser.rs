@@ -0,0 +1,467 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{NaiveDate, TimeZone, Utc};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
+use std::collections::HashMap;
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::db::comments;
+use crate::degraded::StaleCache;
+use crate::events::{self, EventKind};
+use crate::paths;
+use crate::settings::SettingsCache;
+use crate::{csrf, log_error, remove_comments, visibility};
+
+// Admin view over all comments at /admin/comments. Filters travel in the
+// query string so a filtered view can be bookmarked or shared.
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct CommentFilters {
+    q: Option<String>,
+    article_id: Option<String>,
+    ip: Option<String>,
+    from: Option<String>,   // YYYY-MM-DD, UTC
+    to: Option<String>,     // YYYY-MM-DD, UTC, inclusive
+    status: Option<String>, // "visible", "hidden", "shadow", "deleted" or blank for all
+    page: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct CommentRow {
+    id: i32,
+    article_id: i32,
+    article_title: String,
+    comment: String,
+    created_at: Option<i64>,
+    ip_representation: Option<String>,
+    hidden: bool,
+    deleted: bool,
+    is_admin: bool,
+    shadow_hidden: bool,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+fn parse_day(value: &str) -> Option<i64> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| Utc.from_utc_datetime(&dt).timestamp())
+}
+
+// Escapes LIKE wildcards so the search text is matched literally
+fn like_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl CommentFilters {
+    fn push_where(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        query.push(" WHERE TRUE");
+        if let Some(q) = non_empty(&self.q) {
+            query.push(" AND c.comment ILIKE ").push_bind(like_pattern(q));
+        }
+        if let Some(id) = non_empty(&self.article_id).and_then(|v| v.parse::<i32>().ok()) {
+            query.push(" AND c.article_id = ").push_bind(id);
+        }
+        if let Some(ip) = non_empty(&self.ip) {
+            query.push(" AND c.ip_representation = ").push_bind(ip.to_string());
+        }
+        if let Some(from) = non_empty(&self.from).and_then(parse_day) {
+            query.push(" AND c.created_at >= ").push_bind(from);
+        }
+        if let Some(to) = non_empty(&self.to).and_then(parse_day) {
+            query.push(" AND c.created_at < ").push_bind(to + 86_400);
+        }
+        match non_empty(&self.status) {
+            Some("visible") => {
+                query.push(" AND ").push(visibility::live_comments("c"));
+            }
+            Some("hidden") => {
+                query.push(" AND c.hidden");
+            }
+            Some("shadow") => {
+                query.push(" AND c.shadow_hidden");
+            }
+            Some("deleted") => {
+                query.push(" AND c.deleted");
+            }
+            _ => {}
+        }
+    }
+
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    // Query string for these filters on another page, e.g. "q=spam&page=2"
+    fn query_string(&self, page: i64) -> String {
+        let mut parts = Vec::new();
+        for (key, value) in [
+            ("q", &self.q),
+            ("article_id", &self.article_id),
+            ("ip", &self.ip),
+            ("from", &self.from),
+            ("to", &self.to),
+            ("status", &self.status),
+        ] {
+            if let Some(v) = non_empty(value) {
+                parts.push(format!("{}={}", key, utf8_percent_encode(v, NON_ALPHANUMERIC)));
+            }
+        }
+        parts.push(format!("page={}", page));
+        parts.join("&")
+    }
+}
+
+fn attr(value: &Option<String>) -> String {
+    html_escape::encode_double_quoted_attribute(non_empty(value).unwrap_or("")).into_owned()
+}
+
+fn format_time(ts: Option<i64>) -> String {
+    ts.and_then(|t| Utc.timestamp_opt(t, 0).single())
+        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+// Where to send the admin after an action: back to the browser, keeping filters
+fn back_location(form: &HashMap<String, String>) -> String {
+    match form.get("back") {
+        Some(back) if back.starts_with("/admin/comments") => paths::url(back),
+        _ => paths::url("/admin/comments"),
+    }
+}
+
+pub async fn browse_comments(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    filters: web::Query<CommentFilters>,
+) -> HttpResponse {
+    let base = paths::base();
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        "",
+        Some(Permission::DeleteComments),
+        "comment browser",
+    )
+    .await
+    {
+        return res;
+    }
+
+    let page = filters.page();
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT c.id, c.article_id, a.title AS article_title, c.comment, c.created_at, c.ip_representation, c.hidden, c.deleted, c.is_admin, c.shadow_hidden
+         FROM comments c JOIN articles a ON a.id = c.article_id",
+    );
+    filters.push_where(&mut query);
+    query
+        .push(" ORDER BY c.id DESC LIMIT ")
+        .push_bind(PAGE_SIZE + 1)
+        .push(" OFFSET ")
+        .push_bind((page - 1) * PAGE_SIZE);
+
+    let mut rows = match query.build_query_as::<CommentRow>().fetch_all(pool.get_ref()).await {
+        Ok(r) => r,
+        Err(e) => {
+            log_error(&format!("Failed to browse comments: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load comments");
+        }
+    };
+    // One extra row tells us whether there is a next page
+    let has_next = rows.len() as i64 > PAGE_SIZE;
+    rows.truncate(PAGE_SIZE as usize);
+
+    let back = format!("/admin/comments?{}", filters.query_string(page));
+    let back_attr = html_escape::encode_double_quoted_attribute(&back).into_owned();
+    // Every POST form here carries the CSRF token plus the password input when needed
+    let auth_fields = format!("{}{}", csrf::field(&req), admin::password_field(&req));
+
+    let mut rows_html = String::new();
+    for row in &rows {
+        // Shown only to its poster until released
+        let release = if row.shadow_hidden && !row.deleted {
+            format!(
+                r#"<form action="{base}/admin/comments/{}/release" method="POST">
+                    <input type="hidden" name="back" value="{}">
+                    {}
+                    <input type="submit" value="Release">
+                </form>"#,
+                row.id, back_attr, auth_fields
+            )
+        } else {
+            String::new()
+        };
+        rows_html.push_str(&format!(
+            r#"<tr{}>
+            <td><input type="checkbox" name="comment_{}" value="1" form="bulk-delete"></td>
+            <td>{}</td>
+            <td><a href="{base}/articles/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>
+                <form action="{base}/admin/comments/{}/hide" method="POST">
+                    <input type="hidden" name="back" value="{}">
+                    {}
+                    <input type="submit" value="{}">
+                </form>
+                {}
+                <a href="{base}/comments/{}/delete" class="delete-link">[x]</a>
+            </td>
+            </tr>"#,
+            if row.shadow_hidden { r#" class="shadow-hidden""# } else { "" },
+            row.id,
+            row.id,
+            row.article_id,
+            html_escape::encode_text(&row.article_title),
+            html_escape::encode_text(&row.comment),
+            format_time(row.created_at),
+            html_escape::encode_text(row.ip_representation.as_deref().unwrap_or("-")),
+            match (row.deleted, row.hidden, row.shadow_hidden, row.is_admin) {
+                (true, _, _, _) => "deleted",
+                (false, true, _, _) => "hidden",
+                (false, false, true, _) => r#"<span class="shadow-badge">shadow-hidden</span>"#,
+                (false, false, false, true) => "visible (official)",
+                (false, false, false, false) => "visible",
+            },
+            row.id,
+            back_attr,
+            auth_fields,
+            if row.hidden { "Unhide" } else { "Hide" },
+            release,
+            row.id
+        ));
+    }
+    if rows.is_empty() {
+        rows_html.push_str(r#"<tr><td colspan="8">No comments match these filters</td></tr>"#);
+    }
+
+    let mut pager_html = String::new();
+    if page > 1 {
+        pager_html.push_str(&format!(
+            r#"<a href="{base}/admin/comments?{}">← Newer</a> "#,
+            html_escape::encode_double_quoted_attribute(&filters.query_string(page - 1))
+        ));
+    }
+    if has_next {
+        pager_html.push_str(&format!(
+            r#"<a href="{base}/admin/comments?{}">Older →</a>"#,
+            html_escape::encode_double_quoted_attribute(&filters.query_string(page + 1))
+        ));
+    }
+
+    let status = non_empty(&filters.status).unwrap_or("");
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Comments</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <h2>Comments</h2>
+        <form action="{base}/admin/comments" method="GET" class="search-form">
+            <input type="text" name="q" placeholder="Text" value="{}">
+            <input type="text" name="article_id" placeholder="Article id" value="{}">
+            <input type="text" name="ip" placeholder="ip:..." value="{}">
+            From <input type="date" name="from" value="{}">
+            To <input type="date" name="to" value="{}">
+            <select name="status">
+                <option value=""{}>All</option>
+                <option value="visible"{}>Visible</option>
+                <option value="hidden"{}>Hidden</option>
+                <option value="shadow"{}>Shadow-hidden</option>
+                <option value="deleted"{}>Deleted</option>
+            </select>
+            <input type="submit" value="Filter">
+        </form>
+        <form id="bulk-delete" action="{base}/admin/comments/bulk-delete" method="POST">
+            <input type="hidden" name="back" value="{}">
+            {}
+            <input type="submit" value="Delete Selected">
+        </form>
+        <table class="activity">
+            <tr><th></th><th>ID</th><th>Article</th><th>Comment</th><th>Posted</th><th>Poster</th><th>Status</th><th>Actions</th></tr>
+            {}
+        </table>
+        <div class="center-link">{}</div>
+        </body>
+        </html>
+        "#,
+        attr(&filters.q),
+        attr(&filters.article_id),
+        attr(&filters.ip),
+        attr(&filters.from),
+        attr(&filters.to),
+        if status.is_empty() { " selected" } else { "" },
+        if status == "visible" { " selected" } else { "" },
+        if status == "hidden" { " selected" } else { "" },
+        if status == "shadow" { " selected" } else { "" },
+        if status == "deleted" { " selected" } else { "" },
+        back_attr,
+        auth_fields,
+        rows_html,
+        pager_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// Flips a comment's hidden flag and notes it on the article's timeline.
+// Returns the comment's article, or None when there is no such comment.
+async fn toggle_and_record(pool: &PgPool, comment_id: i32, actor: &str) -> Result<Option<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let toggled: Option<(i32, bool)> =
+        sqlx::query_as("UPDATE comments SET hidden = NOT hidden WHERE id = $1 RETURNING article_id, hidden")
+            .bind(comment_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if let Some((article_id, hidden)) = toggled {
+        let kind = if hidden { EventKind::CommentHidden } else { EventKind::CommentShown };
+        let detail = format!("comment #{}", comment_id);
+        events::record(&mut tx, article_id, kind, actor, Some(&detail)).await?;
+        comments::refresh_counts(&mut tx, &[article_id]).await?;
+    }
+    tx.commit().await?;
+    Ok(toggled.map(|(article_id, _)| article_id))
+}
+
+// Toggles whether a comment is shown on its article
+pub async fn toggle_hidden(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    stale_cache: web::Data<StaleCache>,
+    path: web::Path<i32>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let comment_id = path.into_inner();
+    let password = form.get("password").map(String::as_str).unwrap_or("");
+    let submitted = form.get(csrf::FIELD_NAME).map(String::as_str).unwrap_or("");
+    if let Err(res) = auth.check_csrf(&req, submitted, password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        password,
+        Some(Permission::DeleteComments),
+        "comment hiding",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    match toggle_and_record(pool.get_ref(), comment_id, &staff.username).await {
+        Ok(Some(article_id)) => stale_cache.article_changed(article_id),
+        Ok(None) => {}
+        Err(e) => {
+            log_error(&format!("Failed to toggle comment visibility: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to update comment.");
+        }
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", back_location(&form)))
+        .finish()
+}
+
+// Deletes every comment ticked in the browser (`comment_<id>` fields)
+pub async fn bulk_delete(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    stale_cache: web::Data<StaleCache>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let password = form.get("password").map(String::as_str).unwrap_or("");
+    let submitted = form.get(csrf::FIELD_NAME).map(String::as_str).unwrap_or("");
+    if let Err(res) = auth.check_csrf(&req, submitted, password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        password,
+        Some(Permission::DeleteComments),
+        "bulk comment deletion",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    let ids: Vec<i32> = form
+        .keys()
+        .filter_map(|k| k.strip_prefix("comment_"))
+        .filter_map(|id| id.parse().ok())
+        .collect();
+
+    if !ids.is_empty() {
+        let tombstone = settings_cache.get(pool.get_ref()).await.tombstone_deleted_comments;
+        match remove_comments(pool.get_ref(), &ids, tombstone, staff.is_super_admin(), &staff.username).await {
+            Ok(article_ids) => article_ids.into_iter().for_each(|id| stale_cache.article_changed(id)),
+            Err(e) => {
+                log_error(&format!("Failed to bulk delete comments: {}", e));
+                return HttpResponse::InternalServerError().body("Failed to delete comments.");
+            }
+        }
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", back_location(&form)))
+        .finish()
+}
+
+// Makes a shadow-hidden comment public
+pub async fn release(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    stale_cache: web::Data<StaleCache>,
+    path: web::Path<i32>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let comment_id = path.into_inner();
+    let password = form.get("password").map(String::as_str).unwrap_or("");
+    let submitted = form.get(csrf::FIELD_NAME).map(String::as_str).unwrap_or("");
+    if let Err(res) = auth.check_csrf(&req, submitted, password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        password,
+        Some(Permission::DeleteComments),
+        "comment release",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    match comments::release(pool.get_ref(), comment_id, &staff.username).await {
+        Ok(Some(article_id)) => stale_cache.article_changed(article_id),
+        Ok(None) => return HttpResponse::NotFound().body("No shadow-hidden comment with that id"),
+        Err(e) => {
+            log_error(&format!("Failed to release comment: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to release comment.");
+        }
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", back_location(&form)))
+        .finish()
+}
@@ -0,0 +1,201 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+use crate::blocklist::Blocklist;
+use crate::degraded::StaleCache;
+use crate::settings::SettingsCache;
+use crate::{csrf, log_error, paths, references, tokens, validate_comment, CommentForm};
+
+// Lets commenters fix their own comment for a while after posting. The
+// browser that posted it holds a signed token for the comment id, which
+// expires with the edit window.
+const EDIT_PURPOSE: &str = "comment-edit";
+const EDIT_COOKIE_PREFIX: &str = "comment_edit_";
+
+fn cookie_path(article_id: i32) -> String {
+    paths::url(&format!("/articles/{}", article_id))
+}
+
+// Cookie handed out by submit_comment. Scoped to the article, which also
+// covers the edit routes below it.
+pub fn edit_cookie(article_id: i32, comment_id: i32, window_minutes: i64) -> Cookie<'static> {
+    let ttl = window_minutes * 60;
+    Cookie::build(
+        format!("{}{}", EDIT_COOKIE_PREFIX, comment_id),
+        tokens::sign_expiring(EDIT_PURPOSE, &comment_id.to_string(), ttl),
+    )
+    .path(cookie_path(article_id))
+    .http_only(true)
+    .same_site(SameSite::Lax)
+    .max_age(CookieDuration::seconds(ttl))
+    .finish()
+}
+
+fn can_edit(req: &HttpRequest, comment_id: i32) -> bool {
+    req.cookie(&format!("{}{}", EDIT_COOKIE_PREFIX, comment_id))
+        .and_then(|c| tokens::verify_expiring(EDIT_PURPOSE, c.value()))
+        .is_some_and(|id| id == comment_id.to_string())
+}
+
+// Ids of comments this request may still edit, for showing edit links
+pub fn editable_comments(req: &HttpRequest) -> HashSet<i32> {
+    let cookies = match req.cookies() {
+        Ok(c) => c,
+        Err(_) => return HashSet::new(),
+    };
+    cookies
+        .iter()
+        .filter_map(|c| c.name().strip_prefix(EDIT_COOKIE_PREFIX))
+        .filter_map(|id| id.parse::<i32>().ok())
+        .filter(|id| can_edit(req, *id))
+        .collect()
+}
+
+fn expired() -> HttpResponse {
+    HttpResponse::Forbidden().body("The edit window for this comment has closed")
+}
+
+pub async fn edit_comment_form(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(i32, i32)>,
+) -> HttpResponse {
+    let base = paths::base();
+    let (article_id, comment_id) = path.into_inner();
+    if !can_edit(&req, comment_id) {
+        return expired();
+    }
+
+    let comment: Option<String> = match sqlx::query_scalar(
+        "SELECT comment FROM comments WHERE id = $1 AND article_id = $2 AND NOT deleted",
+    )
+    .bind(comment_id)
+    .bind(article_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            log_error(&format!("Failed to fetch comment for editing: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load comment");
+        }
+    };
+    let comment = match comment {
+        Some(c) => c,
+        None => return HttpResponse::NotFound().body("Comment not found"),
+    };
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Edit Comment</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Edit Comment</h2>
+        <form action="{base}/articles/{}/comments/{}/edit" method="POST">
+            {}
+            <textarea name="comment" rows="4" required>{}</textarea><br>
+            <input type="submit" value="Save Comment">
+        </form>
+        <a href="{base}/articles/{}">← Back to Article</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        article_id,
+        comment_id,
+        csrf::field(&req),
+        html_escape::encode_text(&comment),
+        article_id
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn save_comment_edit(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    blocklist: web::Data<Blocklist>,
+    stale_cache: web::Data<StaleCache>,
+    path: web::Path<(i32, i32)>,
+    form: web::Form<CommentForm>,
+) -> HttpResponse {
+    let (article_id, comment_id) = path.into_inner();
+    if let Err(res) = csrf::check(&req, &form.csrf_token) {
+        return res.into();
+    }
+    // Turning the window off also stops tokens that were already issued
+    let settings = settings_cache.get(pool.get_ref()).await;
+    if !can_edit(&req, comment_id) || settings.comment_edit_minutes == 0 {
+        return expired();
+    }
+    // Screened like a new comment, so an edit can't add a blocked term
+    let checked = match validate_comment(&settings, &form.comment) {
+        Ok(text) => blocklist.screen(pool.get_ref(), "comment", text).await,
+        Err(message) => Err(message),
+    };
+    let text = match checked {
+        Ok(text) => text,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            log_error(&format!("Failed to start transaction: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to save comment.");
+        }
+    };
+
+    // Deliberately leaves bump_time alone: an edit is not new activity
+    match sqlx::query(
+        "UPDATE comments SET comment = $1, edited_at = $2 WHERE id = $3 AND article_id = $4 AND NOT deleted",
+    )
+    .bind(&text)
+    .bind(Utc::now().timestamp())
+    .bind(comment_id)
+    .bind(article_id)
+    .execute(&mut *tx)
+    .await
+    {
+        Ok(r) if r.rows_affected() == 0 => return HttpResponse::NotFound().body("Comment not found"),
+        Ok(_) => {}
+        Err(e) => {
+            log_error(&format!("Failed to save comment edit: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to save comment.");
+        }
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM article_links WHERE source_comment_id = $1")
+        .bind(comment_id)
+        .execute(&mut *tx)
+        .await
+    {
+        log_error(&format!("Failed to clear comment references: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to save comment.");
+    }
+
+    if let Err(e) = references::record_comment_refs(&mut tx, article_id, comment_id, &text).await {
+        log_error(&format!("Failed to store comment references: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to save comment.");
+    }
+    if let Err(e) = references::record_comment_replies(&mut tx, article_id, comment_id, &text).await {
+        log_error(&format!("Failed to store comment replies: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to save comment.");
+    }
+
+    if let Err(e) = tx.commit().await {
+        log_error(&format!("Failed to commit comment edit: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to save comment.");
+    }
+    stale_cache.forget_article(article_id);
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url(&format!("/articles/{}#c{}", article_id, comment_id))))
+        .finish()
+}
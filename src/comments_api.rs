@@ -0,0 +1,139 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::db::comments::{self, CommentOrder, CommentRow};
+use crate::log_error;
+
+// Read-only JSON listing of an article's comments. Pages walk the same keyset
+// as the article page: the cursor is the one a truncated page passes as
+// ?after= (a comment id, or "{score}.{id}" for top), so the two can be mixed
+// freely.
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct CommentsQuery {
+    order: Option<String>,  // "oldest" (default), "newest" or "top"
+    cursor: Option<String>, // next_cursor from the previous page
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ApiError {
+    error: &'static str,
+}
+
+#[derive(Serialize)]
+struct CommentJson {
+    id: i32,
+    // None for tombstones, which keep their place in the thread
+    text: Option<String>,
+    deleted: bool,
+    official: bool,
+    author_name: Option<String>,
+    created_at: Option<i64>,
+    edited_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct OrderJson {
+    key: &'static str,
+    sort: &'static str,
+}
+
+#[derive(Serialize)]
+struct PageJson {
+    next_cursor: Option<String>,
+    has_more: bool,
+    total: i64,
+}
+
+#[derive(Serialize)]
+struct CommentsJson {
+    article_id: i32,
+    order: OrderJson,
+    comments: Vec<CommentJson>,
+    page: PageJson,
+}
+
+impl From<CommentRow> for CommentJson {
+    fn from(row: CommentRow) -> Self {
+        CommentJson {
+            id: row.id,
+            text: (!row.deleted).then_some(row.comment),
+            deleted: row.deleted,
+            official: row.is_admin,
+            author_name: row.author_name,
+            created_at: row.created_at,
+            edited_at: row.edited_at,
+        }
+    }
+}
+
+// Comment ids never change, so an oldest or newest walk neither repeats nor
+// skips a comment while the thread is being written to. Scores do; a top walk
+// can miss a comment reported past its cursor.
+fn order_json(order: CommentOrder) -> OrderJson {
+    OrderJson {
+        key: order.key(),
+        sort: match order {
+            CommentOrder::Oldest => "id ascending",
+            CommentOrder::Newest => "id descending",
+            CommentOrder::Top => "score descending, then id ascending",
+        },
+    }
+}
+
+pub async fn list_comments(
+    pool: web::Data<PgPool>,
+    path: web::Path<i32>,
+    query: web::Query<CommentsQuery>,
+) -> HttpResponse {
+    let article_id = path.into_inner();
+    let order = CommentOrder::from_query(query.order.as_deref());
+    let after = match query.cursor.as_deref() {
+        None => order.start(),
+        Some(cursor) => match order.parse_cursor(cursor) {
+            Some(cursor) => cursor,
+            None => return HttpResponse::BadRequest().json(ApiError { error: "invalid cursor" }),
+        },
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let total = match comments::visible_count(pool.get_ref(), article_id).await {
+        Ok(Some(total)) => total,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError { error: "article not found" }),
+        Err(e) => {
+            log_error(&format!("Failed to count comments for API: {}", e));
+            return HttpResponse::InternalServerError().json(ApiError { error: "database error" });
+        }
+    };
+
+    // One extra row tells whether another page follows
+    let mut rows = match comments::visible_page(pool.get_ref(), article_id, None, order, after, limit + 1).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            log_error(&format!("Failed to fetch comments for API: {}", e));
+            return HttpResponse::InternalServerError().json(ApiError { error: "database error" });
+        }
+    };
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let next_cursor = if has_more {
+        rows.last().map(|row| order.cursor_param(order.after(row)))
+    } else {
+        None
+    };
+
+    HttpResponse::Ok().json(CommentsJson {
+        article_id,
+        order: order_json(order),
+        comments: rows.into_iter().map(CommentJson::from).collect(),
+        page: PageJson {
+            next_cursor,
+            has_more,
+            total,
+        },
+    })
+}
@@ -0,0 +1,123 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::settings::SettingsCache;
+use crate::{csrf, paths};
+
+// Cookie consent, when the require_cookie_consent setting is on. Until a
+// visitor accepts or declines, public pages show a banner and no optional
+// cookies are set; declining keeps it that way. The choice itself, the CSRF
+// token, admin sessions and comment edit tokens are strictly necessary and
+// always set. Everything else goes through `add_cookie`.
+const CONSENT_COOKIE: &str = "cookie_consent";
+const SKIPPED_PREFIXES: &[&str] = &["/api/", "/static/", "/uploads/"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Consent {
+    NotRequired,
+    Pending,
+    Accepted,
+    Declined,
+}
+
+fn from_cookie(req: &HttpRequest) -> Consent {
+    match req.cookie(CONSENT_COOKIE).as_ref().map(Cookie::value) {
+        Some("accepted") => Consent::Accepted,
+        Some("declined") => Consent::Declined,
+        _ => Consent::Pending,
+    }
+}
+
+// Middleware working out the visitor's consent state once per request
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !SKIPPED_PREFIXES.iter().any(|p| req.path().starts_with(p)) {
+        let cache = req.app_data::<web::Data<SettingsCache>>().cloned();
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let required = match (cache, pool) {
+            (Some(cache), Some(pool)) => cache.get(pool.get_ref()).await.require_cookie_consent,
+            _ => false,
+        };
+        let consent = if required {
+            from_cookie(req.request())
+        } else {
+            Consent::NotRequired
+        };
+        req.extensions_mut().insert(consent);
+    }
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+fn consent(req: &HttpRequest) -> Option<Consent> {
+    req.extensions().get::<Consent>().copied()
+}
+
+// Whether optional cookies may be set on this response
+pub fn allows_optional(req: &HttpRequest) -> bool {
+    matches!(consent(req), Some(Consent::NotRequired | Consent::Accepted))
+}
+
+// Sets an optional cookie, or silently doesn't without consent
+pub fn add_cookie(req: &HttpRequest, response: &mut HttpResponseBuilder, cookie: Cookie<'static>) {
+    if allows_optional(req) {
+        response.cookie(cookie);
+    }
+}
+
+// Accept/decline form for the top of public pages, while undecided
+pub fn banner_html(req: &HttpRequest) -> String {
+    if consent(req) != Some(Consent::Pending) {
+        return String::new();
+    }
+    format!(
+        r#"<div class="announcement cookie-consent">
+        <form action="{}/consent" method="POST">
+            {}
+            This site can remember your watched articles, your comments and dismissed notices in cookies.
+            <button type="submit" name="choice" value="accept">Accept</button>
+            <button type="submit" name="choice" value="decline">Decline</button>
+        </form>
+        </div>"#,
+        paths::base(),
+        csrf::field(req)
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ConsentForm {
+    choice: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+// POST /consent
+pub async fn record_choice(req: HttpRequest, form: web::Form<ConsentForm>) -> HttpResponse {
+    if let Err(res) = csrf::check(&req, &form.csrf_token) {
+        return res.into();
+    }
+    let value = match form.choice.as_str() {
+        "accept" => "accepted",
+        "decline" => "declined",
+        _ => return HttpResponse::BadRequest().body("choice must be accept or decline"),
+    };
+    let cookie = Cookie::build(CONSENT_COOKIE, value)
+        .path(paths::url("/"))
+        .max_age(CookieDuration::days(365))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish();
+
+    // Back to the page the banner was on, but never off-site
+    let location = paths::referer_path(&req).unwrap_or_else(|| paths::url("/articles"));
+    HttpResponse::Found()
+        .cookie(cookie)
+        .append_header(("Location", location))
+        .finish()
+}
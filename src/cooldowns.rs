@@ -0,0 +1,95 @@
+use actix_web::HttpRequest;
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+
+use crate::post_limits::PostKind;
+use crate::{client, media};
+
+// Board-style wait between posts: after an article or comment, the same
+// client (by IP) can't post another of that kind for a number of seconds set
+// in the settings. Unlike post_limits this lives in the database, so it holds
+// across restarts and workers, and it is claimed in the same transaction as
+// the post, so a post that fails to store doesn't start the clock.
+
+// Rows older than this are pruned, so no cooldown can be longer
+pub const MAX_SECS: i64 = 7 * 86_400;
+
+pub struct Cooldown {
+    ip_hash: String,
+    action: &'static str,
+    secs: i64,
+}
+
+impl Cooldown {
+    // None when `secs` is 0, which turns the cooldown off
+    pub fn for_request(req: &HttpRequest, kind: PostKind, secs: i64) -> Option<Self> {
+        if secs <= 0 {
+            return None;
+        }
+        Some(Cooldown {
+            ip_hash: media::content_hash(client::client_ip(req).as_bytes()),
+            action: match kind {
+                PostKind::Article => "article",
+                PostKind::Comment => "comment",
+                PostKind::Report => "report",
+            },
+            secs: secs.min(MAX_SECS),
+        })
+    }
+
+    // Seconds left to wait, or 0. Only a read, for refusing early; the
+    // binding check is `claim`.
+    pub async fn remaining(&self, pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let last: Option<i64> =
+            sqlx::query_scalar("SELECT last_post_at FROM post_cooldowns WHERE ip_hash = $1 AND action = $2")
+                .bind(&self.ip_hash)
+                .bind(self.action)
+                .fetch_optional(pool)
+                .await?;
+        Ok(last.map_or(0, |last| self.left(last, Utc::now().timestamp())))
+    }
+
+    // Starts the cooldown as part of the post's transaction. Returns the
+    // seconds left to wait instead when the last post was too recent. The
+    // row lock taken here makes concurrent posts from one client queue up,
+    // so only one of them gets through.
+    pub async fn claim(&self, conn: &mut PgConnection) -> Result<Option<i64>, sqlx::Error> {
+        let now = Utc::now().timestamp();
+        let claimed: Option<i64> = sqlx::query_scalar(
+            "INSERT INTO post_cooldowns (ip_hash, action, last_post_at) VALUES ($1, $2, $3)
+             ON CONFLICT (ip_hash, action) DO UPDATE SET last_post_at = EXCLUDED.last_post_at
+             WHERE post_cooldowns.last_post_at <= EXCLUDED.last_post_at - $4
+             RETURNING last_post_at",
+        )
+        .bind(&self.ip_hash)
+        .bind(self.action)
+        .bind(now)
+        .bind(self.secs)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if claimed.is_some() {
+            return Ok(None);
+        }
+
+        let last: i64 =
+            sqlx::query_scalar("SELECT last_post_at FROM post_cooldowns WHERE ip_hash = $1 AND action = $2")
+                .bind(&self.ip_hash)
+                .bind(self.action)
+                .fetch_one(&mut *conn)
+                .await?;
+        Ok(Some(self.left(last, now).max(1)))
+    }
+
+    fn left(&self, last_post_at: i64, now: i64) -> i64 {
+        (last_post_at + self.secs - now).max(0)
+    }
+}
+
+// Drops rows too old to hold anyone back
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM post_cooldowns WHERE last_post_at < $1")
+        .bind(Utc::now().timestamp() - MAX_SECS)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,106 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse};
+use rand::RngCore;
+
+use crate::{log_error, media_api, paths, tokens};
+
+// Double-submit CSRF protection. Every browser gets a signed random token
+// in a cookie; forms that POST carry the same token in a hidden field, and
+// handlers refuse the POST unless the two match. A third-party page can
+// make the browser send the cookie but can't read it to fill in the field.
+// The signature stops a sibling subdomain from planting a token of its own.
+const CSRF_COOKIE: &str = "csrf";
+const CSRF_PURPOSE: &str = "csrf";
+pub const FIELD_NAME: &str = "csrf_token";
+const SKIPPED_PREFIXES: &[&str] = &["/api/", "/static/", "/uploads/"];
+
+// A token minted for this request, before the browser has the cookie
+struct Issued(String);
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let nonce: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    tokens::sign(CSRF_PURPOSE, &nonce)
+}
+
+fn cookie_token(req: &HttpRequest) -> Option<String> {
+    let value = req.cookie(CSRF_COOKIE)?.value().to_string();
+    tokens::verify(CSRF_PURPOSE, &value).map(|_| value)
+}
+
+// Middleware giving browsers without a valid token cookie a new one. The
+// token is also stashed on the request so a form rendered in this same
+// response can use it.
+pub async fn issue_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let skip = SKIPPED_PREFIXES.iter().any(|p| req.path().starts_with(p));
+    if skip || cookie_token(req.request()).is_some() {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let token = new_token();
+    req.extensions_mut().insert(Issued(token.clone()));
+    let mut res = next.call(req).await?;
+    let cookie = Cookie::build(CSRF_COOKIE, token)
+        .path(paths::url("/"))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish();
+    if let Err(e) = res.response_mut().add_cookie(&cookie) {
+        log_error(&format!("Failed to set CSRF cookie: {}", e));
+    }
+    Ok(res.map_into_boxed_body())
+}
+
+// The token forms on this page must carry
+pub fn token(req: &HttpRequest) -> String {
+    if let Some(issued) = req.extensions().get::<Issued>() {
+        return issued.0.clone();
+    }
+    cookie_token(req).unwrap_or_default()
+}
+
+// Hidden input for every form that POSTs
+pub fn field(req: &HttpRequest) -> String {
+    format!(
+        r#"<input type="hidden" name="{}" value="{}">"#,
+        FIELD_NAME,
+        html_escape::encode_double_quoted_attribute(&token(req))
+    )
+}
+
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// A POST refused for a missing or mismatched token
+pub struct Rejected;
+
+impl From<Rejected> for HttpResponse {
+    fn from(_: Rejected) -> Self {
+        HttpResponse::Forbidden()
+            .body("This form has expired or came from another site. Please reload the page and try again.")
+    }
+}
+
+// Refuses a POST whose submitted token doesn't match the cookie. A request
+// carrying the API token is let through: a browser can't be made to send
+// that header to another site.
+pub fn check(req: &HttpRequest, submitted: &str) -> Result<(), Rejected> {
+    if media_api::has_valid_token(req) {
+        return Ok(());
+    }
+    match cookie_token(req) {
+        Some(expected) if same(expected.as_bytes(), submitted.as_bytes()) => Ok(()),
+        _ => {
+            log_error(&format!("CSRF check failed for {} {}", req.method(), req.path()));
+            Err(Rejected)
+        }
+    }
+}
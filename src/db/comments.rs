@@ -0,0 +1,316 @@
+use chrono::Utc;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::bumps::{self, Activity};
+use crate::cooldowns::Cooldown;
+use crate::events::{self, EventKind};
+use crate::visibility::{self, Viewer};
+use crate::references;
+
+// The one place an article's comments are read from. Every read takes a
+// cursor and a limit, so a thread of any size is walked in batches and
+// never loaded into a single Vec.
+
+// Upper bound on any one read
+pub const MAX_LIMIT: i64 = 1000;
+
+#[derive(FromRow)]
+pub struct CommentRow {
+    pub id: i32,
+    pub comment: String,
+    pub deleted: bool,
+    pub edited_at: Option<i64>,
+    pub is_admin: bool,
+    pub author_name: Option<String>,
+    pub created_at: Option<i64>,
+    pub shadow_hidden: bool,
+    // The top order's score; 0 in the other orders
+    pub score: i64,
+}
+
+// Display order for an article's comments, picked with ?comments=
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CommentOrder {
+    Oldest,
+    Newest,
+    // Highest score first, older first among equal scores
+    Top,
+}
+
+// Where a walk has got to: the last comment read, and its score for the top
+// order. Links and API pages write it as the id, or "{score}.{id}" for top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub score: i64,
+    pub id: i32,
+}
+
+impl CommentOrder {
+    pub const ALL: [CommentOrder; 3] = [CommentOrder::Oldest, CommentOrder::Newest, CommentOrder::Top];
+
+    pub fn from_query(value: Option<&str>) -> Self {
+        match value {
+            Some("newest") => CommentOrder::Newest,
+            Some("top") => CommentOrder::Top,
+            _ => CommentOrder::Oldest,
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            CommentOrder::Oldest => "oldest",
+            CommentOrder::Newest => "newest",
+            CommentOrder::Top => "top",
+        }
+    }
+
+    // Cursor that starts a walk from the first comment in this order
+    pub fn start(self) -> Cursor {
+        match self {
+            CommentOrder::Oldest => Cursor { score: 0, id: 0 },
+            CommentOrder::Newest => Cursor { score: 0, id: i32::MAX },
+            CommentOrder::Top => Cursor { score: i64::MAX, id: 0 },
+        }
+    }
+
+    // Cursor that continues a walk after `row`
+    pub fn after(self, row: &CommentRow) -> Cursor {
+        Cursor { score: row.score, id: row.id }
+    }
+
+    pub fn parse_cursor(self, value: &str) -> Option<Cursor> {
+        match self {
+            CommentOrder::Top => {
+                let (score, id) = value.split_once('.')?;
+                Some(Cursor { score: score.parse().ok()?, id: id.parse().ok()? })
+            }
+            _ => Some(Cursor { score: 0, id: value.parse().ok()? }),
+        }
+    }
+
+    pub fn cursor_param(self, cursor: Cursor) -> String {
+        match self {
+            CommentOrder::Top => format!("{}.{}", cursor.score, cursor.id),
+            _ => cursor.id.to_string(),
+        }
+    }
+
+    // The score column, the keyset condition on the cursor (score $3, id $2)
+    // and the sort, for a query selecting from `comments`
+    fn keyset(self) -> (String, &'static str, &'static str) {
+        match self {
+            CommentOrder::Oldest => ("0::BIGINT".to_string(), "id > $2", "id ASC"),
+            CommentOrder::Newest => ("0::BIGINT".to_string(), "id < $2", "id DESC"),
+            CommentOrder::Top => (
+                score("comments"),
+                "(score < $3 OR (score = $3 AND id > $2))",
+                "score DESC, id ASC",
+            ),
+        }
+    }
+}
+
+// A comment's score for the top order. Comments have no reactions, so the
+// only signal is negative: one point off per visitor with an open report
+// against it. Unreported comments tie at 0 and keep their age order.
+fn score(table: &str) -> String {
+    format!(
+        "-(SELECT COUNT(*) FROM reports r
+           WHERE r.target_type = 'comment' AND r.target_id = {table}.id AND r.resolved_at IS NULL)"
+    )
+}
+
+// Comments `viewer` sees (tombstones included) on an article after the
+// `after` cursor, at most `limit` of them. Pass the last row's id as the next
+// cursor; an empty result means the end of the thread. None as the viewer
+// is what everyone sees.
+pub async fn visible_page(
+    pool: &PgPool,
+    article_id: i32,
+    viewer: Option<&Viewer>,
+    order: CommentOrder,
+    after: Cursor,
+    limit: i64,
+) -> Result<Vec<CommentRow>, sqlx::Error> {
+    debug_assert!((1..=MAX_LIMIT).contains(&limit), "comment read limit {} out of range", limit);
+    let (score, keyset, sort) = order.keyset();
+    sqlx::query_as::<_, CommentRow>(&format!(
+        "SELECT * FROM (
+             SELECT id, comment, deleted, edited_at, is_admin, author_name, created_at, shadow_hidden, {} AS score
+             FROM comments WHERE article_id = $1 AND {}
+         ) comments
+         WHERE {} ORDER BY {} LIMIT $4",
+        score,
+        match viewer {
+            Some(viewer) => visibility::shown_comments_to("comments", viewer),
+            None => visibility::shown_comments("comments"),
+        },
+        keyset,
+        sort
+    ))
+    .bind(article_id)
+    .bind(after.id)
+    .bind(after.score)
+    .bind(limit.clamp(1, MAX_LIMIT))
+    .fetch_all(pool)
+    .await
+}
+
+// Whether `viewer` is shown shadow-hidden comments on an article that nobody
+// else sees
+pub async fn shadowed_for(pool: &PgPool, article_id: i32, viewer: &Viewer) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        "SELECT EXISTS(SELECT 1 FROM comments WHERE article_id = $1 AND {})",
+        visibility::shadowed_for("comments", viewer)
+    ))
+    .bind(article_id)
+    .fetch_one(pool)
+    .await
+}
+
+// Comments a reader can see, not counting tombstones, as kept on the article
+// row by `refresh_counts`. None when the article doesn't exist or isn't shown.
+pub async fn visible_count(pool: &PgPool, article_id: i32) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        "SELECT comment_count FROM articles WHERE id = $1 AND {}",
+        visibility::shown_articles("articles")
+    ))
+    .bind(article_id)
+    .fetch_optional(pool)
+    .await
+}
+
+// Recounts articles.comment_count for the given articles. Every write that
+// adds, removes, hides or shows a comment calls this inside its transaction,
+// so the stored count can't drift from what the thread shows.
+pub async fn refresh_counts(conn: &mut PgConnection, article_ids: &[i32]) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!(
+        "UPDATE articles a SET comment_count =
+            (SELECT COUNT(*) FROM comments c WHERE c.article_id = a.id AND {})
+         WHERE a.id = ANY($1)",
+        visibility::live_comments("c")
+    ))
+    .bind(article_ids)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub struct NewComment<'a> {
+    pub article_id: i32,
+    pub text: &'a str,
+    // Who posted it, for moderation and the activity log
+    pub actor: &'a str,
+    pub author_name: Option<&'a str>,
+    pub official: bool,
+    // Checked and started with the insert; None posts without one
+    pub cooldown: Option<&'a Cooldown>,
+    // The poster's address hash when shadow banned: the comment is stored
+    // shadow-hidden, shown only to them, and doesn't bump the article
+    pub shadow_viewer: Option<&'a str>,
+}
+
+pub enum Inserted {
+    Stored(i32),
+    // The poster's cooldown has this many seconds left; nothing was written
+    CoolingDown(i64),
+}
+
+// Stores a comment with its references and replies, bumps the article and
+// logs both events, all in one transaction, after claiming the cooldown. A
+// shadow-hidden one bumps nothing until released. Validation and the
+// closed-thread check are the caller's.
+pub async fn insert(pool: &PgPool, new: &NewComment<'_>) -> Result<Inserted, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let now = Utc::now().timestamp();
+
+    if let Some(cooldown) = new.cooldown {
+        if let Some(wait) = cooldown.claim(&mut tx).await? {
+            return Ok(Inserted::CoolingDown(wait));
+        }
+    }
+
+    let comment_id: i32 = sqlx::query_scalar(
+        "INSERT INTO comments (article_id, comment, created_at, ip_representation, is_admin, author_name, shadow_hidden, shadow_viewer)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+    )
+    .bind(new.article_id)
+    .bind(new.text)
+    .bind(now)
+    .bind(new.actor)
+    .bind(new.official)
+    .bind(new.author_name)
+    .bind(new.shadow_viewer.is_some())
+    .bind(new.shadow_viewer)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    references::record_comment_refs(&mut tx, new.article_id, comment_id, new.text).await?;
+    references::record_comment_replies(&mut tx, new.article_id, comment_id, new.text).await?;
+
+    let detail = format!("comment #{}", comment_id);
+    if new.shadow_viewer.is_some() {
+        let detail = format!("{} (shadow-hidden)", detail);
+        events::record(&mut tx, new.article_id, EventKind::Commented, new.actor, Some(&detail)).await?;
+    } else {
+        bumps::record(&mut tx, new.article_id, Activity::Commented, now).await?;
+        refresh_counts(&mut tx, &[new.article_id]).await?;
+        for kind in [EventKind::Commented, EventKind::Bumped] {
+            events::record(&mut tx, new.article_id, kind, new.actor, Some(&detail)).await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(Inserted::Stored(comment_id))
+}
+
+// Makes a shadow-hidden comment public. It counts and bumps the article as
+// if posted now. Returns the comment's article, or None when there is no such
+// shadow-hidden comment.
+pub async fn release(pool: &PgPool, comment_id: i32, actor: &str) -> Result<Option<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let released: Option<i32> = sqlx::query_scalar(
+        "UPDATE comments SET shadow_hidden = FALSE, shadow_viewer = NULL
+         WHERE id = $1 AND shadow_hidden RETURNING article_id",
+    )
+    .bind(comment_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let article_id = match released {
+        Some(article_id) => article_id,
+        None => return Ok(None),
+    };
+
+    bumps::record(&mut tx, article_id, Activity::Commented, Utc::now().timestamp()).await?;
+    refresh_counts(&mut tx, &[article_id]).await?;
+    let detail = format!("comment #{}", comment_id);
+    for kind in [EventKind::Released, EventKind::Bumped] {
+        events::record(&mut tx, article_id, kind, actor, Some(&detail)).await?;
+    }
+    tx.commit().await?;
+    Ok(Some(article_id))
+}
+
+// The bound is a debug assertion, so these only hold in debug builds
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+
+    // Never connected: the bound is checked before any query is sent
+    fn pool() -> PgPool {
+        PgPoolOptions::new().connect_lazy("postgres://articles@127.0.0.1:1/articles").unwrap()
+    }
+
+    #[actix_web::test]
+    #[should_panic(expected = "out of range")]
+    async fn reads_over_the_bound_are_caught() {
+        let _ = visible_page(&pool(), 1, None, CommentOrder::Oldest, CommentOrder::Oldest.start(), MAX_LIMIT + 1).await;
+    }
+
+    #[actix_web::test]
+    #[should_panic(expected = "out of range")]
+    async fn reads_without_a_bound_are_caught() {
+        let _ = visible_page(&pool(), 1, None, CommentOrder::Newest, CommentOrder::Newest.start(), 0).await;
+    }
+}
@@ -0,0 +1,2 @@
+// Queries shared by several handlers
+pub mod comments;
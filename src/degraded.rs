@@ -0,0 +1,314 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::caches::{Invalidate, Target};
+use crate::{log_error, paths};
+
+// Degraded mode for database outages. The article list and article pages
+// keep their last complete anonymous rendering in memory; while Postgres is
+// unreachable those copies are served with a banner, and anything that
+// writes gets a maintenance page. An outage is noticed from the error of a
+// page's first query, so normal requests pay nothing for this; during one,
+// requests check for recovery first, and the first that gets through ends it.
+const MAX_CACHED_ARTICLES: usize = 200;
+pub const MAX_CACHED_PAGE_BYTES: usize = 2 * 1024 * 1024;
+// How long a check during an outage waits for a connection
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+const RETRY_AFTER_SECS: u64 = 30;
+
+#[derive(Default)]
+pub struct StaleCache {
+    down: AtomicBool,
+    list: Mutex<Option<String>>,
+    // Most recently rendered last
+    articles: Mutex<Vec<(i32, String)>>,
+}
+
+impl StaleCache {
+    pub fn store_list(&self, page: String) {
+        if let Ok(mut list) = self.list.lock() {
+            *list = Some(page);
+        }
+    }
+
+    pub fn store_article(&self, article_id: i32, page: String) {
+        if let Ok(mut articles) = self.articles.lock() {
+            articles.retain(|(id, _)| *id != article_id);
+            if articles.len() >= MAX_CACHED_ARTICLES {
+                articles.remove(0);
+            }
+            articles.push((article_id, page));
+        }
+    }
+
+    // Drops an article's copy so a deleted or edited article isn't served
+    // from before the change
+    pub fn forget_article(&self, article_id: i32) {
+        if let Ok(mut articles) = self.articles.lock() {
+            articles.retain(|(id, _)| *id != article_id);
+        }
+    }
+
+    // For changes that also show in the list, such as a title, a comment
+    // count or the article disappearing
+    pub fn article_changed(&self, article_id: i32) {
+        self.forget_article(article_id);
+        self.forget_list();
+    }
+
+    // Drops the list's copy, as when an article is added
+    pub fn forget_list(&self) -> bool {
+        self.list.lock().ok().and_then(|mut list| list.take()).is_some()
+    }
+
+    fn list(&self) -> Option<String> {
+        self.list.lock().ok().and_then(|list| list.clone())
+    }
+
+    fn article(&self, article_id: i32) -> Option<String> {
+        let articles = self.articles.lock().ok()?;
+        articles.iter().find(|(id, _)| *id == article_id).map(|(_, page)| page.clone())
+    }
+
+    // Whether reads should try the database. Outside an outage this is true
+    // without touching the pool; during one it checks out a connection to
+    // see whether Postgres is back, and leaves degraded mode if so.
+    pub async fn database_available(&self, pool: &PgPool) -> bool {
+        if !self.down.load(Ordering::Relaxed) {
+            return true;
+        }
+        match tokio::time::timeout(CHECK_TIMEOUT, pool.acquire()).await {
+            Ok(Ok(_)) => {
+                self.succeeded();
+                true
+            }
+            Ok(Err(e)) if !is_outage(pool, &e) => {
+                self.succeeded();
+                true
+            }
+            // Still down
+            _ => false,
+        }
+    }
+
+    // Classifies the error of a query that just failed: true, entering
+    // degraded mode, when it means Postgres is out of reach rather than the
+    // query or a busy pool being the problem
+    pub fn failed(&self, pool: &PgPool, e: &sqlx::Error) -> bool {
+        if !is_outage(pool, e) {
+            return false;
+        }
+        if !self.down.swap(true, Ordering::Relaxed) {
+            log_error(&format!("Database unreachable, entering degraded mode: {}", e));
+        }
+        true
+    }
+
+    fn succeeded(&self) {
+        if self.down.swap(false, Ordering::Relaxed) {
+            log_error("Database reachable again, leaving degraded mode");
+        }
+    }
+
+    // Response for the article list during an outage
+    pub fn list_response(&self) -> HttpResponse {
+        match self.list() {
+            Some(page) => stale_response(&page),
+            None => maintenance_page(),
+        }
+    }
+
+    // Response for an article page during an outage
+    pub fn article_response(&self, article_id: i32) -> HttpResponse {
+        match self.article(article_id) {
+            Some(page) => stale_response(&page),
+            None => maintenance_page(),
+        }
+    }
+}
+
+impl Invalidate for StaleCache {
+    fn invalidate(&self, target: Target) -> Vec<String> {
+        let mut cleared = Vec::new();
+        if matches!(target, Target::All | Target::Listing) && self.forget_list() {
+            cleared.push("fallback article list".to_string());
+        }
+        match target {
+            Target::All => {
+                if let Ok(mut articles) = self.articles.lock() {
+                    cleared.push(format!("fallback copies of {} articles", articles.len()));
+                    articles.clear();
+                }
+            }
+            Target::Article(id) if self.article(id).is_some() => {
+                self.forget_article(id);
+                cleared.push(format!("fallback copy of article {}", id));
+            }
+            _ => {}
+        }
+        cleared
+    }
+}
+
+// A refused connection is retried until the pool's acquire timeout, so an
+// outage often shows up as PoolTimedOut. A busy pool times out the same way,
+// but has its connections checked out; only an empty one is an outage.
+fn is_outage(pool: &PgPool, e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::PoolTimedOut => pool.size() == 0,
+        _ => false,
+    }
+}
+
+// A cached page with the staleness banner at the top of the body
+fn stale_response(page: &str) -> HttpResponse {
+    let banner = r#"<div class="stale-banner">Showing cached content — live data temporarily unavailable</div>"#;
+    let html = match page.find("<body>") {
+        Some(at) => {
+            let (head, rest) = page.split_at(at + "<body>".len());
+            format!("{}{}{}", head, banner, rest)
+        }
+        None => format!("{}{}", banner, page),
+    };
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .append_header(("Cache-Control", "no-store"))
+        .body(html)
+}
+
+pub fn maintenance_page() -> HttpResponse {
+    let base = paths::base();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Temporarily Unavailable</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Temporarily Unavailable</h2>
+        <p>The site can't reach its database right now. Nothing you sent was saved; please try again in a minute.</p>
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#
+    );
+    HttpResponse::ServiceUnavailable()
+        .append_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+        .content_type("text/html")
+        .body(html)
+}
+
+// Middleware turning away writes while the database is unreachable, before
+// their handlers start reading request bodies
+pub async fn guard_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.method() == Method::GET || req.method() == Method::HEAD {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let cache = req.app_data::<web::Data<StaleCache>>().cloned();
+    let pool = req.app_data::<web::Data<PgPool>>().cloned();
+    if let (Some(cache), Some(pool)) = (cache, pool) {
+        if !cache.database_available(pool.get_ref()).await {
+            return Ok(req.into_response(maintenance_page()));
+        }
+    }
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use sqlx::postgres::PgPoolOptions;
+
+    // Nothing listens on port 1, so every connection is refused
+    fn unreachable_pool() -> PgPool {
+        PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(1))
+            .connect_lazy("postgres://articles@127.0.0.1:1/articles")
+            .unwrap()
+    }
+
+    async fn body(res: HttpResponse) -> String {
+        let bytes = actix_web::body::to_bytes(res.into_body()).await.ok().unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn refused_connections_serve_the_cached_copy() {
+        let cache = StaleCache::default();
+        let pool = unreachable_pool();
+        assert!(cache.database_available(&pool).await, "checked before any query failed");
+        let e = pool.acquire().await.unwrap_err();
+        assert!(cache.failed(&pool, &e));
+        assert!(cache.down.load(Ordering::Relaxed));
+        assert!(!cache.database_available(&pool).await);
+
+        // Nothing cached yet
+        assert_eq!(cache.list_response().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        cache.store_list("<html><body><h1>Articles</h1></body></html>".to_string());
+        cache.store_article(7, "<html><body><h1>Seven</h1></body></html>".to_string());
+        let res = cache.list_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            body(res).await,
+            r#"<html><body><div class="stale-banner">Showing cached content — live data temporarily unavailable</div><h1>Articles</h1></body></html>"#
+        );
+        assert!(body(cache.article_response(7)).await.contains("stale-banner"));
+        assert_eq!(cache.article_response(8).status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        cache.forget_article(7);
+        assert_eq!(cache.article_response(7).status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    // The next check that gets a connection ends degraded mode
+    #[actix_web::test]
+    async fn reachable_database_ends_degraded_mode() {
+        let (url, _container) = match crate::dev_db::test_server().await {
+            Some(server) => server,
+            None => return,
+        };
+        let cache = StaleCache::default();
+        let unreachable = unreachable_pool();
+        assert!(cache.failed(&unreachable, &unreachable.acquire().await.unwrap_err()));
+        let pool = PgPoolOptions::new().connect_lazy(&url).unwrap();
+        assert!(cache.database_available(&pool).await);
+        assert!(!cache.down.load(Ordering::Relaxed));
+    }
+
+    // Every connection checked out times out the same way as a refused one,
+    // but isn't an outage
+    #[actix_web::test]
+    async fn busy_pool_is_not_an_outage() {
+        let (url, _container) = match crate::dev_db::test_server().await {
+            Some(server) => server,
+            None => return,
+        };
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy(&url)
+            .unwrap();
+        let _busy = pool.acquire().await.unwrap();
+        let e = pool.acquire().await.unwrap_err();
+        assert!(matches!(e, sqlx::Error::PoolTimedOut), "{}", e);
+
+        let cache = StaleCache::default();
+        assert!(!cache.failed(&pool, &e));
+        assert!(!cache.down.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::Utc;
+use sqlx::{FromRow, PgPool};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::log_error;
+use crate::media::{disk_path, types};
+
+// WebP (and, built with the `avif` feature, AVIF) copies of the image types
+// the media registry marks for derivatives, for browsers that take them. A background job encodes them
+// with the cwebp / avifenc command-line tools; pages then wrap the image in
+// a <picture> so other browsers keep getting the original. A derivative sits
+// next to its original as "<file>.webp" / "<file>.avif", so identical
+// uploads share one, and it is removed along with the original.
+const BATCH_SIZE: i64 = 20;
+// Per-image limit; an encoder still running is killed
+const ENCODE_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Format {
+    extension: &'static str,
+    mime: &'static str,
+    column: &'static str,
+    // Encoder command line for (input, output)
+    command: fn(&Path, &Path) -> tokio::process::Command,
+}
+
+fn cwebp(input: &Path, output: &Path) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("cwebp");
+    command.arg("-quiet").arg("-q").arg("80").arg(input).arg("-o").arg(output);
+    command
+}
+
+#[cfg(feature = "avif")]
+fn avifenc(input: &Path, output: &Path) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("avifenc");
+    command.arg("--speed").arg("6").arg(input).arg(output);
+    command
+}
+
+fn formats() -> Vec<Format> {
+    let formats = vec![Format {
+        extension: "webp",
+        mime: "image/webp",
+        column: "webp_path",
+        command: cwebp,
+    }];
+    #[cfg(feature = "avif")]
+    let formats = {
+        let mut formats = formats;
+        // Listed first: <picture> takes the first source the browser supports
+        formats.insert(
+            0,
+            Format {
+                extension: "avif",
+                mime: "image/avif",
+                column: "avif_path",
+                command: avifenc,
+            },
+        );
+        formats
+    };
+    formats
+}
+
+// Every derivative a file could have, for removing them with it
+pub fn sibling_paths(media_path: &str) -> Vec<String> {
+    ["webp", "avif"].iter().map(|ext| format!("{}.{}", media_path, ext)).collect()
+}
+
+// Wraps an <img> in a <picture> offering whichever derivatives exist. With
+// none the <img> is returned as it was.
+pub fn picture_html(img: String, webp_path: Option<&str>, avif_path: Option<&str>) -> String {
+    let sources: String = [(avif_path, "image/avif"), (webp_path, "image/webp")]
+        .iter()
+        .filter_map(|(path, mime)| {
+            path.map(|path| {
+                format!(
+                    r#"<source srcset="{}" type="{}">"#,
+                    html_escape::encode_double_quoted_attribute(&crate::paths::url(path)),
+                    mime
+                )
+            })
+        })
+        .collect();
+    if sources.is_empty() {
+        img
+    } else {
+        format!("<picture>{}{}</picture>", sources, img)
+    }
+}
+
+#[derive(FromRow)]
+struct PendingImage {
+    id: i32,
+    media_path: String,
+    mime_type: Option<String>,
+    size_bytes: Option<i64>,
+}
+
+enum Encoded {
+    Made(String),
+    // Failed, timed out, or came out no smaller than the original
+    NotWorthIt,
+    // The encoder isn't installed; nothing more can be done this run
+    NoEncoder,
+}
+
+async fn encode(format: &Format, media_path: &str, original_size: u64) -> Encoded {
+    let derivative = format!("{}.{}", media_path, format.extension);
+    let (input, output) = match (disk_path(media_path), disk_path(&derivative)) {
+        (Some(input), Some(output)) => (input, output),
+        _ => return Encoded::NotWorthIt,
+    };
+    // Shared content: another row already made this one
+    if output.exists() {
+        return Encoded::Made(derivative);
+    }
+
+    // Written aside and renamed in, so a half-written file is never served
+    let partial = output.with_extension(format!("{}.partial", format.extension));
+    let mut command = (format.command)(&input, &partial);
+    command.stdout(Stdio::null()).stderr(Stdio::null()).kill_on_drop(true);
+    let status = match command.spawn() {
+        Ok(mut child) => tokio::time::timeout(ENCODE_TIMEOUT, child.wait()).await,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Encoded::NoEncoder,
+        Err(e) => {
+            log_error(&format!("Failed to start the {} encoder: {}", format.extension, e));
+            return Encoded::NotWorthIt;
+        }
+    };
+
+    let smaller = match status {
+        Ok(Ok(status)) if status.success() => tokio::fs::metadata(&partial)
+            .await
+            .is_ok_and(|m| m.len() < original_size),
+        Ok(_) => false,
+        Err(_) => {
+            log_error(&format!("Encoding {} as {} timed out", media_path, format.extension));
+            false
+        }
+    };
+    if smaller && tokio::fs::rename(&partial, &output).await.is_ok() {
+        return Encoded::Made(derivative);
+    }
+    let _ = tokio::fs::remove_file(&partial).await;
+    Encoded::NotWorthIt
+}
+
+// Whether every encoder this build uses can be run. Checked once at start so
+// a server without them logs that once instead of failing every pass.
+pub async fn encoders_installed() -> bool {
+    let commands = vec![("cwebp", "-version")];
+    #[cfg(feature = "avif")]
+    let commands = [commands, vec![("avifenc", "--version")]].concat();
+    for (program, arg) in commands {
+        let found = tokio::process::Command::new(program)
+            .arg(arg)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .is_ok();
+        if !found {
+            log_error(&format!("{} isn't installed; WebP/AVIF image derivatives are off", program));
+            return false;
+        }
+    }
+    true
+}
+
+// One pass of the background job: encodes a batch of images that haven't
+// been tried yet. Returns how many rows were done.
+pub async fn run(pool: &PgPool) -> Result<usize, String> {
+    // Types the registry marks as taking derivatives
+    let mimes: Vec<&str> = types::MEDIA_TYPES.iter().filter(|t| t.derivatives).map(|t| t.mime).collect();
+    let pending = sqlx::query_as::<_, PendingImage>(
+        "SELECT id, media_path, mime_type, size_bytes FROM article_media
+         WHERE derivatives_at IS NULL AND mime_type = ANY($1)
+         ORDER BY id LIMIT $2",
+    )
+    .bind(&mimes)
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch images for derivatives: {}", e))?;
+
+    let formats = formats();
+    for image in &pending {
+        let original_size = match image.size_bytes {
+            Some(size) => size as u64,
+            None => match disk_path(&image.media_path) {
+                Some(path) => tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0),
+                None => 0,
+            },
+        };
+
+        for format in &formats {
+            // A WebP original gets no WebP copy of itself
+            if image.mime_type.as_deref() == Some(format.mime) {
+                continue;
+            }
+            let derivative = match encode(format, &image.media_path, original_size).await {
+                Encoded::Made(path) => Some(path),
+                Encoded::NotWorthIt => None,
+                Encoded::NoEncoder => {
+                    return Err(format!(
+                        "The {} encoder isn't installed; image derivatives are paused",
+                        format.extension
+                    ))
+                }
+            };
+            sqlx::query(&format!("UPDATE article_media SET {} = $1 WHERE id = $2", format.column))
+                .bind(&derivative)
+                .bind(image.id)
+                .execute(pool)
+                .await
+                .map_err(|e| format!("Failed to record {} derivative: {}", format.mime, e))?;
+        }
+
+        sqlx::query("UPDATE article_media SET derivatives_at = $1 WHERE id = $2")
+            .bind(Utc::now().timestamp())
+            .bind(image.id)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to mark derivatives done: {}", e))?;
+    }
+
+    Ok(pending.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMG: &str = r#"<img src="/uploads/a.png" alt="">"#;
+
+    #[test]
+    fn derivatives_become_sources_ahead_of_the_original() {
+        assert_eq!(
+            picture_html(IMG.to_string(), Some("/uploads/a.png.webp"), Some("/uploads/a.png.avif")),
+            concat!(
+                r#"<picture><source srcset="/uploads/a.png.avif" type="image/avif">"#,
+                r#"<source srcset="/uploads/a.png.webp" type="image/webp">"#,
+                r#"<img src="/uploads/a.png" alt=""></picture>"#
+            )
+        );
+        assert_eq!(
+            picture_html(IMG.to_string(), Some("/uploads/a.png.webp"), None),
+            format!(r#"<picture><source srcset="/uploads/a.png.webp" type="image/webp">{}</picture>"#, IMG)
+        );
+    }
+
+    #[test]
+    fn without_derivatives_the_original_is_left_as_it_was() {
+        assert_eq!(picture_html(IMG.to_string(), None, None), IMG);
+    }
+
+    #[test]
+    fn source_paths_are_escaped() {
+        let html = picture_html(IMG.to_string(), Some(r#"/uploads/"a".webp"#), None);
+        assert!(html.contains(r#"srcset="/uploads/&quot;a&quot;.webp""#), "{}", html);
+    }
+}
@@ -0,0 +1,230 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDate, Utc};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+
+use crate::feed_cache::{self, FeedCache};
+use crate::settings::SettingsCache;
+use crate::{log_error, oembed, paths, visibility};
+
+// Daily summary of activity at /digest/{yyyy-mm-dd}, plus a feed with one
+// entry per day. Days run midnight to midnight UTC.
+const MOST_COMMENTED_LIMIT: i64 = 10;
+const FEED_DAYS: i64 = 14;
+
+// Epoch-second bounds [start, end) of a UTC day
+pub fn day_bounds(day: NaiveDate) -> (i64, i64) {
+    let start = day.and_hms_opt(0, 0, 0).map(|dt| dt.and_utc().timestamp()).unwrap_or_default();
+    (start, start + 86_400)
+}
+
+fn yesterday() -> NaiveDate {
+    Utc::now().date_naive() - Duration::days(1)
+}
+
+#[derive(FromRow)]
+struct NewArticle {
+    id: i32,
+    title: String,
+}
+
+#[derive(FromRow)]
+struct CommentedArticle {
+    id: i32,
+    title: String,
+    comments: i64,
+}
+
+// Articles and comments posted per day from `first` for `days` days, keyed
+// by the day's offset from `first`
+async fn daily_counts(pool: &PgPool, first: NaiveDate, days: i64) -> Result<HashMap<i64, (i64, i64)>, sqlx::Error> {
+    let (start, _) = day_bounds(first);
+    let end = start + days * 86_400;
+    let rows = sqlx::query_as::<_, (i64, i64, i64)>(&format!(
+        "SELECT day, SUM(articles)::BIGINT, SUM(comments)::BIGINT FROM (
+            SELECT (created_at - $1) / 86400 AS day, 1 AS articles, 0 AS comments
+            FROM articles WHERE created_at >= $1 AND created_at < $2 AND {}
+            UNION ALL
+            SELECT (created_at - $1) / 86400, 0, 1
+            FROM comments WHERE created_at >= $1 AND created_at < $2 AND {}
+         ) activity GROUP BY day",
+        visibility::shown_articles("articles"),
+        visibility::live_comments("comments")
+    ))
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|(day, articles, comments)| (day, (articles, comments))).collect())
+}
+
+pub async fn digest_yesterday(pool: web::Data<PgPool>) -> HttpResponse {
+    digest_page(pool.get_ref(), yesterday()).await
+}
+
+pub async fn digest_for_day(pool: web::Data<PgPool>, path: web::Path<String>) -> HttpResponse {
+    match NaiveDate::parse_from_str(&path.into_inner(), "%Y-%m-%d") {
+        Ok(day) => digest_page(pool.get_ref(), day).await,
+        Err(_) => HttpResponse::NotFound().body("Not a date"),
+    }
+}
+
+async fn digest_page(pool: &PgPool, day: NaiveDate) -> HttpResponse {
+    let base = paths::base();
+    if day > Utc::now().date_naive() {
+        return HttpResponse::NotFound().body("No digest for future dates");
+    }
+    let (start, end) = day_bounds(day);
+
+    let new_articles = sqlx::query_as::<_, NewArticle>(&format!(
+        "SELECT id, title FROM articles WHERE created_at >= $1 AND created_at < $2 AND {} ORDER BY id",
+        visibility::shown_articles("articles")
+    ))
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await;
+    let most_commented = sqlx::query_as::<_, CommentedArticle>(&format!(
+        "SELECT a.id, a.title, COUNT(*) AS comments
+         FROM comments c JOIN articles a ON a.id = c.article_id
+         WHERE c.created_at >= $1 AND c.created_at < $2 AND {} AND {}
+         GROUP BY a.id, a.title ORDER BY comments DESC, a.id LIMIT $3",
+        visibility::live_comments("c"),
+        visibility::shown_articles("a")
+    ))
+    .bind(start)
+    .bind(end)
+    .bind(MOST_COMMENTED_LIMIT)
+    .fetch_all(pool)
+    .await;
+    let counts = daily_counts(pool, day, 1).await;
+
+    let (new_articles, most_commented, counts) = match (new_articles, most_commented, counts) {
+        (Ok(a), Ok(c), Ok(n)) => (a, c, n),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            log_error(&format!("Failed to build digest for {}: {}", day, e));
+            return HttpResponse::InternalServerError().body("Failed to load digest");
+        }
+    };
+    let (article_total, comment_total) = counts.get(&0).copied().unwrap_or((0, 0));
+
+    let mut body_html = String::new();
+    if article_total == 0 && comment_total == 0 {
+        body_html.push_str("<p>Nothing was posted on this day.</p>");
+    } else {
+        body_html.push_str(&format!(
+            "<p>{} new articles, {} new comments.</p>",
+            article_total, comment_total
+        ));
+        body_html.push_str("<h3>New Articles</h3><ul>");
+        for article in &new_articles {
+            body_html.push_str(&format!(
+                r#"<li><a href="{base}/articles/{}" dir="auto">{}</a></li>"#,
+                article.id,
+                html_escape::encode_text(&article.title)
+            ));
+        }
+        if new_articles.is_empty() {
+            body_html.push_str("<li>None</li>");
+        }
+        body_html.push_str("</ul><h3>Most Commented</h3><ul>");
+        for article in &most_commented {
+            body_html.push_str(&format!(
+                r#"<li><a href="{base}/articles/{}" dir="auto">{}</a> ({})</li>"#,
+                article.id,
+                html_escape::encode_text(&article.title),
+                article.comments
+            ));
+        }
+        if most_commented.is_empty() {
+            body_html.push_str("<li>None</li>");
+        }
+        body_html.push_str("</ul>");
+    }
+
+    let previous = day - Duration::days(1);
+    let next = if day < Utc::now().date_naive() {
+        format!(r#" | <a href="{base}/digest/{}">Next day →</a>"#, day + Duration::days(1))
+    } else {
+        String::new()
+    };
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Digest for {day}</title>
+        <link rel="alternate" type="application/rss+xml" title="Daily digest" href="{base}/digest/feed.xml">
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>
+        <div class="article">
+        <h1>Digest for {day} (UTC)</h1>
+        {}
+        <div class="center-link"><a href="{base}/digest/{}">← Previous day</a>{}</div>
+        </div>
+        </body>
+        </html>
+        "#,
+        body_html, previous, next
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+fn xml_escape(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
+
+// RSS feed with one item per completed day, newest first
+pub async fn digest_feed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    feeds: web::Data<FeedCache>,
+) -> HttpResponse {
+    let site = oembed::site_url(&req, &settings_cache.get(pool.get_ref()).await);
+    feed_cache::serve(feeds, format!("digest|{}", site), move || build_feed(req, pool, settings_cache)).await
+}
+
+async fn build_feed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+) -> Result<String, String> {
+    let settings = settings_cache.get(pool.get_ref()).await;
+    let site = oembed::site_url(&req, &settings);
+    let last = yesterday();
+    let first = last - Duration::days(FEED_DAYS - 1);
+
+    let counts = daily_counts(pool.get_ref(), first, FEED_DAYS)
+        .await
+        .map_err(|e| format!("Failed to build digest feed: {}", e))?;
+
+    let mut items = String::new();
+    for offset in (0..FEED_DAYS).rev() {
+        let day = first + Duration::days(offset);
+        let (articles, comments) = counts.get(&offset).copied().unwrap_or((0, 0));
+        let link = format!("{}/digest/{}", site, day);
+        let pub_date = day_bounds(day).1;
+        let pub_date = chrono::DateTime::from_timestamp(pub_date, 0)
+            .map(|dt| dt.to_rfc2822())
+            .unwrap_or_default();
+        items.push_str(&format!(
+            "<item><title>Digest for {}</title><link>{}</link><guid>{}</guid><pubDate>{}</pubDate><description>{}</description></item>",
+            day,
+            xml_escape(&link),
+            xml_escape(&link),
+            pub_date,
+            xml_escape(&format!("{} new articles, {} new comments", articles, comments))
+        ));
+    }
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"><channel><title>{} daily digest</title><link>{}</link><description>Activity per day</description>{}</channel></rss>"#,
+        xml_escape(&settings.provider_name),
+        xml_escape(&format!("{}/digest", site)),
+        items
+    );
+    Ok(xml)
+}
@@ -0,0 +1,221 @@
+use actix_web::web;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::log_error;
+use crate::read_only::{ReadOnly, Trigger};
+
+// Watches free space on the uploads volume, and on the database's data
+// directory when Postgres runs on this host (DISK_WATCH_DB_PATH). Below
+// DISK_LOW_MB on any of them the site goes read-only on its own; once all
+// are back above DISK_RECOVER_MB it becomes writable again. The gap keeps it
+// from flapping around one threshold.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_LOW_MB: u64 = 500;
+const DEFAULT_RECOVER_MB: u64 = 1000;
+// Actor on the admin_actions entries the watchdog writes
+const ACTOR: &str = "disk-watchdog";
+
+struct Config {
+    volumes: Vec<(&'static str, PathBuf)>,
+    low_bytes: u64,
+    recover_bytes: u64,
+}
+
+fn mb_from_env(var: &str, default: u64) -> u64 {
+    env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+// None when DISK_LOW_MB is 0, which turns the watchdog off
+fn config_from_env() -> Option<Config> {
+    let low = mb_from_env("DISK_LOW_MB", DEFAULT_LOW_MB);
+    if low == 0 {
+        return None;
+    }
+    let recover = mb_from_env("DISK_RECOVER_MB", DEFAULT_RECOVER_MB).max(low);
+
+    let mut volumes = vec![("uploads", PathBuf::from("./uploads"))];
+    if let Some(db_path) = env::var("DISK_WATCH_DB_PATH").ok().filter(|p| !p.trim().is_empty()) {
+        volumes.push(("database", PathBuf::from(db_path.trim())));
+    }
+    Some(Config {
+        volumes,
+        low_bytes: low * 1024 * 1024,
+        recover_bytes: recover * 1024 * 1024,
+    })
+}
+
+// Free bytes on the filesystem holding `path`, as reported by POSIX `df`
+async fn free_bytes(path: &PathBuf) -> Result<u64, String> {
+    let output = tokio::process::Command::new("df")
+        .arg("-Pk")
+        .arg(path)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run df: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("df failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    // Second line, fourth column: available 1024-byte blocks
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|blocks| blocks.parse::<u64>().ok())
+        .map(|blocks| blocks * 1024)
+        .ok_or_else(|| "Unexpected df output".to_string())
+}
+
+enum Transition {
+    Enter(String),
+    Leave,
+    Stay,
+}
+
+// The decision on its own, from free space per volume and whether the
+// watchdog already holds the site read-only
+fn decide(free: &[(&str, u64)], holding: bool, low_bytes: u64, recover_bytes: u64) -> Transition {
+    if !holding {
+        let low: Vec<String> = free
+            .iter()
+            .filter(|(_, bytes)| *bytes < low_bytes)
+            .map(|(name, bytes)| format!("{} volume has {} MB free", name, bytes / (1024 * 1024)))
+            .collect();
+        if low.is_empty() {
+            Transition::Stay
+        } else {
+            Transition::Enter(low.join(", "))
+        }
+    } else if free.iter().all(|(_, bytes)| *bytes >= recover_bytes) {
+        Transition::Leave
+    } else {
+        Transition::Stay
+    }
+}
+
+async fn record(pool: &PgPool, action: &str, detail: &str) {
+    if let Err(e) = sqlx::query("INSERT INTO admin_actions (actor, action, detail, created_at) VALUES ($1, $2, $3, $4)")
+        .bind(ACTOR)
+        .bind(action)
+        .bind(detail)
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await
+    {
+        log_error(&format!("Failed to record disk watchdog action: {}", e));
+    }
+}
+
+// Applies one round of readings to `read_only`, returning the admin action
+// to record when the state changed
+fn apply(read_only: &ReadOnly, free: &[(&str, u64)], config: &Config) -> Option<(&'static str, String)> {
+    let holding = read_only.automatic().is_some();
+    match decide(free, holding, config.low_bytes, config.recover_bytes) {
+        Transition::Enter(reason) => {
+            log_error(&format!("Disk space low, switching to read-only mode: {}", reason));
+            read_only.set_automatic(Some(Trigger {
+                reason: reason.clone(),
+                since: Utc::now().timestamp(),
+            }));
+            Some(("read_only_on", reason))
+        }
+        Transition::Leave => {
+            log_error("Disk space recovered, leaving read-only mode");
+            read_only.set_automatic(None);
+            Some(("read_only_off", "disk space recovered".to_string()))
+        }
+        Transition::Stay => None,
+    }
+}
+
+async fn check(pool: &PgPool, read_only: &ReadOnly, config: &Config) {
+    let mut free = Vec::with_capacity(config.volumes.len());
+    for (name, path) in &config.volumes {
+        match free_bytes(path).await {
+            Ok(bytes) => free.push((*name, bytes)),
+            // Without a reading the current state stands
+            Err(e) => {
+                log_error(&format!("Disk watchdog could not check the {} volume: {}", name, e));
+                return;
+            }
+        }
+    }
+
+    if let Some((action, detail)) = apply(read_only, &free, config) {
+        record(pool, action, &detail).await;
+    }
+}
+
+pub fn start(pool: PgPool, read_only: web::Data<ReadOnly>) {
+    let config = match config_from_env() {
+        Some(config) => config,
+        None => return,
+    };
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            check(&pool, &read_only, &config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn config() -> Config {
+        Config {
+            volumes: vec![("uploads", PathBuf::from("./uploads")), ("database", PathBuf::from("/var/lib/postgresql"))],
+            low_bytes: 500 * MB,
+            recover_bytes: 1000 * MB,
+        }
+    }
+
+    #[test]
+    fn goes_read_only_when_low_and_back_once_recovered() {
+        let config = config();
+        let read_only = ReadOnly::default();
+        // Readings as df would give them, one round per minute
+        let rounds: [[(&str, u64); 2]; 5] = [
+            [("uploads", 2000 * MB), ("database", 2000 * MB)],
+            [("uploads", 2000 * MB), ("database", 400 * MB)],
+            [("uploads", 2000 * MB), ("database", 700 * MB)],
+            [("uploads", 900 * MB), ("database", 1200 * MB)],
+            [("uploads", 1000 * MB), ("database", 1200 * MB)],
+        ];
+        let actions: Vec<_> = rounds
+            .iter()
+            .map(|free| apply(&read_only, free, &config).map(|(action, _)| action))
+            .collect();
+        assert_eq!(actions, [None, Some("read_only_on"), None, None, Some("read_only_off")]);
+        assert!(read_only.automatic().is_none());
+    }
+
+    #[test]
+    fn trigger_names_the_low_volume() {
+        let read_only = ReadOnly::default();
+        let (_, reason) = apply(&read_only, &[("uploads", 300 * MB), ("database", 2000 * MB)], &config()).unwrap();
+        assert_eq!(reason, "uploads volume has 300 MB free");
+        assert_eq!(read_only.automatic().unwrap().reason, reason);
+    }
+
+    #[test]
+    fn recovering_leaves_an_admin_read_only_period_alone() {
+        let config = config();
+        let read_only = ReadOnly::default();
+        apply(&read_only, &[("uploads", 100 * MB)], &config);
+        apply(&read_only, &[("uploads", 5000 * MB)], &config);
+        assert!(read_only.automatic().is_none());
+        let status = read_only.status_html(true);
+        assert!(status.contains("switched on by an admin") && !status.contains("disk watchdog"), "{}", status);
+    }
+}
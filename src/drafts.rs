@@ -0,0 +1,116 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::HttpRequest;
+use chrono::Utc;
+use rand::Rng;
+use sqlx::PgPool;
+
+use crate::{client, paths};
+
+// Drafts of rejected comments, kept so the text survives navigating away
+// from the error page. The cookie is scoped to the article's path.
+const DRAFT_COOKIE: &str = "comment_draft";
+const DRAFT_TTL_SECS: i64 = 60 * 60;
+// Oldest drafts from the same client are dropped beyond this
+const MAX_DRAFTS_PER_IP: i64 = 5;
+
+fn cookie_path(article_id: i32) -> String {
+    paths::url(&format!("/articles/{}", article_id))
+}
+
+// Stores the text and returns the cookie pointing at it
+pub async fn stash(
+    pool: &PgPool,
+    req: &HttpRequest,
+    article_id: i32,
+    comment: &str,
+) -> Result<Cookie<'static>, sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let client_ip = client::ip_representation(req);
+    let token: String = (0..16)
+        .map(|_| format!("{:02x}", rand::thread_rng().gen::<u8>()))
+        .collect();
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM comment_drafts WHERE expires_at <= $1")
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    // Replace any earlier draft for this article rather than piling them up
+    if let Some(old) = req.cookie(DRAFT_COOKIE) {
+        sqlx::query("DELETE FROM comment_drafts WHERE token = $1 AND article_id = $2")
+            .bind(old.value())
+            .bind(article_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO comment_drafts (token, article_id, client_ip, comment, created_at, expires_at)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(&token)
+    .bind(article_id)
+    .bind(&client_ip)
+    .bind(comment)
+    .bind(now)
+    .bind(now + DRAFT_TTL_SECS)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM comment_drafts WHERE client_ip = $1 AND token NOT IN (
+            SELECT token FROM comment_drafts WHERE client_ip = $1 ORDER BY created_at DESC LIMIT $2
+         )",
+    )
+    .bind(&client_ip)
+    .bind(MAX_DRAFTS_PER_IP)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Cookie::build(DRAFT_COOKIE, token)
+        .path(cookie_path(article_id))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(CookieDuration::seconds(DRAFT_TTL_SECS))
+        .finish())
+}
+
+// The stashed text for this article, if the request carries a live draft cookie
+pub async fn load(pool: &PgPool, req: &HttpRequest, article_id: i32) -> Result<Option<String>, sqlx::Error> {
+    let token = match req.cookie(DRAFT_COOKIE) {
+        Some(c) => c.value().to_string(),
+        None => return Ok(None),
+    };
+
+    sqlx::query_scalar(
+        "SELECT comment FROM comment_drafts WHERE token = $1 AND article_id = $2 AND expires_at > $3",
+    )
+    .bind(token)
+    .bind(article_id)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await
+}
+
+// Drops the draft after a successful submission. Returns the removal cookie
+// when there was one to clear.
+pub async fn clear(pool: &PgPool, req: &HttpRequest, article_id: i32) -> Result<Option<Cookie<'static>>, sqlx::Error> {
+    let token = match req.cookie(DRAFT_COOKIE) {
+        Some(c) => c.value().to_string(),
+        None => return Ok(None),
+    };
+
+    sqlx::query("DELETE FROM comment_drafts WHERE token = $1 AND article_id = $2")
+        .bind(token)
+        .bind(article_id)
+        .execute(pool)
+        .await?;
+
+    let mut cookie = Cookie::build(DRAFT_COOKIE, "").path(cookie_path(article_id)).finish();
+    cookie.make_removal();
+    Ok(Some(cookie))
+}
@@ -0,0 +1,283 @@
+use actix_web::HttpRequest;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+
+use crate::admin::{self, Staff};
+use crate::body_format::BodyFormat;
+use crate::media::types;
+use crate::scan::ScanRecord;
+use crate::{csrf, paths, tokens, unlinks, FormMedia};
+
+// The "Preview changes" step of the article edit form: a unified diff of the
+// title and body between the stored article and what was submitted. The
+// confirm form re-posts the submitted fields with a fresh edit token, and a
+// replacement file already stored on disk travels as a signed token so it
+// isn't uploaded twice. The file is noted in staged_uploads meanwhile, so it
+// is removed if the edit is abandoned or the token runs out.
+const STAGED_PURPOSE: &str = "staged-media";
+const STAGED_TTL_SECS: i64 = 60 * 60;
+// Unchanged lines kept around each change; longer unchanged runs collapse
+const CONTEXT_LINES: usize = 3;
+// A diff longer than this is cut short rather than sent whole
+const MAX_DIFF_LINES: usize = 2000;
+
+#[derive(Serialize, Deserialize)]
+struct StagedMedia {
+    article_id: i32,
+    path: String,
+    original_filename: String,
+    mime: Option<String>,
+    hash: String,
+    scan_status: String,
+    scan_duration_ms: Option<i64>,
+}
+
+// A token standing in for an uploaded replacement between preview and save
+pub fn stage(article_id: i32, media: &FormMedia) -> String {
+    let staged = StagedMedia {
+        article_id,
+        path: media.path.clone(),
+        original_filename: media.original_filename.clone(),
+        mime: media.mime.map(str::to_string),
+        hash: media.hash.clone(),
+        scan_status: media.scan.status.to_string(),
+        scan_duration_ms: media.scan.duration_ms,
+    };
+    tokens::sign_expiring(STAGED_PURPOSE, &serde_json::to_string(&staged).unwrap_or_default(), STAGED_TTL_SECS)
+}
+
+// The replacement a stage token stands for, if it's valid and for this article
+pub fn unstage(article_id: i32, token: &str) -> Option<FormMedia> {
+    let staged: StagedMedia = serde_json::from_str(&tokens::verify_expiring(STAGED_PURPOSE, token)?).ok()?;
+    if staged.article_id != article_id {
+        return None;
+    }
+    Some(FormMedia {
+        path: staged.path,
+        original_filename: staged.original_filename,
+        mime: staged.mime.as_deref().and_then(types::by_mime).map(|t| t.mime),
+        hash: staged.hash,
+        scan: ScanRecord::stored(&staged.scan_status, staged.scan_duration_ms),
+    })
+}
+
+// Notes a replacement shown in a preview, keeping its file until the edit is
+// saved, discarded or expires
+pub async fn record(pool: &PgPool, article_id: i32, media_path: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO staged_uploads (article_id, media_path, created_at) VALUES ($1, $2, $3)
+         ON CONFLICT (article_id, media_path) DO UPDATE SET created_at = EXCLUDED.created_at",
+    )
+    .bind(article_id)
+    .bind(media_path)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Drops the note for a replacement, in the transaction that saves it
+pub async fn forget(conn: &mut PgConnection, article_id: i32, media_path: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM staged_uploads WHERE article_id = $1 AND media_path = $2")
+        .bind(article_id)
+        .bind(media_path)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// Queues a replacement the edit won't use for removal, staged or not
+pub async fn discard(pool: &PgPool, article_id: i32, media_path: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    forget(&mut tx, article_id, media_path).await?;
+    unlinks::enqueue(&mut tx, &[media_path.to_string()]).await?;
+    tx.commit().await
+}
+
+// Drops replacements staged longer ago than a stage token lasts and queues
+// their files, which the unlink queue keeps if an article ended up using them
+pub async fn expire(pool: &PgPool) -> Result<u64, String> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let paths: Vec<String> =
+        sqlx::query_scalar("DELETE FROM staged_uploads WHERE created_at <= $1 RETURNING media_path")
+            .bind(Utc::now().timestamp() - STAGED_TTL_SECS)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to expire staged uploads: {}", e))?;
+    unlinks::enqueue(&mut tx, &paths)
+        .await
+        .map_err(|e| format!("Failed to queue expired staged uploads: {}", e))?;
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit staged upload expiry: {}", e))?;
+    Ok(paths.len() as u64)
+}
+
+// Unified diff of two texts as escaped HTML, one hunk per run of changes
+fn diff_html(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = String::new();
+    let mut shown = 0;
+    'hunks: for group in diff.grouped_ops(CONTEXT_LINES) {
+        let (first, last) = match (group.first(), group.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        lines.push_str(&format!(
+            r#"<div class="diff-hunk">@@ -{},{} +{},{} @@</div>"#,
+            old_range.start + 1,
+            old_range.len(),
+            new_range.start + 1,
+            new_range.len()
+        ));
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                if shown == MAX_DIFF_LINES {
+                    lines.push_str(r#"<div class="diff-hunk">… the rest of the diff is too long to show</div>"#);
+                    break 'hunks;
+                }
+                let (class, sign) = match change.tag() {
+                    ChangeTag::Delete => ("diff-del", '-'),
+                    ChangeTag::Insert => ("diff-ins", '+'),
+                    ChangeTag::Equal => ("diff-eq", ' '),
+                };
+                lines.push_str(&format!(
+                    r#"<div class="{}">{}{}</div>"#,
+                    class,
+                    sign,
+                    html_escape::encode_text(change.value().trim_end_matches(['\r', '\n']))
+                ));
+                shown += 1;
+            }
+        }
+    }
+    if lines.is_empty() {
+        "<p>No changes.</p>".to_string()
+    } else {
+        format!(r#"<pre class="diff">{}</pre>"#, lines)
+    }
+}
+
+// The stored article and the submitted edit, as the preview compares them
+pub struct Preview<'a> {
+    pub article_id: i32,
+    pub old_title: &'a str,
+    pub old_body: &'a str,
+    pub old_format: BodyFormat,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub format: Option<BodyFormat>,
+    pub media_text: &'a HashMap<i32, (String, String)>,
+    pub new_media: Option<&'a FormMedia>,
+}
+
+pub fn page(req: &HttpRequest, staff: &Staff, preview: &Preview) -> String {
+    let base = paths::base();
+    let hidden = |name: &str, value: &str| {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            name,
+            html_escape::encode_double_quoted_attribute(value)
+        )
+    };
+
+    let mut fields = String::new();
+    fields.push_str(&hidden("edit_token", &admin::edit_token(staff, preview.article_id)));
+    fields.push_str(&hidden("mode", "save"));
+    fields.push_str(&hidden("title", preview.title));
+    fields.push_str(&hidden("body", preview.body));
+    if let Some(format) = preview.format {
+        fields.push_str(&hidden("body_format", format.name()));
+    }
+    for (media_id, (alt, caption)) in preview.media_text {
+        fields.push_str(&hidden(&format!("alt_{}", media_id), alt));
+        fields.push_str(&hidden(&format!("caption_{}", media_id), caption));
+    }
+    if let Some(media) = preview.new_media {
+        fields.push_str(&hidden("staged_media", &stage(preview.article_id, media)));
+    }
+
+    let format_note = match preview.format {
+        Some(format) if format != preview.old_format => {
+            format!("<p>Format: {} → {}</p>", preview.old_format.name(), format.name())
+        }
+        _ => String::new(),
+    };
+    let media_note = match preview.new_media {
+        Some(media) => format!(
+            "<p>Media: all current media will be replaced by <strong>{}</strong>.</p>",
+            html_escape::encode_text(&media.original_filename)
+        ),
+        None => "<p>Media: unchanged.</p>".to_string(),
+    };
+    let title_diff = if preview.title == preview.old_title {
+        "<p>Unchanged.</p>".to_string()
+    } else {
+        diff_html(preview.old_title, preview.title)
+    };
+
+    format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Preview Changes</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Preview Changes</h2>
+        <h3>Title</h3>
+        {}
+        <h3>Body</h3>
+        {}
+        {}
+        {}
+        <form action="{base}/articles/{}/edit" method="POST" enctype="multipart/form-data">
+            {}
+            {}
+            <input type="submit" value="Confirm Save">
+            <input type="submit" name="back" value="Back">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        title_diff,
+        diff_html(preview.old_body, preview.body),
+        format_note,
+        media_note,
+        preview.article_id,
+        csrf::field(req),
+        fields
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_sides_are_escaped() {
+        let html = diff_html("<b>old</b>\n", "<script>new</script>\n");
+        assert!(html.contains(r#"<div class="diff-del">-&lt;b&gt;old&lt;/b&gt;</div>"#), "{}", html);
+        assert!(html.contains(r#"<div class="diff-ins">+&lt;script&gt;new&lt;/script&gt;</div>"#), "{}", html);
+    }
+
+    #[test]
+    fn unchanged_runs_collapse_to_the_context() {
+        let old: String = (0..500).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 250\n", "line 250 edited\n");
+        let html = diff_html(&old, &new);
+        assert_eq!(html.matches(r#"class="diff-eq""#).count(), 2 * CONTEXT_LINES, "{}", html);
+        assert!(html.contains("@@ -248,7 +248,7 @@"), "{}", html);
+        assert!(!html.contains("> line 10<"), "{}", html);
+        assert_eq!(diff_html(&old, &old), "<p>No changes.</p>");
+    }
+}
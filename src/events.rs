@@ -0,0 +1,191 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{TimeZone, Utc};
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::admin::AdminAuth;
+use crate::{admin, csrf, log_error, paths, PasswordForm};
+
+// Oldest events beyond this are pruned whenever a new one is recorded
+const MAX_EVENTS_PER_ARTICLE: i64 = 500;
+
+// Actors recorded for actions authorized with the admin password and the
+// moderation password; moderator accounts are recorded by username
+pub const ADMIN_ACTOR: &str = "admin";
+pub const MOD_PASSWORD_ACTOR: &str = "mod-password";
+
+#[derive(Clone, Copy)]
+pub enum EventKind {
+    Created,
+    Commented,
+    Bumped,
+    Edited,
+    MediaReplaced,
+    CommentDeleted,
+    CommentHidden,
+    CommentShown,
+    Locked,
+    Unlocked,
+    Released,
+}
+
+impl EventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Commented => "commented",
+            EventKind::Bumped => "bumped",
+            EventKind::Edited => "edited",
+            EventKind::MediaReplaced => "media_replaced",
+            EventKind::CommentDeleted => "comment_deleted",
+            EventKind::CommentHidden => "comment_hidden",
+            EventKind::CommentShown => "comment_shown",
+            EventKind::Locked => "locked",
+            EventKind::Unlocked => "unlocked",
+            EventKind::Released => "released",
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct ArticleEvent {
+    kind: String,
+    actor: String,
+    detail: Option<String>,
+    created_at: i64,
+}
+
+// Records an event. Pass the transaction making the change itself so the
+// timeline is only written if the change commits.
+pub async fn record(
+    conn: &mut PgConnection,
+    article_id: i32,
+    kind: EventKind,
+    actor: &str,
+    detail: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO article_events (article_id, kind, actor, detail, created_at)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(article_id)
+    .bind(kind.as_str())
+    .bind(actor)
+    .bind(detail)
+    .bind(Utc::now().timestamp())
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM article_events WHERE article_id = $1 AND id NOT IN (
+            SELECT id FROM article_events WHERE article_id = $1 ORDER BY id DESC LIMIT $2
+         )",
+    )
+    .bind(article_id)
+    .bind(MAX_EVENTS_PER_ARTICLE)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn activity_form(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Article Activity</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Enter Password to View Activity</h2>
+        <form action="{base}/articles/{}/activity" method="POST">
+            {}
+            {}
+            <input type="submit" value="View Activity">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        article_id,
+        csrf::field(&req),
+        admin::password_field(&req)
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn view_activity(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    path: web::Path<i32>,
+    form: web::Form<PasswordForm>,
+) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    // Any staff member may read the timeline
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), &form.password, None, "article activity").await {
+        return res;
+    }
+
+    let events = match sqlx::query_as::<_, ArticleEvent>(
+        "SELECT kind, actor, detail, created_at FROM article_events WHERE article_id = $1 ORDER BY id",
+    )
+    .bind(article_id)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(e) => e,
+        Err(e) => {
+            log_error(&format!("Failed to fetch article events: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load activity");
+        }
+    };
+
+    let mut rows_html = String::new();
+    for event in &events {
+        let when = Utc
+            .timestamp_opt(event.created_at, 0)
+            .single()
+            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default();
+        rows_html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            when,
+            html_escape::encode_text(&event.kind),
+            html_escape::encode_text(&event.actor),
+            html_escape::encode_text(event.detail.as_deref().unwrap_or(""))
+        ));
+    }
+
+    if events.is_empty() {
+        rows_html.push_str(r#"<tr><td colspan="4">No recorded activity</td></tr>"#);
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Article Activity</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles/{}">← Back to Article</a></div>
+        <h2>Activity for Article {}</h2>
+        <table class="activity">
+            <tr><th>Time</th><th>Event</th><th>Actor</th><th>Detail</th></tr>
+            {}
+        </table>
+        </body>
+        </html>
+        "#,
+        article_id, article_id, rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
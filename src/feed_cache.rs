@@ -0,0 +1,239 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use crate::caches::{Invalidate, Target};
+use crate::log_error;
+
+// Generated feeds kept in memory with stale-while-revalidate semantics. A
+// cached copy is always served at once; when it's older than FRESH_SECS, or
+// articles changed since it was made, a background task regenerates it for
+// the next request, so only the first request for a feed waits on the
+// database. A failed regeneration keeps the old copy in service and is
+// logged once until one succeeds.
+const FRESH_SECS: i64 = 300;
+const STALE_SECS: i64 = 3600;
+// Keys include the site URL, which may come from the Host header
+const MAX_ENTRIES: usize = 32;
+
+struct Entry {
+    body: String,
+    generated_at: i64,
+    stale: bool,
+    refreshing: bool,
+    failing: bool,
+}
+
+#[derive(Default)]
+pub struct FeedCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl FeedCache {
+    // Marks every feed for regeneration; the old copies are served meanwhile
+    pub fn articles_changed(&self) -> usize {
+        match self.entries.lock() {
+            Ok(mut entries) => {
+                entries.values_mut().for_each(|entry| entry.stale = true);
+                entries.len()
+            }
+            Err(_) => 0,
+        }
+    }
+
+    fn clear(&self) -> usize {
+        self.entries.lock().map(|mut entries| entries.drain().count()).unwrap_or(0)
+    }
+
+    fn store(&self, key: &str, body: String) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= MAX_ENTRIES && !entries.contains_key(key) {
+                return;
+            }
+            let entry = Entry {
+                body,
+                generated_at: Utc::now().timestamp(),
+                stale: false,
+                refreshing: false,
+                failing: false,
+            };
+            entries.insert(key.to_string(), entry);
+        }
+    }
+
+    fn refresh_failed(&self, key: &str, error: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.refreshing = false;
+                if !entry.failing {
+                    entry.failing = true;
+                    log_error(&format!("{}; serving the cached copy until it works", error));
+                }
+            }
+        }
+    }
+
+    // The cached body and its age in seconds, and whether this caller should
+    // regenerate it (only one caller at a time is told to)
+    fn lookup(&self, key: &str) -> Option<(String, i64, bool)> {
+        let mut entries = self.entries.lock().ok()?;
+        let entry = entries.get_mut(key)?;
+        let age = (Utc::now().timestamp() - entry.generated_at).max(0);
+        let due = (entry.stale || age >= FRESH_SECS) && !entry.refreshing;
+        if due {
+            entry.refreshing = true;
+        }
+        Some((entry.body.clone(), age, due))
+    }
+}
+
+impl Invalidate for FeedCache {
+    fn invalidate(&self, target: Target) -> Vec<String> {
+        let count = match target {
+            Target::All | Target::Feeds => self.clear(),
+            Target::Listing | Target::Article(_) => self.articles_changed(),
+            _ => 0,
+        };
+        if count == 0 {
+            return Vec::new();
+        }
+        vec![format!("{} cached feeds", count)]
+    }
+}
+
+fn feed_response(body: String, age: i64) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/rss+xml")
+        .append_header(("Age", age.to_string()))
+        .append_header((
+            "Cache-Control",
+            format!("public, max-age={}, stale-while-revalidate={}", FRESH_SECS, STALE_SECS),
+        ))
+        .body(body)
+}
+
+// Serves the feed cached under `key`. `build` makes the document, or the
+// message to log when it can't; it runs inline only when there's no copy yet.
+pub async fn serve<F, Fut>(cache: web::Data<FeedCache>, key: String, build: F) -> HttpResponse
+where
+    F: FnOnce() -> Fut + 'static,
+    Fut: Future<Output = Result<String, String>> + 'static,
+{
+    match cache.lookup(&key) {
+        Some((body, age, due)) => {
+            if due {
+                let cache = cache.clone();
+                actix_web::rt::spawn(async move {
+                    match build().await {
+                        Ok(body) => cache.store(&key, body),
+                        Err(e) => cache.refresh_failed(&key, &e),
+                    }
+                });
+            }
+            feed_response(body, age)
+        }
+        None => match build().await {
+            Ok(body) => {
+                cache.store(&key, body.clone());
+                feed_response(body, 0)
+            }
+            Err(e) => {
+                log_error(&e);
+                HttpResponse::InternalServerError().body("Failed to load feed")
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const KEY: &str = "http://localhost/gallery/feed.xml";
+
+    // Serves the feed, with `result` as what building it would give
+    async fn get(
+        cache: &web::Data<FeedCache>,
+        builds: &Arc<AtomicUsize>,
+        result: Result<&'static str, &'static str>,
+    ) -> (String, i64) {
+        let builds = builds.clone();
+        let res = serve(cache.clone(), KEY.to_string(), move || async move {
+            builds.fetch_add(1, Ordering::SeqCst);
+            result.map(str::to_string).map_err(str::to_string)
+        })
+        .await;
+        let age = res.headers().get("Age").unwrap().to_str().unwrap().parse().unwrap();
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), age)
+    }
+
+    // Winds the cached copy's clock back, as if `secs` had passed
+    fn age_by(cache: &FeedCache, secs: i64) {
+        for entry in cache.entries.lock().unwrap().values_mut() {
+            entry.generated_at -= secs;
+        }
+    }
+
+    // Lets a spawned regeneration run
+    async fn settle() {
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    #[actix_web::test]
+    async fn stale_copy_is_served_while_the_next_is_built() {
+        let cache = web::Data::new(FeedCache::default());
+        let builds = Arc::new(AtomicUsize::new(0));
+
+        assert_eq!(get(&cache, &builds, Ok("first")).await, ("first".to_string(), 0));
+        assert_eq!(get(&cache, &builds, Ok("unused")).await.0, "first");
+        settle().await;
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        age_by(&cache, FRESH_SECS);
+        let (body, age) = get(&cache, &builds, Ok("second")).await;
+        assert_eq!(body, "first");
+        assert!(age >= FRESH_SECS, "{}", age);
+        settle().await;
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+        assert_eq!(get(&cache, &builds, Ok("unused")).await.0, "second");
+    }
+
+    #[actix_web::test]
+    async fn new_articles_set_off_a_rebuild_before_the_copy_is_old() {
+        let cache = web::Data::new(FeedCache::default());
+        let builds = Arc::new(AtomicUsize::new(0));
+        get(&cache, &builds, Ok("first")).await;
+
+        assert_eq!(cache.articles_changed(), 1);
+        assert_eq!(get(&cache, &builds, Ok("second")).await.0, "first");
+        settle().await;
+        assert_eq!(get(&cache, &builds, Ok("unused")).await.0, "second");
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn failed_rebuild_keeps_the_old_copy_and_retries() {
+        let cache = web::Data::new(FeedCache::default());
+        let builds = Arc::new(AtomicUsize::new(0));
+        get(&cache, &builds, Ok("first")).await;
+
+        age_by(&cache, FRESH_SECS);
+        assert_eq!(get(&cache, &builds, Err("database down")).await.0, "first");
+        settle().await;
+        assert!(cache.entries.lock().unwrap()[KEY].failing);
+        assert_eq!(get(&cache, &builds, Err("database down")).await.0, "first");
+        settle().await;
+        assert_eq!(get(&cache, &builds, Ok("second")).await.0, "first");
+        settle().await;
+        assert_eq!(builds.load(Ordering::SeqCst), 4);
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries[KEY].body, "second");
+        assert!(!entries[KEY].failing);
+    }
+}
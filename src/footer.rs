@@ -0,0 +1,189 @@
+use actix_web::body::{self, BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{web, Error};
+use sqlx::PgPool;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::settings::{Settings, SettingsCache};
+use crate::{paths, text};
+
+// Site footer, added to every HTML page on its way out so handlers don't
+// each have to render it. Plain-text error responses are turned into an
+// HTML error page first so they get one too. Machine-facing routes are
+// left alone.
+const SKIPPED_PREFIXES: &[&str] = &["/api/", "/oembed", "/static/", "/uploads/"];
+const CLOSING_BODY: &[u8] = b"</body>";
+
+fn footer_html(settings: &Settings) -> String {
+    let mut html = String::from(r#"<footer class="site-footer">"#);
+    if !settings.footer_markdown.is_empty() {
+        html.push_str(&format!(
+            r#"<div class="footer-custom">{}</div>"#,
+            text::render_markdown(&settings.footer_markdown)
+        ));
+    }
+    let build = match option_env!("GIT_COMMIT") {
+        Some(commit) => format!("v{} ({})", env!("CARGO_PKG_VERSION"), commit),
+        None => format!("v{}", env!("CARGO_PKG_VERSION")),
+    };
+    html.push_str(&format!(r#"<div class="footer-build">{}"#, build));
+    if settings.footer_powered_by {
+        html.push_str(r#" · Powered by <a href="https://github.com/x1a7x/Articles-1">Articles</a>"#);
+    }
+    html.push_str("</div></footer>");
+    html
+}
+
+fn error_page(status: &str, message: &str) -> String {
+    let base = paths::base();
+    format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>{}</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>{}</h2>
+        <p>{}</p>
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        status,
+        status,
+        html_escape::encode_text(message)
+    )
+}
+
+fn is_html(res: &ServiceResponse<impl MessageBody>) -> bool {
+    res.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"))
+}
+
+fn is_plain_error(res: &ServiceResponse<impl MessageBody>) -> bool {
+    let status = res.status();
+    let plain = match res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        None => true,
+        Some(v) => v.starts_with("text/plain"),
+    };
+    (status.is_client_error() || status.is_server_error()) && plain
+}
+
+pub async fn append_footer(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if SKIPPED_PREFIXES.iter().any(|p| req.path().starts_with(p)) {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let res = next.call(req).await?;
+    if !is_html(&res) && !is_plain_error(&res) {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    // Read after the handler, so a settings save shows its new footer at once
+    let cache = res.request().app_data::<web::Data<SettingsCache>>().cloned();
+    let pool = res.request().app_data::<web::Data<PgPool>>().cloned();
+    let settings = match (cache, pool) {
+        (Some(cache), Some(pool)) => cache.get(pool.get_ref()).await,
+        _ => Settings::default(),
+    };
+    let footer = footer_html(&settings);
+
+    if is_html(&res) {
+        return Ok(res.map_body(|_, body| {
+            BoxBody::new(FooterBody {
+                inner: body.boxed(),
+                footer: Some(footer),
+                carry: BytesMut::new(),
+                finished: false,
+            })
+        }));
+    }
+
+    let (req, res) = res.into_parts();
+    let status = res.status();
+    let title = status.canonical_reason().unwrap_or("Error");
+    let (mut head, body) = res.into_parts();
+    let message = match body::to_bytes(body).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => String::new(),
+    };
+    let page = error_page(title, &message).replacen("</body>", &format!("{}</body>", footer), 1);
+    head.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+    let res = head.set_body(BoxBody::new(page));
+    Ok(ServiceResponse::new(req, res))
+}
+
+// Passes a page through, putting the footer in front of its closing body
+// tag. The tag may be split across chunks, so the last few bytes of each
+// chunk are held back until the next one arrives.
+struct FooterBody {
+    inner: BoxBody,
+    footer: Option<String>,
+    carry: BytesMut,
+    finished: bool,
+}
+
+impl MessageBody for FooterBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        match self.inner.size() {
+            BodySize::None => BodySize::None,
+            _ => BodySize::Stream,
+        }
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
+        }
+        loop {
+            let chunk = match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(Some(Ok(chunk))) => chunk,
+                Poll::Ready(None) => {
+                    // A page without a closing body tag goes out unchanged
+                    this.finished = true;
+                    if this.carry.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(this.carry.split().freeze())));
+                }
+            };
+            let footer = match this.footer.as_ref() {
+                Some(footer) => footer,
+                None => return Poll::Ready(Some(Ok(chunk))),
+            };
+
+            this.carry.extend_from_slice(&chunk);
+            if let Some(at) = this.carry.windows(CLOSING_BODY.len()).position(|w| w == CLOSING_BODY) {
+                let mut out = BytesMut::with_capacity(this.carry.len() + footer.len());
+                out.extend_from_slice(&this.carry[..at]);
+                out.extend_from_slice(footer.as_bytes());
+                out.extend_from_slice(&this.carry[at..]);
+                this.carry.clear();
+                this.footer = None;
+                return Poll::Ready(Some(Ok(out.freeze())));
+            }
+            let keep = CLOSING_BODY.len() - 1;
+            if this.carry.len() > keep {
+                let ready = this.carry.split_to(this.carry.len() - keep);
+                return Poll::Ready(Some(Ok(ready.freeze())));
+            }
+        }
+    }
+}
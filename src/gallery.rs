@@ -0,0 +1,187 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::DateTime;
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool};
+
+use crate::feed_cache::{self, FeedCache};
+use crate::settings::SettingsCache;
+use crate::{derivatives, log_error, media, oembed, paths, visibility};
+
+// Image attachments across all articles, newest upload first: a grid at
+// /gallery and an RSS feed at /gallery/feed.xml with one item per image.
+// Video, audio and documents are left out by their stored MIME type.
+const PAGE_SIZE: i64 = 48;
+const FEED_ITEMS: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct GalleryQuery {
+    after: Option<String>, // "{uploaded_at}.{id}", from the previous page's "older" link
+}
+
+#[derive(FromRow)]
+struct GalleryImage {
+    id: i32,
+    article_id: i32,
+    title: String,
+    media_path: String,
+    mime_type: String,
+    alt_text: Option<String>,
+    size_bytes: Option<i64>,
+    uploaded_at: i64,
+    webp_path: Option<String>,
+    avif_path: Option<String>,
+}
+
+async fn images(pool: &PgPool, after: Option<(i64, i32)>, limit: i64) -> Result<Vec<GalleryImage>, sqlx::Error> {
+    let (uploaded_at, id) = after.unwrap_or((i64::MAX, i32::MAX));
+    sqlx::query_as::<_, GalleryImage>(&format!(
+        "SELECT m.id, m.article_id, a.title, m.media_path, m.mime_type, m.alt_text, m.size_bytes, m.uploaded_at, m.webp_path, m.avif_path
+         FROM article_media m JOIN articles a ON a.id = m.article_id
+         WHERE m.mime_type LIKE 'image/%' AND (m.uploaded_at, m.id) < ($1, $2) AND {}
+         ORDER BY m.uploaded_at DESC, m.id DESC LIMIT $3",
+        visibility::shown_articles("a")
+    ))
+    .bind(uploaded_at)
+    .bind(id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+fn alt(image: &GalleryImage) -> String {
+    match image.alt_text.as_deref().map(str::trim) {
+        Some(text) if !text.is_empty() => text.to_string(),
+        _ => format!("Image from {}", image.title),
+    }
+}
+
+// GET /gallery
+pub async fn gallery(pool: web::Data<PgPool>, query: web::Query<GalleryQuery>) -> HttpResponse {
+    let base = paths::base();
+    let after: Option<(i64, i32)> = query.after.as_deref().and_then(|after| {
+        let (uploaded_at, id) = after.split_once('.')?;
+        Some((uploaded_at.parse().ok()?, id.parse().ok()?))
+    });
+
+    let rows = match images(pool.get_ref(), after, PAGE_SIZE).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            log_error(&format!("Failed to load gallery: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load gallery");
+        }
+    };
+
+    let mut grid = String::new();
+    for image in &rows {
+        let img = format!(
+            r#"<img src="{}" alt="{}" loading="lazy">"#,
+            html_escape::encode_double_quoted_attribute(&paths::url(&image.media_path)),
+            html_escape::encode_double_quoted_attribute(&alt(image)),
+        );
+        grid.push_str(&format!(
+            r#"<a href="{base}/articles/{}" class="gallery-item" title="{}">{}</a>"#,
+            image.article_id,
+            html_escape::encode_double_quoted_attribute(&image.title),
+            derivatives::picture_html(img, image.webp_path.as_deref(), image.avif_path.as_deref()),
+        ));
+    }
+    if rows.is_empty() {
+        grid.push_str("<p>No images yet.</p>");
+    }
+
+    // A full page may have more behind it
+    let older = match rows.last() {
+        Some(last) if rows.len() as i64 == PAGE_SIZE => format!(
+            r#"<div class="center-link"><a href="{base}/gallery?after={}.{}">Older images →</a></div>"#,
+            last.uploaded_at, last.id
+        ),
+        _ => String::new(),
+    };
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Gallery</title>
+        <link rel="alternate" type="application/rss+xml" title="Gallery" href="{base}/gallery/feed.xml">
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>
+        <h1>Gallery</h1>
+        <div class="gallery-grid">{}</div>
+        {}
+        </body>
+        </html>
+        "#,
+        grid, older
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+fn xml_escape(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
+
+// Enclosures need a length; rows stored before sizes were recorded use the file's
+fn size_of(image: &GalleryImage) -> u64 {
+    match image.size_bytes {
+        Some(size) => size.max(0) as u64,
+        None => media::disk_path(&image.media_path)
+            .and_then(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .unwrap_or(0),
+    }
+}
+
+// GET /gallery/feed.xml
+pub async fn gallery_feed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    feeds: web::Data<FeedCache>,
+) -> HttpResponse {
+    let site = oembed::site_url(&req, &settings_cache.get(pool.get_ref()).await);
+    feed_cache::serve(feeds, format!("gallery|{}", site), move || build_feed(req, pool, settings_cache)).await
+}
+
+async fn build_feed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+) -> Result<String, String> {
+    let settings = settings_cache.get(pool.get_ref()).await;
+    let site = oembed::site_url(&req, &settings);
+
+    let rows = images(pool.get_ref(), None, FEED_ITEMS)
+        .await
+        .map_err(|e| format!("Failed to build gallery feed: {}", e))?;
+
+    let mut items = String::new();
+    for image in &rows {
+        let link = oembed::article_url(&req, &settings, image.article_id);
+        let url = format!("{}{}", site, image.media_path);
+        let pub_date = DateTime::from_timestamp(image.uploaded_at, 0)
+            .map(|dt| dt.to_rfc2822())
+            .unwrap_or_default();
+        items.push_str(&format!(
+            r#"<item><title>{}</title><link>{}</link><guid isPermaLink="false">{}</guid><pubDate>{}</pubDate><description>{}</description><enclosure url="{}" length="{}" type="{}"/></item>"#,
+            xml_escape(&image.title),
+            xml_escape(&link),
+            xml_escape(&format!("{}/media/{}", site, image.id)),
+            pub_date,
+            xml_escape(&alt(image)),
+            html_escape::encode_double_quoted_attribute(&url),
+            size_of(image),
+            html_escape::encode_double_quoted_attribute(&image.mime_type)
+        ));
+    }
+
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"><channel><title>{} gallery</title><link>{}</link><description>Newest images</description>{}</channel></rss>"#,
+        xml_escape(&settings.provider_name),
+        xml_escape(&format!("{}/gallery", site)),
+        items
+    );
+    Ok(xml)
+}
@@ -0,0 +1,85 @@
+use actix_web::HttpRequest;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::{captcha, client, csrf, log_error, submissions};
+
+// A form field people never see and dumb bots fill in. A post arriving with
+// it filled is dropped without telling the sender. HONEYPOT_FIELD names the
+// field (default "website") so it can be rotated once bots learn it; "off"
+// turns the trap off.
+const DEFAULT_FIELD: &str = "website";
+
+static FIELD: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn init_from_env() {
+    let configured = env::var("HONEYPOT_FIELD").unwrap_or_default();
+    let configured = configured.trim();
+    let field = match configured {
+        "" => Some(DEFAULT_FIELD.to_string()),
+        "off" => None,
+        name if is_form_field(name) => {
+            log_error(&format!(
+                "HONEYPOT_FIELD={} is a real form field; using \"{}\" instead",
+                name, DEFAULT_FIELD
+            ));
+            Some(DEFAULT_FIELD.to_string())
+        }
+        name => Some(name.to_string()),
+    };
+    let _ = FIELD.set(field);
+}
+
+// Names the submit and comment forms already use
+fn is_form_field(name: &str) -> bool {
+    [
+        "title",
+        "body",
+        "body_format",
+        "media",
+        "confirm_duplicate",
+        "comment",
+        "official",
+        "password",
+        csrf::FIELD_NAME,
+        submissions::FIELD_NAME,
+        captcha::TOKEN_FIELD,
+        captcha::ANSWER_FIELD,
+    ]
+    .contains(&name)
+}
+
+fn field_name() -> Option<&'static str> {
+    FIELD.get().and_then(Option::as_deref)
+}
+
+// Whether a submitted field is the trap
+pub fn is_field(name: &str) -> bool {
+    field_name() == Some(name)
+}
+
+// Whether the trap was filled in
+pub fn tripped(value: &str) -> bool {
+    !value.trim().is_empty()
+}
+
+// The hidden input, for inclusion in a form. Kept off screen rather than
+// type="hidden", which bots know to leave alone.
+pub fn field() -> String {
+    match field_name() {
+        Some(name) => format!(
+            r#"<div class="hp-field" aria-hidden="true"><label>Leave this empty <input type="text" name="{}" value="" tabindex="-1" autocomplete="off"></label></div>"#,
+            html_escape::encode_double_quoted_attribute(name)
+        ),
+        None => String::new(),
+    }
+}
+
+// Notes a dropped post so admins can see the trap working
+pub fn record_drop(req: &HttpRequest, what: &str) {
+    log_error(&format!(
+        "Honeypot: dropped {} from {}",
+        what,
+        client::ip_representation(req)
+    ));
+}
@@ -0,0 +1,188 @@
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use crate::log_error;
+
+// Rows fetched per round trip when streaming long pages
+pub const BATCH_SIZE: i64 = 300;
+
+// Render budget: once a page has sent this many bytes, no more batches
+// are fetched and the page ends with a link that continues from the
+// cursor. At least one batch always goes out, so a continuation page with
+// a heavy head still makes progress. MAX_PAGE_BYTES=0 turns it off.
+const DEFAULT_MAX_PAGE_BYTES: usize = 8 * 1024 * 1024;
+static MAX_PAGE_BYTES: OnceLock<usize> = OnceLock::new();
+
+pub fn init_from_env() {
+    let max = match env::var("MAX_PAGE_BYTES") {
+        Ok(v) => v.trim().parse().unwrap_or_else(|_| {
+            log_error(&format!("Invalid MAX_PAGE_BYTES {:?}, using {}", v, DEFAULT_MAX_PAGE_BYTES));
+            DEFAULT_MAX_PAGE_BYTES
+        }),
+        Err(_) => DEFAULT_MAX_PAGE_BYTES,
+    };
+    let _ = MAX_PAGE_BYTES.set(max);
+}
+
+fn max_page_bytes() -> Option<usize> {
+    match MAX_PAGE_BYTES.get().copied().unwrap_or(DEFAULT_MAX_PAGE_BYTES) {
+        0 => None,
+        max => Some(max),
+    }
+}
+
+// Body for a page whose middle is a long list: `head` goes out immediately,
+// then `next_batch` is called with the cursor until it returns None, and
+// `tail` closes the page. Only one batch is held in memory at a time. A
+// failing batch is logged and the list cut short, but the tail is still
+// sent so the client gets a complete document. A page over the render
+// budget gets `truncated(cursor)` in place of its remaining batches.
+pub fn paged<C, F, Fut, M, T>(
+    head: String,
+    cursor: C,
+    next_batch: F,
+    truncated: M,
+    tail: T,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>>
+where
+    C: 'static,
+    F: FnMut(C) -> Fut + 'static,
+    Fut: Future<Output = Result<Option<(String, C)>, sqlx::Error>> + 'static,
+    M: FnOnce(C) -> String + 'static,
+    T: Future<Output = String> + 'static,
+{
+    page_chunks(head, cursor, next_batch, truncated, tail, Rc::new(Cell::new(false)))
+        .map(|chunk| Ok(Bytes::from(chunk)))
+}
+
+// Same as `paged`, but also hands the whole page to `record` once it has
+// been sent. Pages cut short by a failing batch or the render budget, or
+// longer than `max_len`, are not recorded.
+pub fn paged_recorded<C, F, Fut, M, T, R>(
+    head: String,
+    cursor: C,
+    next_batch: F,
+    truncated: M,
+    tail: T,
+    max_len: usize,
+    record: R,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>>
+where
+    C: 'static,
+    F: FnMut(C) -> Fut + 'static,
+    Fut: Future<Output = Result<Option<(String, C)>, sqlx::Error>> + 'static,
+    M: FnOnce(C) -> String + 'static,
+    T: Future<Output = String> + 'static,
+    R: FnOnce(String) + 'static,
+{
+    let failed = Rc::new(Cell::new(false));
+    let page = Rc::new(RefCell::new(Some(String::new())));
+
+    let collected = page.clone();
+    let chunks = page_chunks(head, cursor, next_batch, truncated, tail, failed.clone()).inspect(move |chunk| {
+        let mut page = collected.borrow_mut();
+        if page.as_ref().is_some_and(|p| p.len() + chunk.len() > max_len) {
+            *page = None;
+        }
+        if let Some(p) = page.as_mut() {
+            p.push_str(chunk);
+        }
+    });
+
+    // Runs after the tail and yields nothing, so no empty chunk is written
+    let finish = stream::once(async move {
+        if !failed.get() {
+            if let Some(page) = page.borrow_mut().take() {
+                record(page);
+            }
+        }
+    })
+    .filter_map(|()| async { None::<String> });
+
+    chunks.chain(finish).map(|chunk| Ok(Bytes::from(chunk)))
+}
+
+// Where the list part of a page has got to
+enum Progress<C, F, M> {
+    Next {
+        cursor: C,
+        next_batch: F,
+        truncated: M,
+        sent: usize,
+        batches: usize,
+    },
+    Done,
+}
+
+fn page_chunks<C, F, Fut, M, T>(
+    head: String,
+    cursor: C,
+    next_batch: F,
+    truncated: M,
+    tail: T,
+    failed: Rc<Cell<bool>>,
+) -> impl Stream<Item = String>
+where
+    C: 'static,
+    F: FnMut(C) -> Fut + 'static,
+    Fut: Future<Output = Result<Option<(String, C)>, sqlx::Error>> + 'static,
+    M: FnOnce(C) -> String + 'static,
+    T: Future<Output = String> + 'static,
+{
+    let budget = max_page_bytes();
+    let start = Progress::Next {
+        cursor,
+        next_batch,
+        truncated,
+        sent: head.len(),
+        batches: 0,
+    };
+    let batches = stream::unfold(start, move |progress| {
+        let failed = failed.clone();
+        async move {
+            let (cursor, mut next_batch, truncated, sent, batches) = match progress {
+                Progress::Next {
+                    cursor,
+                    next_batch,
+                    truncated,
+                    sent,
+                    batches,
+                } => (cursor, next_batch, truncated, sent, batches),
+                Progress::Done => return None,
+            };
+            if budget.is_some_and(|max| sent >= max) && batches > 0 {
+                // Cut like a failed batch, so the page isn't kept as a fallback copy
+                failed.set(true);
+                return Some((truncated(cursor), Progress::Done));
+            }
+            match next_batch(cursor).await {
+                Ok(Some((chunk, cursor))) => {
+                    let sent = sent + chunk.len();
+                    let next = Progress::Next {
+                        cursor,
+                        next_batch,
+                        truncated,
+                        sent,
+                        batches: batches + 1,
+                    };
+                    Some((chunk, next))
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    log_error(&format!("Failed to stream page batch: {}", e));
+                    failed.set(true);
+                    None
+                }
+            }
+        }
+    });
+
+    stream::once(async move { head })
+        .chain(batches)
+        .chain(stream::once(tail))
+}
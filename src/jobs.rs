@@ -0,0 +1,91 @@
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::{bans, captcha, cooldowns, derivatives, edit_preview, link_checks, log_error, search, submissions, unlinks};
+
+// Periodic maintenance that runs for the lifetime of the server
+const SEARCH_REINDEX_INTERVAL: Duration = Duration::from_secs(60);
+const UNLINK_DRAIN_INTERVAL: Duration = Duration::from_secs(60);
+const LINK_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+const SUBMISSION_EXPIRY_INTERVAL: Duration = Duration::from_secs(600);
+const EXPIRED_ROWS_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+const DERIVATIVES_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn start(pool: PgPool) {
+    let search_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(SEARCH_REINDEX_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = search::reindex_stale(&search_pool).await {
+                log_error(&format!("Search reindex job failed: {}", e));
+            }
+        }
+    });
+
+    let unlink_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(UNLINK_DRAIN_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = unlinks::drain(&unlink_pool).await {
+                log_error(&format!("File cleanup job failed: {}", e));
+            }
+        }
+    });
+
+    let submission_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(SUBMISSION_EXPIRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = submissions::expire(&submission_pool).await {
+                log_error(&format!("Submission expiry job failed: {}", e));
+            }
+            if let Err(e) = edit_preview::expire(&submission_pool).await {
+                log_error(&format!("Staged upload expiry job failed: {}", e));
+            }
+        }
+    });
+
+    let prune_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRED_ROWS_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = cooldowns::prune(&prune_pool).await {
+                log_error(&format!("Cooldown prune job failed: {}", e));
+            }
+            if let Err(e) = captcha::prune(&prune_pool).await {
+                log_error(&format!("Captcha prune job failed: {}", e));
+            }
+            if let Err(e) = bans::prune(&prune_pool).await {
+                log_error(&format!("Ban prune job failed: {}", e));
+            }
+        }
+    });
+
+    let derivatives_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        if !derivatives::encoders_installed().await {
+            return;
+        }
+        let mut interval = tokio::time::interval(DERIVATIVES_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = derivatives::run(&derivatives_pool).await {
+                log_error(&format!("Image derivatives job failed: {}", e));
+            }
+        }
+    });
+
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(LINK_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = link_checks::run(&pool).await {
+                log_error(&format!("Link check job failed: {}", e));
+            }
+        }
+    });
+}
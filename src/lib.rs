@@ -0,0 +1,3286 @@
+use actix_files::Files;
+use actix_multipart::Multipart;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::KeepAlive;
+use actix_web::middleware::from_fn;
+use actix_web::error::{ErrorInternalServerError, ErrorNotFound};
+use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer};
+use chrono::Utc;
+use futures_util::stream::StreamExt as _;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+use admin::{AdminAuth, Permission};
+use announcements::AnnouncementCache;
+use blocklist::Blocklist;
+use body_format::BodyFormat;
+use caches::CacheRegistry;
+use cooldowns::Cooldown;
+use db::comments::{self, CommentOrder, CommentRow, Cursor, Inserted};
+use degraded::StaleCache;
+use events::EventKind;
+use feed_cache::FeedCache;
+use login_limits::LoginLimiter;
+use media::types::{self, MediaType, Renderer};
+use post_limits::{PostKind, PostLimiter};
+use read_only::ReadOnly;
+use scan::{ScanRecord, ScanRejection, UploadScanner};
+use settings::{Settings, SettingsCache};
+use upload_limits::UploadLimiter;
+
+mod access_log;
+mod admin;
+pub mod admin_cli;
+mod announcements;
+mod bans;
+mod blocklist;
+mod body_format;
+mod bumps;
+mod caches;
+pub mod captcha;
+mod client;
+mod comment_browser;
+mod comment_edits;
+mod comments_api;
+mod consent;
+mod cooldowns;
+mod csrf;
+mod db;
+mod degraded;
+mod derivatives;
+pub mod dev_db;
+mod digest;
+mod disk_watch;
+mod drafts;
+mod edit_preview;
+mod events;
+mod feed_cache;
+mod footer;
+mod gallery;
+mod honeypot;
+mod html_stream;
+mod jobs;
+mod link_checks;
+mod login_limits;
+mod mail_gateway;
+mod mailer;
+mod media;
+mod media_api;
+mod media_download;
+mod media_integrity;
+mod media_migration;
+mod moderators;
+mod normalize;
+mod oembed;
+mod outbound;
+mod paths;
+mod post_limits;
+pub mod query_count;
+mod read_only;
+mod references;
+mod reports;
+mod scan;
+mod search;
+mod security_headers;
+mod settings;
+mod structured_data;
+mod submissions;
+mod telemetry;
+mod text;
+pub mod tokens;
+pub mod unlinks;
+mod upload_limits;
+mod uploads;
+pub mod version;
+mod visibility;
+mod watched;
+mod wxr_import;
+
+const MAIN_PAGE_TITLE: &str = "All Articles";
+// Marks shadow-hidden posts for staff; their posters see them unmarked
+const SHADOW_HIDDEN_BADGE: &str = r#"<span class="shadow-badge">shadow-hidden</span> "#;
+
+#[derive(Serialize, Deserialize)]
+struct CommentForm {
+    comment: String,
+    // Asks for an official comment; only honoured for the super admin's session
+    official: Option<String>,
+    #[serde(default)]
+    csrf_token: String,
+    #[serde(default)]
+    captcha_token: String,
+    #[serde(default)]
+    captcha_answer: String,
+    // Anything else, where the honeypot field turns up under its configured name
+    #[serde(flatten)]
+    other: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PasswordForm {
+    // Left empty when a staff session authorizes the action
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[derive(Deserialize)]
+struct ArticleQuery {
+    quote: Option<String>,
+    comments: Option<String>, // "oldest" (default), "newest" or "top"
+    after: Option<String>,    // comment cursor, from a truncated page's continuation link
+    skipped: Option<String>,  // "/"-separated names of duplicate uploads left out of a post or edit
+    #[serde(rename = "continue")]
+    resume: Option<String>, // any value: jump to the first unseen comment on a watched article
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    after: Option<String>, // "{bump_time}.{id}", from a truncated page's continuation link
+}
+
+#[derive(Serialize, FromRow)]
+struct DbArticle {
+    id: i32,
+    title: String,
+    body: String,
+    body_format: String,
+    bump_time: i64,
+    created_at: Option<i64>,
+    locked_at: Option<i64>,
+    shadow_hidden: bool,
+}
+
+#[derive(Serialize, FromRow)]
+struct ArticleMedia {
+    id: i32,
+    media_path: String,
+    mime_type: Option<String>,
+    alt_text: Option<String>,
+    caption: Option<String>,
+    media_integrity: Option<String>,
+    webp_path: Option<String>,
+    avif_path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Article {
+    id: i32,
+    title: String,
+    body: String,
+    body_format: String,
+    media: Vec<ArticleMedia>,
+    bump_time: i64,
+}
+
+// The whole program: one-off commands when asked for, the web server otherwise
+pub async fn run() -> std::io::Result<()> {
+    if env::args().nth(1).as_deref() == Some("--hash-password") {
+        return admin::print_password_hash();
+    }
+
+    query_count::init_logging();
+    telemetry::init_from_env();
+    init_from_env().map_err(|e| {
+        log_error(&e);
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+    })?;
+    create_and_set_permissions("uploads")?;
+    create_and_set_permissions("quarantine")?;
+
+    // Held until shutdown so a --dev database outlives the server
+    let (database_url, _dev_database) = dev_db::database_url().await.map_err(|e| {
+        log_error(&e);
+        std::io::Error::new(std::io::ErrorKind::NotFound, "DATABASE_URL not set")
+    })?;
+
+    let pool = PgPool::connect(&database_url).await.map_err(|e| {
+        log_error(&format!("Failed to connect to Postgres: {}", e));
+        std::io::Error::other("DB connection failed")
+    })?;
+
+    version::migrate(&pool).await.map_err(|e| {
+        log_error(&e);
+        std::io::Error::other(e)
+    })?;
+
+    // One-off commands run against the migrated database instead of serving
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != "--dev").collect();
+    if args.first().map(String::as_str) == Some("import-wxr") {
+        return wxr_import::run(&pool, &args[1..]).await.map_err(|e| {
+            log_error(&e);
+            std::io::Error::other(e)
+        });
+    }
+    if args.first().map(String::as_str) == Some("admin") {
+        return admin_cli::run(&pool, &args[1..]).await.map_err(|e| {
+            log_error(&e);
+            std::io::Error::other(e)
+        });
+    }
+
+    media_migration::resume_unfinished(&pool).await;
+    media_integrity::start_on_boot(&pool).await;
+    jobs::start(pool.clone());
+
+    let state = AppState::from_env(pool.clone()).map_err(|e| {
+        log_error(&e);
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+    })?;
+    mail_gateway::start(
+        pool.clone(),
+        state.settings_cache.clone(),
+        state.blocklist.clone(),
+        state.stale_cache.clone(),
+    );
+    disk_watch::start(pool.clone(), state.read_only.clone());
+
+    let served = HttpServer::new(move || app(&state))
+    // Explicit limits on slow clients: time to send request headers, to close
+    // after a response, and to idle between keep-alive requests
+    .client_request_timeout(secs_from_env("HTTP_CLIENT_REQUEST_TIMEOUT_SECS", 5))
+    .client_disconnect_timeout(secs_from_env("HTTP_CLIENT_DISCONNECT_TIMEOUT_SECS", 2))
+    .keep_alive(KeepAlive::Timeout(secs_from_env("HTTP_KEEP_ALIVE_SECS", 5)))
+    .bind("127.0.0.1:8080")?
+    .run()
+    .await;
+    telemetry::shutdown();
+    served
+}
+
+// Configuration every module reads from the environment once at startup
+pub fn init_from_env() -> Result<(), String> {
+    tokens::init_from_env();
+    paths::init_from_env();
+    client::init_from_env();
+    access_log::init_from_env();
+    media_api::init_from_env();
+    html_stream::init_from_env();
+    mail_gateway::init_from_env();
+    honeypot::init_from_env();
+    captcha::init_from_env();
+    security_headers::init_from_env();
+    admin::init_from_env()
+}
+
+// State shared by every worker: the pool plus the caches and limiters that
+// must not be per-worker copies
+#[derive(Clone)]
+pub struct AppState {
+    pool: web::Data<PgPool>,
+    announcement_cache: web::Data<AnnouncementCache>,
+    blocklist: web::Data<Blocklist>,
+    feed_cache: web::Data<FeedCache>,
+    settings_cache: web::Data<SettingsCache>,
+    upload_scanner: web::Data<UploadScanner>,
+    upload_limiter: web::Data<UploadLimiter>,
+    login_limiter: web::Data<LoginLimiter>,
+    post_limiter: web::Data<PostLimiter>,
+    outbox: web::Data<mailer::Outbox>,
+    stale_cache: web::Data<StaleCache>,
+    read_only: web::Data<ReadOnly>,
+    cache_registry: web::Data<CacheRegistry>,
+}
+
+impl AppState {
+    pub fn from_env(pool: PgPool) -> Result<Self, String> {
+        let outbox = mailer::Outbox::from_env()?;
+        let state = AppState {
+            pool: web::Data::new(pool),
+            announcement_cache: web::Data::new(AnnouncementCache::default()),
+            blocklist: web::Data::new(Blocklist::default()),
+            feed_cache: web::Data::new(FeedCache::default()),
+            settings_cache: web::Data::new(SettingsCache::default()),
+            upload_scanner: web::Data::new(UploadScanner::from_env()),
+            upload_limiter: web::Data::new(UploadLimiter::from_env()),
+            login_limiter: web::Data::new(LoginLimiter::from_env()),
+            post_limiter: web::Data::new(PostLimiter::from_env()),
+            outbox: web::Data::new(outbox),
+            stale_cache: web::Data::new(StaleCache::default()),
+            read_only: web::Data::new(ReadOnly::default()),
+            cache_registry: web::Data::new(CacheRegistry::default()),
+        };
+        state.cache_registry.register(state.stale_cache.clone().into_inner());
+        state.cache_registry.register(state.settings_cache.clone().into_inner());
+        state.cache_registry.register(state.announcement_cache.clone().into_inner());
+        state.cache_registry.register(state.blocklist.clone().into_inner());
+        state.cache_registry.register(state.feed_cache.clone().into_inner());
+        Ok(state)
+    }
+}
+
+// The site as one App, with all middleware, for the server and for tests
+pub fn app(
+    state: &AppState,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .wrap(from_fn(consent::track))
+        .wrap(from_fn(csrf::issue_token))
+        .wrap(from_fn(bans::guard_writes))
+        .wrap(from_fn(read_only::guard_writes))
+        .wrap(from_fn(degraded::guard_writes))
+        .wrap(from_fn(footer::append_footer))
+        .wrap(from_fn(security_headers::add_headers))
+        .wrap(from_fn(normalize::redirect_to_canonical))
+        .wrap(from_fn(query_count::count_queries))
+        .wrap(from_fn(telemetry::trace_requests))
+        .wrap(from_fn(access_log::json_access_log))
+        .app_data(state.pool.clone())
+        .app_data(state.announcement_cache.clone())
+        .app_data(state.blocklist.clone())
+        .app_data(state.feed_cache.clone())
+        .app_data(state.settings_cache.clone())
+        .app_data(state.upload_scanner.clone())
+        .app_data(state.upload_limiter.clone())
+        .app_data(state.login_limiter.clone())
+        .app_data(state.post_limiter.clone())
+        .app_data(state.outbox.clone())
+        .app_data(state.stale_cache.clone())
+        .app_data(state.read_only.clone())
+        .app_data(state.cache_registry.clone())
+        .configure(routes)
+}
+
+// Every route of the site, for the server and for tests building an App
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::get().to(new_article_form))
+        .route("/submit", web::post().to(submit_article))
+        .route("/articles", web::get().to(list_articles))
+        .route("/watched", web::get().to(watched::watched_articles))
+        .route("/digest", web::get().to(digest::digest_yesterday))
+        .route("/digest/feed.xml", web::get().to(digest::digest_feed))
+        .route("/digest/{date}", web::get().to(digest::digest_for_day))
+        .route("/gallery", web::get().to(gallery::gallery))
+        .route("/gallery/feed.xml", web::get().to(gallery::gallery_feed))
+        .route("/search", web::get().to(search::search))
+        .route("/oembed", web::get().to(oembed::oembed))
+        .route("/out", web::get().to(outbound::follow))
+        .route("/consent", web::post().to(consent::record_choice))
+        .route("/articles/{id}", web::get().to(view_article))
+        .route("/articles/{id}/comment", web::post().to(submit_comment))
+        .route("/articles/{id}/quote", web::get().to(quote_article))
+        .route("/articles/{id}/watch", web::get().to(watched::toggle_watch))
+        .route("/articles/{id}/comments/{comment_id}/edit", web::get().to(comment_edits::edit_comment_form))
+        .route("/articles/{id}/comments/{comment_id}/edit", web::post().to(comment_edits::save_comment_edit))
+        // Delete routes
+        .route("/articles/{id}/delete", web::get().to(delete_article_form))
+        .route("/articles/{id}/delete", web::post().to(delete_article))
+        .route("/articles/{id}/release", web::post().to(release_article))
+        .route("/comments/{id}/delete", web::get().to(delete_comment_form))
+        .route("/comments/{id}/delete", web::post().to(delete_comment))
+        // Report routes
+        .route("/articles/{id}/report", web::get().to(reports::report_article_form))
+        .route("/articles/{id}/report", web::post().to(reports::report_article))
+        .route("/comments/{id}/report", web::get().to(reports::report_comment_form))
+        .route("/comments/{id}/report", web::post().to(reports::report_comment))
+        // Edit routes
+        .route("/articles/{id}/edit", web::get().to(edit_article_form))
+        .route("/articles/{id}/edit", web::post().to(edit_article))
+        .route("/articles/{id}/activity", web::get().to(events::activity_form))
+        .route("/articles/{id}/activity", web::post().to(events::view_activity))
+        .route("/announcements/{id}/dismiss", web::get().to(announcements::dismiss_announcement))
+        .route("/admin/login", web::get().to(admin::login_form))
+        .route("/admin/login", web::post().to(admin::login))
+        .route("/admin/logout", web::get().to(admin::logout_form))
+        .route("/admin/logout", web::post().to(admin::logout))
+        .route("/admin/comments", web::get().to(comment_browser::browse_comments))
+        .route("/admin/comments/bulk-delete", web::post().to(comment_browser::bulk_delete))
+        .route("/admin/comments/{id}/hide", web::post().to(comment_browser::toggle_hidden))
+        .route("/admin/comments/{id}/release", web::post().to(comment_browser::release))
+        .route("/admin/links", web::get().to(link_checks::broken_links))
+        .route("/admin/reports", web::get().to(reports::list_reports))
+        .route("/admin/reports/{target_type}/{id}/dismiss", web::post().to(reports::dismiss))
+        .route("/admin/clicks", web::get().to(outbound::top_domains))
+        .route("/admin/moderators", web::get().to(moderators::list_moderators))
+        .route("/admin/moderators", web::post().to(moderators::create_moderator))
+        .route("/admin/moderators/{id}/delete", web::post().to(moderators::delete_moderator))
+        // Admin announcement routes
+        .route("/admin/announcements", web::get().to(announcements::list_announcements))
+        .route("/admin/announcements", web::post().to(announcements::create_announcement))
+        .route("/admin/announcements/{id}/edit", web::get().to(announcements::edit_announcement_form))
+        .route("/admin/announcements/{id}/edit", web::post().to(announcements::edit_announcement))
+        .route("/admin/announcements/{id}/delete", web::post().to(announcements::delete_announcement))
+        .route("/admin/bans", web::get().to(bans::list_bans))
+        .route("/admin/bans", web::post().to(bans::create_ban))
+        .route("/admin/bans/{id}/lift", web::post().to(bans::lift_ban))
+        .route("/admin/blocklist", web::get().to(blocklist::list_terms))
+        .route("/admin/blocklist", web::post().to(blocklist::add_term))
+        .route("/admin/blocklist/{id}/delete", web::post().to(blocklist::delete_term))
+        .route("/admin/settings", web::get().to(settings::settings_form))
+        .route("/admin/settings", web::post().to(settings::save_settings))
+        .route("/admin/invalidate", web::post().to(caches::invalidate))
+        // Admin maintenance routes
+        .route("/admin/migrations/media", web::get().to(media_migration::migration_status))
+        .route("/admin/migrations/media", web::post().to(media_migration::start_migration))
+        .route("/admin/media/integrity", web::get().to(media_integrity::scan_status))
+        .route("/admin/media/integrity", web::post().to(media_integrity::start_scan))
+        .route("/media/{id}/download", web::get().to(media_download::download_media))
+        .route("/media/{id}/inline", web::get().to(media_download::inline_media))
+        .route("/api/articles/{id}/comments", web::get().to(comments_api::list_comments))
+        .route("/api/articles/{id}/media", web::put().to(media_api::replace_media))
+        .route("/api/version", web::get().to(version::version))
+        .route("/api/ready", web::get().to(version::ready))
+        .route("/uploads/{name}", web::get().to(uploads::serve_upload))
+        .service(Files::new("/static", "./static"));
+}
+
+// Duration in whole seconds from an environment variable, or the default
+// when it is unset or not a positive number
+fn secs_from_env(var: &str, default: u64) -> Duration {
+    let secs = env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default);
+    Duration::from_secs(secs)
+}
+
+fn create_and_set_permissions(dir: &str) -> std::io::Result<()> {
+    if !Path::new(dir).exists() {
+        fs::create_dir(dir)?;
+    }
+    Ok(())
+}
+
+// Logs all errors to error.txt (and to stdout as JSON when LOG_FORMAT=json)
+fn log_error(error_message: &str) {
+    if let Ok(file) = OpenOptions::new().create(true).append(true).open("error.txt") {
+        let mut writer = BufWriter::new(file);
+        let _ = writeln!(writer, "ERROR: {}", error_message);
+    }
+    access_log::emit_error(error_message);
+}
+
+async fn new_article_form(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    announcement_cache: web::Data<AnnouncementCache>,
+    settings_cache: web::Data<SettingsCache>,
+) -> HttpResponse {
+    let banner = announcements::banner_html(&req, pool.get_ref(), &announcement_cache, None).await;
+    let format = settings_cache.get(pool.get_ref()).await.default_body_format;
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(article_form_html(&req, &banner, "", "", "", format, false, false, &submissions::new_id()))
+}
+
+// The new-article form. `notice` is trusted markup shown above the fields;
+// `confirm_duplicate` resubmits past the duplicate-title warning. With
+// `file_kept` the file an earlier attempt stored under `submission_id` is
+// reused, so choosing one again is optional.
+#[allow(clippy::too_many_arguments)]
+fn article_form_html(
+    req: &HttpRequest,
+    banner: &str,
+    notice: &str,
+    title: &str,
+    body: &str,
+    format: BodyFormat,
+    confirm_duplicate: bool,
+    file_kept: bool,
+    submission_id: &str,
+) -> String {
+    let base = paths::base();
+    let (confirm_field, submit_label) = if confirm_duplicate {
+        (r#"<input type="hidden" name="confirm_duplicate" value="1">"#, "Post Anyway")
+    } else {
+        ("", "Submit Article")
+    };
+    let file_required = if file_kept { "" } else { " required" };
+    format!(r#"
+    <!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta charset="UTF-8">
+        <title>Submit a New Article</title>
+        <link rel="stylesheet" href="{base}/static/style.css">
+    </head>
+    <body>
+        {}
+        <div class="post-form-box">
+            <h1>Submit a New Article</h1>
+            {}
+            <form action="{base}/submit" method="POST" enctype="multipart/form-data">
+                {}
+                {}
+                <input type="hidden" name="{}" value="{}">
+                {}
+                <input type="text" name="title" placeholder="Title" value="{}" required><br>
+                <textarea name="body" rows="10" placeholder="Body" required>{}</textarea><br>
+                {}
+                <input type="file" name="media" accept="{}"{}><br><br>
+                <label>{}</label><br><br>
+                {}
+                <input type="submit" value="{}">
+            </form>
+        </div>
+        <br>
+        <div class="center-link"><a href="{base}/articles">View All Articles</a></div>
+    </body>
+    </html>
+    "#,
+        banner,
+        notice,
+        csrf::field(req),
+        honeypot::field(),
+        submissions::FIELD_NAME,
+        html_escape::encode_double_quoted_attribute(submission_id),
+        confirm_field,
+        html_escape::encode_double_quoted_attribute(title),
+        html_escape::encode_text(body),
+        body_format::select_html(format),
+        types::accept_attribute(),
+        file_required,
+        types::accepted_labels(),
+        captcha::field(req),
+        submit_label
+    )
+}
+
+// An existing article whose title matches case-insensitively
+async fn find_duplicate_title(pool: &PgPool, title: &str) -> Result<Option<(i32, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (i32, String)>(&format!(
+        "SELECT id, title FROM articles WHERE lower(title) = lower($1) AND {} ORDER BY id DESC LIMIT 1",
+        visibility::shown_articles("articles")
+    ))
+    .bind(title.trim())
+    .fetch_optional(pool)
+    .await
+}
+
+// A file accepted through the submit or edit form
+struct FormMedia {
+    path: String,
+    original_filename: String,
+    mime: Option<&'static str>,
+    hash: String,
+    scan: ScanRecord,
+}
+
+// Where to send the poster after a post or edit, naming any duplicate
+// uploads that were left out. A name can't contain "/", so it separates them.
+fn article_location(article_id: i32, skipped: &[String]) -> String {
+    let path = format!("/articles/{}", article_id);
+    if skipped.is_empty() {
+        return paths::url(&path);
+    }
+    let names = skipped.join("/");
+    paths::url(&format!("{}?skipped={}", path, utf8_percent_encode(&names, NON_ALPHANUMERIC)))
+}
+
+fn skipped_notice_html(skipped: &str) -> String {
+    let names: Vec<_> = skipped.split('/').filter(|n| !n.is_empty()).collect();
+    if names.is_empty() {
+        return String::new();
+    }
+    format!(
+        r#"<div class="thread-age-notice">Posted. These files were left out because the article already has them: {}</div>"#,
+        html_escape::encode_text(&names.join(", "))
+    )
+}
+
+// An upload refused by detect_media_type, carrying the file's name
+enum RefusedUpload {
+    Unsupported(String),
+    TooLarge(String),
+}
+
+impl From<RefusedUpload> for HttpResponse {
+    fn from(refused: RefusedUpload) -> Self {
+        match refused {
+            RefusedUpload::Unsupported(filename) => HttpResponse::BadRequest().body(format!(
+                "\"{}\" isn't a supported file type. Accepted formats: {}.",
+                filename,
+                types::accepted_labels()
+            )),
+            RefusedUpload::TooLarge(filename) => {
+                HttpResponse::PayloadTooLarge().body(format!("\"{}\" is too large", filename))
+            }
+        }
+    }
+}
+
+// The registry type of an upload, judged by its leading bytes. The name's
+// extension and the browser's content type aren't trusted, so an executable
+// or HTML page renamed to .png is refused.
+fn detect_media_type(data: &[u8], filename: &str) -> Result<&'static MediaType, RefusedUpload> {
+    let media_type = match types::sniff(data) {
+        Some(t) => t,
+        None => return Err(RefusedUpload::Unsupported(filename.to_string())),
+    };
+    if media_type.max_size.is_some_and(|max| data.len() as u64 > max) {
+        return Err(RefusedUpload::TooLarge(filename.to_string()));
+    }
+    Ok(media_type)
+}
+
+// Queues a rejected submission's uploads for removal
+async fn discard_uploads(pool: &PgPool, media: Vec<FormMedia>) -> Result<(), sqlx::Error> {
+    let paths: Vec<String> = media.into_iter().map(|m| m.path).collect();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let mut conn = pool.acquire().await?;
+    unlinks::enqueue(&mut conn, &paths).await
+}
+
+// Writes an upload to ./quarantine, scans it, and only moves it into
+// ./uploads (where it becomes visible) once the scan lets it through
+async fn store_scanned_upload(
+    scanner: &UploadScanner,
+    fail_open: bool,
+    data: &[u8],
+    filepath: &str,
+) -> Result<ScanRecord, HttpResponse> {
+    // Whatever the name, the file may only land directly in ./uploads
+    let in_uploads = Path::new(filepath)
+        .parent()
+        .and_then(|dir| dir.canonicalize().ok())
+        .zip(Path::new("./uploads").canonicalize().ok())
+        .is_some_and(|(dir, uploads)| dir == uploads);
+    if !in_uploads || Path::new(filepath).file_name().is_none() {
+        log_error(&format!("Refusing to store upload outside ./uploads: {}", filepath));
+        return Err(HttpResponse::InternalServerError().body("Failed to write file"));
+    }
+    let file_name = Path::new(filepath)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let quarantine_path = Path::new("./quarantine").join(format!(
+        "{}_{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        file_name
+    ));
+
+    let written = tracing::info_span!("media.quarantine", bytes = data.len())
+        .in_scope(|| File::create(&quarantine_path).and_then(|mut f| f.write_all(data)));
+    if let Err(e) = written {
+        log_error(&format!("Failed to write quarantined upload: {}", e));
+        let _ = fs::remove_file(&quarantine_path);
+        return Err(HttpResponse::InternalServerError().body("Failed to write file"));
+    }
+
+    let scanned = scan::scan_file(scanner, fail_open, &quarantine_path)
+        .instrument(tracing::info_span!("media.scan"))
+        .await;
+    match scanned {
+        Ok(record) => {
+            if let Err(e) = fs::rename(&quarantine_path, filepath) {
+                log_error(&format!("Failed to move upload out of quarantine: {}", e));
+                let _ = fs::remove_file(&quarantine_path);
+                return Err(HttpResponse::InternalServerError().body("Failed to write file"));
+            }
+            Ok(record)
+        }
+        Err(rejection) => {
+            let _ = fs::remove_file(&quarantine_path);
+            Err(match rejection {
+                ScanRejection::Infected => {
+                    HttpResponse::UnprocessableEntity().body("file failed security scanning")
+                }
+                ScanRejection::Unavailable => HttpResponse::ServiceUnavailable()
+                    .body("Security scanning is unavailable, please try again later"),
+            })
+        }
+    }
+}
+
+// Text fields the submit form sends; any other field but the file is
+// skipped without being held
+fn is_submit_field(name: &str) -> bool {
+    [
+        csrf::FIELD_NAME,
+        submissions::FIELD_NAME,
+        "title",
+        "body",
+        "body_format",
+        "confirm_duplicate",
+        captcha::TOKEN_FIELD,
+        captcha::ANSWER_FIELD,
+    ]
+    .contains(&name)
+        || honeypot::is_field(name)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn submit_article(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    blocklist: web::Data<Blocklist>,
+    stale_cache: web::Data<StaleCache>,
+    feed_cache: web::Data<FeedCache>,
+    upload_scanner: web::Data<UploadScanner>,
+    upload_limiter: web::Data<UploadLimiter>,
+    post_limiter: web::Data<PostLimiter>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    if let Err(res) = post_limiter.check(&req, PostKind::Article) {
+        return Ok(res.into());
+    }
+    let upload_permit = match upload_limiter.try_acquire(&req) {
+        Ok(permit) => permit,
+        Err(busy) => return Ok(busy.into()),
+    };
+
+    let mut csrf_token = String::new();
+    let mut submission_id = String::new();
+    let mut title = String::new();
+    let mut body = String::new();
+    let mut confirm_duplicate = false;
+    let mut trapped = false;
+    let mut captcha_token = String::new();
+    let mut captcha_answer = String::new();
+    let mut media_paths = Vec::new();
+    let mut skipped = Vec::new();
+    let settings = settings_cache.get(pool.get_ref()).await;
+    let fail_open = settings.scan_fail_open;
+    let mut format = settings.default_body_format;
+
+    // Staff post without a cooldown. Refuse early so a waiting client doesn't
+    // upload for nothing; insert_article makes the binding check.
+    let cooldown = if admin::current_staff(&req, pool.get_ref()).await.is_some() {
+        None
+    } else {
+        Cooldown::for_request(&req, PostKind::Article, settings.article_cooldown_secs)
+    };
+    if let Some(cooldown) = &cooldown {
+        let wait = cooldown.remaining(pool.get_ref()).await.map_err(|e| {
+            log_error(&format!("Failed to check post cooldown: {}", e));
+            ErrorInternalServerError("Database error")
+        })?;
+        if wait > 0 {
+            return Ok(article_cooldown_response(wait));
+        }
+    }
+
+    create_and_set_permissions("uploads").map_err(|e| {
+        log_error(&format!("Failed to create uploads dir: {}", e));
+        ErrorInternalServerError("Failed to setup uploads directory")
+    })?;
+
+    // Every way out of reading the form passes the cleanup below, so files
+    // stored before a refusal or a failed read don't outlive the request
+    let read: Result<Option<HttpResponse>, Error> = async {
+        while let Some(item) = upload_permit.within_deadline(payload.next()).await? {
+            let mut field = item.map_err(|e| {
+                log_error(&format!("Error reading multipart field: {}", e));
+                ErrorInternalServerError("Multipart read error")
+            })?;
+            upload_permit.next_field()?;
+
+            let cd = match field.content_disposition() {
+                Some(cd) => cd,
+                None => {
+                    log_error("Missing content disposition in multipart field");
+                    return Err(ErrorInternalServerError("Missing content disposition"));
+                }
+            };
+
+            let field_name = match cd.get_name() {
+                Some(n) => n.to_string(),
+                None => {
+                    log_error("Missing field name in content disposition");
+                    return Err(ErrorInternalServerError("Missing field name"));
+                }
+            };
+
+            let filename = cd.get_filename().map(|f| f.to_string());
+            let is_file = field_name == "media";
+            if !is_file && !is_submit_field(&field_name) {
+                upload_permit.skip(&mut field).await?;
+                continue;
+            }
+            // The form sends its token first, so a file isn't read, scanned
+            // or stored for a request that was never going to be accepted
+            if is_file {
+                if let Err(res) = csrf::check(&req, &csrf_token) {
+                    return Ok(Some(res.into()));
+                }
+            }
+
+            // Collect field data
+            let mut value = Vec::new();
+            while let Some(chunk) = upload_permit.within_deadline(field.next()).await? {
+                let chunk = chunk.map_err(|e| {
+                    log_error(&format!("Error reading chunk: {}", e));
+                    ErrorInternalServerError("Error reading chunk")
+                })?;
+                if is_file {
+                    upload_permit.receive(value.len(), chunk.len())?;
+                } else {
+                    upload_permit.receive_text(value.len(), chunk.len())?;
+                }
+                value.extend_from_slice(&chunk);
+            }
+
+            if field_name == csrf::FIELD_NAME {
+                csrf_token = String::from_utf8(value).unwrap_or_default();
+            } else if field_name == submissions::FIELD_NAME {
+                let id = String::from_utf8(value).unwrap_or_default().trim().to_string();
+                if submissions::valid_id(&id) {
+                    // A retry of a submission that already went through goes to what it became
+                    match submissions::published(pool.get_ref(), &id).await {
+                        Ok(Some(article_id)) => {
+                            return Ok(Some(
+                                HttpResponse::Found()
+                                    .append_header(("Location", article_location(article_id, &[])))
+                                    .finish(),
+                            ))
+                        }
+                        Ok(None) => {}
+                        Err(e) => log_error(&format!("Failed to look up submission: {}", e)),
+                    }
+                    submission_id = id;
+                }
+            } else if field_name == "title" {
+                title = String::from_utf8(value).unwrap_or_default();
+            } else if field_name == "body" {
+                body = String::from_utf8(value).unwrap_or_default();
+            } else if field_name == "body_format" {
+                match BodyFormat::parse(&String::from_utf8_lossy(&value)) {
+                    Some(f) => format = f,
+                    None => return Ok(Some(HttpResponse::BadRequest().body("Unknown body format"))),
+                }
+            } else if field_name == "confirm_duplicate" {
+                confirm_duplicate = value == b"1";
+            } else if field_name == captcha::TOKEN_FIELD {
+                captcha_token = String::from_utf8(value).unwrap_or_default();
+            } else if field_name == captcha::ANSWER_FIELD {
+                captcha_answer = String::from_utf8(value).unwrap_or_default();
+            } else if honeypot::is_field(&field_name) {
+                trapped = honeypot::tripped(&String::from_utf8_lossy(&value));
+            } else if field_name == "media" && !trapped {
+                // A file input left empty sends no name and no data
+                if let Some(fname) = filename.filter(|f| !f.is_empty() || !value.is_empty()) {
+                    let media_type = match detect_media_type(&value, &fname) {
+                        Ok(t) => t,
+                        Err(refused) => return Ok(Some(refused.into())),
+                    };
+                    let fname = media::clean_filename(&fname, media_type);
+                    // The same file attached twice is kept once
+                    let hash = media::content_hash(&value);
+                    if media_paths.iter().any(|m: &FormMedia| m.hash == hash) {
+                        skipped.push(fname);
+                        continue;
+                    }
+                    if !submission_id.is_empty() {
+                        match submissions::find_upload(pool.get_ref(), &submission_id, &fname, value.len()).await {
+                            Ok(Some(stored)) => {
+                                media_paths.push(FormMedia {
+                                    scan: stored.scan(),
+                                    path: stored.media_path,
+                                    original_filename: stored.original_filename,
+                                    mime: Some(media_type.mime),
+                                    hash,
+                                });
+                                continue;
+                            }
+                            Ok(None) => {}
+                            Err(e) => log_error(&format!("Failed to look up earlier upload: {}", e)),
+                        }
+                    }
+                    let media_path = media::hashed_media_path(&hash, media_type);
+                    let filepath = format!(".{}", media_path);
+                    let scan = match store_scanned_upload(&upload_scanner, fail_open, &value, &filepath).await {
+                        Ok(scan) => scan,
+                        Err(res) => return Ok(Some(res)),
+                    };
+                    // Kept for a retry if this attempt dies before publishing
+                    if !submission_id.is_empty() {
+                        let recorded = submissions::record_upload(
+                            pool.get_ref(),
+                            &submission_id,
+                            &fname,
+                            value.len(),
+                            &media_path,
+                            &scan,
+                        )
+                        .await;
+                        if let Err(e) = recorded {
+                            log_error(&format!("Failed to record upload for retries: {}", e));
+                        }
+                    }
+                    media_paths.push(FormMedia {
+                        path: media_path,
+                        original_filename: fname,
+                        mime: Some(media_type.mime),
+                        hash,
+                        scan,
+                    });
+                }
+            }
+        }
+        Ok(None)
+    }
+    .await;
+    if let Some(exit) = read.transpose() {
+        // A submission keeps its files for the retry; without one they'd only be orphans
+        if submission_id.is_empty() {
+            if let Err(e) = discard_uploads(pool.get_ref(), media_paths).await {
+                log_error(&format!("Failed to queue rejected upload for removal: {}", e));
+            }
+        }
+        return exit;
+    }
+
+    if let Err(res) = csrf::check(&req, &csrf_token) {
+        if !submission_id.is_empty() {
+            if let Err(e) = submissions::discard(pool.get_ref(), &submission_id).await {
+                log_error(&format!("Failed to discard rejected submission: {}", e));
+            }
+        }
+        if let Err(e) = discard_uploads(pool.get_ref(), media_paths).await {
+            log_error(&format!("Failed to queue rejected upload for removal: {}", e));
+        }
+        return Ok(res.into());
+    }
+
+    // Looks like any other successful post to the sender
+    if trapped {
+        honeypot::record_drop(&req, "an article");
+        if !submission_id.is_empty() {
+            if let Err(e) = submissions::discard(pool.get_ref(), &submission_id).await {
+                log_error(&format!("Failed to discard dropped submission: {}", e));
+            }
+        }
+        if let Err(e) = discard_uploads(pool.get_ref(), media_paths).await {
+            log_error(&format!("Failed to queue dropped upload for removal: {}", e));
+        }
+        return Ok(HttpResponse::Found()
+            .append_header(("Location", paths::url("/articles")))
+            .finish());
+    }
+
+    // A retry that didn't send the file again uses what earlier attempts stored
+    if media_paths.is_empty() && !submission_id.is_empty() {
+        let stored = submissions::stored_uploads(pool.get_ref(), &submission_id).await.map_err(|e| {
+            log_error(&format!("Failed to load earlier uploads: {}", e));
+            ErrorInternalServerError("Database error")
+        })?;
+        media_paths = stored
+            .into_iter()
+            .map(|upload| FormMedia {
+                scan: upload.scan(),
+                // Stored under the registry's extension for the sniffed type
+                mime: types::for_media(&upload.media_path, None).map(|t| t.mime),
+                hash: media::path_hash(&upload.media_path).unwrap_or_default().to_string(),
+                path: upload.media_path,
+                original_filename: upload.original_filename,
+            })
+            .collect();
+    }
+
+    // A failed challenge is handled like invalid input: the form comes back
+    // with a new challenge and what was typed
+    let checked = match captcha::check(&req, pool.get_ref(), &captcha_token, &captcha_answer).await {
+        Ok(()) => validate_article(&settings, &title, &body),
+        Err(message) => Err(message),
+    };
+    let checked = match checked {
+        Ok((title, body)) => blocklist.screen_article(pool.get_ref(), title, body).await,
+        Err(message) => Err(message),
+    };
+    let (title, body) = match checked {
+        Ok(valid) => valid,
+        Err(message) => {
+            // The form comes back with what was typed. Uploads stay stored
+            // under the submission; without one they're discarded.
+            let file_kept = !submission_id.is_empty() && !media_paths.is_empty();
+            if submission_id.is_empty() {
+                discard_uploads(pool.get_ref(), media_paths).await.map_err(|e| {
+                    log_error(&format!("Failed to queue discarded upload for removal: {}", e));
+                    ErrorInternalServerError("Database error")
+                })?;
+                submission_id = submissions::new_id();
+            }
+            let notice = format!(
+                r#"<div class="thread-age-notice">{}{}</div>"#,
+                html_escape::encode_text(&message),
+                if file_kept { " Your file is kept, so you don't need to choose it again." } else { "" }
+            );
+            return Ok(HttpResponse::BadRequest().content_type("text/html").body(article_form_html(
+                &req,
+                "",
+                &notice,
+                &title,
+                &body,
+                format,
+                confirm_duplicate,
+                file_kept,
+                &submission_id,
+            )));
+        }
+    };
+
+    if media_paths.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("Media file is required"));
+    }
+
+    // A repeated title gets a warning and a chance to post anyway, never a hard block
+    if !confirm_duplicate {
+        let duplicate = find_duplicate_title(pool.get_ref(), &title).await.map_err(|e| {
+            log_error(&format!("Failed to check for duplicate titles: {}", e));
+            ErrorInternalServerError("Database error")
+        })?;
+        if let Some((existing_id, existing_title)) = duplicate {
+            // The upload stays stored under the submission for "Post Anyway".
+            // Without a submission it's discarded and the form asks for it again.
+            let file_kept = !submission_id.is_empty();
+            let instruction = if file_kept {
+                "press \"Post Anyway\"; your file is kept for an hour"
+            } else {
+                discard_uploads(pool.get_ref(), media_paths).await.map_err(|e| {
+                    log_error(&format!("Failed to queue discarded upload for removal: {}", e));
+                    ErrorInternalServerError("Database error")
+                })?;
+                submission_id = submissions::new_id();
+                "choose your file again and press \"Post Anyway\""
+            };
+
+            let notice = format!(
+                r#"<div class="thread-age-notice">An article with this title already exists: <a href="{}/articles/{}">{}</a>. To post yours anyway, {}.</div>"#,
+                paths::base(),
+                existing_id,
+                html_escape::encode_text(&existing_title),
+                instruction
+            );
+            return Ok(HttpResponse::Ok()
+                .content_type("text/html")
+                .body(article_form_html(&req, "", &notice, &title, &body, format, true, file_kept, &submission_id)));
+        }
+    }
+
+    let actor = client::ip_representation(&req);
+    let inserted = insert_article(
+        pool.get_ref(),
+        &title,
+        &body,
+        format,
+        &media_paths,
+        &submission_id,
+        &actor,
+        cooldown.as_ref(),
+        bans::Shadowed::of(&req).as_deref(),
+    )
+    .await;
+    let article_id = match inserted {
+        Ok(ArticleInsert::Stored(id)) => id,
+        // Another attempt at this submission was published while this one ran
+        Ok(ArticleInsert::AlreadyPublished) => {
+            let location = match submissions::published(pool.get_ref(), &submission_id).await {
+                Ok(Some(article_id)) => article_location(article_id, &[]),
+                Ok(None) => paths::url("/articles"),
+                Err(e) => {
+                    log_error(&format!("Failed to look up submission: {}", e));
+                    paths::url("/articles")
+                }
+            };
+            return Ok(HttpResponse::Found().append_header(("Location", location)).finish());
+        }
+        // Posted something else since the early check; same handling as a duplicate title
+        Ok(ArticleInsert::CoolingDown(wait)) => {
+            let file_kept = !submission_id.is_empty();
+            if !file_kept {
+                discard_uploads(pool.get_ref(), media_paths).await.map_err(|e| {
+                    log_error(&format!("Failed to queue discarded upload for removal: {}", e));
+                    ErrorInternalServerError("Database error")
+                })?;
+                submission_id = submissions::new_id();
+            }
+            let notice = format!(
+                r#"<div class="thread-age-notice">{}</div>"#,
+                html_escape::encode_text(&wait_message("article", wait))
+            );
+            return Ok(HttpResponse::TooManyRequests()
+                .append_header(("Retry-After", wait.to_string()))
+                .content_type("text/html")
+                .body(article_form_html(&req, "", &notice, &title, &body, format, confirm_duplicate, file_kept, &submission_id)));
+        }
+        Err(e) => {
+            log_error(&e);
+            // Nothing was written. A submission keeps its files for the
+            // retry; without one they'd only be orphans.
+            if submission_id.is_empty() {
+                if let Err(e) = discard_uploads(pool.get_ref(), media_paths).await {
+                    log_error(&format!("Failed to queue upload for removal: {}", e));
+                }
+            }
+            return Err(ErrorInternalServerError("Failed to store article"));
+        }
+    };
+
+    stale_cache.forget_list();
+    feed_cache.articles_changed();
+
+    // Skipped duplicates are reported on the new article's page
+    let location = if skipped.is_empty() {
+        paths::url("/articles")
+    } else {
+        article_location(article_id, &skipped)
+    };
+    Ok(HttpResponse::Found().append_header(("Location", location)).finish())
+}
+
+enum ArticleInsert {
+    Stored(i32),
+    // Another attempt at the same submission was published first
+    AlreadyPublished,
+    // The poster's cooldown has this many seconds left
+    CoolingDown(i64),
+}
+
+// Writes a new article with its media, search entry, references and
+// creation event in one transaction, so a failure leaves no trace of it.
+// The cooldown is claimed in the same transaction. With `shadow_viewer` (the
+// poster's address hash, when shadow banned) it is stored shadow-hidden.
+#[allow(clippy::too_many_arguments)]
+async fn insert_article(
+    pool: &PgPool,
+    title: &str,
+    body: &str,
+    format: BodyFormat,
+    media: &[FormMedia],
+    submission_id: &str,
+    actor: &str,
+    cooldown: Option<&Cooldown>,
+    shadow_viewer: Option<&str>,
+) -> Result<ArticleInsert, String> {
+    let created_at = Utc::now().timestamp();
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    if let Some(cooldown) = cooldown {
+        let claimed = cooldown
+            .claim(&mut tx)
+            .await
+            .map_err(|e| format!("Failed to claim post cooldown: {}", e))?;
+        if let Some(wait) = claimed {
+            return Ok(ArticleInsert::CoolingDown(wait));
+        }
+    }
+
+    let article_id: i32 = sqlx::query_scalar(
+        "INSERT INTO articles (title, body, body_format, bump_time, updated_at, created_at, shadow_hidden, shadow_viewer)
+         VALUES ($1, $2, $3, $4, $4, $4, $5, $6) RETURNING id",
+    )
+    .bind(title)
+    .bind(body)
+    .bind(format.name())
+    .bind(created_at)
+    .bind(shadow_viewer.is_some())
+    .bind(shadow_viewer)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to store article: {}", e))?;
+
+    for m in media {
+        sqlx::query(
+            "INSERT INTO article_media
+                 (article_id, media_path, original_filename, mime_type, content_hash, scan_status, scan_duration_ms)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(article_id)
+        .bind(&m.path)
+        .bind(&m.original_filename)
+        .bind(m.mime)
+        .bind(&m.hash)
+        .bind(m.scan.status)
+        .bind(m.scan.duration_ms)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to store media: {}", e))?;
+    }
+    let media_paths: Vec<&str> = media.iter().map(|m| m.path.as_str()).collect();
+    unlinks::hold(&mut tx, &media_paths).await?;
+
+    if !submission_id.is_empty() {
+        let claimed = submissions::claim(&mut tx, submission_id, article_id)
+            .await
+            .map_err(|e| format!("Failed to claim submission: {}", e))?;
+        if !claimed {
+            return Ok(ArticleInsert::AlreadyPublished);
+        }
+    }
+
+    search::index_article(&mut tx, article_id)
+        .await
+        .map_err(|e| format!("Failed to index article: {}", e))?;
+    references::record_article_refs(&mut tx, article_id, body)
+        .await
+        .map_err(|e| format!("Failed to store article references: {}", e))?;
+    events::record(&mut tx, article_id, EventKind::Created, actor, None)
+        .await
+        .map_err(|e| format!("Failed to record article event: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit article: {}", e))?;
+    Ok(ArticleInsert::Stored(article_id))
+}
+
+fn wait_message(what: &str, wait: i64) -> String {
+    format!(
+        "You're posting too fast. Please wait {} {} before posting another {}.",
+        wait,
+        if wait == 1 { "second" } else { "seconds" },
+        what
+    )
+}
+
+// 429 page for a client still in its article cooldown
+fn article_cooldown_response(wait: i64) -> HttpResponse {
+    let base = paths::base();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Please Wait</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Please Wait</h2>
+        <p>{}</p>
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        html_escape::encode_text(&wait_message("article", wait))
+    );
+    HttpResponse::TooManyRequests()
+        .append_header(("Retry-After", wait.to_string()))
+        .content_type("text/html")
+        .body(html)
+}
+
+// One batch of the article list: at most $3 articles `shown` to the viewer
+// before the ($1, $2) = (bump_time, id) cursor. Shaped to stay an index-only
+// scan of articles_listing_idx, which carries the comment count for the
+// badges and the shadow columns; tests/query_plans.rs holds it to that.
+pub fn article_list_query(shown: &str) -> String {
+    format!(
+        "SELECT id, title, bump_time, comment_count, shadow_hidden FROM articles
+         WHERE (bump_time, id) < ($1, $2) AND {} ORDER BY bump_time DESC, id DESC LIMIT $3",
+        shown
+    )
+}
+
+async fn list_articles(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    announcement_cache: web::Data<AnnouncementCache>,
+    stale_cache: web::Data<StaleCache>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    let base = paths::base();
+    if !stale_cache.database_available(pool.get_ref()).await {
+        return stale_cache.list_response();
+    }
+    let start: Option<(i64, i32)> = query.after.as_deref().and_then(|after| {
+        let (bump_time, id) = after.split_once('.')?;
+        Some((bump_time.parse().ok()?, id.parse().ok()?))
+    });
+    let banner = announcements::banner_html(&req, pool.get_ref(), &announcement_cache, None).await;
+
+    let articles_html = format!(r#"
+    <!DOCTYPE html>
+    <html lang="en">
+    <head>
+        <meta charset="UTF-8">
+        <title>{}</title>
+        <link rel="stylesheet" href="{base}/static/style.css">
+    </head>
+    <body>
+        {}
+        <h1>{}</h1>
+        <div class="center-link"><a href="{base}/">Submit a New Article</a> | <a href="{base}/watched">Watched Articles</a> | <a href="{base}/gallery">Gallery</a></div>
+        <form action="{base}/search" method="GET" class="search-form">
+            <input type="text" name="q" placeholder="Search articles">
+            <input type="submit" value="Search">
+        </form>
+    "#, MAIN_PAGE_TITLE, banner, MAIN_PAGE_TITLE);
+
+    let show_admin_links = admin::is_admin(&req);
+    let watched_ids = Arc::new(watched::watched_ids(&req));
+    let viewer = visibility::Viewer::of(&req, pool.get_ref()).await;
+    // A client shown its own shadow-hidden articles gets a page of its own
+    let shadowed = match sqlx::query_scalar::<_, bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM articles WHERE {})",
+        visibility::shadowed_for("articles", &viewer)
+    ))
+    .fetch_one(pool.get_ref())
+    .await
+    {
+        Ok(shadowed) => shadowed,
+        Err(e) if stale_cache.failed(pool.get_ref(), &e) => return stale_cache.list_response(),
+        Err(e) => {
+            log_error(&format!("Failed to check for shadow-hidden articles: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load articles");
+        }
+    };
+
+    // Walks articles newest-bump first in batches, keyed on (bump_time, id)
+    let articles_pool = pool.get_ref().clone();
+    let next_articles_watched = watched_ids.clone();
+    let shown = Arc::new(visibility::shown_articles_to("articles", &viewer));
+    let next_articles = move |after: Option<(i64, i32)>| {
+        let pool = articles_pool.clone();
+        let watched_ids = next_articles_watched.clone();
+        let shown = shown.clone();
+        async move {
+            let (bump_time, id) = after.unwrap_or((i64::MAX, i32::MAX));
+            let rows = sqlx::query_as::<_, (i32, String, i64, i64, bool)>(&article_list_query(&shown))
+                .bind(bump_time)
+                .bind(id)
+                .bind(html_stream::BATCH_SIZE)
+                .fetch_all(&pool)
+                .await?;
+
+            let after = match rows.last() {
+                Some((id, _, bump_time, _, _)) => (*bump_time, *id),
+                None => return Ok(None),
+            };
+            let mut chunk = String::new();
+            for (id, title, _, comment_count, shadow_hidden) in &rows {
+                let admin_links = if show_admin_links {
+                    format!(
+                        r#"{}<a href="{base}/articles/{}/delete" class="delete-link">[x]</a>
+                <a href="{base}/articles/{}/edit" class="edit-link">[+]</a>"#,
+                        if *shadow_hidden { SHADOW_HIDDEN_BADGE } else { "" },
+                        id,
+                        id
+                    )
+                } else {
+                    String::new()
+                };
+                let badge = if *comment_count > 0 {
+                    format!(r#" <span class="badge">{}</span>"#, comment_count)
+                } else {
+                    String::new()
+                };
+                let continue_link = if watched_ids.contains(id) {
+                    watched::continue_link_html(*id)
+                } else {
+                    String::new()
+                };
+                chunk.push_str(&format!(
+                    r#"<div class="article">
+                <h2><a href="{base}/articles/{}" dir="auto">{}</a>{}{}</h2>
+                {}
+            </div>"#,
+                    id,
+                    html_escape::encode_text(title),
+                    badge,
+                    continue_link,
+                    admin_links
+                ));
+            }
+            Ok::<_, sqlx::Error>(Some((chunk, Some(after))))
+        }
+    };
+
+    let truncated = move |after: Option<(i64, i32)>| match after {
+        Some((bump_time, id)) => {
+            log_error(&format!("Article list truncated at the render budget after article {}", id));
+            format!(
+                r#"<div class="page-truncated">Page truncated — <a href="{base}/articles?after={}.{}">continue the list</a></div>"#,
+                bump_time, id
+            )
+        }
+        None => String::new(),
+    };
+    let tail = async { "</body></html>".to_string() };
+
+    // Only the anonymous first page is kept as the fallback copy
+    if show_admin_links || shadowed || start.is_some() || !watched_ids.is_empty() {
+        return HttpResponse::Ok()
+            .content_type("text/html")
+            .streaming(html_stream::paged(articles_html, start, next_articles, truncated, tail));
+    }
+    let cache = stale_cache.into_inner();
+    HttpResponse::Ok().content_type("text/html").streaming(html_stream::paged_recorded(
+        articles_html,
+        None,
+        next_articles,
+        truncated,
+        tail,
+        degraded::MAX_CACHED_PAGE_BYTES,
+        move |page| cache.store_list(page),
+    ))
+}
+
+async fn view_article(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    announcement_cache: web::Data<AnnouncementCache>,
+    settings_cache: web::Data<SettingsCache>,
+    stale_cache: web::Data<StaleCache>,
+    path: web::Path<i32>,
+    query: web::Query<ArticleQuery>,
+) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+    if !stale_cache.database_available(pool.get_ref()).await {
+        return stale_cache.article_response(article_id);
+    }
+    if query.resume.is_some() {
+        return match watched::continue_location(pool.get_ref(), &req, article_id).await {
+            Ok(location) => HttpResponse::Found().append_header(("Location", location)).finish(),
+            Err(e) => {
+                log_error(&format!("Failed to find the reading position: {}", e));
+                HttpResponse::InternalServerError().body("Failed to load article")
+            }
+        };
+    }
+
+    let viewer = visibility::Viewer::of(&req, pool.get_ref()).await;
+    let article_db = match sqlx::query_as::<_, DbArticle>(&format!(
+        "SELECT id, title, body, body_format, bump_time, created_at, locked_at, shadow_hidden FROM articles
+         WHERE id = $1 AND {}",
+        visibility::shown_articles_to("articles", &viewer)
+    ))
+    .bind(article_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(Some(a)) => a,
+        Ok(None) => return HttpResponse::NotFound().body("Article not found"),
+        Err(e) if stale_cache.failed(pool.get_ref(), &e) => return stale_cache.article_response(article_id),
+        Err(e) => {
+            log_error(&format!("Failed to fetch article: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load article");
+        }
+    };
+
+    let media = fetch_article_media(pool.get_ref(), article_db.id)
+        .await
+        .unwrap_or_else(|e| {
+            log_error(&format!("Failed to fetch article media: {}", e));
+            Vec::new()
+        });
+
+    let article = Article {
+        id: article_db.id,
+        title: article_db.title,
+        body: article_db.body,
+        body_format: article_db.body_format,
+        bump_time: article_db.bump_time,
+        media,
+    };
+
+    let settings = settings_cache.get(pool.get_ref()).await;
+
+    let mut article_html = String::new();
+    article_html.push_str(r#"<!DOCTYPE html><html lang="en"><head><meta charset="UTF-8">"#);
+    article_html.push_str(&format!("<title>{}</title>", html_escape::encode_text(&article.title)));
+    article_html.push_str(&oembed::canonical_link(&req, &settings, article.id));
+    article_html.push_str(&oembed::discovery_link(&req, &settings, article.id, &article.title));
+    let facts = structured_data::ArticleFacts {
+        id: article.id,
+        title: &article.title,
+        created_at: article_db.created_at,
+        bump_time: article.bump_time,
+        image_paths: article
+            .media
+            .iter()
+            .filter(|m| types::renderer_for(&m.media_path, m.mime_type.as_deref()) == Renderer::Image)
+            .map(|m| m.media_path.as_str())
+            .collect(),
+    };
+    article_html.push_str(&structured_data::script_tag(pool.get_ref(), &req, &settings, &facts).await);
+    article_html.push_str(&format!(r#"<link rel="stylesheet" href="{base}/static/style.css"></head><body>"#));
+    article_html.push_str(&announcements::banner_html(&req, pool.get_ref(), &announcement_cache, None).await);
+    article_html.push_str(&format!(r#"<div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>"#));
+
+    // Article container
+    article_html.push_str(r#"<div class="article">"#);
+    article_html.push_str(&format!(r#"<h1 dir="auto">{}</h1>"#, html_escape::encode_text(&article.title)));
+    if let Some(skipped) = query.skipped.as_deref() {
+        article_html.push_str(&skipped_notice_html(skipped));
+    }
+    if article_db.shadow_hidden && matches!(viewer, visibility::Viewer::Staff) {
+        article_html.push_str(&format!(
+            r#"<div class="thread-age-notice">{}Posted from a shadow-banned address: only its poster and staff see this article.
+            <form action="{base}/articles/{}/release" method="POST">{}{}<input type="submit" value="Release"></form></div>"#,
+            SHADOW_HIDDEN_BADGE,
+            article.id,
+            csrf::field(&req),
+            admin::password_field(&req)
+        ));
+    }
+
+    for media in &article.media {
+        article_html.push_str(&media_html(media));
+    }
+
+    let ref_titles = references::referenced_titles(pool.get_ref(), article.id)
+        .await
+        .unwrap_or_else(|e| {
+            log_error(&format!("Failed to fetch referenced articles: {}", e));
+            HashMap::new()
+        });
+
+    let age = thread_age(&settings, article.bump_time, article_db.locked_at.is_some());
+    // Pages showing anything specific to this visitor aren't kept as the fallback copy
+    let shadowed = article_db.shadow_hidden
+        || comments::shadowed_for(pool.get_ref(), article.id, &viewer)
+            .await
+            .unwrap_or_else(|e| {
+                log_error(&format!("Failed to check for shadow-hidden comments: {}", e));
+                true
+            });
+    let mut personalized = shadowed
+        || query.quote.is_some()
+        || query.comments.is_some()
+        || query.after.is_some()
+        || query.skipped.is_some()
+        || watched::is_watched(&req, article.id);
+
+    let rendered_body = tracing::info_span!("render.body", format = %article.body_format).in_scope(|| {
+        let format = body_format::from_column(&article.body_format);
+        let outbound_for = settings.wrap_outbound_links.then_some(article.id);
+        body_html(&settings, &body_format::render(format, &article.body, &ref_titles, outbound_for))
+    });
+    article_html.push_str(&format!(
+        r#"{}<a href="{base}/articles/{}/quote" class="quote-link">[quote selection]</a> <a href="{base}/articles/{}/report" class="quote-link">[report]</a>{}<h3>Leave a Comment</h3>"#,
+        rendered_body,
+        article.id,
+        article.id,
+        watched::toggle_link_html(&req, article.id)
+    ));
+    article_html.push_str(&thread_age_notice(&settings, &age));
+    let captcha_field = captcha::field(&req);
+    if age.takes_comments() {
+        // A requested quote takes the place of any stashed draft
+        let mut prefill = None;
+        if let Some(quote) = query.quote.as_deref() {
+            match text::quote_prefill(quote, settings.max_quote_chars as usize) {
+                Ok(q) => prefill = Some(q),
+                Err(e) => article_html.push_str(&format!(
+                    r#"<div class="thread-age-notice">{}</div>"#,
+                    html_escape::encode_text(&e)
+                )),
+            }
+        }
+        if prefill.is_none() {
+            prefill = drafts::load(pool.get_ref(), &req, article.id)
+                .await
+                .unwrap_or_else(|e| {
+                    log_error(&format!("Failed to load comment draft: {}", e));
+                    None
+                });
+        }
+        personalized |= prefill.is_some();
+        article_html.push_str(&comment_form_html(&req, pool.get_ref(), article.id, prefill.as_deref().unwrap_or(""), &captcha_field).await);
+    }
+    let order = CommentOrder::from_query(query.comments.as_deref());
+    let order_links: Vec<String> = CommentOrder::ALL
+        .iter()
+        .map(|o| {
+            if *o == order {
+                o.key().to_string()
+            } else {
+                format!(r#"<a href="{base}/articles/{}?comments={}">{}</a>"#, article.id, o.key(), o.key())
+            }
+        })
+        .collect();
+    article_html.push_str(&format!(
+        r#"<h3>Comments</h3><div class="quote-link">Sort: {}</div>"#,
+        order_links.join(" | ")
+    ));
+
+    // Admin links inside article, only for logged-in admins
+    let show_admin_links = admin::is_admin(&req);
+    if show_admin_links {
+        article_html.push_str(&format!(r#"<a href="{base}/articles/{}/delete" class="delete-link">[x]</a>"#, article.id));
+        article_html.push_str(&format!(r#"<a href="{base}/articles/{}/edit" class="edit-link">[+]</a>"#, article.id));
+        article_html.push_str(&format!(r#"<a href="{base}/articles/{}/activity" class="activity-link">[~]</a>"#, article.id));
+    }
+
+    article_html.push_str("</div>"); // end of .article
+
+    // Comments are streamed in batches so memory use doesn't grow with the thread
+    let comments_pool = pool.get_ref().clone();
+    let ref_titles = Arc::new(ref_titles);
+    let official_name = Arc::new(settings.admin_display_name.clone());
+    let editable = Arc::new(if settings.comment_edit_minutes > 0 {
+        comment_edits::editable_comments(&req)
+    } else {
+        HashSet::new()
+    });
+    personalized |= show_admin_links || !editable.is_empty();
+    let viewer = Arc::new(viewer);
+    let next_comments = move |last: Cursor| {
+        let pool = comments_pool.clone();
+        let ref_titles = ref_titles.clone();
+        let editable = editable.clone();
+        let official_name = official_name.clone();
+        let viewer = viewer.clone();
+        async move {
+            let rows =
+                comments::visible_page(&pool, article_id, Some(&viewer), order, last, html_stream::BATCH_SIZE).await?;
+
+            let last = match rows.last() {
+                Some(row) => order.after(row),
+                None => return Ok(None),
+            };
+            let chunk = tracing::info_span!("render.comments", count = rows.len()).in_scope(|| {
+                let mut chunk = String::new();
+                for row in &rows {
+                    let can_edit = editable.contains(&row.id);
+                    chunk.push_str(&comment_html(article_id, row, show_admin_links, can_edit, &ref_titles, &official_name));
+                }
+                chunk
+            });
+            Ok::<_, sqlx::Error>(Some((chunk, last)))
+        }
+    };
+
+    let truncated = move |last: Cursor| {
+        log_error(&format!(
+            "Article {} truncated at the render budget after comment #{}",
+            article_id, last.id
+        ));
+        format!(
+            r#"<div class="page-truncated">Page truncated — <a href="{base}/articles/{}?comments={}&after={}">continue reading after comment #{}</a></div>"#,
+            article_id,
+            order.key(),
+            order.cursor_param(last),
+            last.id
+        )
+    };
+
+    let tail_pool = pool.get_ref().clone();
+    let tail = async move {
+        let mut tail_html = backlinks_html(&tail_pool, article_id).await;
+        tail_html.push_str("</body></html>");
+        tail_html
+    };
+
+    let mut response = HttpResponse::Ok();
+    if let Some(cookie) = watched::mark_seen(pool.get_ref(), &req, article_id).await {
+        consent::add_cookie(&req, &mut response, cookie);
+    }
+    response.content_type("text/html");
+    let start = query.after.as_deref().and_then(|after| order.parse_cursor(after)).unwrap_or(order.start());
+    if personalized {
+        return response.streaming(html_stream::paged(article_html, start, next_comments, truncated, tail));
+    }
+    // The fallback copy is served to everyone, so it mustn't carry this
+    // visitor's CSRF token or captcha challenge
+    let csrf_token = csrf::token(&req);
+    let cache = stale_cache.into_inner();
+    response.streaming(html_stream::paged_recorded(
+        article_html,
+        start,
+        next_comments,
+        truncated,
+        tail,
+        degraded::MAX_CACHED_PAGE_BYTES,
+        move |page| cache.store_article(article_id, page.replace(&csrf_token, "").replace(&captcha_field, "")),
+    ))
+}
+
+// Rendered article body, with the tail of very long bodies folded into an expander
+fn body_html(settings: &Settings, body: &str) -> String {
+    let split = match settings.reveal_body_after_chars {
+        0 => None,
+        max => text::split_for_reveal(body, max as usize),
+    };
+    match split {
+        Some((head, rest)) => format!(
+            r#"<div class="article-body" dir="auto">{}<details class="body-reveal"><summary>Read the rest ({} more words)</summary>{}</details></div>"#,
+            head,
+            text::visible_word_count(rest),
+            rest
+        ),
+        None => format!(r#"<div class="article-body" dir="auto">{}</div>"#, body),
+    }
+}
+
+fn comment_html(
+    article_id: i32,
+    row: &CommentRow,
+    show_admin_links: bool,
+    can_edit: bool,
+    ref_titles: &HashMap<i32, String>,
+    official_name: &str,
+) -> String {
+    let base = paths::base();
+    // Deleting a tombstone purges it for good
+    let delete_link = match (show_admin_links, row.deleted) {
+        (false, _) => String::new(),
+        (true, false) => format!(r#"<a href="{base}/comments/{}/delete" class="delete-link">[x]</a>"#, row.id),
+        (true, true) => format!(r#"<a href="{base}/comments/{}/delete" class="delete-link">[purge]</a>"#, row.id),
+    };
+    let content = if row.deleted {
+        r#"<span class="tombstone">[deleted]</span>"#.to_string()
+    } else {
+        text::render_comment(&row.comment, ref_titles)
+    };
+    let edited = if row.edited_at.is_some() && !row.deleted {
+        r#" <span class="edited">(edited)</span>"#
+    } else {
+        ""
+    };
+    let edit_link = if can_edit && !row.deleted {
+        format!(
+            r#"<a href="{base}/articles/{}/comments/{}/edit" class="quote-link">[edit]</a>"#,
+            article_id, row.id
+        )
+    } else {
+        String::new()
+    };
+    let report_link = if row.deleted {
+        String::new()
+    } else {
+        format!(r#"<a href="{base}/comments/{}/report" class="quote-link">[report]</a>"#, row.id)
+    };
+    let (class, badge) = if row.is_admin && !row.deleted {
+        (
+            "comment official",
+            format!(r#"<span class="official-badge">{}</span>"#, html_escape::encode_text(official_name)),
+        )
+    } else if row.shadow_hidden && show_admin_links {
+        ("comment shadow-hidden", SHADOW_HIDDEN_BADGE.to_string())
+    } else {
+        ("comment", String::new())
+    };
+    // Only imported comments carry an author name
+    let author = match row.author_name.as_deref() {
+        Some(name) if !row.deleted => format!(r#"<span class="comment-author" dir="auto">{}</span>"#, html_escape::encode_text(name)),
+        _ => String::new(),
+    };
+    format!(
+        r#"<div class="{}" id="c{}">{}{}<p dir="auto">{}{}</p>{}{}{}</div>"#,
+        class, row.id, badge, author, content, edited, edit_link, report_link, delete_link
+    )
+}
+
+async fn backlinks_html(pool: &PgPool, article_id: i32) -> String {
+    let base = paths::base();
+    let backlinks = references::backlinks(pool, article_id)
+        .await
+        .unwrap_or_else(|e| {
+            log_error(&format!("Failed to fetch backlinks: {}", e));
+            Vec::new()
+        });
+    if backlinks.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from(r#"<div class="backlinks"><h3>Referenced by</h3><ul>"#);
+    for linked in &backlinks {
+        html.push_str(&format!(
+            r#"<li><a href="{base}/articles/{}" dir="auto">{}</a></li>"#,
+            linked.id,
+            html_escape::encode_text(&linked.title)
+        ));
+    }
+    html.push_str("</ul></div>");
+    html
+}
+
+async fn comment_form_html(req: &HttpRequest, pool: &PgPool, article_id: i32, draft: &str, captcha_field: &str) -> String {
+    let base = paths::base();
+    // Only the super admin may post an official reply. Staff sessions never
+    // get the cached copy, so no one else is served this checkbox.
+    let super_admin = admin::current_staff(req, pool).await.is_some_and(|staff| staff.is_super_admin());
+    let official_reply = if super_admin {
+        r#"<label class="official-reply"><input type="checkbox" name="official" value="1"> Official reply</label>"#
+    } else {
+        ""
+    };
+    format!(
+        r#"
+        <form action="{base}/articles/{}/comment" method="POST" id="comment-form">
+            {}
+            {}
+            <textarea name="comment" rows="4" required>{}</textarea><br>
+            {}
+            {}
+            <input type="submit" value="Submit Comment">
+        </form>
+        {}
+    "#,
+        article_id,
+        csrf::field(req),
+        honeypot::field(),
+        html_escape::encode_text(draft),
+        captcha_field,
+        official_reply,
+        email_reply_hint(article_id)
+    )
+}
+
+fn email_reply_hint(article_id: i32) -> String {
+    match mail_gateway::reply_address(article_id) {
+        Some(address) => format!(
+            r#"<p class="email-reply">Or comment by email: <a href="mailto:{}">{}</a></p>"#,
+            address, address
+        ),
+        None => String::new(),
+    }
+}
+
+// Without JS there is no text selection to read, so quoting goes through a
+// page listing the body's sentences, each linking back with `?quote=`
+async fn quote_article(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+
+    let body: Option<String> = match sqlx::query_scalar(&format!(
+        "SELECT body FROM articles WHERE id = $1 AND {}",
+        visibility::shown_articles_to("articles", &visibility::Viewer::of(&req, pool.get_ref()).await)
+    ))
+    .bind(article_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(b) => b,
+        Err(e) => {
+            log_error(&format!("Failed to fetch article for quoting: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load article");
+        }
+    };
+    let body = match body {
+        Some(b) => b,
+        None => return HttpResponse::NotFound().body("Article not found"),
+    };
+
+    let settings = settings_cache.get(pool.get_ref()).await;
+    let max_chars = settings.max_quote_chars as usize;
+    let mut fragments_html = String::new();
+    for fragment in text::quote_fragments(&body) {
+        if fragment.chars().count() > max_chars {
+            continue;
+        }
+        fragments_html.push_str(&format!(
+            r##"<li><a href="{base}/articles/{}?quote={}#comment-form">{}</a></li>"##,
+            article_id,
+            utf8_percent_encode(fragment, NON_ALPHANUMERIC),
+            html_escape::encode_text(fragment)
+        ));
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Quote Article</title>
+        {}
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles/{}">← Back to Article</a></div>
+        <div class="article">
+        <h2>Choose a Sentence to Quote</h2>
+        <ul>{}</ul>
+        </div>
+        </body>
+        </html>
+        "#,
+        oembed::canonical_link(&req, &settings, article_id),
+        article_id,
+        fragments_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// Trims an article's title and body, balances their bidi controls and
+// checks them against the configured limits (0 is no limit)
+fn validate_article(settings: &Settings, title: &str, body: &str) -> Result<(String, String), String> {
+    let title = text::balance_bidi(title);
+    let body = text::balance_bidi(body);
+    let title = title.trim();
+    let body = body.trim();
+    if title.is_empty() {
+        return Err("Title cannot be empty.".to_string());
+    }
+    if body.is_empty() {
+        return Err("Body cannot be empty.".to_string());
+    }
+    let max_title = settings.max_title_chars as usize;
+    if max_title > 0 && title.chars().count() > max_title {
+        return Err(format!("Title is too long (maximum {} characters).", max_title));
+    }
+    let max_body = settings.max_body_chars as usize;
+    if max_body > 0 && body.chars().count() > max_body {
+        return Err(format!("Body is too long (maximum {} characters).", max_body));
+    }
+    Ok((title.to_string(), body.to_string()))
+}
+
+// Trims a comment, balances its bidi controls and checks it against
+// max_comment_chars (0 is no limit)
+fn validate_comment(settings: &Settings, comment: &str) -> Result<String, String> {
+    let comment = text::balance_bidi(comment);
+    let comment = comment.trim();
+    if comment.is_empty() {
+        return Err("Comment cannot be empty.".to_string());
+    }
+    let max = settings.max_comment_chars as usize;
+    let length = comment.chars().count();
+    if max > 0 && length > max {
+        return Err(format!("Comment is too long: {} characters, the maximum is {}.", length, max));
+    }
+    Ok(comment.to_string())
+}
+
+async fn fetch_article_media(pool: &PgPool, article_id: i32) -> Result<Vec<ArticleMedia>, sqlx::Error> {
+    sqlx::query_as::<_, ArticleMedia>(
+        "SELECT id, media_path, mime_type, alt_text, caption, media_integrity, webp_path, avif_path FROM article_media WHERE article_id = $1 ORDER BY id",
+    )
+    .bind(article_id)
+    .fetch_all(pool)
+    .await
+}
+
+// Whether the article already has a file with this content hash
+async fn attached_hash(pool: &PgPool, article_id: i32, hash: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM article_media WHERE article_id = $1 AND content_hash = $2)")
+        .bind(article_id)
+        .bind(hash)
+        .fetch_one(pool)
+        .await
+}
+
+// Used when no alt text has been written for an attachment
+fn fallback_alt(media_path: &str) -> String {
+    let filename = media_path.rsplit('/').next().unwrap_or(media_path);
+    format!("Article image: {}", filename)
+}
+
+fn media_alt(media: &ArticleMedia) -> String {
+    match media.alt_text.as_deref().map(str::trim) {
+        Some(alt) if !alt.is_empty() => alt.to_string(),
+        _ => fallback_alt(&media.media_path),
+    }
+}
+
+// How much of a text attachment is shown inline on the article page
+const TEXT_PREVIEW_BYTES: usize = 16 * 1024;
+
+// Markup for one attachment, chosen by the media type registry
+fn media_html(media: &ArticleMedia) -> String {
+    let caption = media.caption.as_deref().map(str::trim).unwrap_or("");
+    // Flagged by the integrity scan: the file is gone or not the one uploaded
+    if media.media_integrity.is_some() {
+        return format!(
+            r#"<figure><p class="article-media media-unavailable">Media unavailable</p><figcaption>{}</figcaption></figure>"#,
+            html_escape::encode_text(caption)
+        );
+    }
+
+    let media_url = html_escape::encode_double_quoted_attribute(&paths::url(&media.media_path)).into_owned();
+    let mime_type = media.mime_type.as_deref();
+    let alt = html_escape::encode_double_quoted_attribute(&media_alt(media)).into_owned();
+    let element = match types::renderer_for(&media.media_path, mime_type) {
+        Renderer::Video => format!(
+            r#"<video controls class="article-media" aria-label="{}">
+                    <source src="{}" type="{}">
+                    Your browser does not support the video tag.
+                </video>"#,
+            alt,
+            media_url,
+            html_escape::encode_double_quoted_attribute(mime_type.unwrap_or("video/mp4"))
+        ),
+        Renderer::Image => derivatives::picture_html(
+            format!(r#"<img src="{}" alt="{}" class="article-media">"#, media_url, alt),
+            media.webp_path.as_deref(),
+            media.avif_path.as_deref(),
+        ),
+        Renderer::Pdf => format!(
+            r#"<iframe sandbox="" src="{}/media/{}/inline" title="{}" class="article-media media-preview"></iframe>"#,
+            paths::base(),
+            media.id,
+            alt
+        ),
+        Renderer::Text => match media_download::text_preview(&media.media_path, TEXT_PREVIEW_BYTES) {
+            Some((text, truncated)) => format!(
+                r#"<pre class="article-media text-preview" aria-label="{}">{}</pre>{}"#,
+                alt,
+                html_escape::encode_text(&text),
+                if truncated {
+                    format!(
+                        r#"<a href="{}/media/{}/inline" class="quote-link">[view full file]</a>"#,
+                        paths::base(),
+                        media.id
+                    )
+                } else {
+                    String::new()
+                }
+            ),
+            None => format!(r#"<a href="{}" class="article-media" download>Download attachment</a>"#, media_url),
+        },
+        Renderer::Download => format!(
+            r#"<a href="{}" class="article-media" download>Download attachment</a>"#,
+            media_url
+        ),
+    };
+
+    let download = format!(
+        r#"<a href="{}/media/{}/download" class="quote-link">[download]</a>"#,
+        paths::base(),
+        media.id
+    );
+    format!(
+        "<figure>{}<figcaption>{} {}</figcaption></figure>",
+        element,
+        html_escape::encode_text(caption),
+        download
+    )
+}
+
+// How long an article has gone without activity, measured against the settings
+// thresholds. A locked article is closed whatever its age.
+enum ThreadAge {
+    Fresh,
+    Old(i64),    // days since last activity
+    Closed(i64), // days since last activity
+    Locked,
+}
+
+impl ThreadAge {
+    fn takes_comments(&self) -> bool {
+        !matches!(self, ThreadAge::Closed(_) | ThreadAge::Locked)
+    }
+}
+
+fn thread_age(settings: &Settings, bump_time: i64, locked: bool) -> ThreadAge {
+    let days = (Utc::now().timestamp() - bump_time).max(0) / 86_400;
+    if locked {
+        ThreadAge::Locked
+    } else if settings.close_comments_after_days > 0 && days >= settings.close_comments_after_days {
+        ThreadAge::Closed(days)
+    } else if settings.warn_necro_after_days > 0 && days >= settings.warn_necro_after_days {
+        ThreadAge::Old(days)
+    } else {
+        ThreadAge::Fresh
+    }
+}
+
+fn thread_age_notice(settings: &Settings, age: &ThreadAge) -> String {
+    let closes = if settings.close_comments_after_days > 0 {
+        format!(
+            " Comments close after {} days without activity.",
+            settings.close_comments_after_days
+        )
+    } else {
+        String::new()
+    };
+
+    match age {
+        ThreadAge::Fresh => String::new(),
+        ThreadAge::Old(days) => format!(
+            r#"<div class="thread-age-notice">This thread is old: the last activity was {} days ago. Please make sure your comment is still relevant.{}</div>"#,
+            days, closes
+        ),
+        ThreadAge::Closed(days) => format!(
+            r#"<div class="thread-age-notice">Comments closed due to age: the last activity was {} days ago.{}</div>"#,
+            days, closes
+        ),
+        ThreadAge::Locked => r#"<div class="thread-age-notice">Comments on this article are locked.</div>"#.to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn submit_comment(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    blocklist: web::Data<Blocklist>,
+    stale_cache: web::Data<StaleCache>,
+    post_limiter: web::Data<PostLimiter>,
+    path: web::Path<i32>,
+    form: web::Form<CommentForm>,
+) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+    let actor = client::ip_representation(&req);
+    if let Err(res) = csrf::check(&req, &form.csrf_token) {
+        return res.into();
+    }
+    if let Err(res) = post_limiter.check(&req, PostKind::Comment) {
+        return res.into();
+    }
+    // Looks like any other successful post to the sender
+    let trapped = form.other.iter().any(|(name, value)| honeypot::is_field(name) && honeypot::tripped(value));
+    if trapped {
+        honeypot::record_drop(&req, &format!("a comment on article {}", article_id));
+        return HttpResponse::Found()
+            .append_header(("Location", paths::url(&format!("/articles/{}", article_id))))
+            .finish();
+    }
+
+    let article: Option<(i64, Option<i64>)> = match sqlx::query_as(&format!(
+        "SELECT bump_time, locked_at FROM articles WHERE id = $1 AND {}",
+        visibility::shown_articles_to("articles", &visibility::Viewer::of(&req, pool.get_ref()).await)
+    ))
+    .bind(article_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(b) => b,
+        Err(e) => {
+            log_error(&format!("Failed to fetch article for comment: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to store comment.");
+        }
+    };
+
+    // A missing article is the poster's mistake, not a storage failure
+    let (bump_time, locked_at) = match article {
+        Some(article) => article,
+        None => {
+            let html = format!(
+                r#"
+                <!DOCTYPE html>
+                <html lang="en">
+                <head><meta charset="UTF-8"><title>Article Not Found</title>
+                <link rel="stylesheet" href="{base}/static/style.css"></head>
+                <body>
+                <div class="post-form-box">
+                <h2>Article Not Found</h2>
+                <p>The article you were commenting on doesn't exist. It may have been deleted.</p>
+                <a href="{base}/articles">← Back to All Articles</a>
+                </div>
+                </body>
+                </html>
+                "#
+            );
+            return HttpResponse::NotFound().content_type("text/html").body(html);
+        }
+    };
+
+    let settings = settings_cache.get(pool.get_ref()).await;
+    let age = thread_age(&settings, bump_time, locked_at.is_some());
+    if !age.takes_comments() {
+        let html = format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head><meta charset="UTF-8"><title>Comments Closed</title>
+            <link rel="stylesheet" href="{base}/static/style.css"></head>
+            <body>
+            <div class="post-form-box">
+            <h2>Comments Closed</h2>
+            {}
+            <a href="{base}/articles/{}">← Back to Article</a>
+            </div>
+            </body>
+            </html>
+            "#,
+            thread_age_notice(&settings, &age),
+            article_id
+        );
+        return HttpResponse::Forbidden().content_type("text/html").body(html);
+    }
+
+    // Official comments speak as admin_display_name, so only the super admin's
+    // session may post one, and only when asked
+    let official = if form.official.is_some() {
+        match auth.authorize_super_admin(&req, pool.get_ref(), "", "official comment").await {
+            Ok(_) => true,
+            Err(res) => return res,
+        }
+    } else {
+        false
+    };
+
+    let checked = match captcha::check(&req, pool.get_ref(), &form.captcha_token, &form.captcha_answer).await {
+        Ok(()) => validate_comment(&settings, &form.comment),
+        Err(message) => Err(message),
+    };
+    let checked = match checked {
+        Ok(text) => blocklist.screen(pool.get_ref(), "comment", text).await,
+        Err(message) => Err(message),
+    };
+    let text = match checked {
+        Ok(text) => text,
+        Err(message) => {
+            let response = HttpResponse::BadRequest();
+            return comment_not_posted(&req, pool.get_ref(), article_id, &form.comment, &message, response).await;
+        }
+    };
+
+    let shadow_viewer = bans::Shadowed::of(&req);
+    // Staff post without a cooldown
+    let cooldown = if official || admin::current_staff(&req, pool.get_ref()).await.is_some() {
+        None
+    } else {
+        Cooldown::for_request(&req, PostKind::Comment, settings.comment_cooldown_secs)
+    };
+    let new_comment = comments::NewComment {
+        article_id,
+        text: &text,
+        actor: &actor,
+        author_name: None,
+        official,
+        cooldown: cooldown.as_ref(),
+        shadow_viewer: shadow_viewer.as_deref(),
+    };
+    let comment_id = match comments::insert(pool.get_ref(), &new_comment).await {
+        Ok(Inserted::Stored(id)) => id,
+        Ok(Inserted::CoolingDown(wait)) => {
+            let mut response = HttpResponse::TooManyRequests();
+            response.append_header(("Retry-After", wait.to_string()));
+            let message = wait_message("comment", wait);
+            return comment_not_posted(&req, pool.get_ref(), article_id, &form.comment, &message, response).await;
+        }
+        Err(e) => {
+            log_error(&format!("Failed to store comment: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to store comment.");
+        }
+    };
+    stale_cache.article_changed(article_id);
+
+    let mut response = HttpResponse::Found();
+    consent::add_cookie(&req, &mut response, watched::remember_comment(&req, comment_id));
+    if settings.comment_edit_minutes > 0 {
+        response.cookie(comment_edits::edit_cookie(article_id, comment_id, settings.comment_edit_minutes));
+    }
+    match drafts::clear(pool.get_ref(), &req, article_id).await {
+        Ok(Some(cookie)) => consent::add_cookie(&req, &mut response, cookie),
+        Ok(None) => {}
+        Err(e) => log_error(&format!("Failed to clear comment draft: {}", e)),
+    }
+    response
+        .append_header(("Location", paths::url(&format!("/articles/{}", article_id))))
+        .finish()
+}
+
+// Page for a comment that wasn't stored, with the form pre-filled. The text is
+// also kept server-side so it is still there if the user leaves this page.
+async fn comment_not_posted(
+    req: &HttpRequest,
+    pool: &PgPool,
+    article_id: i32,
+    comment: &str,
+    message: &str,
+    mut response: HttpResponseBuilder,
+) -> HttpResponse {
+    let base = paths::base();
+    // Without consent to cookies the form below is still pre-filled
+    if consent::allows_optional(req) {
+        match drafts::stash(pool, req, article_id, comment).await {
+            Ok(cookie) => consent::add_cookie(req, &mut response, cookie),
+            Err(e) => log_error(&format!("Failed to stash comment draft: {}", e)),
+        }
+    }
+    let form = comment_form_html(req, pool, article_id, comment, &captcha::field(req)).await;
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Comment Not Posted</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Comment Not Posted</h2>
+        <p>{}</p>
+        {}
+        <a href="{base}/articles/{}">← Back to Article</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        html_escape::encode_text(message),
+        form,
+        article_id
+    );
+    response.content_type("text/html").body(html)
+}
+
+async fn delete_article_form(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Delete Article</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Enter Password to Delete Article</h2>
+        <form action="{base}/articles/{}/delete" method="POST">
+            {}
+            {}
+            <input type="submit" value="Delete Article">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        article_id,
+        csrf::field(&req),
+        admin::password_field(&req)
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+async fn delete_article(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    stale_cache: web::Data<StaleCache>,
+    feed_cache: web::Data<FeedCache>,
+    path: web::Path<i32>,
+    form: web::Form<PasswordForm>,
+) -> HttpResponse {
+    let article_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::DeleteArticles),
+        "article deletion",
+    )
+    .await
+    {
+        return res;
+    }
+
+    if let Err(e) = delete_article_rows(pool.get_ref(), article_id).await {
+        log_error(&format!("Failed to delete article: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to delete article.");
+    }
+    unlinks::drain_soon(pool.get_ref());
+    stale_cache.article_changed(article_id);
+    feed_cache.articles_changed();
+
+    HttpResponse::Found().append_header(("Location", paths::url("/articles"))).finish()
+}
+
+// Deletes an article (comments and media rows cascade), queues its files for
+// removal and resolves reports of it and its comments
+async fn delete_article_rows(pool: &PgPool, article_id: i32) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    reports::resolve_article(&mut tx, article_id).await?;
+    let media_paths: Vec<String> = sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(article_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    unlinks::enqueue(&mut tx, &media_paths).await?;
+    sqlx::query("DELETE FROM articles WHERE id = $1")
+        .bind(article_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+// Locks or unlocks comments on an article, logged on its timeline. False when
+// there is no such article.
+async fn set_article_locked(pool: &PgPool, article_id: i32, locked: bool, actor: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let locked_at = locked.then(|| Utc::now().timestamp());
+    let updated = sqlx::query("UPDATE articles SET locked_at = $2 WHERE id = $1")
+        .bind(article_id)
+        .bind(locked_at)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    let kind = if locked { EventKind::Locked } else { EventKind::Unlocked };
+    events::record(&mut tx, article_id, kind, actor, None).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// Makes a shadow-hidden article public, bumped as if posted now. False when
+// there is no such shadow-hidden article.
+async fn release_article_row(pool: &PgPool, article_id: i32, actor: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let updated = sqlx::query(
+        "UPDATE articles SET shadow_hidden = FALSE, shadow_viewer = NULL
+         WHERE id = $1 AND shadow_hidden",
+    )
+    .bind(article_id)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+    bumps::record(&mut tx, article_id, bumps::Activity::Released, Utc::now().timestamp()).await?;
+    events::record(&mut tx, article_id, EventKind::Released, actor, None).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// POST /articles/{id}/release
+async fn release_article(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    stale_cache: web::Data<StaleCache>,
+    feed_cache: web::Data<FeedCache>,
+    path: web::Path<i32>,
+    form: web::Form<PasswordForm>,
+) -> HttpResponse {
+    let article_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::EditArticles),
+        "article release",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    match release_article_row(pool.get_ref(), article_id, &staff.username).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body("No shadow-hidden article with that id"),
+        Err(e) => {
+            log_error(&format!("Failed to release article: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to release article.");
+        }
+    }
+    stale_cache.article_changed(article_id);
+    feed_cache.articles_changed();
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url(&format!("/articles/{}", article_id))))
+        .finish()
+}
+
+async fn delete_comment_form(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    let base = paths::base();
+    let comment_id = path.into_inner();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Delete Comment</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Enter Password to Delete Comment</h2>
+        <form action="{base}/comments/{}/delete" method="POST">
+            {}
+            {}
+            <input type="submit" value="Delete Comment">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        comment_id,
+        csrf::field(&req),
+        admin::password_field(&req)
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+async fn delete_comment(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    stale_cache: web::Data<StaleCache>,
+    path: web::Path<i32>,
+    form: web::Form<PasswordForm>,
+) -> HttpResponse {
+    let comment_id = path.into_inner();
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::DeleteComments),
+        "comment deletion",
+    )
+    .await
+    {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    // Comments can't outlive their article, so a missing row means the comment is gone
+    let (article_id, deleted): (i32, bool) =
+        match sqlx::query_as("SELECT article_id, deleted FROM comments WHERE id = $1")
+            .bind(comment_id)
+            .fetch_optional(pool.get_ref())
+            .await
+        {
+            Ok(Some(row)) => row,
+            Ok(None) => return HttpResponse::NotFound().body("Comment not found"),
+            Err(e) => {
+                log_error(&format!("Failed to look up comment for deletion: {}", e));
+                return HttpResponse::InternalServerError().body("Failed to delete comment.");
+            }
+        };
+
+    let tombstone = settings_cache.get(pool.get_ref()).await.tombstone_deleted_comments;
+    // Purging a tombstone can't be undone, so it's kept for the super admin,
+    // including tombstones left from before the setting was turned off
+    let purge = staff.is_super_admin();
+    if deleted && !purge {
+        return HttpResponse::Forbidden().body("Only the admin can purge a deleted comment.");
+    }
+    if let Err(e) = remove_comments(pool.get_ref(), &[comment_id], tombstone, purge, &staff.username).await {
+        log_error(&format!("Failed to delete comment: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to delete comment.");
+    }
+    stale_cache.article_changed(article_id);
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url(&format!("/articles/{}", article_id))))
+        .finish()
+}
+
+// Deletes comments, or with `tombstone` blanks them and marks them deleted so
+// the thread keeps its shape. Comments that are already tombstones are purged
+// when `purge` is set and left alone otherwise, whatever `tombstone` says. Each removal goes on its
+// article's timeline under `actor`. Returns the articles that lost a comment.
+async fn remove_comments(
+    pool: &PgPool,
+    ids: &[i32],
+    tombstone: bool,
+    purge: bool,
+    actor: &str,
+) -> Result<Vec<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let removed: Vec<(i32, i32)> = if !tombstone {
+        sqlx::query_as("DELETE FROM comments WHERE id = ANY($1) AND (NOT deleted OR $2) RETURNING id, article_id")
+            .bind(ids)
+            .bind(purge)
+            .fetch_all(&mut *tx)
+            .await?
+    } else {
+        let mut purged: Vec<(i32, i32)> = if purge {
+            sqlx::query_as("DELETE FROM comments WHERE id = ANY($1) AND deleted RETURNING id, article_id")
+                .bind(ids)
+                .fetch_all(&mut *tx)
+                .await?
+        } else {
+            Vec::new()
+        };
+        let blanked: Vec<(i32, i32)> = sqlx::query_as(
+            "UPDATE comments SET comment = '', deleted = TRUE WHERE id = ANY($1) AND NOT deleted RETURNING id, article_id",
+        )
+        .bind(ids)
+        .fetch_all(&mut *tx)
+        .await?;
+        purged.extend(blanked);
+        purged
+    };
+    for (comment_id, article_id) in &removed {
+        let detail = format!("comment #{}", comment_id);
+        events::record(&mut tx, *article_id, EventKind::CommentDeleted, actor, Some(&detail)).await?;
+    }
+    let article_ids: Vec<i32> = removed.iter().map(|(_, article_id)| *article_id).collect();
+    comments::refresh_counts(&mut tx, &article_ids).await?;
+    let comment_ids: Vec<i32> = removed.iter().map(|(comment_id, _)| *comment_id).collect();
+    reports::resolve_comments(&mut tx, &comment_ids).await?;
+
+    if !tombstone {
+        tx.commit().await?;
+        return Ok(article_ids);
+    }
+
+    // The blanked text no longer references or replies to anything
+    sqlx::query("DELETE FROM article_links WHERE source_comment_id = ANY($1)")
+        .bind(ids)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM comment_replies WHERE source_comment_id = ANY($1)")
+        .bind(ids)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(article_ids)
+}
+
+async fn edit_article_form(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    let base = paths::base();
+    let article_id = path.into_inner();
+    // Include enctype here as well to ensure multipart form submission.
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Edit Article</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Enter Password to Edit Article</h2>
+        <form action="{base}/articles/{}/edit" method="POST" enctype="multipart/form-data">
+            {}
+            {}
+            <input type="hidden" name="mode" value="check">
+            <input type="submit" value="Continue">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        article_id,
+        csrf::field(&req),
+        admin::password_field(&req)
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// What an editor typed, for showing the edit form again after a rejected save
+struct EditDraft<'a> {
+    title: &'a str,
+    body: &'a str,
+    format: Option<BodyFormat>,
+    media_text: &'a HashMap<i32, (String, String)>,
+}
+
+// The edit form, filled from the stored article or from `draft`
+async fn edit_form_html(
+    req: &HttpRequest,
+    pool: &PgPool,
+    staff: &admin::Staff,
+    article_id: i32,
+    notice: &str,
+    draft: Option<&EditDraft<'_>>,
+) -> Result<String, Error> {
+    let base = paths::base();
+    let article = sqlx::query_as::<_, DbArticle>(
+        "SELECT id, title, body, body_format, bump_time, created_at, locked_at, shadow_hidden FROM articles WHERE id = $1",
+    )
+    .bind(article_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        log_error(&format!("Failed to fetch article for editing: {}", e));
+        ErrorInternalServerError("Failed to fetch article")
+    })?
+    .ok_or_else(|| ErrorNotFound("Article not found"))?;
+
+    let media = fetch_article_media(pool, article_id).await.map_err(|e| {
+        log_error(&format!("Failed to fetch media for editing: {}", e));
+        ErrorInternalServerError("Failed to fetch media")
+    })?;
+
+    let (title, body, format) = match draft {
+        Some(d) => (
+            d.title,
+            d.body,
+            d.format.unwrap_or_else(|| body_format::from_column(&article.body_format)),
+        ),
+        None => (
+            article.title.as_str(),
+            article.body.as_str(),
+            body_format::from_column(&article.body_format),
+        ),
+    };
+
+    let mut current_media = String::new();
+    for item in &media {
+        let typed = draft.and_then(|d| d.media_text.get(&item.id));
+        let alt = typed.map_or(item.alt_text.as_deref().unwrap_or(""), |t| t.0.as_str());
+        let caption = typed.map_or(item.caption.as_deref().unwrap_or(""), |t| t.1.as_str());
+        current_media.push_str(&format!(
+            r#"<div class="media-edit">
+                <img src="{}" alt="{}" class="media-edit-thumb"><br>
+                Alt text (blank: "{}"):<br>
+                <input type="text" name="alt_{}" value="{}"><br>
+                Caption (optional):<br>
+                <input type="text" name="caption_{}" value="{}"><br>
+            </div>"#,
+            html_escape::encode_double_quoted_attribute(&paths::url(&item.media_path)),
+            html_escape::encode_double_quoted_attribute(&media_alt(item)),
+            html_escape::encode_text(&fallback_alt(&item.media_path)),
+            item.id,
+            html_escape::encode_double_quoted_attribute(alt),
+            item.id,
+            html_escape::encode_double_quoted_attribute(caption)
+        ));
+    }
+
+    Ok(format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Edit Article</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Edit Article</h2>
+        {}
+        <form action="{base}/articles/{}/edit" method="POST" enctype="multipart/form-data">
+            {}
+            <input type="hidden" name="edit_token" value="{}">
+            <input type="hidden" name="mode" value="save">
+            <input type="text" name="title" value="{}" required><br>
+            <textarea name="body" rows="10" required>{}</textarea><br>
+            {}
+            Current Media: <br>
+            {}<br>
+            Replace Media (optional, clears alt text and caption): <br>
+            <input type="file" name="media" accept="{}"><br><br>
+            <input type="submit" value="Save Changes">
+            <input type="submit" name="preview" value="Preview Changes">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        notice,
+        article_id,
+        csrf::field(req),
+        html_escape::encode_double_quoted_attribute(&admin::edit_token(staff, article_id)),
+        html_escape::encode_double_quoted_attribute(title),
+        html_escape::encode_text(body),
+        body_format::select_html(format),
+        current_media,
+        types::accept_attribute()
+    ))
+}
+
+// CSRF check for the edit form. Saving always needs the token; opening the
+// form may be done by password alone, as for other admin forms.
+fn edit_csrf(auth: &AdminAuth, req: &HttpRequest, mode: &str, token: &str, password: &str) -> Result<(), csrf::Rejected> {
+    if mode == "save" {
+        csrf::check(req, token)
+    } else {
+        auth.check_csrf(req, token, password)
+    }
+}
+
+// Text fields the edit form and its preview send, the same way
+fn is_edit_field(name: &str) -> bool {
+    let media_field = name
+        .strip_prefix("alt_")
+        .or_else(|| name.strip_prefix("caption_"))
+        .is_some_and(|id| id.parse::<i32>().is_ok());
+    media_field
+        || [
+            csrf::FIELD_NAME,
+            "password",
+            "edit_token",
+            "mode",
+            "preview",
+            "back",
+            "staged_media",
+            "title",
+            "body",
+            "body_format",
+        ]
+        .contains(&name)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn edit_article(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    blocklist: web::Data<Blocklist>,
+    stale_cache: web::Data<StaleCache>,
+    feed_cache: web::Data<FeedCache>,
+    upload_scanner: web::Data<UploadScanner>,
+    upload_limiter: web::Data<UploadLimiter>,
+    path: web::Path<i32>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let upload_permit = match upload_limiter.try_acquire(&req) {
+        Ok(permit) => permit,
+        Err(busy) => return Ok(busy.into()),
+    };
+
+    let article_id = path.into_inner();
+    let mut csrf_token = String::new();
+    let mut password = String::new();
+    let mut edit_token = String::new();
+    let mut mode = String::new();
+    let mut preview = false;
+    let mut back = false;
+    let mut staged_media = String::new();
+    let mut new_title = String::new();
+    let mut new_body = String::new();
+    let mut new_format = None;
+    let mut new_media: Option<FormMedia> = None;
+    let mut skipped = Vec::new();
+    let mut media_text: HashMap<i32, (String, String)> = HashMap::new(); // media id -> (alt, caption)
+    let fail_open = settings_cache.get(pool.get_ref()).await.scan_fail_open;
+
+    while let Some(item) = upload_permit.within_deadline(payload.next()).await? {
+        let mut field = item.map_err(|e| {
+            log_error(&format!("Error reading edit form field: {}", e));
+            ErrorInternalServerError("Multipart read error")
+        })?;
+        upload_permit.next_field()?;
+
+        let cd = match field.content_disposition() {
+            Some(cd) => cd,
+            None => {
+                log_error("Missing content disposition in edit article field");
+                return Err(ErrorInternalServerError("Missing content disposition"));
+            }
+        };
+
+        let field_name = match cd.get_name() {
+            Some(n) => n.to_string(),
+            None => {
+                log_error("Missing field name in edit article form");
+                return Err(ErrorInternalServerError("Missing field name"));
+            }
+        };
+
+        let filename = cd.get_filename().map(|f| f.to_string());
+        let is_file = field_name == "media";
+        if !is_file && !is_edit_field(&field_name) {
+            upload_permit.skip(&mut field).await?;
+            continue;
+        }
+        // As in submit_article, the fields that pass the check come before the file
+        if is_file {
+            if let Err(res) = edit_csrf(&auth, &req, &mode, &csrf_token, &password) {
+                if let Some(media) = new_media {
+                    if let Err(e) = discard_uploads(pool.get_ref(), vec![media]).await {
+                        log_error(&format!("Failed to queue rejected upload for removal: {}", e));
+                    }
+                }
+                return Ok(res.into());
+            }
+        }
+
+        let mut value = Vec::new();
+        while let Some(chunk) = upload_permit.within_deadline(field.next()).await? {
+            let chunk = chunk.map_err(|e| {
+                log_error(&format!("Error reading chunk in edit form: {}", e));
+                ErrorInternalServerError("Error reading chunk")
+            })?;
+            if is_file {
+                upload_permit.receive(value.len(), chunk.len())?;
+            } else {
+                upload_permit.receive_text(value.len(), chunk.len())?;
+            }
+            value.extend_from_slice(&chunk);
+        }
+
+        if field_name == csrf::FIELD_NAME {
+            csrf_token = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "password" {
+            password = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "edit_token" {
+            edit_token = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "mode" {
+            mode = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "preview" {
+            preview = true;
+        } else if field_name == "back" {
+            back = true;
+        } else if field_name == "staged_media" {
+            staged_media = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "title" {
+            new_title = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "body" {
+            new_body = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "body_format" {
+            match BodyFormat::parse(&String::from_utf8_lossy(&value)) {
+                Some(f) => new_format = Some(f),
+                None => return Ok(HttpResponse::BadRequest().body("Unknown body format")),
+            }
+        } else if let Some(id) = field_name.strip_prefix("alt_").and_then(|id| id.parse().ok()) {
+            media_text.entry(id).or_default().0 = String::from_utf8(value).unwrap_or_default();
+        } else if let Some(id) = field_name.strip_prefix("caption_").and_then(|id| id.parse().ok()) {
+            media_text.entry(id).or_default().1 = String::from_utf8(value).unwrap_or_default();
+        } else if field_name == "media" && !value.is_empty() {
+            if let Some(fname) = filename {
+                let media_type = match detect_media_type(&value, &fname) {
+                    Ok(t) => t,
+                    Err(refused) => return Ok(refused.into()),
+                };
+                let fname = media::clean_filename(&fname, media_type);
+                // A file sent twice, or one the article already has, replaces nothing
+                let hash = media::content_hash(&value);
+                let attached = new_media.as_ref().is_some_and(|m| m.hash == hash)
+                    || attached_hash(pool.get_ref(), article_id, &hash).await.map_err(|e| {
+                        log_error(&format!("Failed to check for duplicate media: {}", e));
+                        ErrorInternalServerError("Database error")
+                    })?;
+                if attached {
+                    skipped.push(fname);
+                    continue;
+                }
+                let media_path = media::hashed_media_path(&hash, media_type);
+                let filepath = format!(".{}", media_path);
+                let scan = match store_scanned_upload(&upload_scanner, fail_open, &value, &filepath).await {
+                    Ok(scan) => scan,
+                    Err(res) => return Ok(res),
+                };
+                new_media = Some(FormMedia {
+                    path: media_path,
+                    original_filename: fname,
+                    mime: Some(media_type.mime),
+                    hash,
+                    scan,
+                });
+            }
+        }
+    }
+
+    // The password opens the form; saving it needs the token the form carries
+    let authorized = match edit_csrf(&auth, &req, &mode, &csrf_token, &password) {
+        Err(res) => Err(res.into()),
+        Ok(()) if mode == "save" => {
+            admin::authorize_edit_token(
+                pool.get_ref(),
+                &edit_token,
+                article_id,
+                Permission::EditArticles,
+                "article editing",
+            )
+            .await
+        }
+        Ok(()) => {
+            auth.authorize(
+                &req,
+                pool.get_ref(),
+                &password,
+                Some(Permission::EditArticles),
+                "article editing",
+            )
+            .await
+        }
+    };
+    let staff = match authorized {
+        Ok(staff) => staff,
+        Err(res) => {
+            // The file was stored while the form was read; a refused request keeps nothing
+            if let Some(media) = new_media {
+                if let Err(e) = discard_uploads(pool.get_ref(), vec![media]).await {
+                    log_error(&format!("Failed to queue rejected upload for removal: {}", e));
+                }
+            }
+            return Ok(res);
+        }
+    };
+
+    if mode == "check" {
+        let html = edit_form_html(&req, pool.get_ref(), &staff, article_id, "", None).await?;
+        return Ok(HttpResponse::Ok().content_type("text/html").body(html));
+    } else if mode == "save" {
+        // A file chosen before previewing was stored then; a new one wins,
+        // and the staged one is let go
+        if !staged_media.is_empty() {
+            match edit_preview::unstage(article_id, &staged_media) {
+                Some(staged) => match &new_media {
+                    None => new_media = Some(staged),
+                    Some(new) if new.path == staged.path => {}
+                    Some(_) => edit_preview::discard(pool.get_ref(), article_id, &staged.path).await.map_err(|e| {
+                        log_error(&format!("Failed to queue replaced upload for removal: {}", e));
+                        ErrorInternalServerError("Database error")
+                    })?,
+                },
+                None => {
+                    return Ok(HttpResponse::Forbidden()
+                        .body("This form has expired or is for another article, please start again"))
+                }
+            }
+        }
+        if back {
+            if let Some(media) = new_media {
+                edit_preview::discard(pool.get_ref(), article_id, &media.path).await.map_err(|e| {
+                    log_error(&format!("Failed to queue discarded upload for removal: {}", e));
+                    ErrorInternalServerError("Database error")
+                })?;
+            }
+            let draft = EditDraft {
+                title: &new_title,
+                body: &new_body,
+                format: new_format,
+                media_text: &media_text,
+            };
+            let html = edit_form_html(&req, pool.get_ref(), &staff, article_id, "", Some(&draft)).await?;
+            return Ok(HttpResponse::Ok().content_type("text/html").body(html));
+        }
+
+        let settings = settings_cache.get(pool.get_ref()).await;
+        let checked = match validate_article(&settings, &new_title, &new_body) {
+            Ok((title, body)) => blocklist.screen_article(pool.get_ref(), title, body).await,
+            Err(message) => Err(message),
+        };
+        let (new_title, new_body) = match checked {
+            Ok(valid) => valid,
+            Err(message) => {
+                // A replacement file isn't kept; the form asks for it again
+                if let Some(media) = new_media {
+                    edit_preview::discard(pool.get_ref(), article_id, &media.path).await.map_err(|e| {
+                        log_error(&format!("Failed to queue discarded upload for removal: {}", e));
+                        ErrorInternalServerError("Database error")
+                    })?;
+                }
+                let draft = EditDraft {
+                    title: &new_title,
+                    body: &new_body,
+                    format: new_format,
+                    media_text: &media_text,
+                };
+                let notice = format!(r#"<div class="thread-age-notice">{}</div>"#, html_escape::encode_text(&message));
+                let html = edit_form_html(&req, pool.get_ref(), &staff, article_id, &notice, Some(&draft)).await?;
+                return Ok(HttpResponse::BadRequest().content_type("text/html").body(html));
+            }
+        };
+
+        if preview {
+            let article = sqlx::query_as::<_, DbArticle>(
+                "SELECT id, title, body, body_format, bump_time, created_at, locked_at, shadow_hidden FROM articles WHERE id = $1",
+            )
+            .bind(article_id)
+            .fetch_optional(pool.get_ref())
+            .await
+            .map_err(|e| {
+                log_error(&format!("Failed to fetch article for preview: {}", e));
+                ErrorInternalServerError("Failed to fetch article")
+            })?
+            .ok_or_else(|| ErrorNotFound("Article not found"))?;
+            if let Some(media) = &new_media {
+                edit_preview::record(pool.get_ref(), article_id, &media.path).await.map_err(|e| {
+                    log_error(&format!("Failed to stage upload: {}", e));
+                    ErrorInternalServerError("Database error")
+                })?;
+            }
+            let html = edit_preview::page(
+                &req,
+                &staff,
+                &edit_preview::Preview {
+                    article_id,
+                    old_title: &article.title,
+                    old_body: &article.body,
+                    old_format: body_format::from_column(&article.body_format),
+                    title: &new_title,
+                    body: &new_body,
+                    format: new_format,
+                    media_text: &media_text,
+                    new_media: new_media.as_ref(),
+                },
+            );
+            return Ok(HttpResponse::Ok().content_type("text/html").body(html));
+        }
+
+        // Update article
+        let mut tx = pool.begin().await.map_err(|e| {
+            log_error(&format!("Failed to start transaction: {}", e));
+            ErrorInternalServerError("Failed to update article")
+        })?;
+
+        // Forms from before the format choice existed keep the stored format
+        let now = Utc::now().timestamp();
+        sqlx::query(
+            "UPDATE articles SET title = $1, body = $2, body_format = COALESCE($3, body_format), updated_at = $4
+             WHERE id = $5",
+        )
+        .bind(&new_title)
+        .bind(&new_body)
+        .bind(new_format.map(BodyFormat::name))
+        .bind(now)
+        .bind(article_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            log_error(&format!("Failed to update article: {}", e));
+            ErrorInternalServerError("Failed to update article")
+        })?;
+        bumps::record(&mut tx, article_id, bumps::Activity::Edited, now).await.map_err(|e| {
+            log_error(&format!("Failed to bump article: {}", e));
+            ErrorInternalServerError("Failed to update article")
+        })?;
+
+        search::index_article(&mut tx, article_id).await.map_err(|e| {
+            log_error(&format!("Failed to index article: {}", e));
+            ErrorInternalServerError("Failed to update article")
+        })?;
+
+        references::record_article_refs(&mut tx, article_id, &new_body).await.map_err(|e| {
+            log_error(&format!("Failed to update article references: {}", e));
+            ErrorInternalServerError("Failed to update article")
+        })?;
+
+        for (media_id, (alt, caption)) in &media_text {
+            let alt = Some(alt.trim()).filter(|a| !a.is_empty());
+            let caption = Some(caption.trim()).filter(|c| !c.is_empty());
+            sqlx::query("UPDATE article_media SET alt_text = $1, caption = $2 WHERE id = $3 AND article_id = $4")
+                .bind(alt)
+                .bind(caption)
+                .bind(media_id)
+                .bind(article_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    log_error(&format!("Failed to update media text: {}", e));
+                    ErrorInternalServerError("Failed to update article")
+                })?;
+        }
+
+        let mut kinds = vec![EventKind::Edited, EventKind::Bumped];
+        let media_replaced = new_media.is_some();
+
+        if let Some(new_media) = new_media {
+            let old_paths: Vec<String> =
+                sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+                    .bind(article_id)
+                    .fetch_all(&mut *tx)
+                    .await
+                    .map_err(|e| {
+                        log_error(&format!("Failed to fetch old media: {}", e));
+                        ErrorInternalServerError("Failed to delete old media")
+                    })?;
+            unlinks::enqueue(&mut tx, &old_paths).await.map_err(|e| {
+                log_error(&format!("Failed to queue old media for removal: {}", e));
+                ErrorInternalServerError("Failed to delete old media")
+            })?;
+
+            sqlx::query("DELETE FROM article_media WHERE article_id = $1")
+                .bind(article_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    log_error(&format!("Failed to delete old media: {}", e));
+                    ErrorInternalServerError("Failed to delete old media")
+                })?;
+
+            sqlx::query(
+                "INSERT INTO article_media
+                     (article_id, media_path, original_filename, mime_type, content_hash, scan_status, scan_duration_ms)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(article_id)
+            .bind(&new_media.path)
+            .bind(new_media.original_filename)
+            .bind(new_media.mime)
+            .bind(new_media.hash)
+            .bind(new_media.scan.status)
+            .bind(new_media.scan.duration_ms)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                log_error(&format!("Failed to store new media: {}", e));
+                ErrorInternalServerError("Failed to store new media")
+            })?;
+            unlinks::hold(&mut tx, &[new_media.path.as_str()]).await.map_err(|e| {
+                log_error(&e);
+                ErrorInternalServerError("Failed to store new media")
+            })?;
+            edit_preview::forget(&mut tx, article_id, &new_media.path).await.map_err(|e| {
+                log_error(&format!("Failed to clear staged upload: {}", e));
+                ErrorInternalServerError("Failed to store new media")
+            })?;
+
+            kinds.push(EventKind::MediaReplaced);
+        }
+
+        for kind in kinds {
+            events::record(&mut tx, article_id, kind, &staff.username, None)
+                .await
+                .map_err(|e| {
+                    log_error(&format!("Failed to record article event: {}", e));
+                    ErrorInternalServerError("Failed to update article")
+                })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            log_error(&format!("Failed to commit article edit: {}", e));
+            ErrorInternalServerError("Failed to update article")
+        })?;
+        if media_replaced {
+            unlinks::drain_soon(pool.get_ref());
+        }
+        stale_cache.article_changed(article_id);
+        feed_cache.articles_changed();
+
+        return Ok(HttpResponse::Found()
+            .append_header(("Location", article_location(article_id, &skipped)))
+            .finish());
+    }
+
+    log_error("Invalid mode for edit article");
+    Ok(HttpResponse::BadRequest().body("Invalid mode"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(max_comment_chars: i64) -> Settings {
+        Settings {
+            max_comment_chars,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn empty_comment_is_refused() {
+        assert_eq!(validate_comment(&settings(10), ""), Err("Comment cannot be empty.".to_string()));
+    }
+
+    #[test]
+    fn whitespace_only_comment_is_refused() {
+        for comment in [" ", "\n\t \r\n", "\u{0}\u{7}", "\u{202C}\u{2069} "] {
+            assert!(validate_comment(&settings(10), comment).is_err(), "{:?}", comment);
+        }
+    }
+
+    #[test]
+    fn comment_at_the_limit_is_accepted() {
+        assert_eq!(validate_comment(&settings(5), "héllo"), Ok("héllo".to_string()));
+        // Surrounding whitespace doesn't count and isn't kept
+        assert_eq!(validate_comment(&settings(5), "  hello\n"), Ok("hello".to_string()));
+    }
+
+    #[test]
+    fn comment_over_the_limit_is_refused() {
+        assert_eq!(
+            validate_comment(&settings(5), "héllo!"),
+            Err("Comment is too long: 6 characters, the maximum is 5.".to_string())
+        );
+    }
+
+    #[test]
+    fn zero_limit_means_no_limit() {
+        let long = "a".repeat(100_000);
+        assert_eq!(validate_comment(&settings(0), &long), Ok(long.clone()));
+    }
+}
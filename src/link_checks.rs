@@ -0,0 +1,272 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{TimeZone, Utc};
+use futures_util::stream::{self, StreamExt as _};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::admin::AdminAuth;
+use crate::{log_error, paths, text};
+
+// Outbound links in article bodies are re-checked slowly in the background.
+// Articles whose links were all healthy wait a week; ones with broken links
+// are retried daily so fixed links drop off the report.
+const HEALTHY_RECHECK_SECS: i64 = 7 * 86_400;
+const BROKEN_RECHECK_SECS: i64 = 86_400;
+const ARTICLES_PER_RUN: i64 = 20;
+const CONCURRENCY: usize = 4;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Upper bound on one run, however many slow hosts it meets
+const RUN_TIMEOUT: Duration = Duration::from_secs(300);
+// Minimum gap between two requests to the same host
+const PER_HOST_GAP: Duration = Duration::from_secs(2);
+
+#[derive(FromRow)]
+struct DueArticle {
+    id: i32,
+    body: String,
+}
+
+struct CheckResult {
+    url: String,
+    status: Option<i32>,
+    error: Option<String>,
+}
+
+// Hands out request slots per host so one site never sees more than one
+// request every PER_HOST_GAP, even with several checks in flight
+#[derive(Default)]
+struct HostThrottle {
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostThrottle {
+    async fn wait(&self, host: &str) {
+        let delay = {
+            let mut slots = match self.next_slot.lock() {
+                Ok(slots) => slots,
+                Err(_) => return,
+            };
+            let now = Instant::now();
+            let slot = slots.get(host).copied().filter(|s| *s > now).unwrap_or(now);
+            slots.insert(host.to_string(), slot + PER_HOST_GAP);
+            slot - now
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_ascii_lowercase))
+}
+
+async fn check_url(client: &reqwest::Client, throttle: &HostThrottle, url: String) -> CheckResult {
+    let host = match host_of(&url) {
+        Some(host) => host,
+        None => {
+            return CheckResult {
+                url,
+                status: None,
+                error: Some("Invalid URL".to_string()),
+            }
+        }
+    };
+
+    throttle.wait(&host).await;
+    let mut response = client.head(&url).send().await;
+
+    // Some servers refuse HEAD outright; ask again with GET before calling it broken
+    if matches!(&response, Ok(r) if r.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED) {
+        throttle.wait(&host).await;
+        response = client.get(&url).send().await;
+    }
+
+    match response {
+        Ok(r) => CheckResult {
+            url,
+            status: Some(i32::from(r.status().as_u16())),
+            error: None,
+        },
+        Err(e) => CheckResult {
+            url,
+            status: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+// One pass of the background job: picks the articles that are due and
+// records the state of every link in them
+pub async fn run(pool: &PgPool) -> Result<usize, String> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::limited(5))
+        .user_agent("articles-link-checker")
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    match tokio::time::timeout(RUN_TIMEOUT, check_due_articles(pool, &client)).await {
+        Ok(result) => result,
+        Err(_) => Err("Link check run timed out".to_string()),
+    }
+}
+
+async fn check_due_articles(pool: &PgPool, client: &reqwest::Client) -> Result<usize, String> {
+    let now = Utc::now().timestamp();
+    let due = sqlx::query_as::<_, DueArticle>(
+        "SELECT a.id, a.body FROM articles a
+         WHERE a.links_checked_at IS NULL
+            OR a.links_checked_at < a.updated_at
+            OR a.links_checked_at < $1
+            OR (a.links_checked_at < $2 AND EXISTS (
+                SELECT 1 FROM link_checks l
+                WHERE l.article_id = a.id AND (l.status IS NULL OR l.status >= 400)
+            ))
+         ORDER BY a.links_checked_at NULLS FIRST, a.id
+         LIMIT $3",
+    )
+    .bind(now - HEALTHY_RECHECK_SECS)
+    .bind(now - BROKEN_RECHECK_SECS)
+    .bind(ARTICLES_PER_RUN)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch articles for link checks: {}", e))?;
+
+    let throttle = HostThrottle::default();
+    for article in &due {
+        let urls: Vec<String> = text::outbound_urls(&article.body).into_iter().collect();
+        let results: Vec<CheckResult> = stream::iter(urls)
+            .map(|url| check_url(client, &throttle, url))
+            .buffer_unordered(CONCURRENCY)
+            .collect()
+            .await;
+
+        save_results(pool, article.id, &results)
+            .await
+            .map_err(|e| format!("Failed to save link checks for article {}: {}", article.id, e))?;
+    }
+
+    Ok(due.len())
+}
+
+async fn save_results(pool: &PgPool, article_id: i32, results: &[CheckResult]) -> Result<(), sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let urls: Vec<&str> = results.iter().map(|r| r.url.as_str()).collect();
+    let mut tx = pool.begin().await?;
+
+    // Links edited out of the body since the last check
+    sqlx::query("DELETE FROM link_checks WHERE article_id = $1 AND NOT (url = ANY($2))")
+        .bind(article_id)
+        .bind(&urls)
+        .execute(&mut *tx)
+        .await?;
+
+    for result in results {
+        sqlx::query(
+            "INSERT INTO link_checks (article_id, url, status, error, checked_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (article_id, url) DO UPDATE
+             SET status = EXCLUDED.status, error = EXCLUDED.error, checked_at = EXCLUDED.checked_at",
+        )
+        .bind(article_id)
+        .bind(&result.url)
+        .bind(result.status)
+        .bind(&result.error)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query("UPDATE articles SET links_checked_at = $1 WHERE id = $2")
+        .bind(now)
+        .bind(article_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+#[derive(FromRow)]
+struct BrokenLink {
+    article_id: i32,
+    title: String,
+    url: String,
+    status: Option<i32>,
+    error: Option<String>,
+    checked_at: i64,
+}
+
+pub async fn broken_links(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Any staff member may read the report
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", None, "link report").await {
+        return res;
+    }
+
+    let links = match sqlx::query_as::<_, BrokenLink>(
+        "SELECT l.article_id, a.title, l.url, l.status, l.error, l.checked_at
+         FROM link_checks l JOIN articles a ON a.id = l.article_id
+         WHERE l.status IS NULL OR l.status >= 400
+         ORDER BY l.article_id DESC, l.url",
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(l) => l,
+        Err(e) => {
+            log_error(&format!("Failed to fetch broken links: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load link report");
+        }
+    };
+
+    let mut rows_html = String::new();
+    for link in &links {
+        let when = Utc
+            .timestamp_opt(link.checked_at, 0)
+            .single()
+            .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let outcome = match (link.status, &link.error) {
+            (Some(status), _) => status.to_string(),
+            (None, Some(error)) => error.clone(),
+            (None, None) => "failed".to_string(),
+        };
+        rows_html.push_str(&format!(
+            r#"<tr><td><a href="{base}/articles/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            link.article_id,
+            html_escape::encode_text(&link.title),
+            html_escape::encode_text(&link.url),
+            html_escape::encode_text(&outcome),
+            when
+        ));
+    }
+    if links.is_empty() {
+        rows_html.push_str(r#"<tr><td colspan="4">No broken links found</td></tr>"#);
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Broken Links</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <h2>Broken Links <span class="badge">{}</span></h2>
+        <table class="activity">
+            <tr><th>Article</th><th>URL</th><th>Result</th><th>Checked</th></tr>
+            {}
+        </table>
+        </body>
+        </html>
+        "#,
+        links.len(),
+        rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
@@ -0,0 +1,170 @@
+use actix_web::{HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{client, log_error};
+
+// Locks a client out of password checks after too many wrong passwords in
+// a window, so the admin and moderator passwords can't be guessed at
+// leisure. The window starts at the first failure and a correct password
+// clears the count. Counts are in memory and start over on restart.
+const DEFAULT_MAX_FAILURES: u64 = 5;
+const DEFAULT_WINDOW_SECS: u64 = 15 * 60;
+
+struct Failures {
+    count: u64,
+    since: Instant,
+}
+
+pub struct LoginLimiter {
+    max_failures: u64,
+    window: Duration,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+// A password check refused during a lockout; a 429 with Retry-After
+pub struct LockedOut {
+    retry_after_secs: u64,
+}
+
+impl From<LockedOut> for HttpResponse {
+    fn from(locked: LockedOut) -> Self {
+        HttpResponse::TooManyRequests()
+            .append_header(("Retry-After", locked.retry_after_secs.to_string()))
+            .body("Too many failed password attempts, please try again later")
+    }
+}
+
+fn number_from_env(var: &str, default: u64) -> u64 {
+    env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+impl LoginLimiter {
+    // LOGIN_MAX_FAILURES and LOGIN_LOCKOUT_SECS override the defaults
+    pub fn from_env() -> Self {
+        LoginLimiter {
+            max_failures: number_from_env("LOGIN_MAX_FAILURES", DEFAULT_MAX_FAILURES),
+            window: Duration::from_secs(number_from_env("LOGIN_LOCKOUT_SECS", DEFAULT_WINDOW_SECS)),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Refuses the check while the client is locked out
+    pub fn check(&self, req: &HttpRequest) -> Result<(), LockedOut> {
+        let ip = client::client_ip(req);
+        let failures = match self.failures.lock() {
+            Ok(f) => f,
+            Err(_) => return Ok(()),
+        };
+        let remaining = match failures.get(&ip) {
+            Some(f) if f.count >= self.max_failures => self.window.checked_sub(f.since.elapsed()),
+            _ => None,
+        };
+        match remaining {
+            Some(left) => {
+                log_error(&format!("Password check refused for {}: too many failed attempts", ip));
+                Err(LockedOut {
+                    retry_after_secs: left.as_secs() + 1,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, req: &HttpRequest) {
+        let ip = client::client_ip(req);
+        if let Ok(mut failures) = self.failures.lock() {
+            let window = self.window;
+            failures.retain(|_, f| f.since.elapsed() < window);
+            let entry = failures.entry(ip).or_insert(Failures {
+                count: 0,
+                since: Instant::now(),
+            });
+            entry.count += 1;
+        }
+    }
+
+    pub fn record_success(&self, req: &HttpRequest) {
+        let ip = client::client_ip(req);
+        if let Ok(mut failures) = self.failures.lock() {
+            failures.remove(&ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn limiter(max_failures: u64) -> LoginLimiter {
+        LoginLimiter {
+            max_failures,
+            window: Duration::from_secs(60),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn request_from(addr: &str) -> HttpRequest {
+        TestRequest::default().peer_addr(addr.parse().unwrap()).to_http_request()
+    }
+
+    #[test]
+    fn locks_out_after_max_failures() {
+        let limiter = limiter(3);
+        let req = request_from("198.51.100.7:4000");
+        for _ in 0..2 {
+            limiter.record_failure(&req);
+            assert!(limiter.check(&req).is_ok());
+        }
+        limiter.record_failure(&req);
+        let res = HttpResponse::from(limiter.check(&req).unwrap_err());
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+        assert!((1..=61).contains(&retry_after));
+    }
+
+    #[test]
+    fn lockout_is_per_client() {
+        let limiter = limiter(1);
+        limiter.record_failure(&request_from("198.51.100.7:4000"));
+        assert!(limiter.check(&request_from("198.51.100.7:4001")).is_err());
+        assert!(limiter.check(&request_from("203.0.113.9:4000")).is_ok());
+    }
+
+    #[test]
+    fn success_resets_the_count() {
+        let limiter = limiter(2);
+        let req = request_from("198.51.100.7:4000");
+        limiter.record_failure(&req);
+        limiter.record_success(&req);
+        limiter.record_failure(&req);
+        assert!(limiter.check(&req).is_ok());
+        limiter.record_failure(&req);
+        assert!(limiter.check(&req).is_err());
+        limiter.record_success(&req);
+        assert!(limiter.check(&req).is_ok());
+    }
+
+    #[test]
+    fn lockout_ends_with_the_window() {
+        let limiter = limiter(1);
+        let req = request_from("198.51.100.7:4000");
+        limiter.record_failure(&req);
+        assert!(limiter.check(&req).is_err());
+        for f in limiter.failures.lock().unwrap().values_mut() {
+            f.since -= limiter.window;
+        }
+        assert!(limiter.check(&req).is_ok());
+        // The expired entry is dropped, so the next failure starts a new count
+        limiter.record_failure(&req);
+        assert_eq!(limiter.failures.lock().unwrap().values().map(|f| f.count).sum::<u64>(), 1);
+    }
+}
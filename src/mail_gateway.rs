@@ -0,0 +1,413 @@
+use actix_web::web;
+use async_imap::types::Fetch;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mail_parser::{Message, MessageParser};
+use sqlx::PgPool;
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::blocklist::Blocklist;
+use crate::degraded::StaleCache;
+use crate::db::comments::{self, Inserted, NewComment};
+use crate::settings::{Settings, SettingsCache};
+use crate::{log_error, text, tokens};
+
+// Comments by email. Each article has a reply address of the form
+// `<local>+<article id>+<signature>@<domain>`, built from
+// MAIL_GATEWAY_ADDRESS. A poller reads the gateway's IMAP mailbox, finds
+// that address among a message's recipients or in its subject, and posts
+// the reply text (quotes and signature stripped) as a comment under the
+// sender's display name. Off unless the address and IMAP login are set.
+const TOKEN_PURPOSE: &str = "mail-reply";
+const ACTOR: &str = "email";
+const FALLBACK_AUTHOR: &str = "Email reply";
+const MAX_AUTHOR_CHARS: usize = 80;
+const DEFAULT_POLL_SECS: u64 = 60;
+
+struct Config {
+    local: String,
+    domain: String,
+    host: String,
+    port: u16,
+    user: String,
+    password: String,
+    mailbox: String,
+    poll: Duration,
+}
+
+static CONFIG: OnceLock<Option<Config>> = OnceLock::new();
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn init_from_env() {
+    let _ = CONFIG.set(config_from_env());
+}
+
+fn config_from_env() -> Option<Config> {
+    let address = var("MAIL_GATEWAY_ADDRESS")?;
+    let (local, domain) = match address.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() => (local, domain),
+        _ => {
+            log_error("MAIL_GATEWAY_ADDRESS is not an email address, the mail gateway is off");
+            return None;
+        }
+    };
+    let (host, user, password) = match (
+        var("MAIL_GATEWAY_IMAP_HOST"),
+        var("MAIL_GATEWAY_IMAP_USER"),
+        var("MAIL_GATEWAY_IMAP_PASSWORD"),
+    ) {
+        (Some(host), Some(user), Some(password)) => (host, user, password),
+        _ => {
+            log_error("MAIL_GATEWAY_ADDRESS is set without the IMAP host, user and password, the mail gateway is off");
+            return None;
+        }
+    };
+    Some(Config {
+        local: local.to_string(),
+        domain: domain.to_string(),
+        host,
+        port: var("MAIL_GATEWAY_IMAP_PORT").and_then(|p| p.parse().ok()).unwrap_or(993),
+        user,
+        password,
+        mailbox: var("MAIL_GATEWAY_MAILBOX").unwrap_or_else(|| "INBOX".to_string()),
+        poll: Duration::from_secs(
+            var("MAIL_GATEWAY_POLL_SECS")
+                .and_then(|s| s.parse().ok())
+                .filter(|s| *s > 0)
+                .unwrap_or(DEFAULT_POLL_SECS),
+        ),
+    })
+}
+
+fn config() -> Option<&'static Config> {
+    CONFIG.get().and_then(Option::as_ref)
+}
+
+// Where replies to `article_id` go, when the gateway is on
+pub fn reply_address(article_id: i32) -> Option<String> {
+    let config = config()?;
+    let id = article_id.to_string();
+    Some(format!(
+        "{}+{}+{}@{}",
+        config.local,
+        id,
+        tokens::sign_short(TOKEN_PURPOSE, &id),
+        config.domain
+    ))
+}
+
+// The article named by the first reply address in `haystack` whose
+// signature checks out
+fn find_article(config: &Config, haystack: &str) -> Result<i32, &'static str> {
+    let haystack = haystack.to_ascii_lowercase();
+    let prefix = format!("{}+", config.local.to_ascii_lowercase());
+    let suffix = format!("@{}", config.domain.to_ascii_lowercase());
+    let mut found = false;
+    for (at, _) in haystack.match_indices(&prefix) {
+        let rest = &haystack[at + prefix.len()..];
+        let token = match rest.find(&suffix) {
+            Some(end) => &rest[..end],
+            None => continue,
+        };
+        let (id, signature) = match token.split_once('+') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let id: i32 = match id.parse() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        found = true;
+        if tokens::verify_short(TOKEN_PURPOSE, &id.to_string(), signature) {
+            return Ok(id);
+        }
+    }
+    Err(if found {
+        "reply address signature doesn't verify"
+    } else {
+        "no reply address"
+    })
+}
+
+// `>>123` is a comment reply here, not a quote
+fn is_quoted(line: &str) -> bool {
+    let line = line.trim_start();
+    match line.strip_prefix(">>") {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => false,
+        _ => line.starts_with('>'),
+    }
+}
+
+// The new text of a reply: everything above the quoted message or the
+// signature, without quoted lines
+fn strip_reply(text: &str) -> String {
+    let mut kept = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line == "--"
+            || line.starts_with("-----Original Message-----")
+            || line.starts_with("Sent from my ")
+            || (line.starts_with("On ") && line.ends_with("wrote:"))
+        {
+            break;
+        }
+        if !is_quoted(line) {
+            kept.push(line);
+        }
+    }
+    kept.join("\n").trim().to_string()
+}
+
+fn sender_name(message: &Message) -> String {
+    let name = message
+        .from()
+        .and_then(|from| from.first())
+        .and_then(|addr| addr.name.as_deref())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(FALLBACK_AUTHOR);
+    let name: String = name.chars().take(MAX_AUTHOR_CHARS).collect();
+    text::balance_bidi(&name).trim().to_string()
+}
+
+pub fn start(
+    pool: PgPool,
+    settings_cache: web::Data<SettingsCache>,
+    blocklist: web::Data<Blocklist>,
+    stale_cache: web::Data<StaleCache>,
+) {
+    let config = match config() {
+        Some(config) => config,
+        None => return,
+    };
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(config.poll);
+        loop {
+            interval.tick().await;
+            if let Err(e) = poll(config, &pool, &settings_cache, &blocklist, &stale_cache).await {
+                log_error(&format!("Mail gateway poll failed: {}", e));
+            }
+        }
+    });
+}
+
+// Handles every message newer than the last one handled. A database error
+// stops the batch unrecorded, so that message is retried on the next poll.
+async fn poll(
+    config: &Config,
+    pool: &PgPool,
+    settings_cache: &SettingsCache,
+    blocklist: &Blocklist,
+    stale_cache: &StaleCache,
+) -> Result<(), String> {
+    let tcp = TcpStream::connect((config.host.as_str(), config.port))
+        .await
+        .map_err(|e| format!("connecting to {}: {}", config.host, e))?;
+    let tls = async_native_tls::TlsConnector::new()
+        .connect(config.host.as_str(), tcp)
+        .await
+        .map_err(|e| format!("TLS with {}: {}", config.host, e))?;
+    let mut session = async_imap::Client::new(tls)
+        .login(&config.user, &config.password)
+        .await
+        .map_err(|(e, _)| format!("login: {}", e))?;
+
+    let mailbox = session
+        .select(&config.mailbox)
+        .await
+        .map_err(|e| format!("selecting {}: {}", config.mailbox, e))?;
+    let uid_validity = i64::from(mailbox.uid_validity.unwrap_or(0));
+    let last_uid: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(uid), 0) FROM mail_gateway_messages WHERE mailbox = $1 AND uid_validity = $2",
+    )
+    .bind(&config.mailbox)
+    .bind(uid_validity)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("reading processed messages: {}", e))?;
+
+    // `n:*` always matches the newest message, even below n
+    let mut uids: Vec<u32> = session
+        .uid_search(format!("UID {}:*", last_uid + 1))
+        .await
+        .map_err(|e| format!("searching {}: {}", config.mailbox, e))?
+        .into_iter()
+        .filter(|uid| i64::from(*uid) > last_uid)
+        .collect();
+    uids.sort_unstable();
+
+    let settings = settings_cache.get(pool).await;
+    for uid in uids {
+        let fetched: Vec<Fetch> = match session.uid_fetch(uid.to_string(), "RFC822").await {
+            Ok(stream) => stream.try_collect().await.map_err(|e| format!("fetching message {}: {}", uid, e))?,
+            Err(e) => return Err(format!("fetching message {}: {}", uid, e)),
+        };
+        let raw = fetched.iter().find_map(|f| f.body()).unwrap_or_default();
+
+        let (outcome, comment_id) = match process(config, pool, &settings, blocklist, stale_cache, uid, raw).await {
+            Ok(Some(id)) => ("posted", Some(id)),
+            Ok(None) => ("rejected", None),
+            Err(e) => {
+                log_error(&format!("Mail gateway: message {} not posted, will retry: {}", uid, e));
+                break;
+            }
+        };
+        if let Err(e) = sqlx::query(
+            "INSERT INTO mail_gateway_messages (mailbox, uid_validity, uid, outcome, comment_id, processed_at)
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING",
+        )
+        .bind(&config.mailbox)
+        .bind(uid_validity)
+        .bind(i64::from(uid))
+        .bind(outcome)
+        .bind(comment_id)
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await
+        {
+            log_error(&format!("Mail gateway: failed to record message {}: {}", uid, e));
+            break;
+        }
+    }
+
+    let _ = session.logout().await;
+    Ok(())
+}
+
+// Posts one message as a comment. Ok(None) means it was rejected, and the
+// reason has been logged.
+async fn process(
+    config: &Config,
+    pool: &PgPool,
+    settings: &Settings,
+    blocklist: &Blocklist,
+    stale_cache: &StaleCache,
+    uid: u32,
+    raw: &[u8],
+) -> Result<Option<i32>, sqlx::Error> {
+    let reject = |reason: &str| log_error(&format!("Mail gateway: message {} rejected: {}", uid, reason));
+
+    let message = match MessageParser::default().parse(raw) {
+        Some(m) => m,
+        None => {
+            reject("not a parseable email");
+            return Ok(None);
+        }
+    };
+    // Vacation replies and bounces
+    if message
+        .header_raw("Auto-Submitted")
+        .is_some_and(|v| !v.trim().eq_ignore_ascii_case("no"))
+    {
+        reject("automatic reply");
+        return Ok(None);
+    }
+
+    let mut haystack = String::new();
+    for header in ["To", "Cc", "Delivered-To", "X-Original-To"] {
+        if let Some(value) = message.header_raw(header) {
+            haystack.push_str(value);
+            haystack.push(' ');
+        }
+    }
+    haystack.push_str(message.subject().unwrap_or(""));
+    let article_id = match find_article(config, &haystack) {
+        Ok(id) => id,
+        Err(reason) => {
+            reject(reason);
+            return Ok(None);
+        }
+    };
+
+    let body = message.body_text(0).map(|body| strip_reply(&body)).unwrap_or_default();
+    let checked = match crate::validate_comment(settings, &body) {
+        Ok(text) => blocklist.screen(pool, "comment", text).await,
+        Err(reason) => Err(reason),
+    };
+    let text = match checked {
+        Ok(text) => text,
+        Err(reason) => {
+            reject(&reason);
+            return Ok(None);
+        }
+    };
+
+    let article: Option<(i64, Option<i64>)> = sqlx::query_as("SELECT bump_time, locked_at FROM articles WHERE id = $1")
+        .bind(article_id)
+        .fetch_optional(pool)
+        .await?;
+    match article {
+        None => {
+            reject(&format!("article {} no longer exists", article_id));
+            return Ok(None);
+        }
+        Some((bump_time, locked_at)) => {
+            if !crate::thread_age(settings, bump_time, locked_at.is_some()).takes_comments() {
+                reject(&format!("comments on article {} are closed", article_id));
+                return Ok(None);
+            }
+        }
+    }
+
+    let author = sender_name(&message);
+    let new_comment = NewComment {
+        article_id,
+        text: &text,
+        actor: ACTOR,
+        author_name: Some(&author),
+        official: false,
+        cooldown: None,
+        shadow_viewer: None,
+    };
+    match comments::insert(pool, &new_comment).await? {
+        Inserted::Stored(comment_id) => {
+            stale_cache.article_changed(article_id);
+            Ok(Some(comment_id))
+        }
+        // Mail is posted without a cooldown, so this can't happen
+        Inserted::CoolingDown(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            local: "replies".to_string(),
+            domain: "example.org".to_string(),
+            host: String::new(),
+            port: 993,
+            user: String::new(),
+            password: String::new(),
+            mailbox: "INBOX".to_string(),
+            poll: Duration::from_secs(DEFAULT_POLL_SECS),
+        }
+    }
+
+    #[test]
+    fn finds_the_article_only_with_a_valid_signature() {
+        tokens::init_from_env();
+        let signature = tokens::sign_short(TOKEN_PURPOSE, "42");
+        let to = format!("Site <Replies+42+{}@Example.org>", signature);
+        assert_eq!(find_article(&config(), &to), Ok(42));
+
+        let forged = format!("replies+43+{}@example.org", signature);
+        assert_eq!(find_article(&config(), &forged), Err("reply address signature doesn't verify"));
+        assert_eq!(find_article(&config(), "replies@example.org"), Err("no reply address"));
+    }
+
+    #[test]
+    fn reply_text_stops_at_the_quote_or_signature() {
+        let reply = "Thanks, agreed.\n>>12 see this one\n> quoted text\n\nOn Mon, 1 Jan 2024, Ann wrote:\n> older";
+        assert_eq!(strip_reply(reply), "Thanks, agreed.\n>>12 see this one");
+        assert_eq!(strip_reply("Short one\n-- \nBob\nSent from my phone"), "Short one");
+    }
+}
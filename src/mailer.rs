@@ -0,0 +1,299 @@
+use actix_web::web;
+use futures_util::future::LocalBoxFuture;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::collections::VecDeque;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::log_error;
+
+// Outgoing email. MAIL_TRANSPORT picks where messages go: "smtp" sends them
+// through SMTP_HOST, "file" writes each one as an .eml file under MAIL_DIR
+// (for development and for checking what would have been sent), and "log",
+// the default, only prints the recipient and subject. Every message counts
+// against one site-wide cap, MAIL_RATE_LIMIT per hour; past it messages are
+// dropped and logged. An incomplete configuration stops the server at startup.
+const DEFAULT_SMTP_PORT: u16 = 587;
+const DEFAULT_MAIL_DIR: &str = "./mail";
+// Sender shown by the log transport when MAIL_FROM is unset
+const DEFAULT_FROM: &str = "articles@localhost";
+const DEFAULT_RATE_LIMIT: usize = 100;
+const RATE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+// A plain-text message. Features build these from their own templates.
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// Anything that can deliver a finished message
+pub trait Mailer {
+    fn send<'a>(&'a self, message: Message) -> LocalBoxFuture<'a, Result<(), String>>;
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, message: Message) -> LocalBoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            self.transport
+                .send(message)
+                .await
+                .map(|_| ())
+                .map_err(|e| format!("SMTP delivery failed: {}", e))
+        })
+    }
+}
+
+pub struct FileMailer {
+    transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, message: Message) -> LocalBoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            self.transport
+                .send(message)
+                .await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to write mail file: {}", e))
+        })
+    }
+}
+
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send<'a>(&'a self, message: Message) -> LocalBoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let to: Vec<String> = message.envelope().to().iter().map(|a| a.to_string()).collect();
+            let subject = message
+                .headers()
+                .get_raw("Subject")
+                .map(str::to_string)
+                .unwrap_or_default();
+            eprintln!("Mail not sent (MAIL_TRANSPORT=log) to {}: {}", to.join(", "), subject);
+            Ok(())
+        })
+    }
+}
+
+// The configured mailer, sender and rate limit. Shared as app data.
+pub struct Outbox {
+    mailer: Box<dyn Mailer + Send + Sync>,
+    from: Mailbox,
+    admin_to: Option<Mailbox>,
+    rate_limit: usize, // 0 turns the limit off
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn mailbox(name: &str, value: &str) -> Result<Mailbox, String> {
+    value
+        .parse()
+        .map_err(|e| format!("{} is not a valid email address ({}): {}", name, value, e))
+}
+
+impl Outbox {
+    // MAIL_FROM is the sender (required for smtp and file), MAIL_ADMIN_TO
+    // gets staff notifications. SMTP also needs SMTP_HOST and takes
+    // SMTP_PORT (default 587, STARTTLS) and SMTP_USER / SMTP_PASSWORD, which
+    // go together.
+    pub fn from_env() -> Result<Self, String> {
+        let transport = var("MAIL_TRANSPORT").unwrap_or_else(|| "log".to_string());
+        let from = match var("MAIL_FROM") {
+            Some(from) => mailbox("MAIL_FROM", &from)?,
+            None if transport == "log" => mailbox("MAIL_FROM", DEFAULT_FROM)?,
+            None => return Err(format!("MAIL_TRANSPORT={} needs MAIL_FROM", transport)),
+        };
+        let admin_to = match var("MAIL_ADMIN_TO") {
+            Some(to) => Some(mailbox("MAIL_ADMIN_TO", &to)?),
+            None => None,
+        };
+
+        let mailer: Box<dyn Mailer + Send + Sync> = match transport.as_str() {
+            "smtp" => {
+                let host = var("SMTP_HOST").ok_or("MAIL_TRANSPORT=smtp needs SMTP_HOST")?;
+                let port = match var("SMTP_PORT") {
+                    Some(port) => port
+                        .parse()
+                        .map_err(|_| format!("SMTP_PORT is not a port number: {}", port))?,
+                    None => DEFAULT_SMTP_PORT,
+                };
+                let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                    .map_err(|e| format!("SMTP_HOST {} can't be used: {}", host, e))?
+                    .port(port);
+                match (var("SMTP_USER"), var("SMTP_PASSWORD")) {
+                    (Some(user), Some(password)) => builder = builder.credentials(Credentials::new(user, password)),
+                    (None, None) => {}
+                    _ => return Err("SMTP_USER and SMTP_PASSWORD must be set together".to_string()),
+                }
+                Box::new(SmtpMailer {
+                    transport: builder.build(),
+                })
+            }
+            "file" => {
+                let dir = var("MAIL_DIR").unwrap_or_else(|| DEFAULT_MAIL_DIR.to_string());
+                std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create MAIL_DIR {}: {}", dir, e))?;
+                Box::new(FileMailer {
+                    transport: AsyncFileTransport::new(dir),
+                })
+            }
+            "log" => Box::new(LogMailer),
+            other => return Err(format!("MAIL_TRANSPORT must be smtp, file or log, not {}", other)),
+        };
+
+        let rate_limit = match var("MAIL_RATE_LIMIT") {
+            Some(limit) => limit
+                .parse()
+                .map_err(|_| format!("MAIL_RATE_LIMIT is not a number: {}", limit))?,
+            None => DEFAULT_RATE_LIMIT,
+        };
+        Ok(Outbox {
+            mailer,
+            from,
+            admin_to,
+            rate_limit,
+            sent: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    // Takes a slot under the hourly cap, or says there is none left
+    fn take_slot(&self) -> bool {
+        if self.rate_limit == 0 {
+            return true;
+        }
+        let mut sent = match self.sent.lock() {
+            Ok(sent) => sent,
+            Err(_) => return false,
+        };
+        let now = Instant::now();
+        while sent.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            sent.pop_front();
+        }
+        if sent.len() >= self.rate_limit {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    pub async fn send(&self, email: &Email) -> Result<(), String> {
+        let to = mailbox("Recipient", &email.to)?;
+        if !self.take_slot() {
+            return Err(format!(
+                "Mail rate limit of {} an hour reached, dropped \"{}\" to {}",
+                self.rate_limit, email.subject, email.to
+            ));
+        }
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject.as_str())
+            .body(email.body.clone())
+            .map_err(|e| format!("Failed to build mail: {}", e))?;
+        self.mailer.send(message).await
+    }
+}
+
+// Sends a notification to MAIL_ADMIN_TO, if it's set, without making the
+// caller wait; failures are logged
+pub fn notify_admins(outbox: &web::Data<Outbox>, subject: String, body: String) {
+    let to = match &outbox.admin_to {
+        Some(to) => to.to_string(),
+        None => return,
+    };
+    let outbox = outbox.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = outbox.send(&Email { to, subject, body }).await {
+            log_error(&e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    // An outbox writing into a fresh directory of its own
+    fn file_outbox(name: &str, rate_limit: usize) -> (Outbox, PathBuf) {
+        let dir = env::temp_dir().join(format!("articles-mail-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let outbox = Outbox {
+            mailer: Box::new(FileMailer {
+                transport: AsyncFileTransport::new(&dir),
+            }),
+            from: "Articles <articles@example.com>".parse().unwrap(),
+            admin_to: None,
+            rate_limit,
+            sent: Mutex::new(VecDeque::new()),
+        };
+        (outbox, dir)
+    }
+
+    fn written(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "eml"))
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    fn email(subject: &str) -> Email {
+        Email {
+            to: "reader@example.com".to_string(),
+            subject: subject.to_string(),
+            body: "A new comment on your article.".to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn file_transport_writes_the_whole_message() {
+        let (outbox, dir) = file_outbox("contents", 0);
+        outbox.send(&email("New comment")).await.unwrap();
+
+        let messages = written(&dir);
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        for line in ["From: Articles <articles@example.com>", "To: reader@example.com", "Subject: New comment"] {
+            assert!(message.contains(line), "no {:?} in {}", line, message);
+        }
+        assert!(message.contains("A new comment on your article."), "{}", message);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn messages_past_the_hourly_cap_are_dropped() {
+        let (outbox, dir) = file_outbox("rate", 2);
+        for n in 0..2 {
+            outbox.send(&email(&format!("Message {}", n))).await.unwrap();
+        }
+        let refused = outbox.send(&email("One too many")).await.unwrap_err();
+        assert!(refused.contains("rate limit of 2 an hour"), "{}", refused);
+        assert_eq!(written(&dir).len(), 2);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn a_bad_recipient_sends_nothing() {
+        let (outbox, dir) = file_outbox("recipient", 0);
+        let mut bad = email("Hello");
+        bad.to = "not an address".to_string();
+        let refused = outbox.send(&bad).await.unwrap_err();
+        assert!(refused.starts_with("Recipient is not a valid email address"), "{}", refused);
+        assert!(written(&dir).is_empty());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,123 @@
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+pub mod types;
+
+use types::MediaType;
+
+// Longest uploaded name kept, in characters, extension included
+const MAX_FILENAME_CHARS: usize = 120;
+
+// Hex-encoded SHA-256 of the file contents
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Width and height for image formats, None for video or unreadable headers
+pub fn dimensions(data: &[u8]) -> Option<(i32, i32)> {
+    imagesize::blob_size(data)
+        .ok()
+        .map(|size| (size.width as i32, size.height as i32))
+}
+
+// Public URL for a file stored under the hashed naming scheme
+pub fn hashed_media_path(hash: &str, media_type: &MediaType) -> String {
+    format!("/uploads/{}.{}", hash, media_type.extension())
+}
+
+// The content hash a hashed_media_path was named after
+pub fn path_hash(media_path: &str) -> Option<&str> {
+    let name = media_path.strip_prefix("/uploads/")?;
+    name.split_once('.').map(|(hash, _)| hash)
+}
+
+// The uploaded name as recorded and offered on download: the last path
+// component only, without control characters or leading and trailing dots
+// and spaces, and capped in length with its extension kept. A name with nothing left
+// (e.g. "..") becomes "upload" plus the type's extension.
+pub fn clean_filename(raw: &str, media_type: &MediaType) -> String {
+    let last = raw.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = last.chars().filter(|c| !c.is_control()).collect();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        return format!("upload.{}", media_type.extension());
+    }
+    if name.chars().count() <= MAX_FILENAME_CHARS {
+        return name.to_string();
+    }
+    let ext = match name.rsplit_once('.') {
+        Some((_, ext)) if !ext.is_empty() && ext.chars().count() <= 16 => ext,
+        _ => "",
+    };
+    let keep = MAX_FILENAME_CHARS - if ext.is_empty() { 0 } else { ext.chars().count() + 1 };
+    let stem: String = name.chars().take(keep).collect();
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem.trim_end_matches('.'), ext)
+    }
+}
+
+// Maps a public /uploads/... URL to its location on disk
+pub fn disk_path(media_path: &str) -> Option<PathBuf> {
+    let name = media_path.strip_prefix("/uploads/")?;
+    if name.is_empty() || name.contains('/') || name.contains("..") {
+        return None;
+    }
+    Some(PathBuf::from("./uploads").join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png() -> &'static MediaType {
+        types::by_mime("image/png").unwrap()
+    }
+
+    #[test]
+    fn keeps_only_the_last_path_component() {
+        assert_eq!(clean_filename("../../etc/passwd", png()), "passwd");
+        assert_eq!(clean_filename("..\\..\\windows\\win.ini", png()), "win.ini");
+        assert_eq!(clean_filename("/tmp/../", png()), "upload.png");
+    }
+
+    #[test]
+    fn empty_names_get_a_default() {
+        assert_eq!(clean_filename("", png()), "upload.png");
+        assert_eq!(clean_filename("\u{0}\u{7}\n", png()), "upload.png");
+    }
+
+    #[test]
+    fn dots_and_spaces_alone_get_a_default() {
+        for raw in [".", "..", "....", "   ", " . . ", ". .. ."] {
+            assert_eq!(clean_filename(raw, png()), "upload.png", "{:?}", raw);
+        }
+        assert_eq!(clean_filename(" .hidden.png. ", png()), "hidden.png");
+    }
+
+    #[test]
+    fn control_characters_are_dropped() {
+        assert_eq!(clean_filename("a\r\nb\u{1b}[31m.png", png()), "ab[31m.png");
+    }
+
+    #[test]
+    fn long_unicode_names_are_cut_on_a_char_boundary() {
+        let raw = format!("{}.jpeg", "日本語の写真".repeat(40));
+        let clean = clean_filename(&raw, png());
+        assert_eq!(clean.chars().count(), MAX_FILENAME_CHARS);
+        assert!(clean.ends_with(".jpeg"), "{}", clean);
+        assert!(clean.starts_with("日本語の写真"), "{}", clean);
+
+        let raw = "é".repeat(MAX_FILENAME_CHARS + 5);
+        assert_eq!(clean_filename(&raw, png()), "é".repeat(MAX_FILENAME_CHARS));
+        assert_eq!(clean_filename(&"é".repeat(MAX_FILENAME_CHARS), png()).chars().count(), MAX_FILENAME_CHARS);
+    }
+
+    #[test]
+    fn overlong_extension_is_not_kept() {
+        let raw = format!("{}.{}", "a".repeat(MAX_FILENAME_CHARS), "x".repeat(20));
+        let clean = clean_filename(&raw, png());
+        assert_eq!(clean, "a".repeat(MAX_FILENAME_CHARS));
+    }
+}
@@ -0,0 +1,285 @@
+// Every media format the site accepts. Validation, storage and rendering all
+// look types up here, so supporting a new format means adding one entry.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Renderer {
+    Image,
+    Video,
+    // Previewed in a sandboxed frame
+    Pdf,
+    // Previewed as an escaped excerpt
+    Text,
+    Download,
+}
+
+pub struct MediaType {
+    pub mime: &'static str,
+    // First entry is the canonical extension used when storing files
+    pub extensions: &'static [&'static str],
+    pub label: &'static str,
+    pub matches: fn(&[u8]) -> bool,
+    pub renderer: Renderer,
+    // Overrides the site-wide upload size limit when set
+    pub max_size: Option<u64>,
+    // Whether thumbnails and other derivatives can be generated
+    pub derivatives: bool,
+}
+
+impl MediaType {
+    pub fn extension(&self) -> &'static str {
+        self.extensions[0]
+    }
+
+    // Types /media/{id}/inline may serve
+    pub fn previewable(&self) -> bool {
+        matches!(self.renderer, Renderer::Pdf | Renderer::Text)
+    }
+}
+
+pub static MEDIA_TYPES: &[MediaType] = &[
+    MediaType {
+        mime: "image/jpeg",
+        extensions: &["jpg", "jpeg"],
+        label: "jpg",
+        matches: is_jpeg,
+        renderer: Renderer::Image,
+        max_size: None,
+        derivatives: true,
+    },
+    MediaType {
+        mime: "image/png",
+        extensions: &["png"],
+        label: "png",
+        matches: is_png,
+        renderer: Renderer::Image,
+        max_size: None,
+        derivatives: true,
+    },
+    MediaType {
+        mime: "image/gif",
+        extensions: &["gif"],
+        label: "gif",
+        matches: is_gif,
+        renderer: Renderer::Image,
+        max_size: None,
+        derivatives: false,
+    },
+    MediaType {
+        mime: "image/webp",
+        extensions: &["webp"],
+        label: "webp",
+        matches: is_webp,
+        renderer: Renderer::Image,
+        max_size: None,
+        derivatives: true,
+    },
+    MediaType {
+        mime: "video/mp4",
+        extensions: &["mp4"],
+        label: "MP4",
+        matches: is_mp4,
+        renderer: Renderer::Video,
+        max_size: None,
+        derivatives: false,
+    },
+    MediaType {
+        mime: "application/pdf",
+        extensions: &["pdf"],
+        label: "PDF",
+        matches: is_pdf,
+        renderer: Renderer::Pdf,
+        max_size: None,
+        derivatives: false,
+    },
+    // Last: its check is the loosest, so the binary formats get first pick
+    MediaType {
+        mime: "text/plain",
+        extensions: &["txt"],
+        label: "plain text",
+        matches: is_text,
+        renderer: Renderer::Text,
+        max_size: None,
+        derivatives: false,
+    },
+];
+
+fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xD8, 0xFF])
+}
+
+fn is_png(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG\r\n\x1a\n")
+}
+
+fn is_gif(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+fn is_webp(data: &[u8]) -> bool {
+    data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP"
+}
+
+// AVIF/HEIF files share the ISO box layout with MP4 but are still images
+fn is_mp4(data: &[u8]) -> bool {
+    data.len() >= 12
+        && &data[4..8] == b"ftyp"
+        && !matches!(&data[8..12], b"avif" | b"avis" | b"heic" | b"heix" | b"mif1" | b"msf1")
+}
+
+fn is_pdf(data: &[u8]) -> bool {
+    data.starts_with(b"%PDF-")
+}
+
+const TEXT_SNIFF_BYTES: usize = 8192;
+
+// Openings a browser would take for a page or an image it can script,
+// whatever the file is served as
+const MARKUP_PREFIXES: &[&[u8]] = &[b"<!doctype", b"<html", b"<script", b"<svg"];
+
+// Whether the text starts, after a BOM and whitespace, like HTML or SVG
+fn looks_like_markup(head: &[u8]) -> bool {
+    let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let start = head.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(head.len());
+    let head = &head[start..];
+    MARKUP_PREFIXES
+        .iter()
+        .any(|prefix| head.len() >= prefix.len() && head[..prefix.len()].eq_ignore_ascii_case(prefix))
+}
+
+// UTF-8 without NUL bytes, judged on the first 8 KiB. A character cut off
+// by the 8 KiB boundary doesn't count against it. Markup is refused, so an
+// HTML page or SVG can't be uploaded as a text file.
+fn is_text(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(TEXT_SNIFF_BYTES)];
+    if head.is_empty() || head.contains(&0) || looks_like_markup(head) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() == TEXT_SNIFF_BYTES,
+    }
+}
+
+// Detects the type of a file from its leading bytes
+pub fn sniff(data: &[u8]) -> Option<&'static MediaType> {
+    MEDIA_TYPES.iter().find(|t| (t.matches)(data))
+}
+
+pub fn by_mime(mime: &str) -> Option<&'static MediaType> {
+    MEDIA_TYPES.iter().find(|t| t.mime.eq_ignore_ascii_case(mime))
+}
+
+pub fn by_extension(ext: &str) -> Option<&'static MediaType> {
+    MEDIA_TYPES
+        .iter()
+        .find(|t| t.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+// Best guess for a stored file: the recorded MIME type when there is one,
+// otherwise the extension of the path (rows predating the mime_type column)
+pub fn for_media(media_path: &str, mime: Option<&str>) -> Option<&'static MediaType> {
+    mime.and_then(by_mime).or_else(|| {
+        media_path
+            .rsplit_once('.')
+            .and_then(|(_, ext)| by_extension(ext))
+    })
+}
+
+pub fn renderer_for(media_path: &str, mime: Option<&str>) -> Renderer {
+    for_media(media_path, mime).map_or(Renderer::Download, |t| t.renderer)
+}
+
+// Value for the `accept` attribute of file inputs, e.g. ".jpg,.jpeg,.png"
+pub fn accept_attribute() -> String {
+    MEDIA_TYPES
+        .iter()
+        .flat_map(|t| t.extensions.iter().map(|e| format!(".{}", e)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Human readable list for form hints, e.g. "jpg, png, gif, webp, or MP4"
+pub fn accepted_labels() -> String {
+    let labels: Vec<&str> = MEDIA_TYPES.iter().map(|t| t.label).collect();
+    match labels.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{}, or {}", rest.join(", "), last),
+        Some((last, _)) => last.to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_extension_and_mime_finds_its_type() {
+        for t in MEDIA_TYPES {
+            assert_eq!(by_mime(t.mime).map(|found| found.mime), Some(t.mime));
+            for ext in t.extensions {
+                assert_eq!(by_extension(ext).map(|found| found.mime), Some(t.mime), "{}", ext);
+                assert_eq!(by_extension(&ext.to_uppercase()).map(|found| found.mime), Some(t.mime), "{}", ext);
+                assert!(accept_attribute().split(',').any(|a| a == format!(".{}", ext)), "{}", ext);
+            }
+        }
+        assert!(by_extension("exe").is_none());
+        assert!(by_mime("application/x-msdownload").is_none());
+    }
+
+    #[test]
+    fn magic_bytes_pick_the_type() {
+        for (data, mime) in [
+            (&b"\xFF\xD8\xFF\xE0rest"[..], "image/jpeg"),
+            (b"\x89PNG\r\n\x1a\nrest", "image/png"),
+            (b"GIF89a rest", "image/gif"),
+            (b"RIFF\0\0\0\0WEBPVP8 ", "image/webp"),
+            (b"\0\0\0\x18ftypisom", "video/mp4"),
+            (b"%PDF-1.7", "application/pdf"),
+        ] {
+            assert_eq!(sniff(data).map(|t| t.mime), Some(mime));
+        }
+        // AVIF shares MP4's box layout but isn't a video
+        assert_ne!(sniff(b"\0\0\0\x18ftypavif\0\0\0\0").map(|t| t.mime), Some("video/mp4"));
+    }
+
+    #[test]
+    fn stored_mime_wins_over_the_extension() {
+        assert_eq!(for_media("uploads/a.png", Some("image/gif")).map(|t| t.mime), Some("image/gif"));
+        assert_eq!(for_media("uploads/a.mp4", None).map(|t| t.renderer), Some(Renderer::Video));
+        assert_eq!(renderer_for("uploads/a.bin", None), Renderer::Download);
+    }
+
+    #[test]
+    fn labels_read_as_a_list() {
+        let labels = accepted_labels();
+        assert!(labels.starts_with("jpg, png"), "{}", labels);
+        assert!(labels.ends_with(", or plain text"), "{}", labels);
+    }
+
+    #[test]
+    fn plain_text_is_text() {
+        assert!(is_text(b"Meeting notes\nbring <snacks>\n"));
+        assert!(is_text("caf\u{e9} <br> later in the line".as_bytes()));
+    }
+
+    #[test]
+    fn markup_is_not_text() {
+        for data in [
+            &b"<!DOCTYPE html><p>hi"[..],
+            b"<html><body>",
+            b"  \n\t<ScRiPt>alert(1)</script>",
+            b"\xEF\xBB\xBF<svg xmlns=\"http://www.w3.org/2000/svg\">",
+            b"\xEF\xBB\xBF \r\n<!doctype html>",
+        ] {
+            assert!(!is_text(data), "{:?}", String::from_utf8_lossy(data));
+            assert!(sniff(data).is_none());
+        }
+    }
+
+    #[test]
+    fn binary_is_not_text() {
+        assert!(!is_text(b""));
+        assert!(!is_text(b"abc\0def"));
+        assert!(!is_text(b"\xff\xfe broken"));
+    }
+}
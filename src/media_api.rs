@@ -0,0 +1,328 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::stream::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::events::{self, EventKind};
+use crate::media::{self, types};
+use crate::scan::{ScanRecord, UploadScanner};
+use crate::settings::SettingsCache;
+use crate::upload_limits::UploadLimiter;
+use crate::{log_error, store_scanned_upload, unlinks};
+
+// Token-authenticated endpoints for automation. Disabled unless API_TOKEN
+// is set; clients send it as `Authorization: Bearer <token>`.
+static API_TOKEN_HASH: OnceLock<Option<String>> = OnceLock::new();
+
+// Actor recorded on article events for changes made through the API
+pub const API_ACTOR: &str = "api";
+
+pub fn init_from_env() {
+    let token = env::var("API_TOKEN")
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+        .map(|t| media::content_hash(t.trim().as_bytes()));
+    let _ = API_TOKEN_HASH.set(token);
+}
+
+// Compares hashes rather than the raw token so the comparison time says
+// nothing about how much of a guess was right. Admin routes accept the
+// token too (admin::AdminAuth).
+pub fn has_valid_token(req: &HttpRequest) -> bool {
+    let expected = match API_TOKEN_HASH.get().and_then(Option::as_ref) {
+        Some(hash) => hash,
+        None => return false,
+    };
+    req.headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| media::content_hash(t.trim().as_bytes()))
+        .is_some_and(|hash| hash == *expected)
+}
+
+// Why an API request was turned away
+enum Refused {
+    Disabled,
+    BadToken,
+}
+
+impl From<Refused> for HttpResponse {
+    fn from(refused: Refused) -> Self {
+        match refused {
+            Refused::Disabled => HttpResponse::NotFound().body("API is disabled"),
+            Refused::BadToken => HttpResponse::Unauthorized()
+                .append_header(("WWW-Authenticate", "Bearer"))
+                .json(ApiError { error: "invalid or missing token" }),
+        }
+    }
+}
+
+fn authorized(req: &HttpRequest) -> Result<(), Refused> {
+    if API_TOKEN_HASH.get().and_then(Option::as_ref).is_none() {
+        return Err(Refused::Disabled);
+    }
+    if has_valid_token(req) {
+        Ok(())
+    } else {
+        Err(Refused::BadToken)
+    }
+}
+
+#[derive(Serialize)]
+struct ApiError {
+    error: &'static str,
+}
+
+fn api_error(mut builder: actix_web::HttpResponseBuilder, error: &'static str) -> HttpResponse {
+    builder.json(ApiError { error })
+}
+
+#[derive(Deserialize)]
+pub struct ReplaceQuery {
+    replace_all: Option<String>,
+}
+
+// A stored upload waiting to be attached
+struct NewMedia {
+    path: String,
+    original_filename: String,
+    mime: &'static str,
+    hash: String,
+    size: i64,
+    width: Option<i32>,
+    height: Option<i32>,
+    scan: ScanRecord,
+}
+
+#[derive(Serialize)]
+struct MediaJson {
+    id: i32,
+    path: String,
+    mime: &'static str,
+    size: i64,
+    width: Option<i32>,
+    height: Option<i32>,
+}
+
+// Queues a stored upload that never got attached for cleanup
+async fn discard_upload(pool: &PgPool, media_path: &str) {
+    let result = match pool.acquire().await {
+        Ok(mut conn) => unlinks::enqueue(&mut conn, &[media_path.to_string()]).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error(&format!("Failed to queue unused upload for removal: {}", e));
+    }
+}
+
+// PUT /api/articles/{id}/media: swaps the article's media for the uploaded
+// file without touching its title, body or bump time. Articles with several
+// media items are refused with 409 unless ?replace_all=1.
+#[allow(clippy::too_many_arguments)]
+pub async fn replace_media(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    upload_scanner: web::Data<UploadScanner>,
+    upload_limiter: web::Data<UploadLimiter>,
+    path: web::Path<i32>,
+    query: web::Query<ReplaceQuery>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    if let Err(res) = authorized(&req) {
+        return res.into();
+    }
+    let upload_permit = match upload_limiter.try_acquire(&req) {
+        Ok(permit) => permit,
+        Err(busy) => return busy.into(),
+    };
+
+    let article_id = path.into_inner();
+    let replace_all = query.replace_all.as_deref() == Some("1");
+
+    // Exactly one file field is accepted
+    let mut data: Option<(String, Vec<u8>)> = None;
+    loop {
+        let item = match upload_permit.within_deadline(payload.next()).await {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => return e.error_response(),
+        };
+        let mut field = match item {
+            Ok(f) => f,
+            Err(e) => {
+                log_error(&format!("Error reading API upload field: {}", e));
+                return api_error(HttpResponse::BadRequest(), "malformed multipart body");
+            }
+        };
+        let filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename().map(str::to_string));
+        let mut value = Vec::new();
+        loop {
+            let chunk = match upload_permit.within_deadline(field.next()).await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => return e.error_response(),
+            };
+            match chunk {
+                Ok(chunk) => {
+                    if let Err(e) = upload_permit.receive(value.len(), chunk.len()) {
+                        return e.error_response();
+                    }
+                    value.extend_from_slice(&chunk);
+                }
+                Err(e) => {
+                    log_error(&format!("Error reading API upload chunk: {}", e));
+                    return api_error(HttpResponse::BadRequest(), "malformed multipart body");
+                }
+            }
+        }
+        if let Some(filename) = filename {
+            if data.is_some() {
+                return api_error(HttpResponse::BadRequest(), "exactly one file is accepted");
+            }
+            data = Some((filename, value));
+        }
+    }
+    let (original_filename, data) = match data {
+        Some((name, d)) if !d.is_empty() => (name, d),
+        _ => return api_error(HttpResponse::BadRequest(), "a file is required"),
+    };
+
+    let media_type = match types::sniff(&data) {
+        Some(t) => t,
+        None => return api_error(HttpResponse::UnsupportedMediaType(), "unsupported file type"),
+    };
+    if media_type.max_size.is_some_and(|max| data.len() as u64 > max) {
+        return api_error(HttpResponse::PayloadTooLarge(), "file is too large");
+    }
+
+    let original_filename = media::clean_filename(&original_filename, media_type);
+    let hash = media::content_hash(&data);
+    let media_path = media::hashed_media_path(&hash, media_type);
+    let disk_path = match media::disk_path(&media_path) {
+        Some(p) => p,
+        None => return api_error(HttpResponse::InternalServerError(), "failed to store file"),
+    };
+
+    let fail_open = settings_cache.get(pool.get_ref()).await.scan_fail_open;
+    let scan = match store_scanned_upload(&upload_scanner, fail_open, &data, &disk_path.to_string_lossy()).await {
+        Ok(scan) => scan,
+        Err(res) => return res,
+    };
+
+    let (width, height) = match media::dimensions(&data) {
+        Some((w, h)) => (Some(w), Some(h)),
+        None => (None, None),
+    };
+    let new_media = NewMedia {
+        path: media_path,
+        original_filename,
+        mime: media_type.mime,
+        hash,
+        size: data.len() as i64,
+        width,
+        height,
+        scan,
+    };
+
+    let new_id = match swap_media(pool.get_ref(), article_id, replace_all, &new_media).await {
+        Ok(id) => id,
+        Err(res) => {
+            discard_upload(pool.get_ref(), &new_media.path).await;
+            return res;
+        }
+    };
+
+    HttpResponse::Ok().json(MediaJson {
+        id: new_id,
+        path: new_media.path,
+        mime: new_media.mime,
+        size: new_media.size,
+        width: new_media.width,
+        height: new_media.height,
+    })
+}
+
+// The swap itself, in one transaction holding the article row lock so
+// concurrent replacements of the same article run one after the other.
+// Replaced files are queued for deletion in the same transaction.
+async fn swap_media(
+    pool: &PgPool,
+    article_id: i32,
+    replace_all: bool,
+    new_media: &NewMedia,
+) -> Result<i32, HttpResponse> {
+    let db_error = |e: sqlx::Error| {
+        log_error(&format!("Failed to replace media through the API: {}", e));
+        api_error(HttpResponse::InternalServerError(), "failed to replace media")
+    };
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+
+    let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM articles WHERE id = $1 FOR UPDATE")
+        .bind(article_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    if exists.is_none() {
+        return Err(api_error(HttpResponse::NotFound(), "article not found"));
+    }
+
+    let old_paths: Vec<String> = sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(article_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    if old_paths.len() > 1 && !replace_all {
+        return Err(api_error(
+            HttpResponse::Conflict(),
+            "article has several media items; pass replace_all=1 to replace them all",
+        ));
+    }
+
+    sqlx::query("DELETE FROM article_media WHERE article_id = $1")
+        .bind(article_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let new_id: i32 = sqlx::query_scalar(
+        "INSERT INTO article_media
+            (article_id, media_path, original_filename, mime_type, content_hash, size_bytes, width, height,
+             scan_status, scan_duration_ms)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
+    )
+    .bind(article_id)
+    .bind(&new_media.path)
+    .bind(&new_media.original_filename)
+    .bind(new_media.mime)
+    .bind(&new_media.hash)
+    .bind(new_media.size)
+    .bind(new_media.width)
+    .bind(new_media.height)
+    .bind(new_media.scan.status)
+    .bind(new_media.scan.duration_ms)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    unlinks::hold(&mut tx, &[new_media.path.as_str()]).await.map_err(|e| {
+        log_error(&e);
+        api_error(HttpResponse::InternalServerError(), "failed to replace media")
+    })?;
+
+    events::record(&mut tx, article_id, EventKind::MediaReplaced, API_ACTOR, None)
+        .await
+        .map_err(db_error)?;
+
+    // The cleanup job keeps files still referenced elsewhere, including this one
+    unlinks::enqueue(&mut tx, &old_paths).await.map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(new_id)
+}
@@ -0,0 +1,222 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{
+    Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue, HeaderValue, CONTENT_SECURITY_POLICY,
+    X_CONTENT_TYPE_OPTIONS,
+};
+use actix_web::{mime, web, HttpRequest, HttpResponse};
+use sqlx::{FromRow, PgPool};
+use std::io::{Read, Seek};
+
+use crate::media::{disk_path, types};
+use crate::{log_error, paths};
+
+#[derive(FromRow)]
+struct DownloadRow {
+    article_id: i32,
+    media_path: String,
+    mime_type: Option<String>,
+    original_filename: Option<String>,
+}
+
+// Name offered to the browser: the uploaded name, or the stored one for
+// rows that predate recording it
+fn download_name(row: &DownloadRow) -> String {
+    row.original_filename
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| row.media_path.rsplit('/').next().unwrap_or("download").to_string())
+}
+
+// Plain `filename=` fallback for clients that ignore `filename*`
+fn ascii_filename(name: &str) -> String {
+    let ascii: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+    if ascii.trim_matches(['_', '.']).is_empty() {
+        "download".to_string()
+    } else {
+        ascii
+    }
+}
+
+// Disposition carrying the UTF-8 name as an RFC 5987 `filename*` (actix
+// percent-encodes it) plus the ASCII fallback
+pub fn disposition(disposition: DispositionType, name: &str) -> ContentDisposition {
+    ContentDisposition {
+        disposition,
+        parameters: vec![
+            DispositionParam::Filename(ascii_filename(name)),
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: name.as_bytes().to_vec(),
+            }),
+        ],
+    }
+}
+
+fn gone_page(article_id: i32) -> HttpResponse {
+    let base = paths::base();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>File Unavailable</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>File Unavailable</h2>
+        <p>This attachment is no longer stored on the server.</p>
+        <a href="{base}/articles/{}">← Back to Article</a>
+        </div>
+        </body>
+        </html>
+        "#,
+        article_id
+    );
+    HttpResponse::Gone().content_type("text/html").body(html)
+}
+
+// GET /media/{id}/download: the stored file under its original name, with
+// range requests handled by NamedFile
+pub async fn download_media(req: HttpRequest, pool: web::Data<PgPool>, path: web::Path<i32>) -> HttpResponse {
+    let media_id = path.into_inner();
+    let (row, mut file) = match open_media(pool.get_ref(), media_id).await {
+        Ok(found) => found,
+        Err(res) => return res,
+    };
+
+    if let Some(mime) = row.mime_type.as_deref().and_then(|m| m.parse::<mime::Mime>().ok()) {
+        file = file.set_content_type(mime);
+    }
+    file.set_content_disposition(disposition(DispositionType::Attachment, &download_name(&row)))
+        .into_response(&req)
+}
+
+async fn open_media(pool: &PgPool, media_id: i32) -> Result<(DownloadRow, NamedFile), HttpResponse> {
+    let row = match sqlx::query_as::<_, DownloadRow>(
+        "SELECT article_id, media_path, mime_type, original_filename FROM article_media WHERE id = $1",
+    )
+    .bind(media_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => return Err(HttpResponse::NotFound().body("Media not found")),
+        Err(e) => {
+            log_error(&format!("Failed to fetch media {}: {}", media_id, e));
+            return Err(HttpResponse::InternalServerError().body("Failed to load media"));
+        }
+    };
+
+    let file = match disk_path(&row.media_path) {
+        Some(p) => NamedFile::open_async(p).await,
+        None => return Err(gone_page(row.article_id)),
+    };
+    match file {
+        Ok(f) => Ok((row, f)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(gone_page(row.article_id)),
+        Err(e) => {
+            log_error(&format!("Failed to open media {}: {}", media_id, e));
+            Err(HttpResponse::InternalServerError().body("Failed to load media"))
+        }
+    }
+}
+
+// Untrusted documents shown in the article page's preview frame. Even if a
+// browser is talked into treating one as HTML, the sandbox keeps it from
+// running scripts, submitting forms or navigating the page around it.
+const PREVIEW_CSP: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; form-action 'none'; base-uri 'none'; frame-ancestors 'self'; sandbox";
+
+// GET /media/{id}/inline: a PDF or text attachment for the preview frame.
+// Other types are refused, as is a file whose contents don't match its type.
+pub async fn inline_media(req: HttpRequest, pool: web::Data<PgPool>, path: web::Path<i32>) -> HttpResponse {
+    let media_id = path.into_inner();
+    let (row, file) = match open_media(pool.get_ref(), media_id).await {
+        Ok(found) => found,
+        Err(res) => return res,
+    };
+
+    let media_type = match types::for_media(&row.media_path, row.mime_type.as_deref()) {
+        Some(t) if t.previewable() => t,
+        _ => return HttpResponse::NotFound().body("No preview for this attachment"),
+    };
+    let mut head = Vec::new();
+    let read = file.file().take(8192).read_to_end(&mut head);
+    if let Err(e) = read.and_then(|_| file.file().rewind()) {
+        log_error(&format!("Failed to read media {} for preview: {}", media_id, e));
+        return HttpResponse::InternalServerError().body("Failed to load media");
+    }
+    if !(media_type.matches)(&head) {
+        log_error(&format!("Media {} doesn't look like {}, preview refused", media_id, media_type.mime));
+        return HttpResponse::NotFound().body("No preview for this attachment");
+    }
+
+    let content_type = match media_type.mime {
+        "text/plain" => mime::TEXT_PLAIN_UTF_8,
+        other => other.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM),
+    };
+    let mut res = file
+        .set_content_type(content_type)
+        .set_content_disposition(disposition(DispositionType::Inline, &download_name(&row)))
+        .into_response(&req);
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_SECURITY_POLICY, HeaderValue::from_static(PREVIEW_CSP));
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    res
+}
+
+// The start of a text attachment for the article page, and whether there
+// is more of it
+pub fn text_preview(media_path: &str, max_bytes: usize) -> Option<(String, bool)> {
+    let file = std::fs::File::open(disk_path(media_path)?).ok()?;
+    let mut data = Vec::new();
+    file.take(max_bytes as u64 + 1).read_to_end(&mut data).ok()?;
+    let truncated = data.len() > max_bytes;
+    data.truncate(max_bytes);
+    Some((String::from_utf8_lossy(&data).into_owned(), truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str) -> String {
+        disposition(DispositionType::Attachment, name).to_string()
+    }
+
+    #[test]
+    fn non_ascii_name_goes_in_filename_ext() {
+        let value = header("résumé 2024.pdf");
+        assert!(value.starts_with("attachment; "), "{}", value);
+        assert!(value.contains("filename*=UTF-8''r%C3%A9sum%C3%A9%202024.pdf"), "{}", value);
+        assert!(value.contains("filename=\"r_sum__2024.pdf\""), "{}", value);
+    }
+
+    #[test]
+    fn ascii_fallback_replaces_unsafe_characters() {
+        assert_eq!(ascii_filename("report-v2_final.txt"), "report-v2_final.txt");
+        assert_eq!(ascii_filename("a\"b;c\r\nd.txt"), "a_b_c__d.txt");
+        assert_eq!(ascii_filename("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(ascii_filename("日本語.png"), "___.png");
+    }
+
+    #[test]
+    fn ascii_fallback_is_never_empty() {
+        assert_eq!(ascii_filename("日本語"), "download");
+        assert_eq!(ascii_filename(""), "download");
+        assert_eq!(ascii_filename("..."), "download");
+    }
+
+    #[test]
+    fn quotes_cannot_break_out_of_the_header() {
+        let value = header("x\"; filename=evil.html");
+        assert!(value.contains("filename=\"x___filename_evil.html\""), "{}", value);
+        assert!(value.contains("filename*=UTF-8''x%22%3B%20filename"), "{}", value);
+        assert_eq!(value.matches(';').count(), 2, "{}", value);
+    }
+}
@@ -0,0 +1,345 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::csrf;
+use crate::log_error;
+use crate::media::disk_path;
+use crate::media_migration::redirect_target;
+use crate::paths;
+
+// Walks article_media in id order and checks that every file is still on disk
+// with the size recorded at upload. Rows that fail are flagged in
+// media_integrity so the article page shows a placeholder instead of a broken
+// link. Progress is kept in migration_state like the normalization job, so a
+// scan cut short by a restart resumes from the last checked row.
+const SCAN_NAME: &str = "media_integrity";
+const BATCH_SIZE: i64 = 100;
+
+pub const MISSING: &str = "missing";
+pub const SIZE_MISMATCH: &str = "size_mismatch";
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize)]
+pub struct ScanForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[derive(FromRow)]
+struct ScanState {
+    last_id: i32,
+    processed: i64,
+    failed: i64,
+    started_at: i64,
+    updated_at: i64,
+    finished_at: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct MediaRow {
+    id: i32,
+    media_path: String,
+    size_bytes: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct AffectedArticle {
+    article_id: i32,
+    title: String,
+    missing: i64,
+    size_mismatch: i64,
+}
+
+// Spawns the scan unless one is already running. Returns false if it was.
+pub fn start(pool: PgPool) -> bool {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+
+    actix_web::rt::spawn(async move {
+        if let Err(e) = run(&pool).await {
+            log_error(&format!("Media integrity scan stopped: {}", e));
+        }
+        RUNNING.store(false, Ordering::SeqCst);
+    });
+
+    true
+}
+
+// Called at startup: continues an interrupted scan, or starts a new one when
+// MEDIA_INTEGRITY_SCAN_ON_START=1
+pub async fn start_on_boot(pool: &PgPool) {
+    let unfinished: Option<i32> = match sqlx::query_scalar(
+        "SELECT last_id FROM migration_state WHERE name = $1 AND finished_at IS NULL",
+    )
+    .bind(SCAN_NAME)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            log_error(&format!("Failed to check media integrity scan state: {}", e));
+            return;
+        }
+    };
+
+    if unfinished.is_some() || env::var("MEDIA_INTEGRITY_SCAN_ON_START").is_ok_and(|v| v.trim() == "1") {
+        start(pool.clone());
+    }
+}
+
+async fn run(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let now = Utc::now().timestamp();
+
+    // A finished scan starts over from the first row; an unfinished one resumes
+    let mut last_id: i32 = sqlx::query_scalar(
+        "INSERT INTO migration_state (name, started_at, updated_at)
+         VALUES ($1, $2, $2)
+         ON CONFLICT (name) DO UPDATE SET
+            last_id = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.last_id ELSE 0 END,
+            processed = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.processed ELSE 0 END,
+            failed = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.failed ELSE 0 END,
+            started_at = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.started_at ELSE EXCLUDED.started_at END,
+            updated_at = EXCLUDED.updated_at,
+            finished_at = NULL
+         RETURNING last_id",
+    )
+    .bind(SCAN_NAME)
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    loop {
+        let rows = sqlx::query_as::<_, MediaRow>(
+            "SELECT id, media_path, size_bytes FROM article_media WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        let last_row = match rows.last() {
+            Some(row) => row.id,
+            None => break,
+        };
+
+        let mut results = Vec::with_capacity(rows.len());
+        for row in &rows {
+            results.push((row.id, check_row(pool, row).await));
+        }
+        let flagged = results.iter().filter(|(_, problem)| problem.is_some()).count() as i64;
+
+        let checked_at = Utc::now().timestamp();
+        let mut tx = pool.begin().await?;
+        for (id, problem) in results {
+            sqlx::query("UPDATE article_media SET media_integrity = $1, integrity_checked_at = $2 WHERE id = $3")
+                .bind(problem)
+                .bind(checked_at)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // Flags and progress commit together, so stopping between batches loses nothing
+        sqlx::query(
+            "UPDATE migration_state SET last_id = $1, processed = processed + $2,
+             failed = failed + $3, updated_at = $4 WHERE name = $5",
+        )
+        .bind(last_row)
+        .bind(rows.len() as i64)
+        .bind(flagged)
+        .bind(checked_at)
+        .bind(SCAN_NAME)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        last_id = last_row;
+    }
+
+    sqlx::query("UPDATE migration_state SET finished_at = $1, updated_at = $1 WHERE name = $2")
+        .bind(Utc::now().timestamp())
+        .bind(SCAN_NAME)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// None when the file is intact, otherwise the flag to store on the row
+async fn check_row(pool: &PgPool, row: &MediaRow) -> Option<&'static str> {
+    let mut path = match disk_path(&row.media_path) {
+        Some(path) => path,
+        None => return Some(MISSING),
+    };
+
+    // Legacy files renamed by the normalization job are served through a redirect
+    if !path.exists() {
+        let target = redirect_target(pool, &row.media_path).await.unwrap_or_else(|e| {
+            log_error(&e);
+            None
+        });
+        match target.as_deref().and_then(disk_path) {
+            Some(new_path) => path = new_path,
+            None => return Some(MISSING),
+        }
+    }
+
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(m) => m,
+        Err(_) => return Some(MISSING),
+    };
+
+    // Rows from before size_bytes existed have nothing to compare against
+    match row.size_bytes {
+        Some(size) if size != metadata.len() as i64 => Some(SIZE_MISMATCH),
+        _ => None,
+    }
+}
+
+pub async fn scan_status(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Any staff member may read the report
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", None, "media integrity report").await {
+        return res;
+    }
+
+    let state = match sqlx::query_as::<_, ScanState>(
+        "SELECT last_id, processed, failed, started_at, updated_at, finished_at
+         FROM migration_state WHERE name = $1",
+    )
+    .bind(SCAN_NAME)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            log_error(&format!("Failed to fetch media integrity scan state: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load scan state");
+        }
+    };
+
+    let affected = match sqlx::query_as::<_, AffectedArticle>(
+        "SELECT m.article_id, a.title,
+                COUNT(*) FILTER (WHERE m.media_integrity = $1) AS missing,
+                COUNT(*) FILTER (WHERE m.media_integrity = $2) AS size_mismatch
+         FROM article_media m JOIN articles a ON a.id = m.article_id
+         WHERE m.media_integrity IS NOT NULL
+         GROUP BY m.article_id, a.title
+         ORDER BY m.article_id DESC",
+    )
+    .bind(MISSING)
+    .bind(SIZE_MISMATCH)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(a) => a,
+        Err(e) => {
+            log_error(&format!("Failed to fetch media integrity report: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load media integrity report");
+        }
+    };
+
+    let status = match &state {
+        None => "Never run".to_string(),
+        Some(s) => {
+            let phase = if RUNNING.load(Ordering::SeqCst) {
+                "Running"
+            } else if s.finished_at.is_some() {
+                "Finished"
+            } else {
+                "Interrupted (will resume)"
+            };
+            format!(
+                "{}<br>Last media id: {}<br>Checked: {} ({} flagged)<br>Started: {}<br>Updated: {}",
+                phase, s.last_id, s.processed, s.failed, s.started_at, s.updated_at
+            )
+        }
+    };
+
+    let flagged_rows: i64 = affected.iter().map(|a| a.missing + a.size_mismatch).sum();
+    let mut rows_html = String::new();
+    for article in &affected {
+        rows_html.push_str(&format!(
+            r#"<tr><td><a href="{base}/articles/{}">{}</a></td><td>{}</td><td>{}</td></tr>"#,
+            article.article_id,
+            html_escape::encode_text(&article.title),
+            article.missing,
+            article.size_mismatch
+        ));
+    }
+    if affected.is_empty() {
+        rows_html.push_str(r#"<tr><td colspan="3">No problems found</td></tr>"#);
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Media Integrity</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Media Integrity <span class="badge">{}</span></h2>
+        <p>{}</p>
+        <form action="{base}/admin/media/integrity" method="POST">
+            {}
+            {}
+            <input type="submit" value="Start / Resume scan">
+        </form>
+        </div>
+        <h3>Affected articles</h3>
+        <table class="activity">
+            <tr><th>Article</th><th>Missing</th><th>Size mismatch</th></tr>
+            {}
+        </table>
+        </body>
+        </html>
+        "#,
+        flagged_rows,
+        status,
+        csrf::field(&req),
+        admin::password_field(&req),
+        rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn start_scan(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    form: web::Form<ScanForm>,
+) -> HttpResponse {
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageSettings),
+        "media integrity scan",
+    )
+    .await
+    {
+        return res;
+    }
+
+    if !start(pool.get_ref().clone()) {
+        return HttpResponse::Conflict().body("Media integrity scan is already running");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/media/integrity")))
+        .finish()
+}
@@ -0,0 +1,429 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::media::{content_hash, dimensions, disk_path, hashed_media_path, types};
+use crate::admin::{self, AdminAuth, Permission};
+use crate::csrf;
+use crate::derivatives;
+use crate::log_error;
+use crate::paths;
+use crate::unlinks;
+
+// Walks article_media in id order and fills in MIME type, hash, size and
+// dimensions for rows uploaded before those columns existed, optionally moving
+// the files to the hashed naming scheme. Progress is stored per row in
+// migration_state so an interrupted run picks up where it stopped.
+const MIGRATION_NAME: &str = "normalize_media";
+const BATCH_SIZE: i64 = 100;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize)]
+pub struct MigrationForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+    rename_files: Option<String>,
+}
+
+#[derive(FromRow)]
+struct MigrationState {
+    last_id: i32,
+    processed: i64,
+    failed: i64,
+    rename_files: bool,
+    started_at: i64,
+    updated_at: i64,
+    finished_at: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct MigrationFailure {
+    media_id: i32,
+    error: String,
+    occurred_at: i64,
+}
+
+#[derive(FromRow)]
+struct MediaRow {
+    id: i32,
+    media_path: String,
+}
+
+// Spawns the job unless one is already running. Returns false if it was.
+pub fn start(pool: PgPool, rename_files: bool) -> bool {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+
+    actix_web::rt::spawn(async move {
+        if let Err(e) = run(&pool, rename_files).await {
+            log_error(&format!("Media normalization stopped: {}", e));
+        }
+        RUNNING.store(false, Ordering::SeqCst);
+    });
+
+    true
+}
+
+// Called at startup so a run interrupted by a restart continues on its own
+pub async fn resume_unfinished(pool: &PgPool) {
+    let unfinished: Option<bool> = match sqlx::query_scalar(
+        "SELECT rename_files FROM migration_state WHERE name = $1 AND finished_at IS NULL",
+    )
+    .bind(MIGRATION_NAME)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            log_error(&format!("Failed to check media normalization state: {}", e));
+            return;
+        }
+    };
+
+    if let Some(rename_files) = unfinished {
+        start(pool.clone(), rename_files);
+    }
+}
+
+async fn run(pool: &PgPool, rename_files: bool) -> Result<(), sqlx::Error> {
+    let now = Utc::now().timestamp();
+
+    // A finished migration starts over from the first row; an unfinished one resumes
+    let last_id: i32 = sqlx::query_scalar(
+        "INSERT INTO migration_state (name, rename_files, started_at, updated_at)
+         VALUES ($1, $2, $3, $3)
+         ON CONFLICT (name) DO UPDATE SET
+            last_id = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.last_id ELSE 0 END,
+            processed = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.processed ELSE 0 END,
+            failed = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.failed ELSE 0 END,
+            started_at = CASE WHEN migration_state.finished_at IS NULL THEN migration_state.started_at ELSE EXCLUDED.started_at END,
+            rename_files = EXCLUDED.rename_files,
+            updated_at = EXCLUDED.updated_at,
+            finished_at = NULL
+         RETURNING last_id",
+    )
+    .bind(MIGRATION_NAME)
+    .bind(rename_files)
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    if last_id == 0 {
+        sqlx::query("DELETE FROM migration_failures WHERE migration = $1")
+            .bind(MIGRATION_NAME)
+            .execute(pool)
+            .await?;
+    }
+
+    let mut last_id = last_id;
+    loop {
+        let rows = sqlx::query_as::<_, MediaRow>(
+            "SELECT id, media_path FROM article_media WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(last_id)
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in rows {
+            let failed = match normalize_row(pool, &row, rename_files).await {
+                Ok(()) => 0,
+                Err(reason) => {
+                    sqlx::query(
+                        "INSERT INTO migration_failures (migration, media_id, error, occurred_at)
+                         VALUES ($1, $2, $3, $4)",
+                    )
+                    .bind(MIGRATION_NAME)
+                    .bind(row.id)
+                    .bind(&reason)
+                    .bind(Utc::now().timestamp())
+                    .execute(pool)
+                    .await?;
+                    1
+                }
+            };
+
+            sqlx::query(
+                "UPDATE migration_state SET last_id = $1, processed = processed + 1,
+                 failed = failed + $2, updated_at = $3 WHERE name = $4",
+            )
+            .bind(row.id)
+            .bind(failed as i64)
+            .bind(Utc::now().timestamp())
+            .bind(MIGRATION_NAME)
+            .execute(pool)
+            .await?;
+
+            last_id = row.id;
+        }
+    }
+
+    sqlx::query("UPDATE migration_state SET finished_at = $1, updated_at = $1 WHERE name = $2")
+        .bind(Utc::now().timestamp())
+        .bind(MIGRATION_NAME)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn redirect_target(pool: &PgPool, old_path: &str) -> Result<Option<String>, String> {
+    sqlx::query_scalar("SELECT new_path FROM media_redirects WHERE old_path = $1")
+        .bind(old_path)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Failed to look up redirect: {}", e))
+}
+
+// Normalizes a single row. Safe to repeat: an already normalized row maps onto itself.
+async fn normalize_row(pool: &PgPool, row: &MediaRow, rename_files: bool) -> Result<(), String> {
+    let mut media_path = row.media_path.clone();
+    let mut path = disk_path(&media_path).ok_or("Media path is not under /uploads")?;
+
+    if !path.exists() {
+        // Another row pointing at the same file may already have moved it
+        match redirect_target(pool, &media_path).await? {
+            Some(new_path) => {
+                path = disk_path(&new_path).ok_or("Redirect target is not under /uploads")?;
+                media_path = new_path;
+            }
+            None => return Err("File missing on disk".to_string()),
+        }
+        if !path.exists() {
+            return Err("File missing on disk".to_string());
+        }
+    }
+
+    let data = tokio::fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let media_type = types::sniff(&data).ok_or("Unrecognized file type")?;
+    let hash = content_hash(&data);
+    let size = data.len() as i64;
+    let (width, height) = match dimensions(&data) {
+        Some((w, h)) => (Some(w), Some(h)),
+        None => (None, None),
+    };
+
+    let mut duplicate = None;
+    if rename_files {
+        let new_path = hashed_media_path(&hash, media_type);
+        if new_path != media_path {
+            let new_disk_path = disk_path(&new_path).ok_or("Invalid hashed path")?;
+
+            // Record the redirect before touching the file so a crash mid-rename is recoverable
+            sqlx::query(
+                "INSERT INTO media_redirects (old_path, new_path) VALUES ($1, $2)
+                 ON CONFLICT (old_path) DO UPDATE SET new_path = EXCLUDED.new_path",
+            )
+            .bind(&media_path)
+            .bind(&new_path)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to store redirect: {}", e))?;
+
+            if new_disk_path.exists() {
+                // Identical content is already stored under its hashed name;
+                // the old copy is queued for removal along with the row update
+                duplicate = Some(media_path.clone());
+            } else {
+                tokio::fs::rename(&path, &new_disk_path)
+                    .await
+                    .map_err(|e| format!("Failed to rename file: {}", e))?;
+            }
+
+            media_path = new_path;
+        }
+    }
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    sqlx::query(
+        "UPDATE article_media SET media_path = $1, mime_type = $2, content_hash = $3,
+         size_bytes = $4, width = $5, height = $6 WHERE id = $7",
+    )
+    .bind(&media_path)
+    .bind(media_type.mime)
+    .bind(&hash)
+    .bind(size)
+    .bind(width)
+    .bind(height)
+    .bind(row.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to update media row: {}", e))?;
+
+    // Derivatives of the old name are stale; the job makes new ones
+    if media_path != row.media_path {
+        sqlx::query("UPDATE article_media SET webp_path = NULL, avif_path = NULL, derivatives_at = NULL WHERE id = $1")
+            .bind(row.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to reset image derivatives: {}", e))?;
+        unlinks::enqueue(&mut tx, &derivatives::sibling_paths(&row.media_path))
+            .await
+            .map_err(|e| format!("Failed to queue old derivatives for removal: {}", e))?;
+    }
+
+    if let Some(old_path) = duplicate {
+        unlinks::enqueue(&mut tx, &[old_path])
+            .await
+            .map_err(|e| format!("Failed to queue duplicate file for removal: {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit media row: {}", e))?;
+
+    Ok(())
+}
+
+pub async fn migration_status(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Any staff member may read the report
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", None, "media migration report").await {
+        return res;
+    }
+    let state = match sqlx::query_as::<_, MigrationState>(
+        "SELECT last_id, processed, failed, rename_files, started_at, updated_at, finished_at
+         FROM migration_state WHERE name = $1",
+    )
+    .bind(MIGRATION_NAME)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            log_error(&format!("Failed to fetch migration state: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load migration state");
+        }
+    };
+
+    let failures = sqlx::query_as::<_, MigrationFailure>(
+        "SELECT media_id, error, occurred_at FROM migration_failures
+         WHERE migration = $1 ORDER BY id LIMIT 200",
+    )
+    .bind(MIGRATION_NAME)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_else(|e| {
+        log_error(&format!("Failed to fetch migration failures: {}", e));
+        Vec::new()
+    });
+
+    let status = match &state {
+        None => "Never run".to_string(),
+        Some(s) => {
+            let phase = if RUNNING.load(Ordering::SeqCst) {
+                "Running"
+            } else if s.finished_at.is_some() {
+                "Finished"
+            } else {
+                "Interrupted (will resume)"
+            };
+            format!(
+                "{}<br>Last media id: {}<br>Processed: {} ({} failed)<br>Rename files: {}<br>Started: {}<br>Updated: {}",
+                phase,
+                s.last_id,
+                s.processed,
+                s.failed,
+                if s.rename_files { "yes" } else { "no" },
+                s.started_at,
+                s.updated_at
+            )
+        }
+    };
+
+    let unlink_backlog = unlinks::backlog(pool.get_ref()).await.unwrap_or_else(|e| {
+        log_error(&format!("Failed to count pending file removals: {}", e));
+        0
+    });
+
+    let mut failures_html = String::new();
+    for f in &failures {
+        failures_html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            f.media_id,
+            html_escape::encode_text(&f.error),
+            f.occurred_at
+        ));
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Media Normalization</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Media Normalization</h2>
+        <p>{}</p>
+        <p>Files waiting to be removed from disk: {}</p>
+        <form action="{base}/admin/migrations/media" method="POST">
+            {}
+            {}
+            <label><input type="checkbox" name="rename_files" value="1"> Rename files to hashed names</label><br><br>
+            <input type="submit" value="Start / Resume">
+        </form>
+        </div>
+        <h3>Failures</h3>
+        <table>
+            <tr><th>Media ID</th><th>Error</th><th>Time</th></tr>
+            {}
+        </table>
+        </body>
+        </html>
+        "#,
+        status,
+        unlink_backlog,
+        csrf::field(&req),
+        admin::password_field(&req),
+        failures_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn start_migration(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    form: web::Form<MigrationForm>,
+) -> HttpResponse {
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        &form.password,
+        Some(Permission::ManageSettings),
+        "media normalization",
+    )
+    .await
+    {
+        return res;
+    }
+
+    if !start(pool.get_ref().clone(), form.rename_files.is_some()) {
+        return HttpResponse::Conflict().body("Media normalization is already running");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/migrations/media")))
+        .finish()
+}
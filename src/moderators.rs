@@ -0,0 +1,246 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Utc;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::{csrf, log_error};
+use crate::paths;
+
+// Moderator accounts, managed by the super-admin at /admin/moderators
+
+#[derive(FromRow)]
+struct Moderator {
+    id: i32,
+    username: String,
+    permissions: i32,
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+// Returns the moderator id when the username and password match
+pub async fn verify_login(pool: &PgPool, username: &str, password: &str) -> Option<i32> {
+    let row: Option<(i32, String)> =
+        match sqlx::query_as("SELECT id, password_hash FROM moderators WHERE username = $1")
+            .bind(username)
+            .fetch_optional(pool)
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                log_error(&format!("Failed to fetch moderator: {}", e));
+                return None;
+            }
+        };
+
+    // An unknown username still costs one hash verification, so response
+    // times don't reveal which usernames exist
+    let (id, stored) = match row {
+        Some(row) => row,
+        None => {
+            if let Some(dummy) = dummy_hash() {
+                if let Ok(parsed) = PasswordHash::new(dummy) {
+                    let _ = Argon2::default().verify_password(password.as_bytes(), &parsed);
+                }
+            }
+            return None;
+        }
+    };
+    let parsed = PasswordHash::new(&stored).ok()?;
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .ok()
+        .map(|_| id)
+}
+
+fn dummy_hash() -> Option<&'static str> {
+    static DUMMY: OnceLock<Option<String>> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password("dummy password").ok()).as_deref()
+}
+
+fn permission_labels(permissions: i32) -> String {
+    let keys: Vec<&str> = Permission::ALL
+        .iter()
+        .filter(|p| permissions & p.bit() != 0)
+        .map(|p| p.key())
+        .collect();
+    if keys.is_empty() {
+        "none".to_string()
+    } else {
+        keys.join(", ")
+    }
+}
+
+pub async fn list_moderators(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Usernames and permissions are for the super admin only, like the changes
+    if let Err(res) = auth.authorize_super_admin(&req, pool.get_ref(), "", "moderator list").await {
+        return res;
+    }
+    let moderators = match sqlx::query_as::<_, Moderator>(
+        "SELECT id, username, permissions FROM moderators ORDER BY username",
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            log_error(&format!("Failed to fetch moderators: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load moderators");
+        }
+    };
+
+    let auth_fields = format!("{}{}", csrf::field(&req), admin::password_field(&req));
+
+    let mut rows_html = String::new();
+    for m in &moderators {
+        rows_html.push_str(&format!(
+            r#"<div class="comment"><p>{}</p><p>Permissions: {}</p>
+            <form action="{base}/admin/moderators/{}/delete" method="POST">
+                {}
+                <input type="submit" value="Delete">
+            </form></div>"#,
+            html_escape::encode_text(&m.username),
+            permission_labels(m.permissions),
+            m.id,
+            auth_fields
+        ));
+    }
+
+    let mut checkboxes_html = String::new();
+    for p in Permission::ALL {
+        checkboxes_html.push_str(&format!(
+            r#"<label><input type="checkbox" name="perm_{}" value="1"> {}</label><br>"#,
+            p.key(),
+            p.key()
+        ));
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Moderators</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>New Moderator</h2>
+        <form action="{base}/admin/moderators" method="POST">
+            {}
+            <input type="text" name="username" placeholder="Username" required>
+            <input type="password" name="new_password" placeholder="Moderator password" required><br>
+            {}<br>
+            <input type="submit" value="Create Moderator">
+        </form>
+        </div>
+        <h2>Moderators</h2>
+        {}
+        </body>
+        </html>
+        "#,
+        auth_fields, checkboxes_html, rows_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+pub async fn create_moderator(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let password = form.get("password").map(String::as_str).unwrap_or("");
+    let submitted = form.get(csrf::FIELD_NAME).map(String::as_str).unwrap_or("");
+    if let Err(res) = auth.check_csrf(&req, submitted, password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize_super_admin(&req, pool.get_ref(), password, "moderator creation").await {
+        return res;
+    }
+
+    let username = form.get("username").map(|u| u.trim()).unwrap_or("");
+    let new_password = form.get("new_password").map(String::as_str).unwrap_or("");
+    if username.is_empty() || new_password.is_empty() {
+        return HttpResponse::BadRequest().body("Username and password are required");
+    }
+
+    let permissions = Permission::ALL
+        .iter()
+        .filter(|p| form.contains_key(&format!("perm_{}", p.key())))
+        .fold(0, |acc, p| acc | p.bit());
+
+    let password_hash = match hash_password(new_password) {
+        Ok(h) => h,
+        Err(e) => {
+            log_error(&e);
+            return HttpResponse::InternalServerError().body("Failed to create moderator.");
+        }
+    };
+
+    match sqlx::query(
+        "INSERT INTO moderators (username, password_hash, permissions, created_at)
+         VALUES ($1, $2, $3, $4) ON CONFLICT (username) DO NOTHING",
+    )
+    .bind(username)
+    .bind(&password_hash)
+    .bind(permissions)
+    .bind(Utc::now().timestamp())
+    .execute(pool.get_ref())
+    .await
+    {
+        Ok(r) if r.rows_affected() == 0 => {
+            return HttpResponse::Conflict().body("A moderator with that username already exists");
+        }
+        Ok(_) => {}
+        Err(e) => {
+            log_error(&format!("Failed to create moderator: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to create moderator.");
+        }
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/moderators")))
+        .finish()
+}
+
+pub async fn delete_moderator(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    path: web::Path<i32>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let moderator_id = path.into_inner();
+    let password = form.get("password").map(String::as_str).unwrap_or("");
+    let submitted = form.get(csrf::FIELD_NAME).map(String::as_str).unwrap_or("");
+    if let Err(res) = auth.check_csrf(&req, submitted, password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize_super_admin(&req, pool.get_ref(), password, "moderator deletion").await {
+        return res;
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM moderators WHERE id = $1")
+        .bind(moderator_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        log_error(&format!("Failed to delete moderator: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to delete moderator.");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/moderators")))
+        .finish()
+}
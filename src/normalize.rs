@@ -0,0 +1,131 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+
+use crate::paths;
+
+// Redirects GET/HEAD requests for non-canonical paths (`/articles/`,
+// `/Articles`, `//articles`) to the canonical one with a 308. Only the
+// fixed words of our routes are lowercased, never ids or file names, and
+// file paths under /static and /uploads are left exactly as requested.
+// actix's NormalizePath isn't used because it rewrites the path silently
+// for every method instead of redirecting.
+const FILE_PREFIXES: &[&str] = &["/static/", "/uploads/"];
+
+// Every literal segment of the registered routes; a test checks it against `routes()`
+const ROUTE_WORDS: &[&str] = &[
+    "activity",
+    "admin",
+    "announcements",
+    "api",
+    "articles",
+    "bans",
+    "blocklist",
+    "bulk-delete",
+    "clicks",
+    "comment",
+    "comments",
+    "consent",
+    "delete",
+    "digest",
+    "dismiss",
+    "download",
+    "edit",
+    "feed.xml",
+    "gallery",
+    "hide",
+    "inline",
+    "integrity",
+    "invalidate",
+    "lift",
+    "links",
+    "login",
+    "logout",
+    "media",
+    "migrations",
+    "moderators",
+    "oembed",
+    "out",
+    "quote",
+    "ready",
+    "release",
+    "report",
+    "reports",
+    "search",
+    "settings",
+    "submit",
+    "uploads",
+    "version",
+    "watch",
+    "watched",
+];
+
+fn canonical_path(path: &str) -> String {
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|segment| {
+            let lower = segment.to_ascii_lowercase();
+            if ROUTE_WORDS.contains(&lower.as_str()) {
+                lower
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect();
+    format!("/{}", segments.join("/"))
+}
+
+pub async fn redirect_to_canonical(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let path = req.path();
+    let skip = !(req.method() == Method::GET || req.method() == Method::HEAD)
+        || FILE_PREFIXES.iter().any(|p| path.starts_with(p));
+    if !skip {
+        let canonical = canonical_path(path);
+        if canonical != path {
+            let location = match req.query_string() {
+                "" => paths::url(&canonical),
+                query => format!("{}?{}", paths::url(&canonical), query),
+            };
+            let res = HttpResponse::PermanentRedirect()
+                .append_header(("Location", location))
+                .finish();
+            return Ok(req.into_response(res));
+        }
+    }
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_words_cover_every_registered_route() {
+        let routes = include_str!("lib.rs");
+        let missing: Vec<&str> = routes
+            .split(".route(\"")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .flat_map(|path| path.split('/'))
+            .filter(|segment| !segment.is_empty() && !segment.starts_with('{'))
+            .filter(|segment| !ROUTE_WORDS.contains(segment))
+            .collect();
+        assert!(missing.is_empty(), "missing from ROUTE_WORDS: {:?}", missing);
+    }
+
+    #[test]
+    fn lowercases_route_words_only() {
+        assert_eq!(canonical_path("/ARTICLES"), "/articles");
+        assert_eq!(canonical_path("/articles/"), "/articles");
+        assert_eq!(canonical_path("//Admin//Settings"), "/admin/settings");
+        assert_eq!(canonical_path("/articles/12/Edit"), "/articles/12/edit");
+        assert_eq!(canonical_path("/Search"), "/search");
+        assert_eq!(canonical_path("/uploads/Photo.PNG"), "/uploads/Photo.PNG");
+    }
+}
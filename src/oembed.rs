@@ -0,0 +1,166 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{FromRow, PgPool};
+
+use crate::log_error;
+use crate::media::types::{self, Renderer};
+use crate::paths;
+use crate::settings::{Settings, SettingsCache};
+use crate::visibility;
+
+// oEmbed (https://oembed.com) responses for article links, so chat apps can
+// show rich previews. Only the JSON format is implemented.
+const CACHE_AGE_SECS: i64 = 3600;
+
+#[derive(Deserialize)]
+pub struct OembedQuery {
+    url: Option<String>,
+    format: Option<String>,
+}
+
+#[derive(FromRow)]
+struct PreviewMedia {
+    media_path: String,
+    mime_type: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+}
+
+// Base URL articles are served under: the provider_url setting, or the
+// scheme and host of the current request plus BASE_PATH
+pub fn site_url(req: &HttpRequest, settings: &Settings) -> String {
+    if !settings.provider_url.is_empty() {
+        return settings.provider_url.clone();
+    }
+    let info = req.connection_info();
+    format!("{}://{}{}", info.scheme(), info.host(), paths::base())
+}
+
+// Absolute address of an article, the form search engines should index
+pub fn article_url(req: &HttpRequest, settings: &Settings, article_id: i32) -> String {
+    format!("{}/articles/{}", site_url(req, settings), article_id)
+}
+
+// <link rel="canonical"> for any page rendering an article's content, so
+// query-string variants like ?quote=... aren't indexed as separate pages
+pub fn canonical_link(req: &HttpRequest, settings: &Settings, article_id: i32) -> String {
+    format!(
+        r#"<link rel="canonical" href="{}">"#,
+        html_escape::encode_double_quoted_attribute(&article_url(req, settings, article_id))
+    )
+}
+
+// Discovery tag for an article page's <head>
+pub fn discovery_link(req: &HttpRequest, settings: &Settings, article_id: i32, title: &str) -> String {
+    let article_url = article_url(req, settings, article_id);
+    format!(
+        r#"<link rel="alternate" type="application/json+oembed" href="{}/oembed?url={}&amp;format=json" title="{}">"#,
+        site_url(req, settings),
+        utf8_percent_encode(&article_url, NON_ALPHANUMERIC),
+        html_escape::encode_double_quoted_attribute(title)
+    )
+}
+
+// Article id for a URL on this site, None for foreign or non-article URLs
+fn article_id_for(url: &str, site: &str) -> Option<i32> {
+    let strip_scheme = |u: &str| -> Option<String> {
+        let rest = u.strip_prefix("https://").or_else(|| u.strip_prefix("http://"))?;
+        Some(rest.to_ascii_lowercase())
+    };
+    let site_host = strip_scheme(site)?;
+    let rest = strip_scheme(url.trim())?;
+
+    let path = rest.strip_prefix(site_host.as_str())?;
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    let id = path.strip_prefix("/articles/")?.trim_end_matches('/');
+    id.parse().ok()
+}
+
+pub async fn oembed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings_cache: web::Data<SettingsCache>,
+    query: web::Query<OembedQuery>,
+) -> HttpResponse {
+    match query.format.as_deref() {
+        None | Some("json") => {}
+        Some("xml") => return HttpResponse::NotImplemented().body("Only format=json is supported"),
+        Some(_) => return HttpResponse::BadRequest().body("Unknown format"),
+    }
+
+    let url = match query.url.as_deref() {
+        Some(u) => u,
+        None => return HttpResponse::BadRequest().body("url is required"),
+    };
+
+    let settings = settings_cache.get(pool.get_ref()).await;
+    let site = site_url(&req, &settings);
+    let article_id = match article_id_for(url, &site) {
+        Some(id) => id,
+        None => return HttpResponse::NotFound().body("Not an article on this site"),
+    };
+
+    let title: Option<String> = match sqlx::query_scalar(&format!(
+        "SELECT title FROM articles WHERE id = $1 AND {}",
+        visibility::shown_articles("articles")
+    ))
+    .bind(article_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            log_error(&format!("Failed to fetch article for oEmbed: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load article");
+        }
+    };
+    let title = match title {
+        Some(t) => t,
+        None => return HttpResponse::NotFound().body("Article not found"),
+    };
+
+    let media = sqlx::query_as::<_, PreviewMedia>(
+        "SELECT media_path, mime_type, width, height FROM article_media WHERE article_id = $1 ORDER BY id LIMIT 1",
+    )
+    .bind(article_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or_else(|e| {
+        log_error(&format!("Failed to fetch media for oEmbed: {}", e));
+        None
+    });
+
+    let mut body = json!({
+        "version": "1.0",
+        "type": "link",
+        "title": title,
+        "provider_name": settings.provider_name,
+        "provider_url": site,
+        "cache_age": CACHE_AGE_SECS,
+    });
+
+    // An image with known dimensions makes this a photo; `photo` requires
+    // width and height, and a thumbnail needs both too, so legacy rows
+    // without them stay plain links
+    if let Some(m) = media {
+        if types::renderer_for(&m.media_path, m.mime_type.as_deref()) == Renderer::Image {
+            let image_url = format!("{}{}", site, m.media_path);
+            if let (Some(w), Some(h)) = (m.width, m.height) {
+                body["thumbnail_url"] = json!(image_url);
+                body["thumbnail_width"] = json!(w);
+                body["thumbnail_height"] = json!(h);
+                body["type"] = json!("photo");
+                body["url"] = json!(image_url);
+                body["width"] = json!(w);
+                body["height"] = json!(h);
+            }
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .append_header(("Cache-Control", format!("public, max-age={}", CACHE_AGE_SECS)))
+        .body(body.to_string())
+}
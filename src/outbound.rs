@@ -0,0 +1,225 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{TimeZone, Utc};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool};
+
+use crate::admin::AdminAuth;
+use crate::{log_error, paths, tokens};
+
+// External links in article bodies go through /out, which counts the click
+// by target domain and redirects without sending a Referer. Each wrapped
+// link carries a signature over its target and article, so /out only ever
+// redirects to links this site rendered and can't serve as an open
+// redirect. The `wrap_outbound_links` setting turns wrapping off; links
+// already on cached pages keep working either way.
+const PURPOSE: &str = "outbound";
+pub const REL: &str = "nofollow noopener noreferrer";
+
+fn signed_payload(article_id: i32, url: &str) -> String {
+    format!("{}|{}", article_id, url)
+}
+
+// Whether a link leaves the site: an absolute http(s) URL
+pub fn is_external(url: &str) -> bool {
+    let lower = url.trim().to_ascii_lowercase();
+    (lower.starts_with("http://") || lower.starts_with("https://")) && lower.len() > "https://".len()
+}
+
+// The /out URL standing in for `url` on `article_id`'s page
+pub fn wrap(article_id: i32, url: &str) -> String {
+    format!(
+        "{}?a={}&u={}&sig={}",
+        paths::url("/out"),
+        article_id,
+        utf8_percent_encode(url, NON_ALPHANUMERIC),
+        tokens::sign_short(PURPOSE, &signed_payload(article_id, url))
+    )
+}
+
+// The target of a wrapped link, when the signature is this site's and the
+// target is an absolute http(s) URL with a host
+fn verified_target(article_id: i32, url: &str, sig: &str) -> Option<reqwest::Url> {
+    if !is_external(url) || !tokens::verify_short(PURPOSE, &signed_payload(article_id, url), sig) {
+        return None;
+    }
+    reqwest::Url::parse(url).ok().filter(|target| target.host_str().is_some())
+}
+
+#[derive(Deserialize)]
+pub struct OutQuery {
+    a: i32,
+    u: String,
+    sig: String,
+}
+
+// GET /out
+pub async fn follow(pool: web::Data<PgPool>, query: web::Query<OutQuery>) -> HttpResponse {
+    let target = match verified_target(query.a, &query.u, &query.sig) {
+        Some(target) => target,
+        None => {
+            return HttpResponse::BadRequest()
+                .append_header(("Referrer-Policy", "no-referrer"))
+                .body("This link isn't valid. Only links on this site's articles can be followed.")
+        }
+    };
+
+    // Who clicked isn't recorded, only where to and from which article
+    let domain = target.host_str().unwrap_or_default().to_ascii_lowercase();
+    // A link from a page cached before its article was deleted counts without one
+    if let Err(e) = sqlx::query(
+        "INSERT INTO link_clicks (article_id, domain, clicked_at) VALUES ((SELECT id FROM articles WHERE id = $1), $2, $3)",
+    )
+        .bind(query.a)
+        .bind(&domain)
+        .bind(Utc::now().timestamp())
+        .execute(pool.get_ref())
+        .await
+    {
+        log_error(&format!("Failed to record link click: {}", e));
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", query.u.as_str()))
+        .append_header(("Referrer-Policy", "no-referrer"))
+        .finish()
+}
+
+#[derive(FromRow)]
+struct DomainClicks {
+    domain: String,
+    clicks: i64,
+    articles: i64,
+    last_clicked_at: i64,
+}
+
+// GET /admin/clicks
+pub async fn top_domains(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Any staff member may read the report
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", None, "link click report").await {
+        return res;
+    }
+
+    let since = Utc::now().timestamp() - 30 * 86_400;
+    let rows = match sqlx::query_as::<_, DomainClicks>(
+        "SELECT domain, COUNT(*) AS clicks, COUNT(DISTINCT article_id) AS articles, MAX(clicked_at) AS last_clicked_at
+         FROM link_clicks WHERE clicked_at >= $1
+         GROUP BY domain ORDER BY clicks DESC, domain LIMIT 50",
+    )
+    .bind(since)
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            log_error(&format!("Failed to fetch link clicks: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load link clicks");
+        }
+    };
+
+    let mut rows_html = String::new();
+    for row in &rows {
+        let last = Utc
+            .timestamp_opt(row.last_clicked_at, 0)
+            .single()
+            .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+        rows_html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            html_escape::encode_text(&row.domain),
+            row.clicks,
+            row.articles,
+            last
+        ));
+    }
+    let table = if rows.is_empty() {
+        "<p>No outbound clicks in the last 30 days.</p>".to_string()
+    } else {
+        format!(
+            "<table><tr><th>Domain</th><th>Clicks</th><th>Articles</th><th>Last click</th></tr>{}</table>",
+            rows_html
+        )
+    };
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Outbound Clicks</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>
+        <h1>Top Clicked Domains</h1>
+        <p>External links followed from article pages in the last 30 days.</p>
+        {}
+        </body>
+        </html>
+        "#,
+        table
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "https://example.com/page?x=1";
+
+    fn signature(article_id: i32, url: &str) -> String {
+        tokens::init_for_tests();
+        tokens::sign_short(PURPOSE, &signed_payload(article_id, url))
+    }
+
+    #[test]
+    fn signed_link_is_followed() {
+        let sig = signature(7, TARGET);
+        let target = verified_target(7, TARGET, &sig).unwrap();
+        assert_eq!(target.host_str(), Some("example.com"));
+    }
+
+    #[test]
+    fn tampered_signature_is_refused() {
+        let sig = signature(7, TARGET);
+        let mut tampered: Vec<char> = sig.chars().collect();
+        tampered[0] = if tampered[0] == '0' { '1' } else { '0' };
+        let tampered: String = tampered.into_iter().collect();
+        assert!(verified_target(7, TARGET, &tampered).is_none());
+        assert!(verified_target(7, TARGET, &sig[..sig.len() - 2]).is_none());
+        assert!(verified_target(7, TARGET, "").is_none());
+    }
+
+    #[test]
+    fn signature_is_bound_to_target_and_article() {
+        let sig = signature(7, TARGET);
+        assert!(verified_target(8, TARGET, &sig).is_none());
+        assert!(verified_target(7, "https://evil.example/", &sig).is_none());
+    }
+
+    #[test]
+    fn off_site_target_without_a_signature_is_refused() {
+        let sig = signature(7, TARGET);
+        assert!(verified_target(7, "https://evil.example/", "").is_none());
+        assert!(verified_target(7, "https://evil.example/", &sig).is_none());
+    }
+
+    #[test]
+    fn protocol_relative_and_other_schemes_are_refused_even_when_signed() {
+        for url in ["//evil.example/", "/\\evil.example/", "javascript:alert(1)", "https://", "ftp://evil.example/"] {
+            let sig = signature(7, url);
+            assert!(verified_target(7, url, &sig).is_none(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn wrapped_link_round_trips() {
+        tokens::init_for_tests();
+        let wrapped = wrap(3, TARGET);
+        let query = wrapped.split_once('?').unwrap().1;
+        let query = web::Query::<OutQuery>::from_query(query).unwrap();
+        assert_eq!(query.a, 3);
+        assert_eq!(query.u, TARGET);
+        assert!(verified_target(query.a, &query.u, &query.sig).is_some());
+    }
+}
@@ -0,0 +1,38 @@
+use actix_web::http::Uri;
+use actix_web::HttpRequest;
+use std::env;
+use std::sync::OnceLock;
+
+// Support for serving the site under a URL prefix behind a reverse proxy,
+// e.g. https://example.com/board/. The proxy is expected to strip the prefix
+// before forwarding (nginx: `location /board/ { proxy_pass http://app/; }`),
+// so routes stay registered at the root and only generated URLs carry it.
+static BASE_PATH: OnceLock<String> = OnceLock::new();
+
+// Reads BASE_PATH, normalized to "" or "/prefix" without a trailing slash
+pub fn init_from_env() {
+    let base = env::var("BASE_PATH").unwrap_or_default();
+    let base = base.trim().trim_matches('/');
+    let base = if base.is_empty() { String::new() } else { format!("/{}", base) };
+    let _ = BASE_PATH.set(base);
+}
+
+pub fn base() -> &'static str {
+    BASE_PATH.get().map(String::as_str).unwrap_or("")
+}
+
+// Public URL for a root-relative app path such as "/articles/3"
+pub fn url(path: &str) -> String {
+    format!("{}{}", base(), path)
+}
+
+// Path of the page a visitor came from, for redirecting back after a small
+// action. Only same-site paths are returned, never another host.
+pub fn referer_path(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Referer")
+        .and_then(|r| r.to_str().ok())
+        .and_then(|r| r.parse::<Uri>().ok())
+        .map(|uri| uri.path().to_string())
+        .filter(|p| p.starts_with('/') && !p.starts_with("//"))
+}
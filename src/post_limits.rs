@@ -0,0 +1,202 @@
+use actix_web::{HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{client, log_error};
+
+// Caps how often one client (by IP, see client::client_ip) may post articles
+// and comments, and report them, in a fixed window that starts with its first post. Over the
+// cap it gets 429 and a Retry-After until the window ends. Counts are in
+// memory and start over on restart.
+const DEFAULT_ARTICLE_MAX: u64 = 5;
+const DEFAULT_ARTICLE_WINDOW_SECS: u64 = 60 * 60;
+const DEFAULT_COMMENT_MAX: u64 = 20;
+const DEFAULT_COMMENT_WINDOW_SECS: u64 = 10 * 60;
+const DEFAULT_REPORT_MAX: u64 = 10;
+const DEFAULT_REPORT_WINDOW_SECS: u64 = 60 * 60;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostKind {
+    Article,
+    Comment,
+    Report,
+}
+
+struct Limit {
+    max: u64, // 0 turns the limit off
+    window: Duration,
+}
+
+struct Window {
+    count: u64,
+    since: Instant,
+}
+
+pub struct PostLimiter {
+    articles: Limit,
+    comments: Limit,
+    reports: Limit,
+    windows: Mutex<HashMap<(PostKind, String), Window>>,
+}
+
+fn number_from_env(var: &str, default: u64) -> u64 {
+    env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+// A post refused for being over the limit; a 429 with Retry-After
+pub struct OverLimit {
+    retry_after_secs: u64,
+}
+
+impl From<OverLimit> for HttpResponse {
+    fn from(over: OverLimit) -> Self {
+        HttpResponse::TooManyRequests()
+            .append_header(("Retry-After", over.retry_after_secs.to_string()))
+            .body("You're posting too often, please try again later")
+    }
+}
+
+impl PostLimiter {
+    // ARTICLE_POST_LIMIT / ARTICLE_POST_WINDOW_SECS and COMMENT_POST_LIMIT /
+    // COMMENT_POST_WINDOW_SECS, and REPORT_LIMIT / REPORT_WINDOW_SECS, override
+    // the defaults; a limit of 0 is off
+    pub fn from_env() -> Self {
+        PostLimiter {
+            articles: Limit {
+                max: number_from_env("ARTICLE_POST_LIMIT", DEFAULT_ARTICLE_MAX),
+                window: Duration::from_secs(
+                    number_from_env("ARTICLE_POST_WINDOW_SECS", DEFAULT_ARTICLE_WINDOW_SECS).max(1),
+                ),
+            },
+            comments: Limit {
+                max: number_from_env("COMMENT_POST_LIMIT", DEFAULT_COMMENT_MAX),
+                window: Duration::from_secs(
+                    number_from_env("COMMENT_POST_WINDOW_SECS", DEFAULT_COMMENT_WINDOW_SECS).max(1),
+                ),
+            },
+            reports: Limit {
+                max: number_from_env("REPORT_LIMIT", DEFAULT_REPORT_MAX),
+                window: Duration::from_secs(number_from_env("REPORT_WINDOW_SECS", DEFAULT_REPORT_WINDOW_SECS).max(1)),
+            },
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn limit(&self, kind: PostKind) -> &Limit {
+        match kind {
+            PostKind::Article => &self.articles,
+            PostKind::Comment => &self.comments,
+            PostKind::Report => &self.reports,
+        }
+    }
+
+    // Counts a post attempt, or refuses it when the client is over its limit
+    pub fn check(&self, req: &HttpRequest, kind: PostKind) -> Result<(), OverLimit> {
+        let limit = self.limit(kind);
+        if limit.max == 0 {
+            return Ok(());
+        }
+        let ip = client::client_ip(req);
+        let mut windows = match self.windows.lock() {
+            Ok(w) => w,
+            Err(_) => return Ok(()),
+        };
+
+        let now = Instant::now();
+        let window = windows.entry((kind, ip.clone())).or_insert(Window { count: 0, since: now });
+        if now.duration_since(window.since) >= limit.window {
+            *window = Window { count: 0, since: now };
+        }
+        if window.count >= limit.max {
+            let left = limit.window.saturating_sub(now.duration_since(window.since));
+            log_error(&format!("Posting refused for {}: over the limit", ip));
+            return Err(OverLimit {
+                retry_after_secs: left.as_secs() + 1,
+            });
+        }
+        window.count += 1;
+
+        // Expired windows are dropped as the map grows
+        if windows.len() > 10_000 {
+            windows.retain(|(kind, _), w| now.duration_since(w.since) < self.limit(*kind).window);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn limiter(max: u64) -> PostLimiter {
+        let limit = || Limit {
+            max,
+            window: Duration::from_secs(60),
+        };
+        PostLimiter {
+            articles: limit(),
+            comments: limit(),
+            reports: limit(),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn request_from(addr: &str) -> HttpRequest {
+        TestRequest::default().peer_addr(addr.parse().unwrap()).to_http_request()
+    }
+
+    #[test]
+    fn the_post_at_the_limit_passes_and_the_next_is_refused() {
+        let limiter = limiter(3);
+        let req = request_from("198.51.100.7:4000");
+        for _ in 0..3 {
+            assert!(limiter.check(&req, PostKind::Article).is_ok());
+        }
+        let res = HttpResponse::from(limiter.check(&req, PostKind::Article).unwrap_err());
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+        assert!((1..=61).contains(&retry_after));
+    }
+
+    #[test]
+    fn limits_are_per_client_and_per_kind() {
+        let limiter = limiter(1);
+        assert!(limiter.check(&request_from("198.51.100.7:4000"), PostKind::Article).is_ok());
+        assert!(limiter.check(&request_from("198.51.100.7:4001"), PostKind::Article).is_err());
+        assert!(limiter.check(&request_from("198.51.100.7:4000"), PostKind::Comment).is_ok());
+        assert!(limiter.check(&request_from("203.0.113.9:4000"), PostKind::Article).is_ok());
+    }
+
+    #[test]
+    fn count_starts_over_when_the_window_ends() {
+        let limiter = limiter(2);
+        let req = request_from("198.51.100.7:4000");
+        for _ in 0..2 {
+            assert!(limiter.check(&req, PostKind::Comment).is_ok());
+        }
+        assert!(limiter.check(&req, PostKind::Comment).is_err());
+        for w in limiter.windows.lock().unwrap().values_mut() {
+            w.since -= Duration::from_secs(60);
+        }
+        for _ in 0..2 {
+            assert!(limiter.check(&req, PostKind::Comment).is_ok());
+        }
+        assert!(limiter.check(&req, PostKind::Comment).is_err());
+    }
+
+    #[test]
+    fn a_limit_of_zero_is_off() {
+        let limiter = limiter(0);
+        let req = request_from("198.51.100.7:4000");
+        for _ in 0..100 {
+            assert!(limiter.check(&req, PostKind::Report).is_ok());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::Error;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::cell::Cell;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+
+use crate::log_error;
+
+// Counts the SQL statements each request runs, to catch N+1 query patterns.
+// sqlx logs every statement under the "sqlx::query" target whichever pooled
+// connection ran it, so the count is taken from those log records and tied
+// to the request through a task-local. Debug builds only, and only when
+// QUERY_COUNT_WARN is set: requests running more statements than that are
+// logged with their count.
+const SQLX_QUERY_TARGET: &str = "sqlx::query";
+
+static WARN_THRESHOLD: OnceLock<Option<u32>> = OnceLock::new();
+
+tokio::task_local! {
+    static QUERIES: Rc<Cell<u32>>;
+}
+
+fn warn_threshold() -> Option<u32> {
+    *WARN_THRESHOLD.get_or_init(|| {
+        if !cfg!(debug_assertions) {
+            return None;
+        }
+        env::var("QUERY_COUNT_WARN")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|n| *n > 0)
+    })
+}
+
+// env_logger, plus counting of sqlx statement records when enabled
+struct CountingLogger {
+    inner: env_logger::Logger,
+    counting: bool,
+}
+
+impl CountingLogger {
+    fn is_statement(&self, metadata: &Metadata) -> bool {
+        self.counting && metadata.target() == SQLX_QUERY_TARGET
+    }
+}
+
+impl Log for CountingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.is_statement(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.is_statement(record.metadata()) {
+            let _ = QUERIES.try_with(|count| count.set(count.get() + 1));
+        }
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Replaces env_logger::init(); RUST_LOG filtering is unchanged
+pub fn init_logging() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let counting = warn_threshold().is_some();
+    // sqlx logs statements at debug level, which must get through to be counted
+    let max_level = if counting {
+        inner.filter().max(LevelFilter::Debug)
+    } else {
+        inner.filter()
+    };
+    if log::set_boxed_logger(Box::new(CountingLogger { inner, counting })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+// Response body that keeps counting while it streams, since streamed pages
+// run their batch queries after the handler has returned. The total is
+// checked once the body is dropped.
+struct CountedBody {
+    inner: BoxBody,
+    count: Rc<Cell<u32>>,
+    threshold: u32,
+    route: String,
+}
+
+impl MessageBody for CountedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.inner.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let count = this.count.clone();
+        QUERIES.sync_scope(count, || Pin::new(&mut this.inner).poll_next(cx))
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        let count = self.count.get();
+        if count > self.threshold {
+            log_error(&format!(
+                "{} ran {} queries, more than QUERY_COUNT_WARN={}",
+                self.route, count, self.threshold
+            ));
+        }
+    }
+}
+
+// Runs `work` with the statements it causes counted, so tests can hold a
+// page to a fixed number of queries. Requests made inside it add to this
+// count instead of keeping their own. Counts only with QUERY_COUNT_WARN set.
+pub async fn counted<F: Future>(work: F) -> (F::Output, u32) {
+    init_logging();
+    let count = Rc::new(Cell::new(0));
+    let output = QUERIES.scope(count.clone(), work).await;
+    (output, count.get())
+}
+
+pub async fn count_queries(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let threshold = match warn_threshold() {
+        Some(t) if QUERIES.try_with(|_| ()).is_err() => t,
+        _ => return next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+    };
+
+    let route = format!("{} {}", req.method(), req.match_pattern().unwrap_or_else(|| req.path().to_string()));
+    let count = Rc::new(Cell::new(0));
+    let res = QUERIES.scope(count.clone(), next.call(req)).await?;
+    Ok(res.map_body(|_, body| {
+        BoxBody::new(CountedBody {
+            inner: body.boxed(),
+            count,
+            threshold,
+            route,
+        })
+    }))
+}
@@ -0,0 +1,125 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use chrono::{TimeZone, Utc};
+use sqlx::PgPool;
+use std::sync::Mutex;
+
+use crate::{admin, paths};
+use crate::settings::SettingsCache;
+
+// Read-only mode: the site keeps serving pages, but anything that writes is
+// turned away with a 503 until it ends. An admin switches it on by hand with
+// the `read_only` setting; the disk watchdog (disk_watch) switches it on and
+// off by itself. The two are separate flags, so the watchdog recovering never
+// ends a read-only period an admin started.
+const RETRY_AFTER_SECS: u64 = 300;
+
+// Why the watchdog put the site in read-only mode
+#[derive(Clone)]
+pub struct Trigger {
+    pub reason: String,
+    pub since: i64,
+}
+
+#[derive(Default)]
+pub struct ReadOnly {
+    automatic: Mutex<Option<Trigger>>,
+}
+
+impl ReadOnly {
+    pub fn automatic(&self) -> Option<Trigger> {
+        self.automatic.lock().ok().and_then(|trigger| trigger.clone())
+    }
+
+    pub fn set_automatic(&self, trigger: Option<Trigger>) {
+        if let Ok(mut current) = self.automatic.lock() {
+            *current = trigger;
+        }
+    }
+
+    // One line for admin pages, or "" while the site is writable
+    pub fn status_html(&self, manual: bool) -> String {
+        let mut reasons = Vec::new();
+        if manual {
+            reasons.push("switched on by an admin (the read_only setting)".to_string());
+        }
+        if let Some(trigger) = self.automatic() {
+            let since = Utc
+                .timestamp_opt(trigger.since, 0)
+                .single()
+                .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default();
+            reasons.push(format!(
+                "switched on by the disk watchdog at {}: {}",
+                since,
+                html_escape::encode_text(&trigger.reason)
+            ));
+        }
+        if reasons.is_empty() {
+            return String::new();
+        }
+        format!(
+            r#"<div class="thread-age-notice">The site is read-only, {}.</div>"#,
+            reasons.join("; and ")
+        )
+    }
+}
+
+fn read_only_page() -> HttpResponse {
+    let base = paths::base();
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Read-Only</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Read-Only</h2>
+        <p>The site is in read-only mode for maintenance. Nothing you sent was saved; please try again later.</p>
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#
+    );
+    HttpResponse::ServiceUnavailable()
+        .append_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+        .content_type("text/html")
+        .body(html)
+}
+
+// Middleware turning away writes in read-only mode, before their handlers
+// start reading request bodies. Admin routes and staff sessions stay open so
+// staff can switch the mode off and delete things to free space; consent
+// choices only set a cookie.
+pub async fn guard_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let exempt = req.method() == Method::GET
+        || req.method() == Method::HEAD
+        || req.path() == "/admin"
+        || req.path().starts_with("/admin/")
+        || req.path() == "/consent"
+        || admin::is_admin(req.request());
+    if exempt {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let automatic = req
+        .app_data::<web::Data<ReadOnly>>()
+        .is_some_and(|read_only| read_only.automatic().is_some());
+    let read_only = automatic
+        || match (req.app_data::<web::Data<SettingsCache>>(), req.app_data::<web::Data<PgPool>>()) {
+            (Some(settings_cache), Some(pool)) => settings_cache.get(pool.get_ref()).await.read_only,
+            _ => false,
+        };
+    if read_only {
+        return Ok(req.into_response(read_only_page()));
+    }
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
@@ -0,0 +1,109 @@
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::collections::HashMap;
+
+use crate::text::{extract_references, extract_reply_targets};
+use crate::visibility;
+
+// Cross-references between articles. Links are extracted when an article or
+// comment is saved, so pages only need one lookup for every reference they show.
+
+#[derive(FromRow)]
+pub struct LinkedArticle {
+    pub id: i32,
+    pub title: String,
+}
+
+// Replaces the references made by an article body with those in `body`
+pub async fn record_article_refs(conn: &mut PgConnection, article_id: i32, body: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM article_links WHERE source_article_id = $1 AND source_comment_id IS NULL")
+        .bind(article_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for target in extract_references(body) {
+        sqlx::query("INSERT INTO article_links (source_article_id, target_article_id) VALUES ($1, $2)")
+            .bind(article_id)
+            .bind(target)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn record_comment_refs(
+    conn: &mut PgConnection,
+    article_id: i32,
+    comment_id: i32,
+    comment: &str,
+) -> Result<(), sqlx::Error> {
+    for target in extract_references(comment) {
+        sqlx::query(
+            "INSERT INTO article_links (source_article_id, source_comment_id, target_article_id) VALUES ($1, $2, $3)",
+        )
+        .bind(article_id)
+        .bind(comment_id)
+        .bind(target)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+// Replaces the `>>id` replies made by a comment. Only comments on the same
+// article count, and a comment can't reply to itself.
+pub async fn record_comment_replies(
+    conn: &mut PgConnection,
+    article_id: i32,
+    comment_id: i32,
+    comment: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM comment_replies WHERE source_comment_id = $1")
+        .bind(comment_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let targets: Vec<i32> = extract_reply_targets(comment).into_iter().collect();
+    if targets.is_empty() {
+        return Ok(());
+    }
+    sqlx::query(
+        "INSERT INTO comment_replies (source_comment_id, target_comment_id)
+         SELECT $1, id FROM comments WHERE id = ANY($2) AND article_id = $3 AND id <> $1",
+    )
+    .bind(comment_id)
+    .bind(&targets)
+    .bind(article_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+// Titles of every shown article referenced from an article or its comments
+pub async fn referenced_titles(pool: &PgPool, article_id: i32) -> Result<HashMap<i32, String>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, LinkedArticle>(&format!(
+        "SELECT DISTINCT a.id, a.title FROM article_links l
+         JOIN articles a ON a.id = l.target_article_id
+         WHERE l.source_article_id = $1 AND {}",
+        visibility::shown_articles("a")
+    ))
+    .bind(article_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.id, r.title)).collect())
+}
+
+// Shown articles whose body or comments reference this one. A reference
+// from a shadow-hidden comment waits for its release.
+pub async fn backlinks(pool: &PgPool, article_id: i32) -> Result<Vec<LinkedArticle>, sqlx::Error> {
+    sqlx::query_as::<_, LinkedArticle>(&format!(
+        "SELECT DISTINCT a.id, a.title FROM article_links l
+         JOIN articles a ON a.id = l.source_article_id
+         LEFT JOIN comments c ON c.id = l.source_comment_id
+         WHERE l.target_article_id = $1 AND {} AND (c.id IS NULL OR NOT c.shadow_hidden)
+         ORDER BY a.id",
+        visibility::shown_articles("a")
+    ))
+    .bind(article_id)
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,419 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::admin::{self, AdminAuth};
+use crate::mailer::{self, Outbox};
+use crate::post_limits::{PostKind, PostLimiter};
+use crate::{client, csrf, log_error, paths, tokens};
+
+// Visitors flag spam with the [report] links on articles and comments; staff
+// work through the open reports at /admin/reports, deleting the target with
+// the usual forms or dismissing the reports. Deleting a target resolves its
+// reports (see resolve_article and resolve_comments).
+const HASH_PURPOSE: &str = "report-ip";
+const MAX_REASON_CHARS: usize = 500;
+
+#[derive(Clone, Copy)]
+pub enum Target {
+    Article,
+    Comment,
+}
+
+impl Target {
+    fn parse(value: &str) -> Option<Target> {
+        match value {
+            "article" => Some(Target::Article),
+            "comment" => Some(Target::Comment),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Target::Article => "article",
+            Target::Comment => "comment",
+        }
+    }
+
+    // Where the public report form lives
+    fn report_path(self, id: i32) -> String {
+        match self {
+            Target::Article => format!("/articles/{}/report", id),
+            Target::Comment => format!("/comments/{}/report", id),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReportForm {
+    #[serde(default)]
+    csrf_token: String,
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct DismissForm {
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn page(title: &str, content: &str) -> String {
+    let base = paths::base();
+    format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>{title}</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>{title}</h2>
+        {content}
+        <a href="{base}/articles">← Back to All Articles</a>
+        </div>
+        </body>
+        </html>
+        "#
+    )
+}
+
+fn form_page(req: &HttpRequest, target: Target, id: i32) -> HttpResponse {
+    let content = format!(
+        r#"<p>Tell the moderators what's wrong with this {}. A reason is optional.</p>
+        <form action="{}" method="POST">
+            {}
+            <textarea name="reason" rows="3" maxlength="{}" placeholder="Reason (optional)"></textarea><br>
+            <input type="submit" value="Report">
+        </form>"#,
+        target.name(),
+        paths::url(&target.report_path(id)),
+        csrf::field(req),
+        MAX_REASON_CHARS
+    );
+    HttpResponse::Ok().content_type("text/html").body(page("Report", &content))
+}
+
+// GET /articles/{id}/report
+pub async fn report_article_form(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    form_page(&req, Target::Article, path.into_inner())
+}
+
+// GET /comments/{id}/report
+pub async fn report_comment_form(req: HttpRequest, path: web::Path<i32>) -> HttpResponse {
+    form_page(&req, Target::Comment, path.into_inner())
+}
+
+async fn target_exists(pool: &PgPool, target: Target, id: i32) -> Result<bool, sqlx::Error> {
+    let query = match target {
+        Target::Article => "SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)",
+        // A tombstone has nothing left to report
+        Target::Comment => "SELECT EXISTS(SELECT 1 FROM comments WHERE id = $1 AND NOT deleted)",
+    };
+    sqlx::query_scalar(query).bind(id).fetch_one(pool).await
+}
+
+// Subject and body of the mail telling staff about a new report
+fn notice_email(target: Target, id: i32, reason: &str) -> (String, String) {
+    let subject = format!("New report: {} #{}", target.name(), id);
+    let reason = if reason.is_empty() { "(no reason given)" } else { reason };
+    let body = format!(
+        "A visitor reported {} #{}.\n\nReason: {}\n\nOpen reports are listed at {} on the site.\n",
+        target.name(),
+        id,
+        reason,
+        paths::url("/admin/reports")
+    );
+    (subject, body)
+}
+
+async fn store(
+    req: &HttpRequest,
+    pool: &PgPool,
+    post_limiter: &PostLimiter,
+    outbox: &web::Data<Outbox>,
+    target: Target,
+    id: i32,
+    form: &ReportForm,
+) -> HttpResponse {
+    if let Err(res) = csrf::check(req, &form.csrf_token) {
+        return res.into();
+    }
+    if let Err(res) = post_limiter.check(req, PostKind::Report) {
+        return res.into();
+    }
+    match target_exists(pool, target, id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body(format!("That {} doesn't exist any more", target.name())),
+        Err(e) => {
+            log_error(&format!("Failed to look up reported {}: {}", target.name(), e));
+            return HttpResponse::InternalServerError().body("Failed to store report.");
+        }
+    }
+
+    let reason: String = form.reason.trim().chars().take(MAX_REASON_CHARS).collect();
+    let now = Utc::now().timestamp();
+    // A repeat from the same client counts on its open report; a new reason replaces the old one
+    let report_count: i32 = match sqlx::query_scalar(
+        "INSERT INTO reports (target_type, target_id, ip_hash, reason, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $5)
+         ON CONFLICT (target_type, target_id, ip_hash) WHERE resolved_at IS NULL DO UPDATE SET
+             report_count = reports.report_count + 1,
+             reason = CASE WHEN EXCLUDED.reason <> '' THEN EXCLUDED.reason ELSE reports.reason END,
+             updated_at = EXCLUDED.updated_at
+         RETURNING report_count",
+    )
+    .bind(target.name())
+    .bind(id)
+    .bind(tokens::keyed_hash(HASH_PURPOSE, &client::client_ip(req)))
+    .bind(&reason)
+    .bind(now)
+    .fetch_one(pool)
+    .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            log_error(&format!("Failed to store report: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to store report.");
+        }
+    };
+    // Repeats from the same client don't mail staff again
+    if report_count == 1 {
+        let (subject, body) = notice_email(target, id, &reason);
+        mailer::notify_admins(outbox, subject, body);
+    }
+
+    let content = format!("<p>Thanks, the moderators will take a look at this {}.</p>", target.name());
+    HttpResponse::Ok().content_type("text/html").body(page("Reported", &content))
+}
+
+// POST /articles/{id}/report
+pub async fn report_article(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    post_limiter: web::Data<PostLimiter>,
+    outbox: web::Data<Outbox>,
+    path: web::Path<i32>,
+    form: web::Form<ReportForm>,
+) -> HttpResponse {
+    store(&req, pool.get_ref(), &post_limiter, &outbox, Target::Article, path.into_inner(), &form).await
+}
+
+// POST /comments/{id}/report
+pub async fn report_comment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    post_limiter: web::Data<PostLimiter>,
+    outbox: web::Data<Outbox>,
+    path: web::Path<i32>,
+    form: web::Form<ReportForm>,
+) -> HttpResponse {
+    store(&req, pool.get_ref(), &post_limiter, &outbox, Target::Comment, path.into_inner(), &form).await
+}
+
+// Resolves the open reports of an article and of its comments. Call it in
+// the transaction deleting the article, before the comments cascade away.
+pub async fn resolve_article(conn: &mut PgConnection, article_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE reports SET resolved_at = $2, resolution = 'deleted'
+         WHERE resolved_at IS NULL AND (
+             (target_type = 'article' AND target_id = $1)
+             OR (target_type = 'comment' AND target_id IN (SELECT id FROM comments WHERE article_id = $1))
+         )",
+    )
+    .bind(article_id)
+    .bind(Utc::now().timestamp())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Resolves the open reports of comments being deleted or tombstoned
+pub async fn resolve_comments(conn: &mut PgConnection, comment_ids: &[i32]) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE reports SET resolved_at = $2, resolution = 'deleted'
+         WHERE resolved_at IS NULL AND target_type = 'comment' AND target_id = ANY($1)",
+    )
+    .bind(comment_ids)
+    .bind(Utc::now().timestamp())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Open reports of one target, taken together
+#[derive(FromRow)]
+struct OpenTarget {
+    target_type: String,
+    target_id: i32,
+    reporters: i64,
+    reports: i64,
+    reasons: Vec<String>,
+    last_reported_at: i64,
+    // The comment's article, for linking to it
+    article_id: Option<i32>,
+    title: Option<String>,
+    excerpt: Option<String>,
+}
+
+// GET /admin/reports
+pub async fn list_reports(req: HttpRequest, auth: AdminAuth, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    // Any staff member may read the queue
+    if let Err(res) = auth.authorize(&req, pool.get_ref(), "", None, "report queue").await {
+        return res;
+    }
+
+    let targets = match sqlx::query_as::<_, OpenTarget>(
+        "SELECT r.target_type, r.target_id,
+                COUNT(*) AS reporters, SUM(r.report_count)::BIGINT AS reports,
+                ARRAY_REMOVE(ARRAY_AGG(NULLIF(r.reason, '') ORDER BY r.updated_at DESC), NULL) AS reasons,
+                MAX(r.updated_at) AS last_reported_at,
+                COALESCE(a.id, c.article_id) AS article_id,
+                COALESCE(a.title, ca.title) AS title,
+                LEFT(c.comment, 200) AS excerpt
+         FROM reports r
+         LEFT JOIN articles a ON r.target_type = 'article' AND a.id = r.target_id
+         LEFT JOIN comments c ON r.target_type = 'comment' AND c.id = r.target_id
+         LEFT JOIN articles ca ON ca.id = c.article_id
+         WHERE r.resolved_at IS NULL
+         GROUP BY r.target_type, r.target_id, a.id, a.title, c.article_id, ca.title, c.comment
+         ORDER BY reports DESC, last_reported_at DESC",
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(targets) => targets,
+        Err(e) => {
+            log_error(&format!("Failed to fetch reports: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load reports");
+        }
+    };
+
+    let auth_fields = format!("{}{}", csrf::field(&req), admin::password_field(&req));
+
+    let mut rows_html = String::new();
+    for t in &targets {
+        let title = html_escape::encode_text(t.title.as_deref().unwrap_or("(gone)")).into_owned();
+        let (what, actions) = match (t.target_type.as_str(), t.article_id) {
+            ("article", Some(article_id)) => (
+                format!(r#"Article <a href="{base}/articles/{}">{}</a>"#, article_id, title),
+                format!(
+                    r#"<a href="{base}/articles/{}/edit" class="edit-link">[edit]</a> <a href="{base}/articles/{}/delete" class="delete-link">[delete]</a>"#,
+                    article_id, article_id
+                ),
+            ),
+            ("comment", Some(article_id)) => (
+                format!(
+                    r#"Comment <a href="{base}/articles/{}#c{}">#{}</a> on {}: <q>{}</q>"#,
+                    article_id,
+                    t.target_id,
+                    t.target_id,
+                    title,
+                    html_escape::encode_text(t.excerpt.as_deref().unwrap_or(""))
+                ),
+                format!(r#"<a href="{base}/comments/{}/delete" class="delete-link">[delete]</a>"#, t.target_id),
+            ),
+            // Deleted some other way than the resolving paths
+            _ => (format!("{} #{} (no longer exists)", t.target_type, t.target_id), String::new()),
+        };
+        let reasons = if t.reasons.is_empty() {
+            "<p>No reason given.</p>".to_string()
+        } else {
+            let items: String = t
+                .reasons
+                .iter()
+                .map(|reason| format!("<li>{}</li>", html_escape::encode_text(reason)))
+                .collect();
+            format!("<ul>{}</ul>", items)
+        };
+        let last = Utc
+            .timestamp_opt(t.last_reported_at, 0)
+            .single()
+            .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+        rows_html.push_str(&format!(
+            r#"<div class="comment"><p>{}</p><p>{} reports from {} clients, last {}</p>{}
+            <p>{}</p>
+            <form action="{base}/admin/reports/{}/{}/dismiss" method="POST">
+                {}
+                <input type="submit" value="Dismiss">
+            </form></div>"#,
+            what,
+            t.reports,
+            t.reporters,
+            last,
+            reasons,
+            actions,
+            t.target_type,
+            t.target_id,
+            auth_fields
+        ));
+    }
+    if targets.is_empty() {
+        rows_html.push_str("<p>No open reports.</p>");
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Reports</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>
+        <h1>Open Reports</h1>
+        {}
+        </body>
+        </html>
+        "#,
+        rows_html
+    );
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// POST /admin/reports/{target_type}/{id}/dismiss
+pub async fn dismiss(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, i32)>,
+    form: web::Form<DismissForm>,
+) -> HttpResponse {
+    let (target_type, target_id) = path.into_inner();
+    let target = match Target::parse(&target_type) {
+        Some(target) => target,
+        None => return HttpResponse::NotFound().body("Unknown report target"),
+    };
+
+    if let Err(res) = auth.check_csrf(&req, &form.csrf_token, &form.password) {
+        return res.into();
+    }
+    let staff = match auth.authorize(&req, pool.get_ref(), &form.password, None, "report dismissal").await {
+        Ok(staff) => staff,
+        Err(res) => return res,
+    };
+
+    if let Err(e) = sqlx::query(
+        "UPDATE reports SET resolved_at = $3, resolution = 'dismissed', resolved_by = $4
+         WHERE resolved_at IS NULL AND target_type = $1 AND target_id = $2",
+    )
+    .bind(target.name())
+    .bind(target_id)
+    .bind(Utc::now().timestamp())
+    .bind(&staff.username)
+    .execute(pool.get_ref())
+    .await
+    {
+        log_error(&format!("Failed to dismiss reports: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to dismiss reports.");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", paths::url("/admin/reports")))
+        .finish()
+}
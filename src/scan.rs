@@ -0,0 +1,267 @@
+use futures_util::future::LocalBoxFuture;
+use std::env;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::log_error;
+
+const CLAMD_TIMEOUT: Duration = Duration::from_secs(30);
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub enum ScanVerdict {
+    Clean,
+    Infected(String),
+}
+
+// Anything that can vet a file on disk. Errors mean the scanner couldn't
+// give a verdict (unreachable, protocol error, ...), not that the file is bad.
+pub trait Scanner {
+    fn scan<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, Result<ScanVerdict, String>>;
+}
+
+// Talks to clamd's INSTREAM command over TCP, streaming the file in chunks
+pub struct ClamdScanner {
+    addr: String,
+}
+
+impl Scanner for ClamdScanner {
+    fn scan<'a>(&'a self, path: &'a Path) -> LocalBoxFuture<'a, Result<ScanVerdict, String>> {
+        Box::pin(async move {
+            tokio::time::timeout(CLAMD_TIMEOUT, self.instream(path))
+                .await
+                .map_err(|_| "clamd timed out".to_string())?
+        })
+    }
+}
+
+impl ClamdScanner {
+    async fn instream(&self, path: &Path) -> Result<ScanVerdict, String> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Failed to open file for scanning: {}", e))?;
+        let mut stream = TcpStream::connect(&self.addr)
+            .await
+            .map_err(|e| format!("Failed to connect to clamd: {}", e))?;
+
+        stream
+            .write_all(b"zINSTREAM\0")
+            .await
+            .map_err(|e| format!("Failed to write to clamd: {}", e))?;
+
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = file
+                .read(&mut buf)
+                .await
+                .map_err(|e| format!("Failed to read file for scanning: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            stream
+                .write_all(&(n as u32).to_be_bytes())
+                .await
+                .map_err(|e| format!("Failed to write to clamd: {}", e))?;
+            stream
+                .write_all(&buf[..n])
+                .await
+                .map_err(|e| format!("Failed to write to clamd: {}", e))?;
+        }
+        stream
+            .write_all(&0u32.to_be_bytes())
+            .await
+            .map_err(|e| format!("Failed to write to clamd: {}", e))?;
+
+        let mut reply = Vec::new();
+        stream
+            .read_to_end(&mut reply)
+            .await
+            .map_err(|e| format!("Failed to read clamd reply: {}", e))?;
+
+        // Replies look like "stream: OK" or "stream: <signature> FOUND", NUL-terminated
+        let reply = String::from_utf8_lossy(&reply);
+        let reply = reply.trim_end_matches('\0').trim();
+        if reply.ends_with("OK") {
+            Ok(ScanVerdict::Clean)
+        } else if let Some(found) = reply.strip_suffix("FOUND") {
+            let signature = found.trim().trim_start_matches("stream:").trim();
+            Ok(ScanVerdict::Infected(signature.to_string()))
+        } else {
+            Err(format!("Unexpected clamd reply: {}", reply))
+        }
+    }
+}
+
+// The configured scanner, if any. Shared as app data.
+pub struct UploadScanner {
+    scanner: Option<Box<dyn Scanner + Send + Sync>>,
+}
+
+impl UploadScanner {
+    // Scanning is enabled by setting CLAMD_ADDR, e.g. "127.0.0.1:3310"
+    pub fn from_env() -> Self {
+        let scanner = env::var("CLAMD_ADDR")
+            .ok()
+            .filter(|addr| !addr.trim().is_empty())
+            .map(|addr| Box::new(ClamdScanner { addr }) as Box<dyn Scanner + Send + Sync>);
+        UploadScanner { scanner }
+    }
+}
+
+// What gets recorded on the media row
+pub struct ScanRecord {
+    pub status: &'static str,
+    pub duration_ms: Option<i64>,
+}
+
+impl ScanRecord {
+    // A record read back from the database; anything unrecognised counts
+    // as unscanned
+    pub fn stored(status: &str, duration_ms: Option<i64>) -> ScanRecord {
+        let status = ["clean", "skipped"].into_iter().find(|s| *s == status).unwrap_or("unscanned");
+        ScanRecord { status, duration_ms }
+    }
+}
+
+pub enum ScanRejection {
+    Infected,
+    Unavailable,
+}
+
+// Scans a quarantined upload. `fail_open` decides whether an unreachable
+// scanner lets the file through (marked "unscanned") or rejects it.
+pub async fn scan_file(
+    upload_scanner: &UploadScanner,
+    fail_open: bool,
+    path: &Path,
+) -> Result<ScanRecord, ScanRejection> {
+    let scanner = match &upload_scanner.scanner {
+        Some(s) => s,
+        None => {
+            return Ok(ScanRecord {
+                status: "skipped",
+                duration_ms: None,
+            })
+        }
+    };
+
+    let started = Instant::now();
+    let verdict = scanner.scan(path).await;
+    let duration_ms = Some(started.elapsed().as_millis() as i64);
+
+    match verdict {
+        Ok(ScanVerdict::Clean) => Ok(ScanRecord {
+            status: "clean",
+            duration_ms,
+        }),
+        Ok(ScanVerdict::Infected(signature)) => {
+            log_error(&format!("Upload rejected by virus scan: {}", signature));
+            Err(ScanRejection::Infected)
+        }
+        Err(e) => {
+            log_error(&format!("Virus scan unavailable: {}", e));
+            if fail_open {
+                Ok(ScanRecord {
+                    status: "unscanned",
+                    duration_ms,
+                })
+            } else {
+                Err(ScanRejection::Unavailable)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // Answers every scan the same way
+    struct Fake(fn() -> Result<ScanVerdict, String>);
+
+    impl Scanner for Fake {
+        fn scan<'a>(&'a self, _path: &'a Path) -> LocalBoxFuture<'a, Result<ScanVerdict, String>> {
+            Box::pin(async move { (self.0)() })
+        }
+    }
+
+    fn with(fake: fn() -> Result<ScanVerdict, String>) -> UploadScanner {
+        UploadScanner {
+            scanner: Some(Box::new(Fake(fake))),
+        }
+    }
+
+    #[actix_web::test]
+    async fn verdicts_become_records_or_rejections() {
+        let path = Path::new("unused");
+        let clean = scan_file(&with(|| Ok(ScanVerdict::Clean)), false, path).await;
+        assert!(matches!(clean, Ok(ScanRecord { status: "clean", duration_ms: Some(_) })));
+        let infected = scan_file(&with(|| Ok(ScanVerdict::Infected("Eicar".into()))), true, path).await;
+        assert!(matches!(infected, Err(ScanRejection::Infected)));
+        let none = scan_file(&UploadScanner { scanner: None }, false, path).await;
+        assert!(matches!(none, Ok(ScanRecord { status: "skipped", duration_ms: None })));
+    }
+
+    #[actix_web::test]
+    async fn unavailable_scanner_follows_fail_open() {
+        let path = Path::new("unused");
+        let open = scan_file(&with(|| Err("down".into())), true, path).await;
+        assert!(matches!(open, Ok(ScanRecord { status: "unscanned", .. })));
+        let closed = scan_file(&with(|| Err("down".into())), false, path).await;
+        assert!(matches!(closed, Err(ScanRejection::Unavailable)));
+    }
+
+    // A one-shot clamd that reads an INSTREAM request and sends `reply`;
+    // yields the bytes of the file as clamd reassembled them
+    async fn fake_clamd(reply: &'static [u8]) -> (String, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut command = [0u8; 10];
+            socket.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+            let mut received = Vec::new();
+            loop {
+                let mut len = [0u8; 4];
+                socket.read_exact(&mut len).await.unwrap();
+                let len = u32::from_be_bytes(len) as usize;
+                if len == 0 {
+                    break;
+                }
+                assert!(len <= CHUNK_SIZE);
+                let mut chunk = vec![0u8; len];
+                socket.read_exact(&mut chunk).await.unwrap();
+                received.extend_from_slice(&chunk);
+            }
+            socket.write_all(reply).await.unwrap();
+            received
+        });
+        (addr, handle)
+    }
+
+    #[actix_web::test]
+    async fn clamd_gets_the_file_in_chunks() {
+        let path = env::temp_dir().join(format!("articles1-scan-{}", std::process::id()));
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let (addr, clamd) = fake_clamd(b"stream: OK\0").await;
+        let verdict = ClamdScanner { addr }.scan(&path).await;
+        assert!(matches!(verdict, Ok(ScanVerdict::Clean)));
+        assert_eq!(clamd.await.unwrap(), data);
+
+        let (addr, _clamd) = fake_clamd(b"stream: Eicar-Test-Signature FOUND\0").await;
+        let verdict = ClamdScanner { addr }.scan(&path).await;
+        match verdict {
+            Ok(ScanVerdict::Infected(signature)) => assert_eq!(signature, "Eicar-Test-Signature"),
+            _ => panic!("expected an infected verdict"),
+        }
+
+        let (addr, _clamd) = fake_clamd(b"stream: what\0").await;
+        assert!(ClamdScanner { addr }.scan(&path).await.is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
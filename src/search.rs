@@ -0,0 +1,125 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::{log_error, paths, visibility};
+
+// Title matches outrank body matches through the A/B weights
+const SEARCH_VECTOR_SQL: &str =
+    "setweight(to_tsvector('english', title), 'A') || setweight(to_tsvector('english', body), 'B')";
+
+const REINDEX_BATCH_SIZE: i64 = 500;
+const MAX_RESULTS: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: Option<String>,
+}
+
+#[derive(FromRow)]
+struct SearchResult {
+    id: i32,
+    title: String,
+}
+
+// Recomputes the stored vector for one article. Call it in the same
+// transaction that changes the title or body.
+pub async fn index_article(conn: &mut PgConnection, article_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!(
+        "UPDATE articles SET search_vector = {}, search_indexed_at = updated_at WHERE id = $1",
+        SEARCH_VECTOR_SQL
+    ))
+    .bind(article_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Re-derives vectors for rows that were never indexed or changed since they
+// were. Works in batches so a large backfill doesn't hold long locks.
+pub async fn reindex_stale(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut total = 0;
+    loop {
+        let updated = sqlx::query(&format!(
+            "UPDATE articles SET search_vector = {}, search_indexed_at = updated_at
+             WHERE id IN (
+                SELECT id FROM articles
+                WHERE search_vector IS NULL OR search_indexed_at IS NULL OR search_indexed_at < updated_at
+                ORDER BY id LIMIT $1
+             )",
+            SEARCH_VECTOR_SQL
+        ))
+        .bind(REINDEX_BATCH_SIZE)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        total += updated;
+        if updated < REINDEX_BATCH_SIZE as u64 {
+            return Ok(total);
+        }
+    }
+}
+
+pub async fn search(pool: web::Data<PgPool>, query: web::Query<SearchQuery>) -> HttpResponse {
+    let base = paths::base();
+    let q = query.q.as_deref().unwrap_or("").trim().to_string();
+
+    let results = if q.is_empty() {
+        Vec::new()
+    } else {
+        match sqlx::query_as::<_, SearchResult>(&format!(
+            "SELECT id, title FROM articles, websearch_to_tsquery('english', $1) AS query
+             WHERE search_vector @@ query AND {}
+             ORDER BY ts_rank_cd(search_vector, query) DESC, bump_time DESC
+             LIMIT $2",
+            visibility::shown_articles("articles")
+        ))
+        .bind(&q)
+        .bind(MAX_RESULTS)
+        .fetch_all(pool.get_ref())
+        .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                log_error(&format!("Failed to search articles: {}", e));
+                return HttpResponse::InternalServerError().body("Search failed");
+            }
+        }
+    };
+
+    let mut results_html = String::new();
+    for result in &results {
+        results_html.push_str(&format!(
+            r#"<div class="article"><h2><a href="{base}/articles/{}" dir="auto">{}</a></h2></div>"#,
+            result.id,
+            html_escape::encode_text(&result.title)
+        ));
+    }
+    if !q.is_empty() && results.is_empty() {
+        results_html.push_str(r#"<div class="center-link">No articles matched your search.</div>"#);
+    }
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Search</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>
+        <h1>Search</h1>
+        <form action="{base}/search" method="GET" class="search-form">
+            <input type="text" name="q" value="{}" placeholder="Search articles">
+            <input type="submit" value="Search">
+        </form>
+        {}
+        </body>
+        </html>
+        "#,
+        html_escape::encode_double_quoted_attribute(&q),
+        results_html
+    );
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
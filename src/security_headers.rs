@@ -0,0 +1,90 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, CONTENT_TYPE, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::log_error;
+
+// Security headers on every response. HTML pages get a Content-Security-Policy
+// and frame protection; the pages carry no inline scripts or styles, so the
+// default policy only allows this site's own. Media under /uploads is on the
+// same origin and covered by 'self'; the article page frames its own
+// /media/{id}/inline previews, hence SAMEORIGIN rather than DENY.
+// CONTENT_SECURITY_POLICY replaces the policy (e.g. to allow an analytics
+// script), and BEHIND_TLS=1 adds Strict-Transport-Security. A header a
+// handler already set is left as it is.
+const DEFAULT_CSP: &str = "default-src 'self'; script-src 'self'; style-src 'self'; img-src 'self'; \
+    media-src 'self'; frame-src 'self'; object-src 'none'; base-uri 'self'; form-action 'self'; \
+    frame-ancestors 'self'";
+const REFERRER: &str = "strict-origin-when-cross-origin";
+const HSTS: &str = "max-age=31536000";
+
+struct Config {
+    csp: HeaderValue,
+    hsts: bool,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub fn init_from_env() {
+    let csp = match env::var("CONTENT_SECURITY_POLICY") {
+        Ok(value) if !value.trim().is_empty() => match HeaderValue::from_str(value.trim()) {
+            Ok(csp) => csp,
+            Err(_) => {
+                log_error("CONTENT_SECURITY_POLICY isn't a valid header value; using the default policy");
+                HeaderValue::from_static(DEFAULT_CSP)
+            }
+        },
+        _ => HeaderValue::from_static(DEFAULT_CSP),
+    };
+    let hsts = env::var("BEHIND_TLS").is_ok_and(|v| matches!(v.trim(), "1" | "true"));
+    let _ = CONFIG.set(Config { csp, hsts });
+}
+
+fn config() -> &'static Config {
+    CONFIG.get_or_init(|| Config {
+        csp: HeaderValue::from_static(DEFAULT_CSP),
+        hsts: false,
+    })
+}
+
+// Whether BEHIND_TLS says the site is only reached over HTTPS
+pub fn behind_tls() -> bool {
+    config().hsts
+}
+
+pub async fn add_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut res = next.call(req).await?;
+    let config = config();
+    let is_html = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+
+    let headers = res.headers_mut();
+    let mut set_default = |name: HeaderName, value: HeaderValue| {
+        if !headers.contains_key(&name) {
+            headers.insert(name, value);
+        }
+    };
+    set_default(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    set_default(REFERRER_POLICY, HeaderValue::from_static(REFERRER));
+    if is_html {
+        set_default(CONTENT_SECURITY_POLICY, config.csp.clone());
+        set_default(X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+    }
+    if config.hsts {
+        set_default(STRICT_TRANSPORT_SECURITY, HeaderValue::from_static(HSTS));
+    }
+    Ok(res)
+}
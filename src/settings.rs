@@ -0,0 +1,437 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::admin::{self, AdminAuth, Permission};
+use crate::body_format::BodyFormat;
+use crate::caches::{Invalidate, Target};
+use crate::csrf;
+use crate::log_error;
+use crate::paths;
+use crate::read_only::ReadOnly;
+
+// Site settings editable at /admin/settings. Stored as key/value rows so
+// adding a setting needs no migration: add the field, its default, and an
+// entry in SETTINGS below.
+#[derive(Clone)]
+pub struct Settings {
+    pub warn_necro_after_days: i64,
+    pub close_comments_after_days: i64,
+    pub scan_fail_open: bool,
+    pub max_quote_chars: i64,
+    pub provider_name: String,
+    pub provider_url: String,
+    pub tombstone_deleted_comments: bool,
+    pub comment_edit_minutes: i64,
+    pub reveal_body_after_chars: i64,
+    pub admin_display_name: String,
+    pub default_body_format: BodyFormat,
+    pub footer_markdown: String,
+    pub footer_powered_by: bool,
+    pub require_cookie_consent: bool,
+    pub structured_data_comments: i64,
+    pub max_title_chars: i64,
+    pub max_body_chars: i64,
+    pub max_comment_chars: i64,
+    pub article_cooldown_secs: i64,
+    pub comment_cooldown_secs: i64,
+    pub read_only: bool,
+    pub wrap_outbound_links: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            warn_necro_after_days: 0,
+            close_comments_after_days: 0,
+            scan_fail_open: false,
+            max_quote_chars: 500,
+            provider_name: "Articles".to_string(),
+            provider_url: String::new(),
+            tombstone_deleted_comments: false,
+            comment_edit_minutes: 15,
+            reveal_body_after_chars: 10_000,
+            admin_display_name: "Admin".to_string(),
+            default_body_format: BodyFormat::Plain,
+            footer_markdown: String::new(),
+            footer_powered_by: true,
+            require_cookie_consent: false,
+            structured_data_comments: 5,
+            max_title_chars: 200,
+            max_body_chars: 65_536,
+            max_comment_chars: 10_000,
+            article_cooldown_secs: 30,
+            comment_cooldown_secs: 10,
+            read_only: false,
+            wrap_outbound_links: true,
+        }
+    }
+}
+
+struct SettingDef {
+    key: &'static str,
+    label: &'static str,
+}
+
+const SETTINGS: &[SettingDef] = &[
+    SettingDef {
+        key: "warn_necro_after_days",
+        label: "Warn when commenting on threads inactive for this many days (0 disables)",
+    },
+    SettingDef {
+        key: "close_comments_after_days",
+        label: "Close comments on threads inactive for this many days (0 disables)",
+    },
+    SettingDef {
+        key: "scan_fail_open",
+        label: "Accept uploads unscanned when the virus scanner is unreachable (true/false)",
+    },
+    SettingDef {
+        key: "max_quote_chars",
+        label: "Longest article quote that can be pre-filled into a comment, in characters",
+    },
+    SettingDef {
+        key: "provider_name",
+        label: "Site name shown in link previews (oEmbed provider_name)",
+    },
+    SettingDef {
+        key: "provider_url",
+        label: "Public base URL of the site, e.g. https://example.com (blank: use the request host)",
+    },
+    SettingDef {
+        key: "tombstone_deleted_comments",
+        label: "Leave a [deleted] placeholder when a comment is deleted; the admin can delete it again to purge it (true/false)",
+    },
+    SettingDef {
+        key: "comment_edit_minutes",
+        label: "Minutes commenters can edit their own comment after posting (0 disables)",
+    },
+    SettingDef {
+        key: "reveal_body_after_chars",
+        label: "Collapse article bodies longer than this many characters behind a \"Read the rest\" expander (0 disables)",
+    },
+    SettingDef {
+        key: "admin_display_name",
+        label: "Name shown on official comments posted by the site operator",
+    },
+    SettingDef {
+        key: "default_body_format",
+        label: "Body format preselected on the submit form (markdown, plain or html)",
+    },
+    SettingDef {
+        key: "footer_markdown",
+        label: "Footer text on every page, in markdown (e.g. imprint and privacy links)",
+    },
+    SettingDef {
+        key: "footer_powered_by",
+        label: "Show \"Powered by Articles\" in the footer (true/false)",
+    },
+    SettingDef {
+        key: "require_cookie_consent",
+        label: "Ask visitors before setting cookies that aren't strictly necessary (true/false)",
+    },
+    SettingDef {
+        key: "structured_data_comments",
+        label: "Comments included in the search engine structured data (JSON-LD) of article pages (0 leaves them out)",
+    },
+    SettingDef {
+        key: "max_title_chars",
+        label: "Longest article title, in characters (0 for no limit)",
+    },
+    SettingDef {
+        key: "max_body_chars",
+        label: "Longest article body, in characters (0 for no limit)",
+    },
+    SettingDef {
+        key: "max_comment_chars",
+        label: "Longest comment, in characters (0 for no limit)",
+    },
+    SettingDef {
+        key: "article_cooldown_secs",
+        label: "Seconds a client must wait after posting an article before posting another (0 disables, at most a week)",
+    },
+    SettingDef {
+        key: "comment_cooldown_secs",
+        label: "Seconds a client must wait after posting a comment before posting another (0 disables, at most a week)",
+    },
+    SettingDef {
+        key: "read_only",
+        label: "Read-only mode: turn away new articles, comments and edits; admin pages keep working (true/false)",
+    },
+    SettingDef {
+        key: "wrap_outbound_links",
+        label: "Send external links in article bodies through /out to count clicks by domain, without passing on the referrer (true/false)",
+    },
+];
+
+impl Settings {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "warn_necro_after_days" => self.warn_necro_after_days = parse_non_negative(key, value)?,
+            "close_comments_after_days" => self.close_comments_after_days = parse_non_negative(key, value)?,
+            "scan_fail_open" => self.scan_fail_open = parse_bool(key, value)?,
+            "max_quote_chars" => self.max_quote_chars = parse_non_negative(key, value)?,
+            "provider_name" => self.provider_name = value.trim().to_string(),
+            "provider_url" => self.provider_url = value.trim().trim_end_matches('/').to_string(),
+            "tombstone_deleted_comments" => self.tombstone_deleted_comments = parse_bool(key, value)?,
+            "comment_edit_minutes" => self.comment_edit_minutes = parse_non_negative(key, value)?,
+            "reveal_body_after_chars" => self.reveal_body_after_chars = parse_non_negative(key, value)?,
+            "admin_display_name" => self.admin_display_name = value.trim().to_string(),
+            "default_body_format" => {
+                self.default_body_format =
+                    BodyFormat::parse(value).ok_or("default_body_format must be markdown, plain or html")?
+            }
+            "footer_markdown" => self.footer_markdown = value.trim().to_string(),
+            "footer_powered_by" => self.footer_powered_by = parse_bool(key, value)?,
+            "require_cookie_consent" => self.require_cookie_consent = parse_bool(key, value)?,
+            "structured_data_comments" => self.structured_data_comments = parse_non_negative(key, value)?,
+            "max_title_chars" => self.max_title_chars = parse_non_negative(key, value)?,
+            "max_body_chars" => self.max_body_chars = parse_non_negative(key, value)?,
+            "max_comment_chars" => self.max_comment_chars = parse_non_negative(key, value)?,
+            "article_cooldown_secs" => self.article_cooldown_secs = parse_non_negative(key, value)?,
+            "comment_cooldown_secs" => self.comment_cooldown_secs = parse_non_negative(key, value)?,
+            "read_only" => self.read_only = parse_bool(key, value)?,
+            "wrap_outbound_links" => self.wrap_outbound_links = parse_bool(key, value)?,
+            _ => return Err(format!("Unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> String {
+        match key {
+            "warn_necro_after_days" => self.warn_necro_after_days.to_string(),
+            "close_comments_after_days" => self.close_comments_after_days.to_string(),
+            "scan_fail_open" => self.scan_fail_open.to_string(),
+            "max_quote_chars" => self.max_quote_chars.to_string(),
+            "provider_name" => self.provider_name.clone(),
+            "provider_url" => self.provider_url.clone(),
+            "tombstone_deleted_comments" => self.tombstone_deleted_comments.to_string(),
+            "comment_edit_minutes" => self.comment_edit_minutes.to_string(),
+            "reveal_body_after_chars" => self.reveal_body_after_chars.to_string(),
+            "admin_display_name" => self.admin_display_name.clone(),
+            "default_body_format" => self.default_body_format.name().to_string(),
+            "footer_markdown" => self.footer_markdown.clone(),
+            "footer_powered_by" => self.footer_powered_by.to_string(),
+            "require_cookie_consent" => self.require_cookie_consent.to_string(),
+            "structured_data_comments" => self.structured_data_comments.to_string(),
+            "max_title_chars" => self.max_title_chars.to_string(),
+            "max_body_chars" => self.max_body_chars.to_string(),
+            "max_comment_chars" => self.max_comment_chars.to_string(),
+            "article_cooldown_secs" => self.article_cooldown_secs.to_string(),
+            "comment_cooldown_secs" => self.comment_cooldown_secs.to_string(),
+            "read_only" => self.read_only.to_string(),
+            "wrap_outbound_links" => self.wrap_outbound_links.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn parse_non_negative(key: &str, value: &str) -> Result<i64, String> {
+    match value.trim().parse::<i64>() {
+        Ok(n) if n >= 0 => Ok(n),
+        _ => Err(format!("{} must be a whole number of 0 or more", key)),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(format!("{} must be true or false", key)),
+    }
+}
+
+#[derive(FromRow)]
+struct SettingRow {
+    key: String,
+    value: String,
+}
+
+// Loaded once and shared by all workers; cleared when an admin saves settings
+#[derive(Default)]
+pub struct SettingsCache {
+    current: RwLock<Option<Settings>>,
+}
+
+impl SettingsCache {
+    pub async fn get(&self, pool: &PgPool) -> Settings {
+        if let Ok(current) = self.current.read() {
+            if let Some(settings) = current.as_ref() {
+                return settings.clone();
+            }
+        }
+
+        let settings = load(pool).await;
+        if let Ok(mut current) = self.current.write() {
+            *current = Some(settings.clone());
+        }
+        settings
+    }
+
+    pub fn invalidate(&self) {
+        if let Ok(mut current) = self.current.write() {
+            *current = None;
+        }
+    }
+}
+
+impl Invalidate for SettingsCache {
+    fn invalidate(&self, target: Target) -> Vec<String> {
+        if !matches!(target, Target::All | Target::Settings) {
+            return Vec::new();
+        }
+        SettingsCache::invalidate(self);
+        vec!["settings".to_string()]
+    }
+}
+
+async fn load(pool: &PgPool) -> Settings {
+    let mut settings = Settings::default();
+
+    let rows = match sqlx::query_as::<_, SettingRow>("SELECT key, value FROM settings")
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            log_error(&format!("Failed to load settings, using defaults: {}", e));
+            return settings;
+        }
+    };
+
+    for row in rows {
+        if let Err(e) = settings.set(&row.key, &row.value) {
+            log_error(&format!("Ignoring stored setting: {}", e));
+        }
+    }
+    settings
+}
+
+fn settings_page(req: &HttpRequest, settings: &Settings, message: &str) -> String {
+    let base = paths::base();
+    let read_only_status = req
+        .app_data::<web::Data<ReadOnly>>()
+        .map(|read_only| read_only.status_html(settings.read_only))
+        .unwrap_or_default();
+    let mut fields_html = String::new();
+    for def in SETTINGS {
+        fields_html.push_str(&format!(
+            r#"<label>{}</label><br>
+            <input type="text" name="{}" value="{}"><br>"#,
+            def.label,
+            def.key,
+            html_escape::encode_double_quoted_attribute(&settings.get(def.key))
+        ));
+    }
+
+    format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Settings</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="post-form-box">
+        <h2>Settings</h2>
+        {}
+        <p>{}</p>
+        <form action="{base}/admin/settings" method="POST">
+            {}
+            {}
+            {}
+            <input type="submit" value="Save Settings">
+        </form>
+        </div>
+        </body>
+        </html>
+        "#,
+        read_only_status,
+        html_escape::encode_text(message),
+        csrf::field(req),
+        fields_html,
+        admin::password_field(req)
+    )
+}
+
+pub async fn settings_form(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    cache: web::Data<SettingsCache>,
+) -> HttpResponse {
+    let settings = cache.get(pool.get_ref()).await;
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(settings_page(&req, &settings, ""))
+}
+
+pub async fn save_settings(
+    req: HttpRequest,
+    auth: AdminAuth,
+    pool: web::Data<PgPool>,
+    cache: web::Data<SettingsCache>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let password = form.get("password").map(String::as_str).unwrap_or("");
+    let submitted = form.get(csrf::FIELD_NAME).map(String::as_str).unwrap_or("");
+    if let Err(res) = auth.check_csrf(&req, submitted, password) {
+        return res.into();
+    }
+    if let Err(res) = auth.authorize(
+        &req,
+        pool.get_ref(),
+        password,
+        Some(Permission::ManageSettings),
+        "settings change",
+    )
+    .await
+    {
+        return res;
+    }
+
+    // Validate everything before writing anything
+    let mut settings = cache.get(pool.get_ref()).await;
+    for def in SETTINGS {
+        if let Some(value) = form.get(def.key) {
+            if let Err(e) = settings.set(def.key, value) {
+                return HttpResponse::BadRequest()
+                    .content_type("text/html")
+                    .body(settings_page(&req, &settings, &e));
+            }
+        }
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            log_error(&format!("Failed to start transaction: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to save settings.");
+        }
+    };
+
+    for def in SETTINGS {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO settings (key, value) VALUES ($1, $2)
+             ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+        )
+        .bind(def.key)
+        .bind(settings.get(def.key))
+        .execute(&mut *tx)
+        .await
+        {
+            log_error(&format!("Failed to save setting {}: {}", def.key, e));
+            return HttpResponse::InternalServerError().body("Failed to save settings.");
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        log_error(&format!("Failed to commit settings: {}", e));
+        return HttpResponse::InternalServerError().body("Failed to save settings.");
+    }
+
+    cache.invalidate();
+
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(settings_page(&req, &settings, "Settings saved."))
+}
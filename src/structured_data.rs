@@ -0,0 +1,161 @@
+use actix_web::HttpRequest;
+use chrono::DateTime;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use crate::db::comments::{self, CommentOrder};
+use crate::log_error;
+use crate::oembed;
+use crate::settings::Settings;
+
+// schema.org Article data for article pages, as a JSON-LD <script> block
+// search engines read. URLs are absolute, built like the canonical link.
+const MAX_COMMENTS: i64 = 50;
+
+pub struct ArticleFacts<'a> {
+    pub id: i32,
+    pub title: &'a str,
+    pub created_at: Option<i64>,
+    pub bump_time: i64,
+    // Public /uploads/... paths of the article's images
+    pub image_paths: Vec<&'a str>,
+}
+
+fn iso_date(timestamp: i64) -> Option<String> {
+    DateTime::from_timestamp(timestamp, 0).map(|dt| dt.to_rfc3339())
+}
+
+// JSON that can sit inside <script> as is: `<`, `>` and `&` are written as
+// \u escapes, which JSON parsers read back unchanged, so a title containing
+// `</script>` can't end the block
+fn script_safe(value: &Value) -> String {
+    value
+        .to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+async fn article_data(
+    pool: &PgPool,
+    req: &HttpRequest,
+    settings: &Settings,
+    article: &ArticleFacts<'_>,
+) -> Value {
+    let site = oembed::site_url(req, settings);
+    let url = oembed::article_url(req, settings, article.id);
+
+    let mut data = json!({
+        "@context": "https://schema.org",
+        "@type": "Article",
+        "headline": article.title,
+        "url": url,
+        "mainEntityOfPage": url,
+        "publisher": {
+            "@type": "Organization",
+            "name": settings.provider_name,
+            "url": site,
+        },
+    });
+    if let Some(published) = article.created_at.and_then(iso_date) {
+        data["datePublished"] = json!(published);
+    }
+    if let Some(modified) = iso_date(article.bump_time) {
+        data["dateModified"] = json!(modified);
+    }
+    if !article.image_paths.is_empty() {
+        let images: Vec<String> = article.image_paths.iter().map(|p| format!("{}{}", site, p)).collect();
+        data["image"] = json!(images);
+    }
+
+    match comments::visible_count(pool, article.id).await {
+        Ok(count) => data["commentCount"] = json!(count.unwrap_or(0)),
+        Err(e) => log_error(&format!("Failed to count comments for structured data: {}", e)),
+    }
+
+    let limit = settings.structured_data_comments.min(MAX_COMMENTS);
+    if limit > 0 {
+        let rows = comments::visible_page(pool, article.id, None, CommentOrder::Oldest, CommentOrder::Oldest.start(), limit)
+            .await
+            .unwrap_or_else(|e| {
+                log_error(&format!("Failed to fetch comments for structured data: {}", e));
+                Vec::new()
+            });
+        let comments: Vec<Value> = rows
+            .iter()
+            .filter(|row| !row.deleted)
+            .map(|row| {
+                let author = if row.is_admin {
+                    settings.admin_display_name.as_str()
+                } else {
+                    row.author_name.as_deref().unwrap_or("Anonymous")
+                };
+                let mut comment = json!({
+                    "@type": "Comment",
+                    "text": row.comment,
+                    "url": format!("{}#c{}", url, row.id),
+                    "author": { "@type": "Person", "name": author },
+                });
+                if let Some(created) = row.created_at.and_then(iso_date) {
+                    comment["dateCreated"] = json!(created);
+                }
+                comment
+            })
+            .collect();
+        if !comments.is_empty() {
+            data["comment"] = json!(comments);
+        }
+    }
+
+    data
+}
+
+fn script_block(data: &Value) -> String {
+    format!(r#"<script type="application/ld+json">{}</script>"#, script_safe(data))
+}
+
+// The <script type="application/ld+json"> block for an article page's <head>
+pub async fn script_tag(pool: &PgPool, req: &HttpRequest, settings: &Settings, article: &ArticleFacts<'_>) -> String {
+    script_block(&article_data(pool, req, settings, article).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPEN: &str = r#"<script type="application/ld+json">"#;
+
+    // The JSON inside a block, as a browser would hand it to a parser: up to
+    // the first `</script>`, whatever the case
+    fn block_json(block: &str) -> Value {
+        let body = block.strip_prefix(OPEN).unwrap();
+        let end = body.to_ascii_lowercase().find("</script").unwrap();
+        assert_eq!(&body[end..], "</script>", "{}", block);
+        serde_json::from_str(&body[..end]).unwrap()
+    }
+
+    #[test]
+    fn block_parses_as_json() {
+        let data = json!({
+            "@context": "https://schema.org",
+            "@type": "Article",
+            "headline": "Plain title",
+            "commentCount": 3,
+        });
+        assert_eq!(block_json(&script_block(&data)), data);
+    }
+
+    #[test]
+    fn closing_script_tag_in_text_stays_inside_the_block() {
+        let title = "Breaking</script><script>alert(1)</script> & </SCRIPT >more";
+        let data = json!({
+            "headline": title,
+            "comment": [{ "@type": "Comment", "text": "<!-- <script> -->" }],
+        });
+        let block = script_block(&data);
+        assert!(!block[OPEN.len()..block.len() - "</script>".len()].contains('<'), "{}", block);
+        let parsed = block_json(&block);
+        assert_eq!(parsed["headline"], title);
+        assert_eq!(parsed["comment"][0]["text"], "<!-- <script> -->");
+    }
+}
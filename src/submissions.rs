@@ -0,0 +1,178 @@
+use chrono::Utc;
+use rand::Rng;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::scan::ScanRecord;
+use crate::{media, unlinks};
+
+// Retries of article submissions. The submit form carries a random
+// submission id; every upload that makes it through scanning is recorded
+// under that id as soon as it's stored. A retry with the same id reuses a
+// stored file that matches by name and size instead of writing and scanning
+// it again, or all of them when the retry sends no file, and a retry of a
+// submission that was already published doesn't post it again. Unpublished
+// uploads expire after an hour.
+pub const FIELD_NAME: &str = "submission_id";
+const SUBMISSION_TTL_SECS: i64 = 60 * 60;
+
+pub fn new_id() -> String {
+    (0..16)
+        .map(|_| format!("{:02x}", rand::thread_rng().gen::<u8>()))
+        .collect()
+}
+
+// Ours are hex; a client making its own may send a UUID
+pub fn valid_id(id: &str) -> bool {
+    (16..=64).contains(&id.len()) && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+#[derive(FromRow)]
+pub struct StoredUpload {
+    pub media_path: String,
+    pub original_filename: String,
+    scan_status: String,
+    scan_duration_ms: Option<i64>,
+}
+
+impl StoredUpload {
+    pub fn scan(&self) -> ScanRecord {
+        ScanRecord::stored(&self.scan_status, self.scan_duration_ms)
+    }
+
+    fn on_disk(&self) -> bool {
+        media::disk_path(&self.media_path).is_some_and(|p| p.is_file())
+    }
+}
+
+// The article a submission already became, if it has
+pub async fn published(pool: &PgPool, submission_id: &str) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar("SELECT article_id FROM submissions WHERE submission_id = $1")
+        .bind(submission_id)
+        .fetch_optional(pool)
+        .await
+}
+
+// A file an earlier attempt stored with this name and size
+pub async fn find_upload(
+    pool: &PgPool,
+    submission_id: &str,
+    original_filename: &str,
+    size_bytes: usize,
+) -> Result<Option<StoredUpload>, sqlx::Error> {
+    let upload = sqlx::query_as::<_, StoredUpload>(
+        "SELECT media_path, original_filename, scan_status, scan_duration_ms FROM submission_uploads
+         WHERE submission_id = $1 AND original_filename = $2 AND size_bytes = $3 AND created_at > $4",
+    )
+    .bind(submission_id)
+    .bind(original_filename)
+    .bind(size_bytes as i64)
+    .bind(Utc::now().timestamp() - SUBMISSION_TTL_SECS)
+    .fetch_optional(pool)
+    .await?;
+    Ok(upload.filter(StoredUpload::on_disk))
+}
+
+// Everything earlier attempts stored, oldest first
+pub async fn stored_uploads(pool: &PgPool, submission_id: &str) -> Result<Vec<StoredUpload>, sqlx::Error> {
+    let uploads = sqlx::query_as::<_, StoredUpload>(
+        "SELECT media_path, original_filename, scan_status, scan_duration_ms FROM submission_uploads
+         WHERE submission_id = $1 AND created_at > $2 ORDER BY created_at",
+    )
+    .bind(submission_id)
+    .bind(Utc::now().timestamp() - SUBMISSION_TTL_SECS)
+    .fetch_all(pool)
+    .await?;
+    Ok(uploads.into_iter().filter(StoredUpload::on_disk).collect())
+}
+
+pub async fn record_upload(
+    pool: &PgPool,
+    submission_id: &str,
+    original_filename: &str,
+    size_bytes: usize,
+    media_path: &str,
+    scan: &ScanRecord,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO submission_uploads
+             (submission_id, original_filename, size_bytes, media_path, scan_status, scan_duration_ms, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (submission_id, original_filename, size_bytes) DO UPDATE
+         SET media_path = EXCLUDED.media_path, scan_status = EXCLUDED.scan_status,
+             scan_duration_ms = EXCLUDED.scan_duration_ms, created_at = EXCLUDED.created_at",
+    )
+    .bind(submission_id)
+    .bind(original_filename)
+    .bind(size_bytes as i64)
+    .bind(media_path)
+    .bind(scan.status)
+    .bind(scan.duration_ms)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Marks the submission published as `article_id`, in the transaction that
+// creates the article. False means a concurrent attempt got there first and
+// the transaction should be rolled back.
+pub async fn claim(conn: &mut PgConnection, submission_id: &str, article_id: i32) -> Result<bool, sqlx::Error> {
+    let claimed = sqlx::query(
+        "INSERT INTO submissions (submission_id, article_id, created_at) VALUES ($1, $2, $3)
+         ON CONFLICT (submission_id) DO NOTHING",
+    )
+    .bind(submission_id)
+    .bind(article_id)
+    .bind(Utc::now().timestamp())
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        == 1;
+    if claimed {
+        sqlx::query("DELETE FROM submission_uploads WHERE submission_id = $1")
+            .bind(submission_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(claimed)
+}
+
+// Forgets a submission's uploads and queues their files for removal
+pub async fn discard(pool: &PgPool, submission_id: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let paths: Vec<String> =
+        sqlx::query_scalar("DELETE FROM submission_uploads WHERE submission_id = $1 RETURNING media_path")
+            .bind(submission_id)
+            .fetch_all(&mut *tx)
+            .await?;
+    unlinks::enqueue(&mut tx, &paths).await?;
+    tx.commit().await
+}
+
+// Drops uploads and published markers older than an hour. The files go to
+// the unlink queue, which keeps any an article ended up using.
+pub async fn expire(pool: &PgPool) -> Result<u64, String> {
+    let cutoff = Utc::now().timestamp() - SUBMISSION_TTL_SECS;
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let paths: Vec<String> =
+        sqlx::query_scalar("DELETE FROM submission_uploads WHERE created_at <= $1 RETURNING media_path")
+            .bind(cutoff)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to expire submission uploads: {}", e))?;
+    unlinks::enqueue(&mut tx, &paths)
+        .await
+        .map_err(|e| format!("Failed to queue expired uploads: {}", e))?;
+    sqlx::query("DELETE FROM submissions WHERE created_at <= $1")
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to expire submissions: {}", e))?;
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit submission expiry: {}", e))?;
+    Ok(paths.len() as u64)
+}
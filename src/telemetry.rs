@@ -0,0 +1,293 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::Error;
+use std::env;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use tracing::Instrument;
+
+use crate::{access_log, log_error};
+
+// OpenTelemetry traces, exported over OTLP when OTEL_EXPORTER_OTLP_ENDPOINT
+// is set in a build with the `otel` feature. Each request becomes a trace
+// whose spans cover its SQL statements, upload scanning and page rendering.
+// Without the endpoint no subscriber is installed, so the spans around the
+// code cost a disabled-callsite check and nothing more.
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+pub fn init_from_env() {
+    let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|e| !e.trim().is_empty());
+    let enabled = match endpoint {
+        Some(endpoint) => install(endpoint.trim()),
+        None => false,
+    };
+    let _ = ENABLED.set(enabled);
+}
+
+fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+#[cfg(feature = "otel")]
+fn install(endpoint: &str) -> bool {
+    match otlp::install(endpoint) {
+        Ok(()) => true,
+        Err(e) => {
+            log_error(&format!("Failed to start OpenTelemetry export, tracing is off: {}", e));
+            false
+        }
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn install(_endpoint: &str) -> bool {
+    log_error("OTEL_EXPORTER_OTLP_ENDPOINT is set, but this build lacks the `otel` feature; tracing is off");
+    false
+}
+
+// Flushes spans still waiting for export
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    {
+        if enabled() {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+// Middleware opening the root span for each request. The span stays open
+// while the body streams, since streamed pages run queries after the
+// handler has returned.
+pub async fn trace_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !enabled() {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let route = req.match_pattern().unwrap_or_else(|| "<unmatched>".to_string());
+    let request_id = access_log::request_id().unwrap_or_else(access_log::new_request_id);
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", req.method(), route),
+        http.method = %req.method(),
+        http.route = %route,
+        request_id = %request_id,
+        http.status_code = tracing::field::Empty,
+    );
+    let res = next.call(req).instrument(span.clone()).await?;
+    span.record("http.status_code", res.status().as_u16());
+    Ok(res.map_body(|_, body| {
+        BoxBody::new(TracedBody {
+            inner: body.boxed(),
+            span,
+        })
+    }))
+}
+
+struct TracedBody {
+    inner: BoxBody,
+    span: tracing::Span,
+}
+
+impl MessageBody for TracedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.inner.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let _entered = this.span.enter();
+        Pin::new(&mut this.inner).poll_next(cx)
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otlp {
+    use opentelemetry::trace::{Span as _, SpanKind, TraceError, Tracer as _};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::{config, Tracer};
+    use opentelemetry_sdk::{runtime, Resource};
+    use std::time::{Duration, SystemTime};
+    use tracing::field::{Field, Visit};
+    use tracing::{Level, Subscriber};
+    use tracing_opentelemetry::{OtelData, PreSampledTracer};
+    use tracing_subscriber::filter::Targets;
+    use tracing_subscriber::layer::{self, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    // sqlx reports each statement as an event once it has finished
+    const SQLX_QUERY_TARGET: &str = "sqlx::query";
+
+    pub fn install(endpoint: &str) -> Result<(), TraceError> {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+            .with_trace_config(
+                config().with_resource(Resource::new(vec![KeyValue::new("service.name", env!("CARGO_PKG_NAME"))])),
+            )
+            .install_batch(runtime::Tokio)?;
+        tracing::subscriber::set_global_default(subscriber(tracer)).map_err(|e| TraceError::Other(Box::new(e)))
+    }
+
+    // Only our own spans and sqlx statements; the exporter's own networking
+    // would otherwise trace itself
+    fn subscriber(tracer: Tracer) -> impl Subscriber + Send + Sync {
+        let targets = Targets::new()
+            .with_target(env!("CARGO_CRATE_NAME"), Level::INFO)
+            .with_target(SQLX_QUERY_TARGET, Level::DEBUG);
+        tracing_subscriber::registry()
+            .with(targets)
+            .with(tracing_opentelemetry::layer().with_tracer(tracer.clone()))
+            .with(QuerySpans { tracer })
+    }
+
+    // Turns sqlx statement events into child spans of whatever span ran
+    // them, backdated by the statement's elapsed time
+    struct QuerySpans {
+        tracer: Tracer,
+    }
+
+    #[derive(Default)]
+    struct QueryFields {
+        statement: Option<String>,
+        summary: Option<String>,
+        elapsed_secs: Option<f64>,
+    }
+
+    impl Visit for QueryFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            match field.name() {
+                "db.statement" => self.statement = Some(value.to_string()),
+                "summary" => self.summary = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        fn record_f64(&mut self, field: &Field, value: f64) {
+            if field.name() == "elapsed_secs" {
+                self.elapsed_secs = Some(value);
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.record_str(field, &format!("{:?}", value));
+        }
+    }
+
+    impl<S> Layer<S> for QuerySpans
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_event(&self, event: &tracing::Event<'_>, ctx: layer::Context<'_, S>) {
+            if event.metadata().target() != SQLX_QUERY_TARGET {
+                return;
+            }
+            let mut fields = QueryFields::default();
+            event.record(&mut fields);
+
+            let parent = ctx
+                .event_span(event)
+                .and_then(|span| {
+                    let mut extensions = span.extensions_mut();
+                    extensions.get_mut::<OtelData>().map(|data| self.tracer.sampled_context(data))
+                })
+                .unwrap_or_default();
+            let end = SystemTime::now();
+            let elapsed = fields.elapsed_secs.filter(|s| s.is_finite() && *s >= 0.0).unwrap_or(0.0);
+            let start = end.checked_sub(Duration::from_secs_f64(elapsed)).unwrap_or(end);
+            // sqlx leaves db.statement empty when the summary is the whole statement
+            let statement = fields
+                .statement
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .or(fields.summary)
+                .unwrap_or_default();
+
+            let mut span = self
+                .tracer
+                .span_builder("db.query")
+                .with_kind(SpanKind::Client)
+                .with_start_time(start)
+                .with_attributes(vec![
+                    KeyValue::new("db.system", "postgresql"),
+                    KeyValue::new("db.statement", statement),
+                ])
+                .start_with_context(&self.tracer, &parent);
+            span.end_with_timestamp(end);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::telemetry::{trace_requests, ENABLED};
+        use actix_web::middleware::from_fn;
+        use actix_web::{test, web, App, HttpResponse};
+        use futures_util::future::BoxFuture;
+        use opentelemetry::trace::TracerProvider as _;
+        use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+        use opentelemetry_sdk::trace::TracerProvider;
+        use sqlx::postgres::PgPoolOptions;
+        use sqlx::PgPool;
+        use std::sync::{Arc, Mutex};
+
+        // Keeps exported spans for the test to look at
+        #[derive(Clone, Debug, Default)]
+        struct Collected(Arc<Mutex<Vec<SpanData>>>);
+
+        impl SpanExporter for Collected {
+            fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+                self.0.lock().unwrap().extend(batch);
+                Box::pin(async { Ok(()) })
+            }
+        }
+
+        async fn one_query(pool: web::Data<PgPool>) -> HttpResponse {
+            sqlx::query("SELECT 1").execute(pool.get_ref()).await.unwrap();
+            HttpResponse::Ok().finish()
+        }
+
+        #[actix_web::test]
+        async fn request_span_has_a_query_child() {
+            let (url, _container) = match crate::dev_db::test_server().await {
+                Some(server) => server,
+                None => return,
+            };
+            let collected = Collected::default();
+            let provider = TracerProvider::builder().with_simple_exporter(collected.clone()).build();
+            let _subscriber = tracing::subscriber::set_default(subscriber(provider.tracer("test")));
+            let _ = ENABLED.set(true);
+
+            let pool = PgPoolOptions::new().connect_lazy(&url).unwrap();
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(pool))
+                    .wrap(from_fn(trace_requests))
+                    .route("/traced", web::get().to(one_query)),
+            )
+            .await;
+            let res = test::call_service(&app, test::TestRequest::get().uri("/traced").to_request()).await;
+            assert!(res.status().is_success());
+            drop(res);
+            provider.force_flush();
+
+            let spans = collected.0.lock().unwrap();
+            let request = spans.iter().find(|s| s.name == "GET /traced").expect("no request span");
+            let query = spans.iter().find(|s| s.name == "db.query").expect("no query span");
+            assert_eq!(query.parent_span_id, request.span_context.span_id());
+            assert_eq!(query.span_context.trace_id(), request.span_context.trace_id());
+            assert!(request.attributes.iter().any(|kv| kv.key.as_str() == "request_id"));
+            assert!(query.attributes.iter().any(|kv| kv.value.as_str() == "SELECT 1"));
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{outbound, paths};
+
+// Renders markdown to HTML. Raw HTML in the source is shown as text and
+// links/images with script-capable schemes are neutralized.
+pub fn render_markdown(source: &str) -> String {
+    render_markdown_with(source, None, None)
+}
+
+// Same as render_markdown, for article bodies: `#123` references in text are
+// linked as in link_references (but not inside links or image alt text), and
+// external links get rel="nofollow noopener noreferrer". With `outbound_for`
+// they also go through /out, signed for that article.
+pub fn render_markdown_linked(source: &str, titles: &HashMap<i32, String>, outbound_for: Option<i32>) -> String {
+    render_markdown_with(source, Some(titles), outbound_for)
+}
+
+fn render_markdown_with(source: &str, titles: Option<&HashMap<i32, String>>, outbound_for: Option<i32>) -> String {
+    let mut link_depth = 0;
+    let parser = Parser::new_ext(source, Options::ENABLE_STRIKETHROUGH).map(|event| match event {
+        Event::Html(raw) => Event::Text(raw),
+        // Written out by hand to carry rel, which the HTML writer has no way to add
+        Event::Start(Tag::Link(_, dest, title)) if titles.is_some() && outbound::is_external(&dest) => {
+            link_depth += 1;
+            let href = match outbound_for {
+                Some(article_id) => outbound::wrap(article_id, dest.trim()),
+                None => dest.trim().to_string(),
+            };
+            let title = if title.is_empty() {
+                String::new()
+            } else {
+                format!(r#" title="{}""#, html_escape::encode_double_quoted_attribute(&title))
+            };
+            Event::Html(
+                format!(
+                    r#"<a href="{}" rel="{}"{}>"#,
+                    html_escape::encode_double_quoted_attribute(&href),
+                    outbound::REL,
+                    title
+                )
+                .into(),
+            )
+        }
+        Event::End(Tag::Link(_, dest, _)) if titles.is_some() && outbound::is_external(&dest) => {
+            link_depth -= 1;
+            Event::Html("</a>".into())
+        }
+        Event::Start(Tag::Link(kind, dest, title)) => {
+            link_depth += 1;
+            Event::Start(Tag::Link(kind, safe_url(dest), title))
+        }
+        Event::End(Tag::Link(kind, dest, title)) => {
+            link_depth -= 1;
+            Event::End(Tag::Link(kind, dest, title))
+        }
+        Event::Start(Tag::Image(kind, dest, title)) => {
+            link_depth += 1;
+            Event::Start(Tag::Image(kind, safe_url(dest), title))
+        }
+        Event::End(Tag::Image(kind, dest, title)) => {
+            link_depth -= 1;
+            Event::End(Tag::Image(kind, dest, title))
+        }
+        Event::Text(text) => match titles {
+            Some(titles) if link_depth == 0 && text.contains('#') => {
+                Event::Html(link_references(&html_escape::encode_text(&text), titles).into())
+            }
+            _ => Event::Text(text),
+        },
+        other => other,
+    });
+
+    let mut out = String::new();
+    html::push_html(&mut out, parser);
+    out
+}
+
+fn safe_url(dest: CowStr<'_>) -> CowStr<'_> {
+    let lower = dest.trim().to_ascii_lowercase();
+    if lower.starts_with("javascript:") || lower.starts_with("vbscript:") || lower.starts_with("data:") {
+        CowStr::Borrowed("#")
+    } else {
+        dest
+    }
+}
+
+// Positions of `#123`-style article references as (start, end, id). The '#'
+// must not follow a word character or '&', so HTML entities like `&#39;`
+// and anchors inside words are left alone.
+fn reference_spans(text: &str) -> Vec<(usize, usize, i32)> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let starts_token = bytes[i] == b'#'
+            && (i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || matches!(bytes[i - 1], b'_' | b'&' | b'#')));
+        if starts_token {
+            let mut j = i + 1;
+            while j < bytes.len() && bytes[j].is_ascii_digit() {
+                j += 1;
+            }
+            let ends_token = j == bytes.len() || !(bytes[j].is_ascii_alphanumeric() || bytes[j] == b'_');
+            if j > i + 1 && ends_token {
+                if let Ok(id) = text[i + 1..j].parse::<i32>() {
+                    spans.push((i, j, id));
+                }
+                i = j;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    spans
+}
+
+// Distinct article ids referenced in a body or comment
+pub fn extract_references(text: &str) -> BTreeSet<i32> {
+    reference_spans(text).into_iter().map(|(_, _, id)| id).collect()
+}
+
+// Replaces references in already-escaped text with links titled after
+// their target. Ids missing
+// from `titles` (deleted or never existed) keep their raw `#123` text.
+pub fn link_references(text: &str, titles: &HashMap<i32, String>) -> String {
+    let base = paths::base();
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end, id) in reference_spans(text) {
+        if let Some(title) = titles.get(&id) {
+            out.push_str(&text[last..start]);
+            out.push_str(&format!(
+                r#"<a href="{base}/articles/{}" class="article-ref" dir="auto">{}</a>"#,
+                id,
+                html_escape::encode_text(title)
+            ));
+            last = end;
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+// Positions of `>>123`-style replies to comment 123 as (start, end, id).
+// The `>>` must start the text or follow whitespace.
+fn reply_spans(text: &str) -> Vec<(usize, usize, i32)> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i + 2 < bytes.len() {
+        let starts_token = bytes[i] == b'>' && bytes[i + 1] == b'>' && (i == 0 || bytes[i - 1].is_ascii_whitespace());
+        if starts_token {
+            let mut j = i + 2;
+            while j < bytes.len() && bytes[j].is_ascii_digit() {
+                j += 1;
+            }
+            let ends_token = j == bytes.len() || !(bytes[j].is_ascii_alphanumeric() || bytes[j] == b'_');
+            if j > i + 2 && ends_token {
+                if let Ok(id) = text[i + 2..j].parse::<i32>() {
+                    spans.push((i, j, id));
+                }
+                i = j;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    spans
+}
+
+// Distinct comment ids a comment replies to
+pub fn extract_reply_targets(text: &str) -> BTreeSet<i32> {
+    reply_spans(text).into_iter().map(|(_, _, id)| id).collect()
+}
+
+// Comment text as HTML: escaped, with references linked, replies linked to
+// the comment's anchor on the same page, and quoted lines as greentext
+pub fn render_comment(text: &str, titles: &HashMap<i32, String>) -> String {
+    text.lines()
+        .map(|line| {
+            let mut out = String::with_capacity(line.len());
+            let mut last = 0;
+            for (start, end, id) in reply_spans(line) {
+                out.push_str(&link_references(&html_escape::encode_text(&line[last..start]), titles));
+                out.push_str(&format!(r##"<a href="#c{}" class="reply-ref">&gt;&gt;{}</a>"##, id, id));
+                last = end;
+            }
+            out.push_str(&link_references(&html_escape::encode_text(&line[last..]), titles));
+            if line.trim_start().starts_with('>') {
+                format!(r#"<span class="greentext">{}</span>"#, out)
+            } else {
+                out
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Sentences of an article body that can be offered as quotes, split on line
+// breaks and sentence-ending punctuation
+pub fn quote_fragments(body: &str) -> Vec<&str> {
+    let mut fragments = Vec::new();
+    for line in body.lines() {
+        let mut start = 0;
+        let bytes = line.as_bytes();
+        for i in 0..bytes.len() {
+            let ends_sentence = matches!(bytes[i], b'.' | b'!' | b'?')
+                && bytes.get(i + 1).is_none_or(|b| b.is_ascii_whitespace());
+            if ends_sentence {
+                fragments.push(line[start..=i].trim());
+                start = i + 1;
+            }
+        }
+        fragments.push(line[start..].trim());
+    }
+    fragments.retain(|f| !f.is_empty());
+    fragments
+}
+
+// Turns a quote into greentext lines for the comment box. Control characters
+// are dropped; quotes over `max_chars` are refused rather than truncated.
+pub fn quote_prefill(quote: &str, max_chars: usize) -> Result<String, String> {
+    let cleaned: String = quote
+        .chars()
+        .filter(|c| *c == '\n' || !c.is_control())
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() {
+        return Err("Quote is empty.".to_string());
+    }
+    if cleaned.chars().count() > max_chars {
+        return Err(format!("Quote is too long (maximum {} characters).", max_chars));
+    }
+
+    let mut out = String::new();
+    for line in cleaned.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        out.push_str("> ");
+        out.push_str(line);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+// Bidi controls that open an embedding, override or isolate, with the
+// character that closes each
+const BIDI_OPENERS: &[(char, char)] = &[
+    ('\u{202A}', '\u{202C}'), // LRE
+    ('\u{202B}', '\u{202C}'), // RLE
+    ('\u{202D}', '\u{202C}'), // LRO
+    ('\u{202E}', '\u{202C}'), // RLO
+    ('\u{2066}', '\u{2069}'), // LRI
+    ('\u{2067}', '\u{2069}'), // RLI
+    ('\u{2068}', '\u{2069}'), // FSI
+];
+
+// Makes user text safe to lay out next to other markup. Control characters
+// other than tabs and line breaks are dropped, a closing PDF or PDI without
+// a matching opener is dropped, and openers still open at the end of a line
+// are closed there, so an override can't run past the text it came with.
+// Bidi marks (LRM, RLM, ALM) are harmless and kept.
+pub fn balance_bidi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut open: Vec<char> = Vec::new(); // closers owed, innermost last
+    let close_all = |out: &mut String, open: &mut Vec<char>| {
+        while let Some(closer) = open.pop() {
+            out.push(closer);
+        }
+    };
+    for c in text.chars() {
+        if let Some((_, closer)) = BIDI_OPENERS.iter().find(|(opener, _)| *opener == c) {
+            open.push(*closer);
+            out.push(c);
+        } else if c == '\u{202C}' || c == '\u{2069}' {
+            if open.last() == Some(&c) {
+                open.pop();
+                out.push(c);
+            }
+        } else if c == '\n' {
+            close_all(&mut out, &mut open);
+            out.push(c);
+        } else if !c.is_control() || c == '\t' || c == '\r' {
+            out.push(c);
+        }
+    }
+    close_all(&mut out, &mut open);
+    out
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+// Splits rendered HTML after roughly `max_chars` visible characters for a
+// "read the rest" expander. The split lands on whitespace outside any tag,
+// entity or open element, so both halves are well-formed on their own.
+// Returns None when the text is short enough or no safe point exists.
+pub fn split_for_reveal(html: &str, max_chars: usize) -> Option<(&str, &str)> {
+    let mut visible = 0;
+    let mut depth: usize = 0;
+    let mut chars = html.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '<' => {
+                let end = html[i..].find('>').map(|e| i + e)?;
+                let tag = &html[i + 1..end];
+                let name: String = tag
+                    .trim_start_matches('/')
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                if tag.starts_with('/') {
+                    depth = depth.saturating_sub(1);
+                } else if !name.is_empty() && !tag.ends_with('/') && !VOID_ELEMENTS.contains(&name.as_str()) {
+                    depth += 1;
+                }
+                while chars.peek().is_some_and(|(j, _)| *j <= end) {
+                    chars.next();
+                }
+            }
+            '&' => {
+                // Entities count as the one character they stand for
+                if let Some(end) = html[i..].find(';').filter(|e| *e <= 32) {
+                    while chars.peek().is_some_and(|(j, _)| *j <= i + end) {
+                        chars.next();
+                    }
+                }
+                visible += 1;
+            }
+            c if c.is_whitespace() && depth == 0 && visible >= max_chars => {
+                let rest = html[i..].trim_start();
+                if rest.is_empty() {
+                    return None;
+                }
+                return Some((&html[..i], rest));
+            }
+            _ => visible += 1,
+        }
+    }
+    None
+}
+
+// Words of visible text in rendered HTML, ignoring tags
+pub fn visible_word_count(html: &str) -> usize {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace().count()
+}
+
+// Distinct http(s) URLs in an article body: markdown link and autolink
+// destinations plus bare URLs in plain text. Other schemes are skipped.
+pub fn outbound_urls(body: &str) -> BTreeSet<String> {
+    let mut urls = BTreeSet::new();
+    for event in Parser::new_ext(body, Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Start(Tag::Link(_, dest, _)) if outbound::is_external(&dest) => {
+                urls.insert(dest.trim().to_string());
+            }
+            Event::Text(text) => {
+                for word in text.split_whitespace() {
+                    let word = word.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'']);
+                    if outbound::is_external(word) {
+                        urls.insert(word.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Open elements left by `html`, with void elements skipped
+    fn open_elements(html: &str) -> Vec<String> {
+        let mut open = Vec::new();
+        for tag in html.split('<').skip(1) {
+            let tag = &tag[..tag.find('>').unwrap()];
+            let name: String = tag.trim_start_matches('/').chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+            if tag.starts_with('/') {
+                assert_eq!(open.pop().as_deref(), Some(name.as_str()), "{}", html);
+            } else if !VOID_ELEMENTS.contains(&name.as_str()) {
+                open.push(name);
+            }
+        }
+        open
+    }
+
+    fn assert_balanced_split(markdown: &str, max_chars: usize) -> (String, String) {
+        let html = render_markdown(markdown);
+        let (shown, rest) = split_for_reveal(&html, max_chars).expect("no split point");
+        assert!(open_elements(shown).is_empty(), "shown half: {}", shown);
+        assert!(open_elements(rest).is_empty(), "rest: {}", rest);
+        (shown.to_string(), rest.to_string())
+    }
+
+    #[test]
+    fn split_waits_for_the_end_of_a_list() {
+        let (shown, rest) = assert_balanced_split("- one two three\n- four five\n\nafter the list", 4);
+        assert!(shown.ends_with("</ul>"), "{}", shown);
+        assert_eq!(rest, "<p>after the list</p>\n");
+    }
+
+    #[test]
+    fn split_waits_for_the_end_of_a_blockquote() {
+        let (shown, rest) = assert_balanced_split("> quoted words\n> more quoted words\n\nreply", 3);
+        assert!(shown.ends_with("</blockquote>"), "{}", shown);
+        assert_eq!(rest, "<p>reply</p>\n");
+    }
+
+    #[test]
+    fn split_waits_for_the_end_of_a_code_block() {
+        let (shown, rest) = assert_balanced_split("```\nlet a = 1;\n\nlet b = 2;\n```\n\nafter the code", 2);
+        assert!(shown.ends_with("</code></pre>"), "{}", shown);
+        assert!(shown.contains("let b = 2;"), "{}", shown);
+        assert_eq!(rest, "<p>after the code</p>\n");
+    }
+
+    #[test]
+    fn split_between_words_of_a_paragraph_waits_for_its_end() {
+        let (shown, rest) = assert_balanced_split("first paragraph here\n\nsecond one", 5);
+        assert_eq!(shown, "<p>first paragraph here</p>");
+        assert_eq!(rest, "<p>second one</p>\n");
+    }
+
+    #[test]
+    fn short_or_unsplittable_text_is_left_whole() {
+        assert_eq!(split_for_reveal(&render_markdown("short"), 100), None);
+        assert_eq!(split_for_reveal(&render_markdown("- a\n- b\n- c"), 1), None);
+    }
+
+    #[test]
+    fn entities_count_as_one_character() {
+        let html = "<p>&amp;&amp;</p> <p>x</p>";
+        assert_eq!(split_for_reveal(html, 2), Some(("<p>&amp;&amp;</p>", "<p>x</p>")));
+        assert_eq!(split_for_reveal(html, 3), None);
+    }
+
+    const RLO: char = '\u{202E}';
+    const LRI: char = '\u{2066}';
+    const RLI: char = '\u{2067}';
+    const PDF: char = '\u{202C}';
+    const PDI: char = '\u{2069}';
+
+    #[test]
+    fn unterminated_openers_are_closed() {
+        assert_eq!(balance_bidi(&format!("a{RLO}evil")), format!("a{RLO}evil{PDF}"));
+        assert_eq!(balance_bidi(&format!("a{LRI}isolated")), format!("a{LRI}isolated{PDI}"));
+        assert_eq!(balance_bidi(&format!("{RLO}{LRI}x")), format!("{RLO}{LRI}x{PDI}{PDF}"));
+    }
+
+    #[test]
+    fn openers_are_closed_at_each_line_end() {
+        assert_eq!(balance_bidi(&format!("{RLO}one\ntwo")), format!("{RLO}one{PDF}\ntwo"));
+        assert_eq!(balance_bidi(&format!("{LRI}one\n{RLI}two")), format!("{LRI}one{PDI}\n{RLI}two{PDI}"));
+    }
+
+    #[test]
+    fn stray_closers_are_dropped() {
+        assert_eq!(balance_bidi(&format!("a{PDF}b{PDI}c")), "abc");
+        // A closer of the wrong kind doesn't close the innermost opener
+        assert_eq!(balance_bidi(&format!("{LRI}a{PDF}b{PDI}c")), format!("{LRI}ab{PDI}c"));
+        assert_eq!(balance_bidi(&format!("{RLO}a{PDF}{PDF}b")), format!("{RLO}a{PDF}b"));
+    }
+
+    #[test]
+    fn nested_isolates_are_balanced() {
+        let nested = format!("{LRI}a{RLI}b{PDI}c{PDI}d");
+        assert_eq!(balance_bidi(&nested), nested);
+        assert_eq!(balance_bidi(&format!("{LRI}a{RLI}b{PDI}c")), format!("{LRI}a{RLI}b{PDI}c{PDI}"));
+        assert_eq!(balance_bidi(&format!("{LRI}a{RLO}b{PDI}c{PDF}")), format!("{LRI}a{RLO}bc{PDF}{PDI}"));
+    }
+
+    #[test]
+    fn other_controls_are_dropped_and_marks_kept() {
+        assert_eq!(balance_bidi("a\u{0}b\u{1b}[0m\tc\r\n"), "ab[0m\tc\r\n");
+        assert_eq!(balance_bidi("a\u{200F}b\u{200E}"), "a\u{200F}b\u{200E}");
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::log_error;
+
+// HMAC-signed tokens for cookies and forms. Every token is bound to a
+// purpose string so a token minted for one use can't be replayed for another.
+type HmacSha256 = Hmac<Sha256>;
+
+static SECRET_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+static KEY_FROM_ENV: OnceLock<bool> = OnceLock::new();
+
+// Loads SECRET_KEY from the environment. Without one a random key is used,
+// which means signed cookies and forms stop validating after a restart.
+pub fn init_from_env() {
+    let key = match env::var("SECRET_KEY") {
+        Ok(k) if !k.trim().is_empty() => {
+            let _ = KEY_FROM_ENV.set(true);
+            k.into_bytes()
+        }
+        _ => {
+            log_error("SECRET_KEY not set, using a random key for this run");
+            let mut key = vec![0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        }
+    };
+    let _ = SECRET_KEY.set(key);
+}
+
+// Whether the key survives a restart, i.e. came from SECRET_KEY
+pub fn key_is_persistent() -> bool {
+    KEY_FROM_ENV.get().copied().unwrap_or(false)
+}
+
+// A fixed key for unit tests
+#[cfg(test)]
+pub fn init_for_tests() {
+    let _ = SECRET_KEY.set(b"test secret key".to_vec());
+}
+
+fn mac(purpose: &str, payload: &str) -> HmacSha256 {
+    let key = SECRET_KEY.get().expect("tokens::init_from_env must run at startup");
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(purpose.as_bytes());
+    mac.update(b"\0");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Returns "<payload>.<signature>"
+pub fn sign(purpose: &str, payload: &str) -> String {
+    let signature = mac(purpose, payload).finalize().into_bytes();
+    format!("{}.{}", payload, to_hex(&signature))
+}
+
+// Returns the payload if the signature is valid for this purpose
+pub fn verify(purpose: &str, token: &str) -> Option<String> {
+    let (payload, signature) = token.rsplit_once('.')?;
+    let signature = from_hex(signature)?;
+    mac(purpose, payload).verify_slice(&signature).ok()?;
+    Some(payload.to_string())
+}
+
+// Like sign, but the token stops verifying after `ttl_secs`
+pub fn sign_expiring(purpose: &str, data: &str, ttl_secs: i64) -> String {
+    let expires_at = Utc::now().timestamp() + ttl_secs;
+    sign(purpose, &format!("{}|{}", data, expires_at))
+}
+
+// Returns the signed data if the token is valid and not yet expired
+pub fn verify_expiring(purpose: &str, token: &str) -> Option<String> {
+    let payload = verify(purpose, token)?;
+    let (data, expires_at) = payload.rsplit_once('|')?;
+    let expires_at: i64 = expires_at.parse().ok()?;
+    if expires_at <= Utc::now().timestamp() {
+        return None;
+    }
+    Some(data.to_string())
+}
+
+// Keyed hash of `payload` alone, for storing a value that must be matchable
+// but not readable or guessable without the key (client addresses)
+pub fn keyed_hash(purpose: &str, payload: &str) -> String {
+    to_hex(&mac(purpose, payload).finalize().into_bytes())
+}
+
+// A signature cut to 64 bits, for places without room for a full one
+// (the local part of an email address is at most 64 characters)
+pub fn sign_short(purpose: &str, payload: &str) -> String {
+    let signature = mac(purpose, payload).finalize().into_bytes();
+    to_hex(&signature[..8])
+}
+
+pub fn verify_short(purpose: &str, payload: &str, signature: &str) -> bool {
+    match from_hex(signature) {
+        Some(bytes) if bytes.len() == 8 => mac(purpose, payload).verify_truncated_left(&bytes).is_ok(),
+        _ => false,
+    }
+}
@@ -0,0 +1,164 @@
+use chrono::Utc;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::{derivatives, log_error, media};
+
+const DRAIN_BATCH_SIZE: i64 = 200;
+
+#[derive(FromRow)]
+struct PendingUnlink {
+    id: i32,
+    media_path: String,
+}
+
+// Queues files for deletion. Call it in the transaction that removes the
+// media rows so the queue entry commits (or rolls back) with them.
+pub async fn enqueue(conn: &mut PgConnection, media_paths: &[String]) -> Result<(), sqlx::Error> {
+    if media_paths.is_empty() {
+        return Ok(());
+    }
+    sqlx::query("INSERT INTO pending_unlinks (media_path, queued_at) SELECT p, $2 FROM UNNEST($1::text[]) AS p")
+        .bind(media_paths)
+        .bind(Utc::now().timestamp())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// Drains the queue now instead of at the next cleanup job, for callers
+// that just committed removals. Failures are left for the job to retry.
+pub fn drain_soon(pool: &PgPool) {
+    let pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = drain(&pool).await {
+            log_error(&format!("File cleanup failed: {}", e));
+        }
+    });
+}
+
+pub async fn backlog(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM pending_unlinks").fetch_one(pool).await
+}
+
+// Keeps a drain from removing `media_paths` until the caller's transaction
+// ends, and checks the files are still on disk. Call it in the transaction
+// that adds the rows referencing them: an upload is written before those rows
+// commit, so a drain that got in between and removed the file makes this
+// fail, and any later drain waits for the rows and then sees them.
+pub async fn hold(conn: &mut PgConnection, media_paths: &[&str]) -> Result<(), String> {
+    let mut media_paths = media_paths.to_vec();
+    // One order everywhere, so two uploads sharing files can't deadlock
+    media_paths.sort_unstable();
+    media_paths.dedup();
+    for media_path in media_paths {
+        lock(&mut *conn, media_path)
+            .await
+            .map_err(|e| format!("Failed to lock {} against removal: {}", media_path, e))?;
+        if !media::disk_path(media_path).is_some_and(|path| path.exists()) {
+            return Err(format!("Upload {} was removed before it was stored", media_path));
+        }
+    }
+    Ok(())
+}
+
+// Serializes a drain's check-and-remove of one file with `hold`
+async fn lock(conn: &mut PgConnection, media_path: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(media_path)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// Deletes queued files and their queue rows. Files are content-addressed and
+// may be shared, so a file still referenced by a media row (as its file or
+// one of its image derivatives), held for a submission retry or staged for an
+// edit preview is kept; files
+// already gone count as done. Each file is checked and removed under its
+// `hold` lock. A file that can't be removed is logged and stays queued for
+// the next run, without holding up the rest. Safe to run repeatedly or
+// concurrently.
+pub async fn drain(pool: &PgPool) -> Result<u64, String> {
+    let mut total = 0;
+    let mut after = 0;
+    loop {
+        let batch = sqlx::query_as::<_, PendingUnlink>(
+            "SELECT id, media_path FROM pending_unlinks WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(after)
+        .bind(DRAIN_BATCH_SIZE)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch pending unlinks: {}", e))?;
+
+        for pending in &batch {
+            after = pending.id;
+            let mut tx = pool
+                .begin()
+                .await
+                .map_err(|e| format!("Failed to start transaction: {}", e))?;
+            lock(&mut tx, &pending.media_path)
+                .await
+                .map_err(|e| format!("Failed to lock {}: {}", pending.media_path, e))?;
+
+            let referenced: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM article_media WHERE media_path = $1 OR webp_path = $1 OR avif_path = $1)
+                     OR EXISTS (SELECT 1 FROM submission_uploads WHERE media_path = $1)
+                     OR EXISTS (SELECT 1 FROM staged_uploads WHERE media_path = $1)",
+            )
+            .bind(&pending.media_path)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to check media references: {}", e))?;
+
+            if !referenced {
+                if let Err(e) = remove_files(&pending.media_path).await {
+                    // Rolled back with `tx`, so the row is retried next run
+                    log_error(&e);
+                    continue;
+                }
+            }
+
+            sqlx::query("DELETE FROM pending_unlinks WHERE id = $1")
+                .bind(pending.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to clear pending unlink: {}", e))?;
+            tx.commit()
+                .await
+                .map_err(|e| format!("Failed to clear pending unlink: {}", e))?;
+            total += 1;
+        }
+
+        if (batch.len() as i64) < DRAIN_BATCH_SIZE {
+            return Ok(total);
+        }
+    }
+}
+
+// Removes a queued file and its WebP/AVIF copies; ones already gone are fine
+async fn remove_files(media_path: &str) -> Result<(), String> {
+    let path = match media::disk_path(media_path) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log_error(&format!("Queued file {} was already gone", media_path));
+        }
+        Err(e) => return Err(format!("Failed to remove {}: {}", media_path, e)),
+    }
+    // Most files have no copies
+    for derivative in derivatives::sibling_paths(media_path) {
+        if let Some(path) = media::disk_path(&derivative) {
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(format!("Failed to remove {}: {}", derivative, e))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,346 @@
+use actix_multipart::Field;
+use actix_web::error::{ErrorBadRequest, ErrorPayloadTooLarge, ErrorRequestTimeout};
+use actix_web::{HttpRequest, HttpResponse};
+use futures_util::stream::StreamExt as _;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+use crate::{client, log_error};
+
+// Caps how many multipart uploads are processed at once, overall and per
+// client, so a few slow uploaders can't occupy every worker. Requests over
+// the cap are turned away immediately instead of queuing.
+const DEFAULT_GLOBAL_LIMIT: usize = 16;
+const DEFAULT_PER_IP_LIMIT: usize = 2;
+const RETRY_AFTER_SECS: u32 = 10;
+
+// Uploads are buffered in memory, so bytes are counted as they arrive and
+// the upload is cut off as soon as one file or the whole request goes over
+// its limit, before the excess is ever held.
+const DEFAULT_MAX_FILE_BYTES: usize = 25 * 1024 * 1024;
+const DEFAULT_MAX_REQUEST_BYTES: usize = 50 * 1024 * 1024;
+
+// An upload must arrive in full within a deadline so a client dribbling
+// bytes can't hold a slot forever. The default allows the largest allowed
+// request at a slow but legitimate rate.
+const MIN_UPLOAD_BYTES_PER_SEC: usize = 64 * 1024;
+
+// Limits on the form fields around the files: how many a request may have,
+// and how large a text field (title, body, password, ...) may get. The body
+// limit is well above max_body_chars even in four-byte characters.
+pub struct FieldLimits {
+    pub max_fields: usize,
+    pub max_text_bytes: usize,
+}
+
+pub const FIELD_LIMITS: FieldLimits = FieldLimits {
+    max_fields: 256,
+    max_text_bytes: 1024 * 1024,
+};
+
+pub struct UploadLimiter {
+    global: Semaphore,
+    per_ip_limit: usize,
+    per_ip: Mutex<HashMap<String, usize>>,
+    deadline: Duration,
+    max_file_bytes: usize,
+    max_request_bytes: usize,
+    fields: FieldLimits,
+}
+
+// Held for the duration of an upload; frees both slots on drop
+pub struct UploadPermit<'a> {
+    _global: SemaphorePermit<'a>,
+    limiter: &'a UploadLimiter,
+    ip: String,
+    deadline: Instant,
+    received: Cell<usize>,
+    fields: Cell<usize>,
+}
+
+impl UploadPermit<'_> {
+    // Awaits one step of reading the upload body, failing with 408 once the
+    // upload's deadline has passed. The clock is checked first, since a step
+    // that is already ready would otherwise win even after the deadline.
+    pub async fn within_deadline<F: Future>(&self, step: F) -> Result<F::Output, actix_web::Error> {
+        let timed_out = || {
+            log_error(&format!("Upload from {} exceeded its deadline", self.ip));
+            ErrorRequestTimeout("Upload took too long")
+        };
+        if Instant::now() >= self.deadline {
+            return Err(timed_out());
+        }
+        tokio::time::timeout_at(self.deadline, step).await.map_err(|_| timed_out())
+    }
+
+    // Counts the start of another field, failing with 400 once the request
+    // has more than the field limit
+    pub fn next_field(&self) -> Result<(), actix_web::Error> {
+        let fields = self.fields.get() + 1;
+        self.fields.set(fields);
+        if fields > self.limiter.fields.max_fields {
+            log_error(&format!("Upload from {} cut off: too many form fields", self.ip));
+            return Err(ErrorBadRequest("Too many form fields"));
+        }
+        Ok(())
+    }
+
+    // Counts a chunk of a file field that has `field_len` bytes so far,
+    // failing with 413 if it takes the file or the request over its limit
+    pub fn receive(&self, field_len: usize, chunk_len: usize) -> Result<(), actix_web::Error> {
+        if field_len + chunk_len > self.limiter.max_file_bytes {
+            log_error(&format!("Upload from {} cut off: a file is over the size limit", self.ip));
+            return Err(ErrorPayloadTooLarge(format!(
+                "Files can be at most {}",
+                human_size(self.limiter.max_file_bytes)
+            )));
+        }
+        self.count(chunk_len)
+    }
+
+    // Like receive, for a text field held to the text field limit
+    pub fn receive_text(&self, field_len: usize, chunk_len: usize) -> Result<(), actix_web::Error> {
+        if field_len + chunk_len > self.limiter.fields.max_text_bytes {
+            log_error(&format!("Upload from {} cut off: a form field is over the size limit", self.ip));
+            return Err(ErrorPayloadTooLarge(format!(
+                "Form fields can be at most {}",
+                human_size(self.limiter.fields.max_text_bytes)
+            )));
+        }
+        self.count(chunk_len)
+    }
+
+    // Reads a field nobody asked for to its end without keeping it; its
+    // bytes still count against the request
+    pub async fn skip(&self, field: &mut Field) -> Result<(), actix_web::Error> {
+        while let Some(chunk) = self.within_deadline(field.next()).await? {
+            let chunk = chunk.map_err(|e| {
+                log_error(&format!("Error reading skipped field: {}", e));
+                ErrorBadRequest("Malformed form data")
+            })?;
+            self.count(chunk.len())?;
+        }
+        Ok(())
+    }
+
+    fn count(&self, chunk_len: usize) -> Result<(), actix_web::Error> {
+        let received = self.received.get() + chunk_len;
+        self.received.set(received);
+        if received > self.limiter.max_request_bytes {
+            log_error(&format!("Upload from {} cut off: the request is over the size limit", self.ip));
+            return Err(ErrorPayloadTooLarge(format!(
+                "An upload can be at most {} in total",
+                human_size(self.limiter.max_request_bytes)
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn human_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 && bytes.is_multiple_of(1024 * 1024) {
+        format!("{} MB", bytes / (1024 * 1024))
+    } else if bytes >= 1024 {
+        format!("{} KB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+impl Drop for UploadPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.release_ip(&self.ip);
+    }
+}
+
+fn limit_from_env(var: &str, default: usize) -> usize {
+    env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+impl UploadLimiter {
+    // UPLOAD_CONCURRENCY, UPLOAD_CONCURRENCY_PER_IP, UPLOAD_DEADLINE_SECS,
+    // MAX_UPLOAD_BYTES (per file) and MAX_UPLOAD_REQUEST_BYTES override the
+    // defaults
+    pub fn from_env() -> Self {
+        let max_file_bytes = limit_from_env("MAX_UPLOAD_BYTES", DEFAULT_MAX_FILE_BYTES);
+        let max_request_bytes =
+            limit_from_env("MAX_UPLOAD_REQUEST_BYTES", DEFAULT_MAX_REQUEST_BYTES.max(max_file_bytes));
+        let default_deadline = (max_request_bytes / MIN_UPLOAD_BYTES_PER_SEC).max(1);
+        UploadLimiter {
+            global: Semaphore::new(limit_from_env("UPLOAD_CONCURRENCY", DEFAULT_GLOBAL_LIMIT)),
+            per_ip_limit: limit_from_env("UPLOAD_CONCURRENCY_PER_IP", DEFAULT_PER_IP_LIMIT),
+            per_ip: Mutex::new(HashMap::new()),
+            deadline: Duration::from_secs(limit_from_env("UPLOAD_DEADLINE_SECS", default_deadline) as u64),
+            max_file_bytes,
+            max_request_bytes,
+            fields: FIELD_LIMITS,
+        }
+    }
+
+    pub fn try_acquire(&self, req: &HttpRequest) -> Result<UploadPermit<'_>, Busy> {
+        let ip = client::client_ip(req);
+
+        {
+            let mut per_ip = self.per_ip.lock().map_err(|_| Busy)?;
+            let count = per_ip.entry(ip.clone()).or_insert(0);
+            if *count >= self.per_ip_limit {
+                return Err(Busy);
+            }
+            *count += 1;
+        }
+
+        match self.global.try_acquire() {
+            Ok(global) => Ok(UploadPermit {
+                _global: global,
+                limiter: self,
+                ip,
+                deadline: Instant::now() + self.deadline,
+                received: Cell::new(0),
+                fields: Cell::new(0),
+            }),
+            Err(_) => {
+                self.release_ip(&ip);
+                Err(Busy)
+            }
+        }
+    }
+
+    fn release_ip(&self, ip: &str) {
+        if let Ok(mut per_ip) = self.per_ip.lock() {
+            if let Some(count) = per_ip.get_mut(ip) {
+                *count -= 1;
+                if *count == 0 {
+                    per_ip.remove(ip);
+                }
+            }
+        }
+    }
+}
+
+// An upload refused because too many are in progress; a 503 with Retry-After
+pub struct Busy;
+
+impl From<Busy> for HttpResponse {
+    fn from(_: Busy) -> Self {
+        HttpResponse::ServiceUnavailable()
+            .append_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+            .body("Too many uploads in progress, please try again shortly")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn limiter(global: usize, per_ip: usize) -> UploadLimiter {
+        UploadLimiter {
+            global: Semaphore::new(global),
+            per_ip_limit: per_ip,
+            per_ip: Mutex::new(HashMap::new()),
+            deadline: Duration::from_secs(60),
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            fields: FIELD_LIMITS,
+        }
+    }
+
+    fn request_from(addr: &str) -> HttpRequest {
+        TestRequest::default().peer_addr(addr.parse().unwrap()).to_http_request()
+    }
+
+    #[test]
+    fn third_upload_from_one_client_is_turned_away() {
+        let limiter = limiter(16, 2);
+        let first = limiter.try_acquire(&request_from("198.51.100.7:4000"));
+        let second = limiter.try_acquire(&request_from("198.51.100.7:4001"));
+        assert!(first.is_ok() && second.is_ok());
+
+        let res = match limiter.try_acquire(&request_from("198.51.100.7:4002")) {
+            Ok(_) => panic!("a third upload was let through"),
+            Err(busy) => HttpResponse::from(busy),
+        };
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers().get("Retry-After").unwrap(), &RETRY_AFTER_SECS.to_string());
+
+        // Other clients are unaffected, and finishing an upload frees its slot
+        assert!(limiter.try_acquire(&request_from("203.0.113.9:4000")).is_ok());
+        drop(first);
+        assert!(limiter.try_acquire(&request_from("198.51.100.7:4003")).is_ok());
+    }
+
+    #[test]
+    fn global_cap_applies_across_clients() {
+        let limiter = limiter(2, 2);
+        let _a = limiter.try_acquire(&request_from("198.51.100.7:4000")).ok().unwrap();
+        let _b = limiter.try_acquire(&request_from("203.0.113.9:4000")).ok().unwrap();
+        assert!(limiter.try_acquire(&request_from("192.0.2.1:4000")).is_err());
+        // A refused request doesn't keep its per-client slot
+        assert_eq!(limiter.per_ip.lock().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn stalled_upload_times_out_with_408() {
+        let mut limiter = limiter(16, 2);
+        limiter.deadline = Duration::from_millis(50);
+        let permit = limiter.try_acquire(&request_from("198.51.100.7:4000")).ok().unwrap();
+
+        assert_eq!(permit.within_deadline(async { 1 }).await.ok(), Some(1));
+        // A client that stops sending never finishes its next chunk
+        let err = permit.within_deadline(std::future::pending::<()>()).await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::REQUEST_TIMEOUT);
+        // The deadline covers the whole upload, not each read
+        let err = permit.within_deadline(async { 2 }).await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn file_at_the_limit_passes_and_one_byte_more_is_cut_off() {
+        let mut limiter = limiter(16, 2);
+        limiter.max_file_bytes = 1000;
+        let permit = limiter.try_acquire(&request_from("198.51.100.7:4000")).ok().unwrap();
+        assert!(permit.receive(0, 600).is_ok());
+        assert!(permit.receive(600, 400).is_ok());
+        let err = permit.receive(1000, 1).unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(err.to_string(), "Files can be at most 1000 bytes");
+    }
+
+    #[test]
+    fn files_under_their_limit_still_count_toward_the_request() {
+        let mut limiter = limiter(16, 2);
+        limiter.max_file_bytes = 1000;
+        limiter.max_request_bytes = 1500;
+        let permit = limiter.try_acquire(&request_from("198.51.100.7:4000")).ok().unwrap();
+        assert!(permit.receive(0, 1000).is_ok());
+        assert!(permit.receive_text(0, 500).is_ok());
+        let err = permit.receive(0, 1).unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn text_fields_and_field_count_have_their_own_limits() {
+        let mut limiter = limiter(16, 2);
+        limiter.fields = FieldLimits {
+            max_fields: 2,
+            max_text_bytes: 10,
+        };
+        let permit = limiter.try_acquire(&request_from("198.51.100.7:4000")).ok().unwrap();
+        assert!(permit.receive_text(0, 10).is_ok());
+        assert!(permit.receive_text(10, 1).is_err());
+        assert!(permit.next_field().is_ok() && permit.next_field().is_ok());
+        let err = permit.next_field().unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,112 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{DispositionType, HeaderValue, CACHE_CONTROL, X_CONTENT_TYPE_OPTIONS};
+use actix_web::{mime, web, HttpRequest, HttpResponse};
+use sqlx::{FromRow, PgPool};
+
+use crate::media::disk_path;
+use crate::media::types::{self, Renderer};
+use crate::{log_error, media_download, media_migration, paths};
+
+// GET /uploads/{name}. Only files an article_media row points at are served,
+// originals and their WebP/AVIF derivatives alike; anything else on disk is
+// a 404, and there is no directory listing. The content type comes from the
+// row rather than a guess from the name, and only images and video are shown
+// inline: every other type (PDF, text, whatever a legacy row holds) is sent
+// as an attachment, so an uploaded page can never render on this origin.
+// Stored names are content hashes, so a file never changes under its URL.
+const CACHE: &str = "public, max-age=31536000, immutable";
+
+#[derive(FromRow)]
+struct UploadRow {
+    media_path: String,
+    mime_type: Option<String>,
+    original_filename: Option<String>,
+    webp_path: Option<String>,
+    avif_path: Option<String>,
+}
+
+// Content type and disposition for a stored file
+fn headers_for(row: &UploadRow, requested: &str) -> (mime::Mime, DispositionType) {
+    let derivative = if row.webp_path.as_deref() == Some(requested) {
+        Some("image/webp")
+    } else if row.avif_path.as_deref() == Some(requested) {
+        Some("image/avif")
+    } else {
+        None
+    };
+    if let Some(content_type) = derivative {
+        return (content_type.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM), DispositionType::Inline);
+    }
+    match types::for_media(&row.media_path, row.mime_type.as_deref()) {
+        Some(t) if matches!(t.renderer, Renderer::Image | Renderer::Video) => {
+            (t.mime.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM), DispositionType::Inline)
+        }
+        Some(t) if t.renderer == Renderer::Text => (mime::TEXT_PLAIN_UTF_8, DispositionType::Attachment),
+        Some(t) => (t.mime.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM), DispositionType::Attachment),
+        None => (mime::APPLICATION_OCTET_STREAM, DispositionType::Attachment),
+    }
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().body("File not found")
+}
+
+pub async fn serve_upload(req: HttpRequest, pool: web::Data<PgPool>, path: web::Path<String>) -> HttpResponse {
+    let requested = format!("/uploads/{}", path.into_inner());
+    let row = match sqlx::query_as::<_, UploadRow>(
+        "SELECT media_path, mime_type, original_filename, webp_path, avif_path FROM article_media
+         WHERE media_path = $1 OR webp_path = $1 OR avif_path = $1 LIMIT 1",
+    )
+    .bind(&requested)
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(Some(row)) => row,
+        // Files renamed by the media normalization keep answering at their old name
+        Ok(None) => {
+            return match media_migration::redirect_target(pool.get_ref(), &requested).await {
+                Ok(Some(new_path)) => HttpResponse::MovedPermanently()
+                    .append_header(("Location", paths::url(&new_path)))
+                    .finish(),
+                Ok(None) => not_found(),
+                Err(e) => {
+                    log_error(&e);
+                    not_found()
+                }
+            };
+        }
+        Err(e) => {
+            log_error(&format!("Failed to look up upload {}: {}", requested, e));
+            return HttpResponse::InternalServerError().body("Failed to load file");
+        }
+    };
+
+    let file = match disk_path(&requested) {
+        Some(p) => NamedFile::open_async(p).await,
+        None => return not_found(),
+    };
+    let file = match file {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return not_found(),
+        Err(e) => {
+            log_error(&format!("Failed to open upload {}: {}", requested, e));
+            return HttpResponse::InternalServerError().body("Failed to load file");
+        }
+    };
+
+    let (content_type, disposition) = headers_for(&row, &requested);
+    let name = row
+        .original_filename
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| requested.rsplit('/').next().unwrap_or("download"));
+    let mut res = file
+        .set_content_type(content_type)
+        .set_content_disposition(media_download::disposition(disposition, name))
+        .into_response(&req);
+    let headers = res.headers_mut();
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static(CACHE));
+    res
+}
@@ -0,0 +1,157 @@
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashSet;
+use std::env;
+use std::sync::OnceLock;
+
+use crate::log_error;
+use crate::settings::SettingsCache;
+
+// What this binary is and which schema it expects. The database may carry
+// migrations this binary doesn't know about only after a downgrade, which
+// is refused at startup unless ALLOW_SCHEMA_MISMATCH=1.
+
+// Applied migration versions missing from this binary, found at startup
+static UNKNOWN_MIGRATIONS: OnceLock<Vec<i64>> = OnceLock::new();
+
+#[derive(FromRow)]
+struct AppliedMigration {
+    version: i64,
+    description: String,
+}
+
+async fn applied_migrations(pool: &PgPool) -> Result<Vec<AppliedMigration>, sqlx::Error> {
+    // A fresh database has no bookkeeping table yet
+    let exists: Option<String> = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations')::text")
+        .fetch_one(pool)
+        .await?;
+    if exists.is_none() {
+        return Ok(Vec::new());
+    }
+    sqlx::query_as::<_, AppliedMigration>(
+        "SELECT version, description FROM _sqlx_migrations WHERE success ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Checks the applied migrations against the embedded set, then applies any
+// that are pending
+pub async fn migrate(pool: &PgPool) -> Result<(), String> {
+    let applied = applied_migrations(pool)
+        .await
+        .map_err(|e| format!("Failed to read applied migrations: {}", e))?;
+    let mut migrator = sqlx::migrate!();
+    let known: HashSet<i64> = migrator.iter().map(|m| m.version).collect();
+    let unknown: Vec<i64> = applied.iter().map(|m| m.version).filter(|v| !known.contains(v)).collect();
+
+    let allow_mismatch = env::var("ALLOW_SCHEMA_MISMATCH").is_ok_and(|v| v.trim() == "1");
+    if !unknown.is_empty() {
+        let listed = unknown.iter().map(i64::to_string).collect::<Vec<_>>().join(", ");
+        if !allow_mismatch {
+            return Err(format!(
+                "The database has migrations this binary doesn't know about ({}); it was last run by a newer \
+                 release. Upgrade the binary, or set ALLOW_SCHEMA_MISMATCH=1 to start anyway.",
+                listed
+            ));
+        }
+        log_error(&format!("Starting with unknown migrations applied ({}) because ALLOW_SCHEMA_MISMATCH=1", listed));
+    }
+    let _ = UNKNOWN_MIGRATIONS.set(unknown);
+
+    migrator.set_ignore_missing(allow_mismatch);
+    migrator
+        .run(pool)
+        .await
+        .map_err(|e| format!("Failed to run migrations: {}", e))
+}
+
+fn schema_state() -> &'static str {
+    match UNKNOWN_MIGRATIONS.get() {
+        Some(unknown) if !unknown.is_empty() => "database_ahead",
+        _ => "ok",
+    }
+}
+
+#[derive(Serialize)]
+struct MigrationJson {
+    version: i64,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct FeaturesJson {
+    dev_db: bool,
+    scan_fail_open: bool,
+    tombstone_deleted_comments: bool,
+    comment_edits: bool,
+    collapsed_long_bodies: bool,
+}
+
+#[derive(Serialize)]
+struct VersionJson {
+    version: &'static str,
+    commit: Option<&'static str>,
+    latest_migration: Option<MigrationJson>,
+    schema: &'static str,
+    unknown_migrations: Vec<i64>,
+    features: FeaturesJson,
+}
+
+// GET /api/version
+pub async fn version(pool: web::Data<PgPool>, settings_cache: web::Data<SettingsCache>) -> HttpResponse {
+    let applied = match applied_migrations(pool.get_ref()).await {
+        Ok(a) => a,
+        Err(e) => {
+            log_error(&format!("Failed to read applied migrations: {}", e));
+            return HttpResponse::InternalServerError().body("Failed to load version information");
+        }
+    };
+    let settings = settings_cache.get(pool.get_ref()).await;
+
+    HttpResponse::Ok().json(VersionJson {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: option_env!("GIT_COMMIT"),
+        latest_migration: applied.into_iter().last().map(|m| MigrationJson {
+            version: m.version,
+            name: m.description,
+        }),
+        schema: schema_state(),
+        unknown_migrations: UNKNOWN_MIGRATIONS.get().cloned().unwrap_or_default(),
+        features: FeaturesJson {
+            dev_db: cfg!(feature = "dev-db"),
+            scan_fail_open: settings.scan_fail_open,
+            tombstone_deleted_comments: settings.tombstone_deleted_comments,
+            comment_edits: settings.comment_edit_minutes > 0,
+            collapsed_long_bodies: settings.reveal_body_after_chars > 0,
+        },
+    })
+}
+
+#[derive(Serialize)]
+struct ReadyJson {
+    database: &'static str,
+    schema: &'static str,
+}
+
+// GET /api/ready: 200 while the database answers, 503 otherwise. A schema
+// mismatch let through by ALLOW_SCHEMA_MISMATCH is reported but still ready.
+pub async fn ready(pool: web::Data<PgPool>) -> HttpResponse {
+    let database = match sqlx::query("SELECT 1").execute(pool.get_ref()).await {
+        Ok(_) => "ok",
+        Err(e) => {
+            log_error(&format!("Readiness check failed: {}", e));
+            "unavailable"
+        }
+    };
+    let body = ReadyJson {
+        database,
+        schema: schema_state(),
+    };
+    if database == "ok" {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
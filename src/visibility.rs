@@ -0,0 +1,102 @@
+use actix_web::HttpRequest;
+use sqlx::PgPool;
+
+use crate::{admin, bans, client};
+
+// The SQL predicates deciding who sees which articles and comments. Every
+// public read (article pages, counts, structured data, the digest, watched
+// articles, search) composes these in rather than spelling out the flags,
+// so a new restriction is one edit here instead of a hunt through queries.
+// `table` is the alias the query gives the table, or its name without one.
+//
+// Shadow-hidden posts (see bans) are shown to the address that sent them and
+// to staff, and to nobody else. Pages take a Viewer for that; counts, bumps,
+// feeds and anything shared between visitors use the plain predicates, which
+// leave them out for everyone.
+
+pub enum Viewer {
+    Staff,
+    // The keyed hash of the client's address, as stored in shadow_viewer
+    Client(String),
+}
+
+impl Viewer {
+    // Staff are looked up rather than trusted from the cookie, so a revoked
+    // moderator's session stops seeing shadow-hidden posts at once
+    pub async fn of(req: &HttpRequest, pool: &PgPool) -> Self {
+        if admin::current_staff(req, pool).await.is_some() {
+            Viewer::Staff
+        } else {
+            Viewer::Client(bans::ip_hash(&client::client_ip(req)))
+        }
+    }
+
+    // Whether a shadow-hidden row of `table` is shown to this viewer. The
+    // hash goes in as a literal so the predicates compose like the others;
+    // a keyed hash is hex, and anything else matches nothing.
+    fn sees_shadowed(&self, table: &str) -> String {
+        match self {
+            Viewer::Staff => "TRUE".to_string(),
+            Viewer::Client(hash) if !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                format!("{table}.shadow_viewer = '{hash}'")
+            }
+            Viewer::Client(_) => "FALSE".to_string(),
+        }
+    }
+}
+
+// Comments shown in a thread. Tombstones stay, as "[deleted]" placeholders.
+pub fn shown_comments(table: &str) -> String {
+    format!("NOT {table}.hidden AND NOT {table}.shadow_hidden")
+}
+
+// The same, for one viewer's page
+pub fn shown_comments_to(table: &str, viewer: &Viewer) -> String {
+    format!(
+        "NOT {table}.hidden AND (NOT {table}.shadow_hidden OR {})",
+        viewer.sees_shadowed(table)
+    )
+}
+
+// Comments that count as activity: shown, and not a tombstone
+pub fn live_comments(table: &str) -> String {
+    format!("NOT {table}.hidden AND NOT {table}.deleted AND NOT {table}.shadow_hidden")
+}
+
+// Articles anyone can see
+pub fn shown_articles(table: &str) -> String {
+    format!("NOT {table}.shadow_hidden")
+}
+
+// The same, for one viewer's page
+pub fn shown_articles_to(table: &str, viewer: &Viewer) -> String {
+    format!("(NOT {table}.shadow_hidden OR {})", viewer.sees_shadowed(table))
+}
+
+// Rows shown to this viewer and nobody else. A page including any is
+// personal, so it can't be kept as the copy served to everyone.
+pub fn shadowed_for(table: &str, viewer: &Viewer) -> String {
+    format!("{table}.shadow_hidden AND {}", viewer.sees_shadowed(table))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_see_only_their_own_shadowed_rows() {
+        let viewer = Viewer::Client("00ff".to_string());
+        assert_eq!(
+            shown_comments_to("c", &viewer),
+            "NOT c.hidden AND (NOT c.shadow_hidden OR c.shadow_viewer = '00ff')"
+        );
+        assert_eq!(shown_articles_to("a", &Viewer::Staff), "(NOT a.shadow_hidden OR TRUE)");
+    }
+
+    #[test]
+    fn a_hash_that_is_not_hex_matches_nothing() {
+        let viewer = Viewer::Client("x' OR '1'='1".to_string());
+        assert_eq!(shadowed_for("a", &viewer), "a.shadow_hidden AND FALSE");
+        assert_eq!(shadowed_for("a", &Viewer::Client(String::new())), "a.shadow_hidden AND FALSE");
+    }
+}
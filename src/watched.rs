@@ -0,0 +1,322 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::{FromRow, PgPool};
+
+use crate::{consent, log_error, paths, visibility};
+
+// Readers can watch articles without an account. The list lives entirely in
+// a cookie of "articleid:lastseencommentid" pairs joined by '_', oldest first,
+// e.g. "12:340_15:0". The last-seen comment id drives the new-comment counts.
+const WATCH_COOKIE: &str = "watched";
+const MAX_WATCHED: usize = 50;
+const MAX_COOKIE_LEN: usize = 1200;
+
+type WatchList = Vec<(i32, i32)>; // (article id, last seen comment id)
+
+// Comments posted from this browser as "id_id_id", newest last, so the watch
+// list can count replies to them
+const OWN_COMMENTS_COOKIE: &str = "my_comments";
+const MAX_OWN_COMMENTS: usize = 100;
+
+fn parse(value: &str) -> Option<WatchList> {
+    if value.len() > MAX_COOKIE_LEN {
+        return None;
+    }
+    let mut list = WatchList::new();
+    for entry in value.split('_').filter(|e| !e.is_empty()) {
+        let (id, seen) = entry.split_once(':')?;
+        let id: i32 = id.parse().ok().filter(|id| *id > 0)?;
+        let seen: i32 = seen.parse().ok().filter(|seen| *seen >= 0)?;
+        if list.iter().any(|(watched, _)| *watched == id) {
+            return None;
+        }
+        list.push((id, seen));
+    }
+    if list.len() > MAX_WATCHED {
+        return None;
+    }
+    Some(list)
+}
+
+// The visitor's watch list. Err means the cookie was malformed or oversized
+// and should be reset.
+fn read(req: &HttpRequest) -> Result<WatchList, ()> {
+    match req.cookie(WATCH_COOKIE) {
+        Some(cookie) => parse(cookie.value()).ok_or(()),
+        None => Ok(WatchList::new()),
+    }
+}
+
+fn watch_cookie(list: &WatchList) -> Cookie<'static> {
+    let value = list
+        .iter()
+        .map(|(id, seen)| format!("{}:{}", id, seen))
+        .collect::<Vec<_>>()
+        .join("_");
+    let mut cookie = Cookie::build(WATCH_COOKIE, value)
+        .path(paths::url("/"))
+        .max_age(CookieDuration::days(365))
+        .http_only(true)
+        .finish();
+    if list.is_empty() {
+        cookie.make_removal();
+    }
+    cookie
+}
+
+// Ids of comments posted from this browser. A malformed cookie counts as none.
+fn own_comment_ids(req: &HttpRequest) -> Vec<i32> {
+    let cookie = match req.cookie(OWN_COMMENTS_COOKIE) {
+        Some(c) if c.value().len() <= MAX_COOKIE_LEN => c,
+        _ => return Vec::new(),
+    };
+    cookie
+        .value()
+        .split('_')
+        .filter_map(|id| id.parse::<i32>().ok().filter(|id| *id > 0))
+        .collect()
+}
+
+// Cookie adding a newly posted comment to this browser's own comments
+pub fn remember_comment(req: &HttpRequest, comment_id: i32) -> Cookie<'static> {
+    let mut ids = own_comment_ids(req);
+    ids.retain(|id| *id != comment_id);
+    ids.push(comment_id);
+    if ids.len() > MAX_OWN_COMMENTS {
+        ids.drain(..ids.len() - MAX_OWN_COMMENTS);
+    }
+    let value = ids.iter().map(i32::to_string).collect::<Vec<_>>().join("_");
+    Cookie::build(OWN_COMMENTS_COOKIE, value)
+        .path(paths::url("/"))
+        .max_age(CookieDuration::days(365))
+        .http_only(true)
+        .finish()
+}
+
+pub fn is_watched(req: &HttpRequest, article_id: i32) -> bool {
+    read(req).is_ok_and(|list| list.iter().any(|(id, _)| *id == article_id))
+}
+
+async fn latest_comment_id(pool: &PgPool, article_id: i32) -> Result<i32, sqlx::Error> {
+    let latest: Option<i32> = sqlx::query_scalar("SELECT MAX(id) FROM comments WHERE article_id = $1")
+        .bind(article_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(latest.unwrap_or(0))
+}
+
+// Updated cookie marking every current comment on a watched article as
+// seen, or None when the article isn't watched or nothing changed
+pub async fn mark_seen(pool: &PgPool, req: &HttpRequest, article_id: i32) -> Option<Cookie<'static>> {
+    let mut list = read(req).ok()?;
+    let position = list.iter().position(|(id, _)| *id == article_id)?;
+    let latest = match latest_comment_id(pool, article_id).await {
+        Ok(latest) => latest,
+        Err(e) => {
+            log_error(&format!("Failed to fetch latest comment for watch list: {}", e));
+            return None;
+        }
+    };
+    if list[position].1 >= latest {
+        return None;
+    }
+    list[position].1 = latest;
+    Some(watch_cookie(&list))
+}
+
+// Watches an unwatched article and vice versa, then sends the visitor back
+pub async fn toggle_watch(req: HttpRequest, pool: web::Data<PgPool>, path: web::Path<i32>) -> HttpResponse {
+    let article_id = path.into_inner();
+    let mut list = read(&req).unwrap_or_default();
+
+    if let Some(position) = list.iter().position(|(id, _)| *id == article_id) {
+        list.remove(position);
+    } else {
+        let latest = match latest_comment_id(pool.get_ref(), article_id).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                log_error(&format!("Failed to fetch latest comment for watch list: {}", e));
+                return HttpResponse::InternalServerError().body("Failed to watch article");
+            }
+        };
+        // Full lists drop the longest-watched article
+        if list.len() >= MAX_WATCHED {
+            list.remove(0);
+        }
+        list.push((article_id, latest));
+    }
+
+    let location = paths::referer_path(&req).unwrap_or_else(|| paths::url(&format!("/articles/{}", article_id)));
+    let mut response = HttpResponse::Found();
+    consent::add_cookie(&req, &mut response, watch_cookie(&list));
+    response.append_header(("Location", location)).finish()
+}
+
+// Toggle link for an article page
+pub fn toggle_link_html(req: &HttpRequest, article_id: i32) -> String {
+    format!(
+        r#" <a href="{}/articles/{}/watch" class="quote-link">[{}]</a>"#,
+        paths::base(),
+        article_id,
+        if is_watched(req, article_id) { "unwatch" } else { "watch" }
+    )
+}
+
+// Ids of the visitor's watched articles; an unreadable cookie counts as none
+pub fn watched_ids(req: &HttpRequest) -> Vec<i32> {
+    read(req).unwrap_or_default().into_iter().map(|(id, _)| id).collect()
+}
+
+// Link from the listing back to where the visitor stopped reading
+pub fn continue_link_html(article_id: i32) -> String {
+    format!(
+        r#" <a href="{}/articles/{}?continue=1" class="quote-link">[continue]</a>"#,
+        paths::base(),
+        article_id
+    )
+}
+
+// Where ?continue=1 sends the visitor: the first comment they haven't seen,
+// else the last one they had, else the top of the article. The page starts
+// at that comment (the keyset cursor is one below its id), so a long thread
+// can't be truncated before it. Deleted comments are skipped, and an article
+// missing from the watch list, or a cleared cookie, gets the plain page.
+pub async fn continue_location(pool: &PgPool, req: &HttpRequest, article_id: i32) -> Result<String, sqlx::Error> {
+    let top = paths::url(&format!("/articles/{}", article_id));
+    let seen = match read(req).ok().and_then(|list| list.into_iter().find(|(id, _)| *id == article_id)) {
+        Some((_, seen)) => seen,
+        None => return Ok(top),
+    };
+    let target: Option<i32> = sqlx::query_scalar(&format!(
+        "SELECT COALESCE(
+            (SELECT MIN(c.id) FROM comments c WHERE c.article_id = $1 AND {live} AND c.id > $2),
+            (SELECT MAX(c.id) FROM comments c WHERE c.article_id = $1 AND {live} AND c.id <= $2))",
+        live = visibility::live_comments("c")
+    ))
+    .bind(article_id)
+    .bind(seen)
+    .fetch_one(pool)
+    .await?;
+    Ok(match target {
+        Some(id) => paths::url(&format!("/articles/{}?after={}#c{}", article_id, id - 1, id)),
+        None => top,
+    })
+}
+
+#[derive(FromRow)]
+struct WatchedArticle {
+    id: i32,
+    title: String,
+    new_comments: i64,
+    new_replies: i64,
+}
+
+pub async fn watched_articles(req: HttpRequest, pool: web::Data<PgPool>) -> HttpResponse {
+    let base = paths::base();
+    let (list, reset) = match read(&req) {
+        Ok(list) => (list, false),
+        Err(()) => (WatchList::new(), true),
+    };
+
+    let ids: Vec<i32> = list.iter().map(|(id, _)| *id).collect();
+    let seen: Vec<i32> = list.iter().map(|(_, seen)| *seen).collect();
+    let own = own_comment_ids(&req);
+    let articles = if ids.is_empty() {
+        Vec::new()
+    } else {
+        match sqlx::query_as::<_, WatchedArticle>(&format!(
+            "SELECT a.id, a.title,
+                (SELECT COUNT(*) FROM comments c
+                 WHERE c.article_id = a.id AND {live}
+                   AND c.id > ($2::int[])[array_position($1::int[], a.id)]) AS new_comments,
+                (SELECT COUNT(DISTINCT r.source_comment_id) FROM comment_replies r
+                 JOIN comments c ON c.id = r.source_comment_id
+                 WHERE c.article_id = a.id AND r.target_comment_id = ANY($3) AND NOT (c.id = ANY($3))
+                   AND {live}
+                   AND c.id > ($2::int[])[array_position($1::int[], a.id)]) AS new_replies
+             FROM articles a WHERE a.id = ANY($1) AND {shown}
+             ORDER BY a.bump_time DESC, a.id DESC",
+            live = visibility::live_comments("c"),
+            shown = visibility::shown_articles_to("a", &visibility::Viewer::of(&req, pool.get_ref()).await)
+        ))
+        .bind(&ids)
+        .bind(&seen)
+        .bind(&own)
+        .fetch_all(pool.get_ref())
+        .await
+        {
+            Ok(a) => a,
+            Err(e) => {
+                log_error(&format!("Failed to fetch watched articles: {}", e));
+                return HttpResponse::InternalServerError().body("Failed to load watched articles");
+            }
+        }
+    };
+
+    let mut rows_html = String::new();
+    for article in &articles {
+        let new_comments = match article.new_comments {
+            0 => String::new(),
+            1 => r#" <span class="badge">1 new comment</span>"#.to_string(),
+            n => format!(r#" <span class="badge">{} new comments</span>"#, n),
+        };
+        let new_replies = match article.new_replies {
+            0 => String::new(),
+            n => format!(r#" <span class="badge">replies to your comments: {}</span>"#, n),
+        };
+        rows_html.push_str(&format!(
+            r#"<div class="article">
+            <h2><a href="{base}/articles/{}" dir="auto">{}</a>{}{}</h2>
+            <a href="{base}/articles/{}/watch" class="quote-link">[unwatch]</a>
+            </div>"#,
+            article.id,
+            html_escape::encode_text(&article.title),
+            new_comments,
+            new_replies,
+            article.id
+        ));
+    }
+
+    // Watched ids the query didn't return have been deleted since
+    for id in ids.iter().filter(|id| !articles.iter().any(|a| a.id == **id)) {
+        rows_html.push_str(&format!(
+            r#"<div class="article">
+            <h2 class="tombstone">Article #{} has been deleted</h2>
+            <a href="{base}/articles/{}/watch" class="quote-link">[remove]</a>
+            </div>"#,
+            id, id
+        ));
+    }
+
+    if list.is_empty() {
+        rows_html.push_str(r#"<p class="center-link">You aren't watching any articles.</p>"#);
+    }
+    let notice = if reset {
+        r#"<div class="thread-age-notice">Your watch list could not be read and has been reset.</div>"#
+    } else {
+        ""
+    };
+
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>Watched Articles</title>
+        <link rel="stylesheet" href="{base}/static/style.css"></head>
+        <body>
+        <div class="center-link"><a href="{base}/articles">← Back to All Articles</a></div>
+        <h1>Watched Articles</h1>
+        {}
+        {}
+        </body>
+        </html>
+        "#,
+        notice, rows_html
+    );
+
+    let mut response = HttpResponse::Ok();
+    if reset {
+        consent::add_cookie(&req, &mut response, watch_cookie(&WatchList::new()));
+    }
+    response.content_type("text/html").body(html)
+}
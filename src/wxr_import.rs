@@ -0,0 +1,690 @@
+use chrono::{NaiveDateTime, Utc};
+use percent_encoding::percent_decode_str;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use crate::body_format::BodyFormat;
+use crate::bumps::{self, Activity};
+use crate::db::comments;
+use crate::events::{self, EventKind};
+use crate::media::{self, types};
+use crate::scan::{ScanRecord, UploadScanner};
+use crate::settings::SettingsCache;
+use crate::{log_error, paths, references, search, store_scanned_upload, text, unlinks};
+
+// `import-wxr`: loads a WordPress WXR export. Posts become articles and
+// approved comments become comments, with media the posts reference
+// downloaded into the uploads pipeline. Every imported item is recorded by
+// its source GUID, so running the same export again only adds what's new.
+//     articles1 import-wxr --file export.xml [--include-drafts] [--body-format markdown|html]
+const IMPORT_ACTOR: &str = "import";
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+// Cap on any single download, before its type is known
+const MAX_DOWNLOAD_BYTES: usize = 64 * 1024 * 1024;
+const WP_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+struct Options {
+    file: String,
+    include_drafts: bool,
+    body_format: BodyFormat,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut file = None;
+    let mut include_drafts = false;
+    let mut body_format = BodyFormat::Markdown;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => file = args.next().cloned(),
+            "--include-drafts" => include_drafts = true,
+            "--body-format" => {
+                body_format = match args.next().map(String::as_str) {
+                    Some("markdown") => BodyFormat::Markdown,
+                    Some("html") => BodyFormat::Html,
+                    _ => return Err("--body-format must be markdown or html".to_string()),
+                }
+            }
+            other => return Err(format!("Unknown import-wxr option: {}", other)),
+        }
+    }
+    let file = file.ok_or("import-wxr needs --file <export.xml>")?;
+    Ok(Options {
+        file,
+        include_drafts,
+        body_format,
+    })
+}
+
+#[derive(Default)]
+struct WxrItem {
+    title: String,
+    guid: String,
+    link: String,
+    content: String,
+    post_id: String,
+    post_type: String,
+    status: String,
+    post_date: String,
+    post_date_gmt: String,
+    parent: String,
+    attachment_url: String,
+    comments: Vec<WxrComment>,
+}
+
+#[derive(Default)]
+struct WxrComment {
+    id: String,
+    author: String,
+    date: String,
+    date_gmt: String,
+    content: String,
+    approved: String,
+    comment_type: String,
+}
+
+// Every <item> in the export, with its comments. Elements are matched by
+// their qualified names, which WordPress always writes with these prefixes.
+fn parse_wxr(xml: &str) -> Result<Vec<WxrItem>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut items = Vec::new();
+    let mut item: Option<WxrItem> = None;
+    let mut comment: Option<WxrComment> = None;
+    let mut text = String::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid WXR at byte {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(e) => {
+                text.clear();
+                match e.name().as_ref() {
+                    b"item" => item = Some(WxrItem::default()),
+                    b"wp:comment" => comment = Some(WxrComment::default()),
+                    _ => {}
+                }
+            }
+            Event::Text(t) => {
+                let unescaped = t
+                    .unescape()
+                    .map_err(|e| format!("Invalid WXR at byte {}: {}", reader.buffer_position(), e))?;
+                text.push_str(&unescaped);
+            }
+            Event::CData(t) => text.push_str(&String::from_utf8_lossy(&t.into_inner())),
+            Event::End(e) => {
+                let value = std::mem::take(&mut text);
+                let name = e.name();
+                if let Some(c) = comment.as_mut() {
+                    match name.as_ref() {
+                        b"wp:comment_id" => c.id = value,
+                        b"wp:comment_author" => c.author = value,
+                        b"wp:comment_date" => c.date = value,
+                        b"wp:comment_date_gmt" => c.date_gmt = value,
+                        b"wp:comment_content" => c.content = value,
+                        b"wp:comment_approved" => c.approved = value,
+                        b"wp:comment_type" => c.comment_type = value,
+                        b"wp:comment" => {
+                            if let (Some(i), Some(c)) = (item.as_mut(), comment.take()) {
+                                i.comments.push(c);
+                            }
+                        }
+                        _ => {}
+                    }
+                } else if let Some(i) = item.as_mut() {
+                    match name.as_ref() {
+                        b"title" => i.title = value,
+                        b"guid" => i.guid = value,
+                        b"link" => i.link = value,
+                        b"content:encoded" => i.content = value,
+                        b"wp:post_id" => i.post_id = value,
+                        b"wp:post_type" => i.post_type = value,
+                        b"wp:status" => i.status = value,
+                        b"wp:post_date" => i.post_date = value,
+                        b"wp:post_date_gmt" => i.post_date_gmt = value,
+                        b"wp:post_parent" => i.parent = value,
+                        b"wp:attachment_url" => i.attachment_url = value,
+                        b"item" => items.extend(item.take()),
+                        _ => {}
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+// Epoch seconds for a WordPress date. Drafts carry a zeroed GMT date, in
+// which case the site-local date is used as if it were UTC.
+fn wp_timestamp(gmt: &str, local: &str) -> Option<i64> {
+    [gmt, local]
+        .iter()
+        .filter_map(|d| NaiveDateTime::parse_from_str(d.trim(), WP_DATE_FORMAT).ok())
+        .map(|dt| dt.and_utc().timestamp())
+        .find(|ts| *ts > 0)
+}
+
+#[derive(Default)]
+struct Summary {
+    posts_imported: usize,
+    posts_existing: usize,
+    drafts_skipped: usize,
+    posts_failed: usize,
+    comments_imported: usize,
+    comments_existing: usize,
+    comments_skipped: usize,
+    comments_failed: usize,
+    media_stored: usize,
+    media_failed: usize,
+}
+
+fn item_failed(message: &str) {
+    log_error(message);
+    eprintln!("{}", message);
+}
+
+pub async fn run(pool: &PgPool, args: &[String]) -> Result<(), String> {
+    let options = parse_args(args)?;
+    let xml = fs::read_to_string(&options.file).map_err(|e| format!("Failed to read {}: {}", options.file, e))?;
+    let items = parse_wxr(&xml)?;
+
+    let client = reqwest::Client::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .user_agent("articles-wxr-import")
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+    let scanner = UploadScanner::from_env();
+    let fail_open = SettingsCache::default().get(pool).await.scan_fail_open;
+
+    // Attachment URLs by the post they belong to
+    let mut attachments: HashMap<&str, Vec<&str>> = HashMap::new();
+    for item in items.iter().filter(|i| i.post_type == "attachment") {
+        if !item.attachment_url.is_empty() && !item.parent.is_empty() && item.parent != "0" {
+            attachments
+                .entry(item.parent.as_str())
+                .or_default()
+                .push(item.attachment_url.as_str());
+        }
+    }
+
+    let mut summary = Summary::default();
+    for item in items.iter().filter(|i| i.post_type == "post") {
+        let is_draft = item.status != "publish";
+        if is_draft && !options.include_drafts {
+            summary.drafts_skipped += 1;
+            continue;
+        }
+        let guid = post_guid(item);
+
+        let existing = match imported_id(pool, &guid, "article").await {
+            Ok(id) => id,
+            Err(e) => {
+                item_failed(&format!("Failed to look up import of {}: {}", guid, e));
+                summary.posts_failed += 1;
+                continue;
+            }
+        };
+        let article_id = match existing {
+            Some(id) => {
+                summary.posts_existing += 1;
+                id
+            }
+            None => {
+                let mut urls = media_urls(&item.content);
+                for url in attachments.get(item.post_id.as_str()).into_iter().flatten() {
+                    if !urls.iter().any(|u| u.as_str() == *url) {
+                        urls.push(url.to_string());
+                    }
+                }
+                let mut stored = Vec::new();
+                for url in urls {
+                    match download_media(&client, &scanner, fail_open, &url).await {
+                        Ok(m) => {
+                            summary.media_stored += 1;
+                            stored.push(m);
+                        }
+                        Err(e) => {
+                            item_failed(&format!("Failed to import media {} for {}: {}", url, guid, e));
+                            summary.media_failed += 1;
+                        }
+                    }
+                }
+
+                match import_post(pool, item, &guid, options.body_format, &stored).await {
+                    Ok(id) => {
+                        summary.posts_imported += 1;
+                        id
+                    }
+                    Err(e) => {
+                        item_failed(&format!("Failed to import post {}: {}", guid, e));
+                        summary.posts_failed += 1;
+                        discard_media(pool, &stored).await;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        for comment in &item.comments {
+            // Pingbacks, trackbacks, spam and held comments stay behind
+            let is_plain = comment.comment_type.is_empty() || comment.comment_type == "comment";
+            if comment.approved != "1" || !is_plain || comment.content.trim().is_empty() {
+                summary.comments_skipped += 1;
+                continue;
+            }
+            let comment_guid = format!("{}#comment-{}", guid, comment.id);
+            match import_comment(pool, article_id, comment, &comment_guid).await {
+                Ok(true) => summary.comments_imported += 1,
+                Ok(false) => summary.comments_existing += 1,
+                Err(e) => {
+                    item_failed(&format!("Failed to import comment {}: {}", comment_guid, e));
+                    summary.comments_failed += 1;
+                }
+            }
+        }
+    }
+
+    println!(
+        "Posts: {} imported, {} already imported, {} drafts skipped, {} failed",
+        summary.posts_imported, summary.posts_existing, summary.drafts_skipped, summary.posts_failed
+    );
+    println!(
+        "Comments: {} imported, {} already imported, {} skipped, {} failed",
+        summary.comments_imported, summary.comments_existing, summary.comments_skipped, summary.comments_failed
+    );
+    println!("Media: {} stored, {} failed", summary.media_stored, summary.media_failed);
+    Ok(())
+}
+
+fn post_guid(item: &WxrItem) -> String {
+    [item.guid.trim(), item.link.trim()]
+        .into_iter()
+        .find(|g| !g.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("wp-post-{}", item.post_id.trim()))
+}
+
+async fn imported_id(pool: &PgPool, guid: &str, kind: &str) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar("SELECT target_id FROM import_sources WHERE source_guid = $1 AND kind = $2")
+        .bind(guid)
+        .bind(kind)
+        .fetch_optional(pool)
+        .await
+}
+
+// A downloaded file now stored under /uploads
+struct StoredMedia {
+    source_url: String,
+    path: String,
+    original_filename: String,
+    mime: &'static str,
+    hash: String,
+    size: i64,
+    width: Option<i32>,
+    height: Option<i32>,
+    scan: ScanRecord,
+}
+
+// Absolute image sources in post HTML, in order of appearance
+fn media_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for (i, _) in content.match_indices("src=") {
+        let rest = &content[i + 4..];
+        let quote = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => q,
+            _ => continue,
+        };
+        let url = match rest[1..].split(quote).next() {
+            Some(u) => html_escape::decode_html_entities(u).into_owned(),
+            None => continue,
+        };
+        let is_http = url.starts_with("http://") || url.starts_with("https://");
+        if is_http && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+async fn download_media(
+    client: &reqwest::Client,
+    scanner: &UploadScanner,
+    fail_open: bool,
+    url: &str,
+) -> Result<StoredMedia, String> {
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if data.len() + chunk.len() > MAX_DOWNLOAD_BYTES {
+            return Err("file is too large".to_string());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    let media_type = types::sniff(&data).ok_or("unsupported file type")?;
+    if media_type.max_size.is_some_and(|max| data.len() as u64 > max) {
+        return Err("file is too large".to_string());
+    }
+
+    let hash = media::content_hash(&data);
+    let path = media::hashed_media_path(&hash, media_type);
+    let disk_path = media::disk_path(&path).ok_or("failed to store file")?;
+    let scan = store_scanned_upload(scanner, fail_open, &data, &disk_path.to_string_lossy())
+        .await
+        .map_err(|res| format!("upload rejected ({})", res.status()))?;
+
+    let (width, height) = match media::dimensions(&data) {
+        Some((w, h)) => (Some(w), Some(h)),
+        None => (None, None),
+    };
+    let name = url.split(['?', '#']).next().unwrap_or(url).rsplit('/').next().unwrap_or("");
+    Ok(StoredMedia {
+        source_url: url.to_string(),
+        path,
+        original_filename: percent_decode_str(name).decode_utf8_lossy().into_owned(),
+        mime: media_type.mime,
+        hash,
+        size: data.len() as i64,
+        width,
+        height,
+        scan,
+    })
+}
+
+// Queues downloads that never got attached for cleanup
+async fn discard_media(pool: &PgPool, stored: &[StoredMedia]) {
+    let paths: Vec<String> = stored.iter().map(|m| m.path.clone()).collect();
+    let result = match pool.acquire().await {
+        Ok(mut conn) => unlinks::enqueue(&mut conn, &paths).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error(&format!("Failed to queue unused imports for removal: {}", e));
+    }
+}
+
+async fn import_post(
+    pool: &PgPool,
+    item: &WxrItem,
+    guid: &str,
+    body_format: BodyFormat,
+    stored: &[StoredMedia],
+) -> Result<i32, String> {
+    let db_error = |e: sqlx::Error| e.to_string();
+
+    // Links to downloaded files point at the local copies from here on
+    let mut content = item.content.clone();
+    for m in stored {
+        content = content.replace(&m.source_url, &paths::url(&m.path));
+    }
+    // HTML bodies are stored as exported and sanitized when shown
+    let body = match body_format {
+        BodyFormat::Html => content.trim().to_string(),
+        _ => html_to_markdown(&content),
+    };
+    let title = match item.title.trim() {
+        "" => "(untitled)",
+        t => t,
+    };
+    let posted_at = wp_timestamp(&item.post_date_gmt, &item.post_date).unwrap_or_else(|| Utc::now().timestamp());
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+
+    let article_id: i32 = sqlx::query_scalar(
+        "INSERT INTO articles (title, body, body_format, bump_time, updated_at, created_at)
+         VALUES ($1, $2, $3, $4, $4, $4) RETURNING id",
+    )
+    .bind(title)
+    .bind(&body)
+    .bind(body_format.name())
+    .bind(posted_at)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    for m in stored {
+        sqlx::query(
+            "INSERT INTO article_media
+                (article_id, media_path, original_filename, mime_type, content_hash, size_bytes, width, height,
+                 scan_status, scan_duration_ms)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(article_id)
+        .bind(&m.path)
+        .bind(&m.original_filename)
+        .bind(m.mime)
+        .bind(&m.hash)
+        .bind(m.size)
+        .bind(m.width)
+        .bind(m.height)
+        .bind(m.scan.status)
+        .bind(m.scan.duration_ms)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+    let media_paths: Vec<&str> = stored.iter().map(|m| m.path.as_str()).collect();
+    unlinks::hold(&mut tx, &media_paths).await?;
+
+    search::index_article(&mut tx, article_id).await.map_err(db_error)?;
+    references::record_article_refs(&mut tx, article_id, &body).await.map_err(db_error)?;
+    events::record(&mut tx, article_id, EventKind::Created, IMPORT_ACTOR, Some(guid))
+        .await
+        .map_err(db_error)?;
+
+    sqlx::query("INSERT INTO import_sources (source_guid, kind, target_id, imported_at) VALUES ($1, 'article', $2, $3)")
+        .bind(guid)
+        .bind(article_id)
+        .bind(Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(article_id)
+}
+
+// Imports one comment unless it already was. Ok(false) means it was skipped
+// as already imported.
+async fn import_comment(pool: &PgPool, article_id: i32, comment: &WxrComment, guid: &str) -> Result<bool, String> {
+    let db_error = |e: sqlx::Error| e.to_string();
+    if imported_id(pool, guid, "comment").await.map_err(db_error)?.is_some() {
+        return Ok(false);
+    }
+
+    let text = html_to_markdown(&comment.content);
+    let created_at = wp_timestamp(&comment.date_gmt, &comment.date).unwrap_or_else(|| Utc::now().timestamp());
+    let author = text::balance_bidi(&comment.author);
+    let author = Some(author.trim()).filter(|a| !a.is_empty());
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+
+    let comment_id: i32 = sqlx::query_scalar(
+        "INSERT INTO comments (article_id, comment, created_at, ip_representation, author_name)
+         VALUES ($1, $2, $3, $4, $5) RETURNING id",
+    )
+    .bind(article_id)
+    .bind(&text)
+    .bind(created_at)
+    .bind(IMPORT_ACTOR)
+    .bind(author)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    references::record_comment_refs(&mut tx, article_id, comment_id, &text)
+        .await
+        .map_err(db_error)?;
+
+    // Imported threads sort by their last real activity, never by import time
+    bumps::record(&mut tx, article_id, Activity::ImportedComment, created_at)
+        .await
+        .map_err(db_error)?;
+    comments::refresh_counts(&mut tx, &[article_id])
+        .await
+        .map_err(db_error)?;
+
+    let detail = format!("comment #{}", comment_id);
+    events::record(&mut tx, article_id, EventKind::Commented, IMPORT_ACTOR, Some(&detail))
+        .await
+        .map_err(db_error)?;
+
+    sqlx::query("INSERT INTO import_sources (source_guid, kind, target_id, imported_at) VALUES ($1, 'comment', $2, $3)")
+        .bind(guid)
+        .bind(comment_id)
+        .bind(Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+    Ok(true)
+}
+
+// One piece of post HTML: a tag (lowercased name, closing?, raw attributes)
+// or the text between tags
+enum Token<'a> {
+    Tag { name: String, closing: bool, attrs: &'a str },
+    Text(&'a str),
+}
+
+// Splits post HTML into tags and text. WordPress content is loose HTML, not
+// XML, so this only looks for angle brackets. Comments are dropped.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.split_once("-->").map(|(_, r)| r).unwrap_or("");
+            continue;
+        }
+        match rest.find('<') {
+            Some(0) => {
+                let end = match rest.find('>') {
+                    Some(end) => end,
+                    None => {
+                        tokens.push(Token::Text(rest));
+                        break;
+                    }
+                };
+                let inner = rest[1..end].trim().trim_end_matches('/');
+                let (closing, inner) = match inner.strip_prefix('/') {
+                    Some(i) => (true, i),
+                    None => (false, inner),
+                };
+                let split = inner.find(|c: char| c.is_whitespace()).unwrap_or(inner.len());
+                tokens.push(Token::Tag {
+                    name: inner[..split].to_ascii_lowercase(),
+                    closing,
+                    attrs: &inner[split..],
+                });
+                rest = &rest[end + 1..];
+            }
+            Some(start) => {
+                tokens.push(Token::Text(&rest[..start]));
+                rest = &rest[start..];
+            }
+            None => {
+                tokens.push(Token::Text(rest));
+                break;
+            }
+        }
+    }
+    tokens
+}
+
+// Value of a quoted attribute such as href="..."
+fn attr_value(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let at = lower.match_indices(&format!("{}=", name)).map(|(i, _)| i).find(|i| {
+        *i == 0 || lower.as_bytes()[*i - 1].is_ascii_whitespace()
+    })?;
+    let rest = &attrs[at + name.len() + 1..];
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = rest[1..].split(quote).next()?;
+    Some(html_escape::decode_html_entities(value).into_owned())
+}
+
+fn safe_href(href: &str) -> bool {
+    let lower = href.trim().to_ascii_lowercase();
+    ["http://", "https://", "mailto:", "/", "#"].iter().any(|p| lower.starts_with(p))
+}
+
+// Elements whose content is never shown
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "iframe", "object"];
+
+fn html_to_markdown(html: &str) -> String {
+    let mut out = String::new();
+    let mut links: Vec<Option<String>> = Vec::new();
+    let mut skipping: Option<String> = None;
+    for token in tokenize(html) {
+        match token {
+            Token::Tag { name, closing, .. } if skipping.is_some() => {
+                if closing && skipping.as_deref() == Some(name.as_str()) {
+                    skipping = None;
+                }
+            }
+            Token::Text(_) if skipping.is_some() => {}
+            Token::Text(text) => out.push_str(&html_escape::decode_html_entities(text)),
+            Token::Tag { name, closing, attrs } => match (name.as_str(), closing) {
+                (n, false) if SKIPPED_ELEMENTS.contains(&n) => skipping = Some(name.clone()),
+                ("p" | "div" | "ul" | "ol" | "blockquote" | "figure", _) => out.push_str("\n\n"),
+                ("br", _) => out.push('\n'),
+                ("hr", _) => out.push_str("\n\n---\n\n"),
+                ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                    let level = name[1..].parse::<usize>().unwrap_or(2);
+                    out.push_str(&format!("\n\n{} ", "#".repeat(level)));
+                }
+                ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => out.push_str("\n\n"),
+                ("li", false) => out.push_str("\n- "),
+                ("strong" | "b", _) => out.push_str("**"),
+                ("em" | "i", _) => out.push('*'),
+                ("code", _) => out.push('`'),
+                ("pre", _) => out.push_str("\n\n```\n"),
+                ("a", false) => {
+                    let href = attr_value(attrs, "href").filter(|h| safe_href(h));
+                    if href.is_some() {
+                        out.push('[');
+                    }
+                    links.push(href);
+                }
+                ("a", true) => {
+                    if let Some(Some(href)) = links.pop() {
+                        out.push_str(&format!("]({})", href));
+                    }
+                }
+                // Images come across as attached media
+                _ => {}
+            },
+        }
+    }
+    collapse_blank_lines(&out)
+}
+
+fn collapse_blank_lines(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut newlines = 0;
+    for c in text.trim().chars() {
+        if c == '\n' {
+            newlines += 1;
+            if newlines > 2 {
+                continue;
+            }
+        } else if !c.is_whitespace() {
+            newlines = 0;
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,89 @@
+use actix_web::cookie::Cookie;
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+// One admin POST reached by each way of authenticating
+const URI: &str = "/admin/blocklist";
+
+#[actix_web::test]
+async fn session_needs_the_csrf_token() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let session = common::login(&app).await;
+
+    let req = common::post_form(URI, &[("pattern", "spam"), ("action", "reject")])
+        .cookie(session.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let req = TestRequest::post()
+        .uri(URI)
+        .cookie(session)
+        .set_form([("pattern", "spam"), ("action", "reject")])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn api_token_needs_no_csrf_token() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let req = TestRequest::post()
+        .uri(URI)
+        .insert_header(("Authorization", format!("Bearer {}", common::API_TOKEN)))
+        .set_form([("pattern", "spam"), ("action", "reject")])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let req = TestRequest::post()
+        .uri(URI)
+        .insert_header(("Authorization", "Bearer not-the-token"))
+        .set_form([("pattern", "spam"), ("action", "reject")])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn password_without_a_session_needs_no_csrf_token() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let req = TestRequest::post()
+        .uri(URI)
+        .set_form([("pattern", "spam"), ("action", "reject"), ("password", common::ADMIN_PASSWORD)])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let req = TestRequest::post()
+        .uri(URI)
+        .set_form([("pattern", "spam"), ("action", "reject"), ("password", "not the password")])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+}
+
+#[actix_web::test]
+async fn password_next_to_a_session_cookie_still_needs_the_csrf_token() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let req = TestRequest::post()
+        .uri(URI)
+        .cookie(Cookie::new("admin_session", "stale"))
+        .set_form([("pattern", "spam"), ("action", "reject"), ("password", common::ADMIN_PASSWORD)])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
@@ -0,0 +1,130 @@
+use actix_web::test;
+use articles1::admin_cli;
+
+mod common;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+async fn exists(db: &common::TestDatabase, query: &str, id: i32) -> bool {
+    sqlx::query_scalar::<_, bool>(query).bind(id).fetch_one(&db.pool).await.unwrap()
+}
+
+#[actix_web::test]
+async fn delete_article_needs_yes() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Doomed", "Body", "198.51.100.1:1000").await;
+    let query = "SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)";
+
+    let refused = admin_cli::run(&db.pool, &args(&format!("delete-article {}", id))).await.unwrap_err();
+    assert!(refused.contains("--yes"), "{}", refused);
+    assert!(exists(&db, query, id).await);
+
+    admin_cli::run(&db.pool, &args(&format!("delete-article {} --yes --json", id))).await.unwrap();
+    assert!(!exists(&db, query, id).await);
+    assert!(admin_cli::run(&db.pool, &args(&format!("delete-article {} --yes", id))).await.is_err());
+}
+
+#[actix_web::test]
+async fn delete_comment_removes_it() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Commented", "Body", "198.51.100.1:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "Rude", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let comment_id: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    assert!(admin_cli::run(&db.pool, &args(&format!("delete-comment {}", comment_id))).await.is_err());
+    admin_cli::run(&db.pool, &args(&format!("delete-comment {} --yes", comment_id))).await.unwrap();
+    let visible = "SELECT EXISTS(SELECT 1 FROM comments WHERE id = $1 AND NOT deleted)";
+    assert!(!exists(&db, visible, comment_id).await);
+}
+
+#[actix_web::test]
+async fn lock_stops_comments_until_unlocked() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Heated", "Body", "198.51.100.1:1000").await;
+
+    admin_cli::run(&db.pool, &args(&format!("lock {}", id))).await.unwrap();
+    let res = test::call_service(&app, common::comment_request(id, "Late", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 403);
+    let page = test::call_service(&app, test::TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let page = common::body(page).await;
+    assert!(page.contains("Comments on this article are locked."));
+    assert!(!page.contains(r#"name="comment""#));
+    let logged = "SELECT EXISTS(SELECT 1 FROM article_events WHERE article_id = $1 AND kind = 'locked' AND actor = 'cli')";
+    assert!(exists(&db, logged, id).await);
+
+    admin_cli::run(&db.pool, &args(&format!("lock {} --unlock --json", id))).await.unwrap();
+    let res = test::call_service(&app, common::comment_request(id, "Late", "198.51.100.3:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+
+    assert!(admin_cli::run(&db.pool, &args("lock 99999")).await.is_err());
+    assert!(admin_cli::run(&db.pool, &args(&format!("delete-article {} --unlock --yes", id))).await.is_err());
+}
+
+#[actix_web::test]
+async fn ban_ip_bans_like_the_admin_form() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Banned", "Body", "198.51.100.1:1000").await;
+
+    assert!(admin_cli::run(&db.pool, &args("ban-ip not-an-address")).await.is_err());
+    admin_cli::run(&db.pool, &args("ban-ip 203.0.113.9 --days 7 --reason spam")).await.unwrap();
+    let (created_by, expires_at): (String, Option<i64>) =
+        sqlx::query_as("SELECT created_by, expires_at FROM bans").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(created_by, "cli");
+    assert!(expires_at.is_some());
+
+    let res = test::call_service(&app, common::comment_request(id, "Hi", "203.0.113.9:1000").to_request()).await;
+    assert_eq!(res.status(), 403);
+
+    // A shadow ban lets the comment through, stored shadow-hidden
+    admin_cli::run(&db.pool, &args("ban-ip 203.0.113.10 --shadow")).await.unwrap();
+    let res = test::call_service(&app, common::comment_request(id, "Hi", "203.0.113.10:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let shadow_hidden: bool = sqlx::query_scalar("SELECT shadow_hidden FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert!(shadow_hidden);
+}
+
+#[actix_web::test]
+async fn list_reports_reads_open_reports() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Reported", "Body", "198.51.100.1:1000").await;
+    let req = common::post_form(&format!("/articles/{}/report", id), &[("reason", "spam")])
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    admin_cli::run(&db.pool, &args("list-reports")).await.unwrap();
+    admin_cli::run(&db.pool, &args("list-reports --json")).await.unwrap();
+    assert!(admin_cli::run(&db.pool, &args("list-reports --yes")).await.is_err());
+    assert!(admin_cli::run(&db.pool, &args("unknown")).await.is_err());
+}
@@ -0,0 +1,27 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+#[actix_web::test]
+async fn edit_and_delete_links_need_a_session() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Linked", "Body", "198.51.100.1:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "A comment", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let session = common::login(&app).await;
+
+    for uri in ["/articles".to_string(), format!("/articles/{}", id)] {
+        let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+        assert!(!page.contains("delete-link"), "{}", uri);
+        assert!(!page.contains("edit-link"), "{}", uri);
+
+        let req = TestRequest::get().uri(&uri).cookie(session.clone()).to_request();
+        let page = common::body(test::call_service(&app, req).await).await;
+        assert!(page.contains(&format!("/articles/{}/delete", id)), "{}", uri);
+        assert!(page.contains(&format!("/articles/{}/edit", id)), "{}", uri);
+    }
+}
@@ -0,0 +1,72 @@
+use actix_web::cookie::Cookie;
+use actix_web::test::{self, TestRequest};
+use articles1::tokens;
+
+mod common;
+
+async fn comment_ids(db: &common::TestDatabase, id: i32) -> Vec<i32> {
+    sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1 AND NOT deleted ORDER BY id")
+        .bind(id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+}
+
+// One login covers several deletions, and their forms stop asking for the password
+#[actix_web::test]
+async fn session_deletes_without_the_password() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Busy", "Body", "198.51.100.1:1000").await;
+    for (n, peer) in ["198.51.100.2:1000", "198.51.100.3:1000", "198.51.100.4:1000"].iter().enumerate() {
+        let req = common::comment_request(id, &format!("Spam {}", n), peer).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 302);
+    }
+    let comments = comment_ids(&db, id).await;
+    assert_eq!(comments.len(), 3);
+
+    let form_uri = format!("/comments/{}/delete", comments[0]);
+    let anonymous = common::body(test::call_service(&app, TestRequest::get().uri(&form_uri).to_request()).await).await;
+    assert!(anonymous.contains(r#"name="password""#));
+
+    let session = common::login(&app).await;
+    let req = TestRequest::get().uri(&form_uri).cookie(session.clone()).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    assert!(!form.contains(r#"name="password""#));
+
+    for comment in &comments {
+        let req = common::post_form(&format!("/comments/{}/delete", comment), &[]).cookie(session.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 302);
+    }
+    assert!(comment_ids(&db, id).await.is_empty());
+}
+
+#[actix_web::test]
+async fn logout_clears_the_cookie_and_expired_sessions_are_refused() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Kept", "Body", "198.51.100.1:1000").await;
+
+    let session = common::login(&app).await;
+    let res = test::call_service(&app, common::post_form("/admin/logout", &[]).cookie(session).to_request()).await;
+    assert_eq!(res.status(), 302);
+    let cleared = res.response().cookies().find(|c| c.name() == "admin_session").expect("cookie not cleared");
+    assert_eq!(cleared.value(), "");
+    assert_eq!(cleared.max_age(), Some(actix_web::cookie::time::Duration::ZERO));
+
+    let expired = Cookie::new("admin_session", tokens::sign_expiring("admin-session", "admin", -60));
+    let req = common::post_form(&format!("/articles/{}/delete", id), &[]).cookie(expired).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert!(exists);
+}
@@ -0,0 +1,35 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+// A scheduled announcement stays private until it starts, so its admin pages
+// need staff with the settings permission
+#[actix_web::test]
+async fn admin_pages_hide_scheduled_announcements() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO announcements (message, starts_at, created_at)
+         VALUES ('Launching next week', EXTRACT(EPOCH FROM NOW())::BIGINT + 86400, 1) RETURNING id",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    let uris = ["/admin/announcements".to_string(), format!("/admin/announcements/{}/edit", id)];
+
+    for uri in &uris {
+        let res = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(res.status(), 401, "{}", uri);
+        assert!(!common::body(res).await.contains("Launching next week"), "{}", uri);
+    }
+
+    let admin = common::login(&app).await;
+    for uri in &uris {
+        let req = TestRequest::get().uri(uri).cookie(admin.clone()).to_request();
+        let page = common::body(test::call_service(&app, req).await).await;
+        assert!(page.contains("Launching next week"), "{}", uri);
+    }
+}
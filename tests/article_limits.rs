@@ -0,0 +1,64 @@
+use actix_web::test;
+
+mod common;
+
+fn submit(title: &str, body: &str) -> actix_http::Request {
+    common::post_multipart("/submit", &[("title", title), ("body", body)], Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request()
+}
+
+// Blank and over-long values come back with the form and what was typed;
+// accepted ones are stored trimmed
+#[actix_web::test]
+async fn titles_and_bodies_are_trimmed_and_limited() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query("INSERT INTO settings (key, value) VALUES ('max_title_chars', '10')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let app = common::app(&db).await;
+
+    let res = test::call_service(&app, submit("   ", "Body")).await;
+    assert_eq!(res.status(), 400);
+    assert!(common::body(res).await.contains("Title cannot be empty."));
+
+    let res = test::call_service(&app, submit("Far too long a title", "Kept body")).await;
+    assert_eq!(res.status(), 400);
+    let page = common::body(res).await;
+    assert!(page.contains("Title is too long (maximum 10 characters)."), "{}", page);
+    assert!(page.contains("Far too long a title") && page.contains("Kept body"), "{}", page);
+
+    assert_eq!(test::call_service(&app, submit("  Trimmed  ", "\n Body \n")).await.status(), 302);
+    let stored: Vec<(String, String)> = sqlx::query_as("SELECT title, body FROM articles")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, [("Trimmed".to_string(), "Body".to_string())]);
+
+    // Saving an edit is held to the same limits
+    let id = common::submit_article(&app, &db, "Short", "Body", "198.51.100.2:1000").await;
+    let edit = format!("/articles/{}/edit", id);
+    let req = common::post_multipart(&edit, &[("mode", "check"), ("password", common::ADMIN_PASSWORD)], None).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    let (_, token) = form.split_once(r#"name="edit_token" value=""#).expect("no edit token");
+    let token = token.split('"').next().unwrap();
+    let req = common::post_multipart(
+        &edit,
+        &[("mode", "save"), ("edit_token", token), ("title", "Far too long a title"), ("body", "Body")],
+        None,
+    )
+    .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 400);
+    assert!(common::body(res).await.contains("Far too long a title"));
+    let title: String = sqlx::query_scalar("SELECT title FROM articles WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(title, "Short");
+}
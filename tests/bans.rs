@@ -0,0 +1,56 @@
+use actix_web::test;
+
+mod common;
+
+const BANNED: &str = "203.0.113.5:4000";
+
+#[actix_web::test]
+async fn banned_address_cannot_comment_unless_staff() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Banned from here", "Body", "198.51.100.1:1000").await;
+    let admin = common::login(&app).await;
+
+    let req = common::post_form("/admin/bans", &[("ip", "203.0.113.5"), ("reason", "Spam"), ("days", "")])
+        .cookie(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let res = test::call_service(&app, common::comment_request(id, "Hello", BANNED).to_request()).await;
+    assert_eq!(res.status(), 403);
+    assert!(common::body(res).await.contains("Reason: Spam"));
+
+    let res = test::call_service(&app, common::comment_request(id, "Hello", "203.0.113.6:4000").to_request()).await;
+    assert_eq!(res.status(), 302);
+
+    let req = common::comment_request(id, "From staff", BANNED).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    // A signed session for a moderator account that no longer exists is nobody
+    let req = common::comment_request(id, "Removed", BANNED)
+        .cookie(common::moderator_session(999))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+}
+
+#[actix_web::test]
+async fn ban_list_needs_the_manage_bans_permission() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let moderation = common::login_with(&app, common::MOD_PASSWORD).await;
+    let req = test::TestRequest::get().uri("/admin/bans").cookie(moderation).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let admin = common::login(&app).await;
+    let req = test::TestRequest::get().uri("/admin/bans").cookie(admin).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    assert!(!common::body(res).await.contains("SECRET_KEY is not set"));
+}
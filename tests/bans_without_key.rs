@@ -0,0 +1,24 @@
+use actix_web::test;
+
+mod common;
+
+// Without SECRET_KEY the address hashes change on restart, so bans are refused
+#[actix_web::test]
+async fn bans_are_refused_without_a_secret_key() {
+    let db = match common::database_with(&[("SECRET_KEY", "")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let admin = common::login(&app).await;
+
+    let req = test::TestRequest::get().uri("/admin/bans").cookie(admin.clone()).to_request();
+    assert!(common::body(test::call_service(&app, req).await).await.contains("SECRET_KEY is not set"));
+
+    let req = common::post_form("/admin/bans", &[("ip", "203.0.113.5"), ("reason", "Spam"), ("days", "")])
+        .cookie(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 503);
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM bans").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(stored, 0);
+}
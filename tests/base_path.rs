@@ -0,0 +1,51 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+// Root-relative links in `page` that don't go through the prefix
+fn unprefixed_links(page: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for attr in ["href=\"", "src=\"", "action=\""] {
+        for (i, _) in page.match_indices(attr) {
+            let link = page[i + attr.len()..].split('"').next().unwrap();
+            if link.starts_with('/') && !link.starts_with("//") && !link.starts_with("/board/") && link != "/board" {
+                found.push(link.to_string());
+            }
+        }
+    }
+    found
+}
+
+#[actix_web::test]
+async fn pages_and_redirects_stay_under_the_prefix() {
+    let db = match common::database_with(&[("BASE_PATH", "/board/")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Prefixed", "Body", "198.51.100.1:1000").await;
+
+    // The proxy strips the prefix, so requests arrive at the root
+    let res = test::call_service(&app, common::comment_request(id, "A reply", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    assert!(location.starts_with(&format!("/board/articles/{}", id)), "{}", location);
+
+    // Non-canonical paths are sent to the canonical one under the prefix
+    let res = test::call_service(&app, TestRequest::get().uri("/Articles/?page=2").to_request()).await;
+    assert_eq!(res.status(), 308);
+    assert_eq!(res.headers().get("Location").unwrap(), "/board/articles?page=2");
+
+    let admin = common::login(&app).await;
+    for uri in ["/articles".to_string(), format!("/articles/{}", id), "/submit".to_string()] {
+        for session in [None, Some(admin.clone())] {
+            let mut req = TestRequest::get().uri(&uri);
+            if let Some(cookie) = session {
+                req = req.cookie(cookie);
+            }
+            let page = common::body(test::call_service(&app, req.to_request()).await).await;
+            assert!(page.contains("/board/"), "{} has no prefixed links", uri);
+            assert_eq!(unprefixed_links(&page), Vec::<String>::new(), "on {}", uri);
+        }
+    }
+}
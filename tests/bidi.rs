@@ -0,0 +1,49 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const RLO: char = '\u{202E}';
+const PDF: char = '\u{202C}';
+
+async fn stored_comment(db: &common::TestDatabase, id: i32, starts_with: &str) -> String {
+    sqlx::query_scalar("SELECT comment FROM comments WHERE article_id = $1 AND comment LIKE $2 || '%'")
+        .bind(id)
+        .bind(starts_with)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn overrides_are_closed_and_user_text_is_isolated() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let title = "שלום and hello";
+    let id = common::submit_article(&app, &db, title, "Body", "198.51.100.1:1000").await;
+
+    let mixed = "Hello שלום עולם, see you";
+    let spoof = format!("Download invoice{}fdp.exe", RLO);
+    for (comment, peer) in [(mixed.to_string(), "198.51.100.2:1000"), (spoof, "198.51.100.3:1000")] {
+        let res = test::call_service(&app, common::comment_request(id, &comment, peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+
+    // Right-to-left text is kept as written; the override is closed where the comment ends
+    assert_eq!(stored_comment(&db, id, "Hello").await, mixed);
+    let closed = format!("Download invoice{}fdp.exe{}", RLO, PDF);
+    assert_eq!(stored_comment(&db, id, "Download").await, closed);
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let page = common::body(res).await;
+    assert!(page.contains(&format!(r#"<h1 dir="auto">{}</h1>"#, title)), "{}", page);
+    assert!(page.contains(&format!(r#"<p dir="auto">{}</p>"#, mixed)), "{}", page);
+    assert!(page.contains(&format!(r#"<p dir="auto">{}</p>"#, closed)), "{}", page);
+    // Nothing after the comment is left inside the override
+    assert_eq!(page.matches(RLO).count(), page.matches(PDF).count());
+
+    let list = common::body(test::call_service(&app, TestRequest::get().uri("/articles").to_request()).await).await;
+    assert!(list.contains(&format!(r#"dir="auto">{}</a>"#, title)), "{}", list);
+}
@@ -0,0 +1,83 @@
+use actix_web::test;
+
+mod common;
+
+// Terms added through the admin page refuse or mask posts straight away
+#[actix_web::test]
+async fn blocked_terms_refuse_or_mask_posts() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Clean", "Body", "198.51.100.1:1000").await;
+
+    let admin = common::login(&app).await;
+    for (pattern, is_regex, action) in [("casino", false, "reject"), ("dar+n", true, "replace")] {
+        let mut fields = vec![("pattern", pattern), ("action", action)];
+        if is_regex {
+            fields.push(("is_regex", "on"));
+        }
+        let req = common::post_form("/admin/blocklist", &fields).cookie(admin.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 302);
+    }
+
+    let fields = [("title", "Offer"), ("body", "Visit the casino")];
+    let req = common::post_multipart("/submit", &fields, Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 400);
+    let page = common::body(res).await;
+    assert!(page.contains("uses a word or phrase that"), "{}", page);
+    assert!(page.contains("Visit the casino"), "{}", page);
+
+    let req = common::comment_request(id, "Well darrn it", "198.51.100.3:1000").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let stored: String = sqlx::query_scalar("SELECT comment FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, "Well ***** it");
+    let articles: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM articles").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(articles, 1);
+}
+
+// Editing a comment can't slip a blocked term in afterwards
+#[actix_web::test]
+async fn comment_edits_are_screened() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Clean", "Body", "198.51.100.1:1000").await;
+    let admin = common::login(&app).await;
+    let req = common::post_form("/admin/blocklist", &[("pattern", "casino"), ("action", "reject")]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let res = test::call_service(&app, common::comment_request(id, "Nice post", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let edit_cookie = res
+        .response()
+        .cookies()
+        .find(|c| c.name().starts_with("comment_edit_"))
+        .expect("no edit cookie")
+        .into_owned();
+    let comment_id: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let uri = format!("/articles/{}/comments/{}/edit", id, comment_id);
+    let req = common::post_form(&uri, &[("comment", "Visit the casino")]).cookie(edit_cookie).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    let stored: String = sqlx::query_scalar("SELECT comment FROM comments WHERE id = $1")
+        .bind(comment_id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, "Nice post");
+}
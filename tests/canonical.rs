@@ -0,0 +1,36 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn page<S, B>(app: &S, uri: &str) -> String
+where
+    S: actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    common::body(test::call_service(app, TestRequest::get().uri(uri).to_request()).await).await
+}
+
+#[actix_web::test]
+async fn article_variants_name_one_canonical_url() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Canonical", "One sentence. Another one.", "198.51.100.1:1000").await;
+    let canonical = format!(r#"<link rel="canonical" href="http://localhost:8080/articles/{}">"#, id);
+
+    for uri in [
+        format!("/articles/{}", id),
+        format!("/articles/{}?quote=One%20sentence.", id),
+        format!("/articles/{}/quote", id),
+    ] {
+        let page = page(&app, &uri).await;
+        assert_eq!(page.matches(r#"rel="canonical""#).count(), 1, "on {}", uri);
+        assert!(page.contains(&canonical), "on {}", uri);
+    }
+
+    // Feeds link to the same address
+    let feed = page(&app, "/gallery/feed.xml").await;
+    assert!(feed.contains(&format!("<link>http://localhost:8080/articles/{}</link>", id)), "{}", feed);
+}
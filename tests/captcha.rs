@@ -0,0 +1,153 @@
+use actix_web::test::{self, TestRequest};
+use articles1::{captcha, tokens};
+use sqlx::PgPool;
+
+mod common;
+
+// Purpose the challenge tokens are signed for
+const TOKEN_PURPOSE: &str = "captcha";
+
+// The token and right answer of a fresh challenge, read from the form field
+fn challenge() -> (String, i64) {
+    let field = captcha::field(&TestRequest::default().to_http_request());
+    let token = field
+        .split(r#"value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .expect("challenge token")
+        .to_string();
+    let question = field
+        .split("What is ")
+        .nth(1)
+        .and_then(|rest| rest.split('?').next())
+        .expect("challenge question");
+    let numbers: Vec<i64> = question
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.parse().unwrap())
+        .collect();
+    let answer = if question.contains('+') {
+        numbers[0] + numbers[1]
+    } else {
+        numbers[0] - numbers[1]
+    };
+    (token, answer)
+}
+
+async fn check(pool: &PgPool, token: &str, answer: i64) -> Result<(), String> {
+    captcha::check(&TestRequest::default().to_http_request(), pool, token, &answer.to_string()).await
+}
+
+#[actix_web::test]
+async fn right_answer_passes_once() {
+    let db = match common::database_with(&[("CAPTCHA", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let (token, answer) = challenge();
+    assert_eq!(check(&db.pool, &token, answer).await, Ok(()));
+    let reused = check(&db.pool, &token, answer).await.unwrap_err();
+    assert!(reused.contains("already used"), "{}", reused);
+}
+
+#[actix_web::test]
+async fn wrong_answer_uses_up_the_challenge() {
+    let db = match common::database_with(&[("CAPTCHA", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let (token, answer) = challenge();
+    let wrong = check(&db.pool, &token, answer + 1).await.unwrap_err();
+    assert!(wrong.contains("Wrong answer"), "{}", wrong);
+    let reused = check(&db.pool, &token, answer).await.unwrap_err();
+    assert!(reused.contains("already used"), "{}", reused);
+}
+
+#[actix_web::test]
+async fn expired_challenge_is_refused_without_using_it_up() {
+    let db = match common::database_with(&[("CAPTCHA", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let (token, answer) = challenge();
+    // The same nonce and answer, in a token whose time has run out
+    let data = tokens::verify_expiring(TOKEN_PURPOSE, &token).expect("fresh token");
+    let expired_token = tokens::sign_expiring(TOKEN_PURPOSE, &data, -1);
+    let expired = check(&db.pool, &expired_token, answer).await.unwrap_err();
+    assert!(expired.contains("expired"), "{}", expired);
+
+    assert_eq!(check(&db.pool, &token, answer).await, Ok(()));
+}
+
+#[actix_web::test]
+async fn tampered_token_is_refused() {
+    let db = match common::database_with(&[("CAPTCHA", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let (token, answer) = challenge();
+    let tampered = format!("{}0", token);
+    assert!(check(&db.pool, &tampered, answer).await.is_err());
+}
+
+#[actix_web::test]
+async fn prune_drops_expired_nonces_only() {
+    let db = match common::database_with(&[("CAPTCHA", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let now = chrono::Utc::now().timestamp();
+    let (old, current) = (format!("old-{}", now), format!("current-{}", now));
+    for (nonce, expires_at) in [(&old, now - 60), (&current, now + 60)] {
+        sqlx::query("INSERT INTO captchas (nonce, expires_at) VALUES ($1, $2)")
+            .bind(nonce)
+            .bind(expires_at)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+    assert!(captcha::prune(&db.pool).await.unwrap() >= 1);
+
+    let left: Vec<String> = sqlx::query_scalar("SELECT nonce FROM captchas WHERE nonce = ANY($1)")
+        .bind(vec![old, current.clone()])
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(left, vec![current]);
+}
+
+// The article page copy kept for outages is shown to everyone, so it can't
+// hand out the challenge its first visitor got
+#[actix_web::test]
+async fn cached_article_page_carries_no_challenge() {
+    let db = match common::database_with(&[("CAPTCHA", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let (token, answer) = challenge();
+    let answer = answer.to_string();
+    let fields = [
+        ("title", "Challenged"),
+        ("body", "Body"),
+        (captcha::TOKEN_FIELD, token.as_str()),
+        (captcha::ANSWER_FIELD, answer.as_str()),
+    ];
+    let req = common::post_multipart("/submit", &fields, Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let id: i32 = sqlx::query_scalar("SELECT id FROM articles WHERE title = 'Challenged'")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let uri = format!("/articles/{}", id);
+    let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+    assert!(page.contains(captcha::TOKEN_FIELD), "{}", page);
+
+    db.pool.close().await;
+    let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+    assert!(page.contains("Challenged"), "{}", page);
+    assert!(!page.contains(captcha::TOKEN_FIELD), "{}", page);
+}
@@ -0,0 +1,60 @@
+use actix_web::test::{self, TestRequest};
+use serde_json::Value;
+
+mod common;
+
+// The stored count next to one taken from the comments themselves
+async fn counts(db: &common::TestDatabase, id: i32) -> (i64, i64) {
+    sqlx::query_as(
+        "SELECT comment_count::BIGINT, (SELECT COUNT(*) FROM comments c
+             WHERE c.article_id = a.id AND NOT c.hidden AND NOT c.deleted AND NOT c.shadow_hidden)
+         FROM articles a WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_one(&db.pool)
+    .await
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn stored_comment_count_follows_inserts_deletes_and_hiding() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Counted", "Body", "198.51.100.1:1000").await;
+    assert_eq!(counts(&db, id).await, (0, 0));
+
+    for n in 0..4 {
+        let peer = format!("198.51.100.{}:1000", n + 2);
+        let res = test::call_service(&app, common::comment_request(id, &format!("Reply {}", n), &peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+    assert_eq!(counts(&db, id).await, (4, 4));
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1 ORDER BY id")
+        .bind(id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(&format!("/comments/{}/delete", ids[0]), &[]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert_eq!(counts(&db, id).await, (3, 3));
+
+    let hide = |comment: i32| common::post_form(&format!("/admin/comments/{}/hide", comment), &[]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, hide(ids[1])).await.status(), 302);
+    assert_eq!(counts(&db, id).await, (2, 2));
+    assert_eq!(test::call_service(&app, hide(ids[1])).await.status(), 302);
+    assert_eq!(counts(&db, id).await, (3, 3));
+    assert_eq!(test::call_service(&app, hide(ids[2])).await.status(), 302);
+    assert_eq!(counts(&db, id).await, (2, 2));
+
+    // Everything reading the stored count agrees with it
+    let req = TestRequest::get().uri(&format!("/api/articles/{}/comments", id)).to_request();
+    let listed: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(listed["page"]["total"], 2);
+    let list = common::body(test::call_service(&app, TestRequest::get().uri("/articles").to_request()).await).await;
+    assert!(list.contains(r#"<span class="badge">2</span>"#), "{}", list);
+}
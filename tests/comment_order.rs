@@ -0,0 +1,123 @@
+use actix_web::test::{self, TestRequest};
+use serde_json::Value;
+
+mod common;
+
+// More than one batch of the streamed page, so the order has to hold across
+// keyset pages
+const COMMENTS: i32 = 350;
+
+async fn seeded_thread(db: &common::TestDatabase, id: i32) {
+    sqlx::query(
+        "INSERT INTO comments (article_id, comment, created_at, ip_representation)
+         SELECT $1, 'Seeded comment ' || n, 1700000000 + n, 'seed' FROM generate_series(1, $2) AS n",
+    )
+    .bind(id)
+    .bind(COMMENTS)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE articles SET comment_count = $2 WHERE id = $1")
+        .bind(id)
+        .bind(COMMENTS as i64)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+}
+
+// Open reports against seeded comment `n`: one reporter for every seventh
+// comment and another for every fiftieth. Every eleventh also has a
+// dismissed report, which doesn't count.
+fn reports(n: i32) -> i64 {
+    i64::from(n % 7 == 0) + i64::from(n % 50 == 0)
+}
+
+async fn seeded_reports(db: &common::TestDatabase, id: i32) {
+    for (reporter, rule, resolved_at) in [("a", 7, None), ("b", 50, None), ("c", 11, Some(1))] {
+        sqlx::query(
+            "INSERT INTO reports (target_type, target_id, ip_hash, created_at, updated_at, resolved_at, resolution)
+             SELECT 'comment', id, $2, 1, 1, $4, CASE WHEN $4 IS NULL THEN NULL ELSE 'dismissed' END FROM comments
+             WHERE article_id = $1 AND split_part(comment, ' ', 3)::int % $3 = 0",
+        )
+        .bind(id)
+        .bind(reporter)
+        .bind(rule)
+        .bind(resolved_at)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+}
+
+// Seeded comment numbers in `order`
+fn expected(order: &str) -> Vec<i32> {
+    let mut numbers: Vec<i32> = (1..=COMMENTS).collect();
+    match order {
+        "newest" => numbers.reverse(),
+        // Fewest reports first, oldest first among ties
+        "top" => numbers.sort_by_key(|&n| (reports(n), n)),
+        _ => {}
+    }
+    numbers
+}
+
+// Numbers of the seeded comments rendered on `page`, in the order they
+// appear (the structured data repeats them, but not as element text)
+fn seeded_order(page: &str) -> Vec<i32> {
+    page.match_indices(">Seeded comment ")
+        .map(|(i, m)| {
+            let rest = &page[i + m.len()..];
+            rest[..rest.find('<').unwrap()].parse().unwrap()
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn article_page_sorts_comments_every_way() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Sorted", "Body", "198.51.100.1:1000").await;
+    seeded_thread(&db, id).await;
+    seeded_reports(&db, id).await;
+
+    for (query, order) in [("", "oldest"), ("?comments=oldest", "oldest"), ("?comments=newest", "newest"), ("?comments=top", "top")] {
+        let req = TestRequest::get().uri(&format!("/articles/{}{}", id, query)).to_request();
+        let page = common::body(test::call_service(&app, req).await).await;
+        assert_eq!(seeded_order(&page), expected(order), "for {:?}", query);
+    }
+}
+
+#[actix_web::test]
+async fn api_pages_through_comments_in_every_order() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Sorted", "Body", "198.51.100.1:1000").await;
+    seeded_thread(&db, id).await;
+    seeded_reports(&db, id).await;
+
+    for order in ["oldest", "newest", "top"] {
+        let mut texts = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let uri = format!("/api/articles/{}/comments?order={}&limit=100{}", id, order, cursor);
+            let page: Value = test::call_and_read_body_json(&app, TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(page["order"]["key"], order);
+            assert_eq!(page["page"]["total"], COMMENTS);
+            for comment in page["comments"].as_array().unwrap() {
+                texts.push(comment["text"].as_str().unwrap().to_string());
+            }
+            match page["page"]["next_cursor"].as_str() {
+                Some(next) => cursor = format!("&cursor={}", next),
+                None => break,
+            }
+        }
+        let expected: Vec<String> = expected(order).iter().map(|n| format!("Seeded comment {}", n)).collect();
+        assert_eq!(texts, expected, "for {}", order);
+    }
+}
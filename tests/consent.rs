@@ -0,0 +1,61 @@
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+// Cookies the site sets whatever the visitor chose
+fn strictly_necessary(name: &str) -> bool {
+    ["csrf", "cookie_consent", "admin_session"].contains(&name) || name.starts_with("comment_edit_")
+}
+
+fn optional_cookies<B>(res: &ServiceResponse<B>) -> Vec<String> {
+    res.response()
+        .cookies()
+        .map(|c| c.name().to_string())
+        .filter(|name| !strictly_necessary(name))
+        .collect()
+}
+
+#[actix_web::test]
+async fn no_optional_cookies_before_acceptance() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query("INSERT INTO settings (key, value) VALUES ('require_cookie_consent', 'true')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Consent", "Body", "198.51.100.1:1000").await;
+    let article = format!("/articles/{}", id);
+    let watch = format!("{}/watch", article);
+
+    let res = test::call_service(&app, TestRequest::get().uri(&article).to_request()).await;
+    assert_eq!(optional_cookies(&res), Vec::<String>::new());
+    assert!(common::body(res).await.contains("cookie-consent"));
+
+    // Watching and commenting still work, they just aren't remembered
+    let res = test::call_service(&app, TestRequest::get().uri(&watch).to_request()).await;
+    assert_eq!(res.status(), 302);
+    assert_eq!(optional_cookies(&res), Vec::<String>::new());
+    let res = test::call_service(&app, common::comment_request(id, "Hello", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    assert_eq!(optional_cookies(&res), Vec::<String>::new());
+
+    // Declining keeps it that way
+    let declined = Cookie::new("cookie_consent", "declined");
+    let res = test::call_service(&app, TestRequest::get().uri(&watch).cookie(declined).to_request()).await;
+    assert_eq!(optional_cookies(&res), Vec::<String>::new());
+
+    let res = test::call_service(&app, common::post_form("/consent", &[("choice", "accept")]).to_request()).await;
+    assert_eq!(res.status(), 302);
+    let accepted = res.response().cookies().find(|c| c.name() == "cookie_consent").unwrap().into_owned();
+    assert_eq!(accepted.value(), "accepted");
+
+    let res = test::call_service(&app, TestRequest::get().uri(&watch).cookie(accepted.clone()).to_request()).await;
+    assert_eq!(optional_cookies(&res), vec!["watched".to_string()]);
+    let res = test::call_service(&app, TestRequest::get().uri(&article).cookie(accepted).to_request()).await;
+    assert!(!common::body(res).await.contains("cookie-consent"));
+}
@@ -0,0 +1,89 @@
+use actix_web::cookie::Cookie;
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn comment_count(db: &common::TestDatabase, id: i32) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn comment_post_needs_a_matching_token() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Protected", "Body", "198.51.100.1:1000").await;
+    let uri = format!("/articles/{}/comment", id);
+    let peer = "198.51.100.2:1000".parse().unwrap();
+
+    // No token at all, as a cross-site form would send
+    let req = TestRequest::post().uri(&uri).peer_addr(peer).set_form([("comment", "Forged")]).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    // A valid token of another browser's in the field
+    let (mine, other) = (common::csrf_token(), common::csrf_token());
+    let req = TestRequest::post()
+        .uri(&uri)
+        .peer_addr(peer)
+        .cookie(Cookie::new("csrf", mine))
+        .set_form([("comment", "Forged"), ("csrf_token", other.as_str())])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    assert_eq!(comment_count(&db, id).await, 0);
+
+    let res = test::call_service(&app, common::comment_request(id, "Genuine", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    assert_eq!(comment_count(&db, id).await, 1);
+}
+
+// The token a first visit is given works for the form on that same page
+#[actix_web::test]
+async fn token_from_the_rendered_form_is_accepted() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Protected", "Body", "198.51.100.1:1000").await;
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let cookie = res.response().cookies().find(|c| c.name() == "csrf").expect("no CSRF cookie").into_owned();
+    let page = common::body(res).await;
+    let (_, field) = page.split_once(r#"name="csrf_token" value=""#).expect("comment form has no token");
+    let token = field.split('"').next().unwrap();
+    assert_eq!(token, cookie.value());
+
+    let req = TestRequest::post()
+        .uri(&format!("/articles/{}/comment", id))
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .cookie(Cookie::new("csrf", cookie.value().to_string()))
+        .set_form([("comment", "From the page"), ("csrf_token", token)])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert_eq!(comment_count(&db, id).await, 1);
+}
+
+#[actix_web::test]
+async fn article_post_needs_the_token() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let req = common::multipart("/submit", &[("title", "Forged"), ("body", "Body")], Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let posted: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM articles").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(posted, 0);
+    // Refused at the file, before it was stored, so nothing waits for removal
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_unlinks").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(queued, 0);
+}
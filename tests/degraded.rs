@@ -0,0 +1,78 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const BANNER: &str = "Showing cached content";
+
+#[actix_web::test]
+async fn outage_serves_cached_pages_and_refuses_writes() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Still readable", "Body", "198.51.100.1:1000").await;
+    let other = common::submit_article(&app, &db, "Never opened", "Body", "198.51.100.2:1000").await;
+
+    // Anonymous visits leave the fallback copies
+    for uri in ["/articles".to_string(), format!("/articles/{}", id)] {
+        let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+        assert!(page.contains("Still readable") && !page.contains(BANNER));
+    }
+
+    // Every connection the app could get now fails
+    db.pool.close().await;
+
+    for uri in ["/articles".to_string(), format!("/articles/{}", id)] {
+        let res = test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(res.status(), 200, "{}", uri);
+        assert_eq!(res.headers().get("Cache-Control").unwrap(), "no-store");
+        let page = common::body(res).await;
+        assert!(page.contains(BANNER) && page.contains("Still readable"), "{}", uri);
+    }
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", other)).to_request()).await;
+    assert_eq!(res.status(), 503);
+
+    let res = test::call_service(&app, common::comment_request(id, "Lost", "198.51.100.3:1000").to_request()).await;
+    assert_eq!(res.status(), 503);
+    assert!(res.headers().contains_key("Retry-After"));
+    assert!(common::body(res).await.contains("Temporarily Unavailable"));
+}
+
+// A copy from before a moderator's change must not bring back what they removed
+#[actix_web::test]
+async fn moderation_drops_the_cached_copies() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Kept", "Body", "198.51.100.1:1000").await;
+    let gone = common::submit_article(&app, &db, "Taken down", "Body", "198.51.100.2:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "Doxxing", "198.51.100.3:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let comment: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    for uri in ["/articles".to_string(), format!("/articles/{}", id), format!("/articles/{}", gone)] {
+        let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+        assert!(!page.contains(BANNER), "{}", uri);
+    }
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(&format!("/admin/comments/{}/hide", comment), &[]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form(&format!("/articles/{}/delete", gone), &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    db.pool.close().await;
+
+    for uri in ["/articles".to_string(), format!("/articles/{}", id), format!("/articles/{}", gone)] {
+        let res = test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(res.status(), 503, "{}", uri);
+    }
+}
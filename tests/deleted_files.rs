@@ -0,0 +1,90 @@
+use actix_web::test;
+use std::path::Path;
+
+mod common;
+
+async fn media_paths(db: &common::TestDatabase, id: i32) -> Vec<String> {
+    sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+}
+
+// Files are removed just after the transaction, so give that a moment
+async fn gone(media_path: &str) -> bool {
+    let path = format!(".{}", media_path);
+    for _ in 0..50 {
+        if !Path::new(&path).exists() {
+            return true;
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    false
+}
+
+#[actix_web::test]
+async fn deleted_article_leaves_no_files_or_rows() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let doomed = ("doomed.txt", "Notes only this article has\n".as_bytes());
+    let id = common::submit_article_with_file(&app, &db, "Doomed", "Body", "198.51.100.1:1000", doomed).await;
+    let kept = common::submit_article(&app, &db, "Kept", "Body", "198.51.100.3:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "Reply", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let files = media_paths(&db, id).await;
+    assert_eq!(files.len(), 1);
+    assert!(Path::new(&format!(".{}", files[0])).is_file());
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(&format!("/articles/{}/delete", id), &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert!(gone(&files[0]).await, "{} is still on disk", files[0]);
+    assert!(media_paths(&db, id).await.is_empty());
+    let comments: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(comments, 0);
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_unlinks").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(queued, 0);
+
+    let other = media_paths(&db, kept).await;
+    assert!(Path::new(&format!(".{}", other[0])).is_file());
+}
+
+#[actix_web::test]
+async fn replaced_media_file_is_removed() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Replaced", "Body", "198.51.100.1:1000").await;
+    let old = media_paths(&db, id).await;
+
+    let session = common::login(&app).await;
+    let edit = format!("/articles/{}/edit", id);
+    let req = common::post_multipart(&edit, &[("mode", "check")], None).cookie(session.clone()).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    let (_, token) = form.split_once(r#"name="edit_token" value=""#).expect("no edit token");
+    let token = token.split('"').next().unwrap();
+    let req = common::post_multipart(
+        &edit,
+        &[("mode", "save"), ("edit_token", token), ("title", "Replaced"), ("body", "Body")],
+        Some(("notes.txt", "New notes\n".as_bytes())),
+    )
+    .cookie(session)
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let new = media_paths(&db, id).await;
+    assert_eq!(new.len(), 1);
+    assert_ne!(new, old);
+    assert!(gone(&old[0]).await, "{} is still on disk", old[0]);
+    assert!(Path::new(&format!(".{}", new[0])).is_file());
+}
@@ -0,0 +1,72 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn media_ids(db: &common::TestDatabase, id: i32) -> Vec<i32> {
+    sqlx::query_scalar("SELECT id FROM article_media WHERE article_id = $1 ORDER BY id")
+        .bind(id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+}
+
+// The same file twice in one submission is kept once and the post still goes through
+#[actix_web::test]
+async fn repeated_file_in_one_submission_is_skipped() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let req = common::post_multipart_files(
+        "/submit",
+        &[("title", "Twice"), ("body", "Body")],
+        &[("pixel.png", common::PNG), ("copy of pixel.png", common::PNG)],
+    )
+    .peer_addr("198.51.100.1:1000".parse().unwrap())
+    .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 302);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap().to_string();
+    assert!(location.ends_with("?skipped=copy%20of%20pixel%2Epng"), "{}", location);
+
+    let id: i32 = sqlx::query_scalar("SELECT id FROM articles WHERE title = 'Twice'").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(media_ids(&db, id).await.len(), 1);
+    let page = common::body(test::call_service(&app, TestRequest::get().uri(&location).to_request()).await).await;
+    assert!(page.contains("left out because the article already has them: copy of pixel.png"), "{}", page);
+}
+
+// Re-sending the file an article already has through an edit keeps the stored one
+#[actix_web::test]
+async fn file_the_article_already_has_is_skipped_on_edit() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Once", "Body", "198.51.100.1:1000").await;
+    let before = media_ids(&db, id).await;
+
+    let session = common::login(&app).await;
+    let edit = format!("/articles/{}/edit", id);
+    let req = common::post_multipart(&edit, &[("mode", "check")], None).cookie(session.clone()).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    let (_, token) = form.split_once(r#"name="edit_token" value=""#).expect("no edit token");
+    let token = token.split('"').next().unwrap();
+    let req = common::post_multipart(
+        &edit,
+        &[("mode", "save"), ("edit_token", token), ("title", "Once"), ("body", "Edited body")],
+        Some(("again.png", common::PNG)),
+    )
+    .cookie(session)
+    .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 302);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    assert!(location.ends_with("?skipped=again%2Epng"), "{}", location);
+
+    assert_eq!(media_ids(&db, id).await, before);
+    let body: String = sqlx::query_scalar("SELECT body FROM articles WHERE id = $1").bind(id).fetch_one(&db.pool).await.unwrap();
+    assert_eq!(body, "Edited body");
+}
@@ -0,0 +1,42 @@
+use actix_web::test;
+
+mod common;
+
+async fn articles_titled(db: &common::TestDatabase, title: &str) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM articles WHERE lower(title) = lower($1)")
+        .bind(title)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn repeated_title_warns_then_posts_when_confirmed() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Weekly Thread", "First", "198.51.100.1:1000").await;
+
+    let fields = [("title", "weekly thread"), ("body", "Second & <more>")];
+    let req = common::post_multipart("/submit", &fields, Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    let form = common::body(res).await;
+    assert!(form.contains(&format!(r#"<a href="/articles/{}">Weekly Thread</a>"#, id)));
+    assert!(form.contains("choose your file again"));
+    assert!(form.contains(r#"name="confirm_duplicate" value="1""#));
+    assert!(form.contains(r#"value="weekly thread""#));
+    assert!(form.contains(">Second &amp; &lt;more&gt;</textarea>"));
+    assert_eq!(articles_titled(&db, "weekly thread").await, 1);
+
+    let confirmed = [("title", "weekly thread"), ("body", "Second"), ("confirm_duplicate", "1")];
+    let req = common::post_multipart("/submit", &confirmed, Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert_eq!(articles_titled(&db, "weekly thread").await, 2);
+}
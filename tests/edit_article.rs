@@ -0,0 +1,215 @@
+use actix_web::test;
+
+mod common;
+
+#[actix_web::test]
+async fn edit_form_needs_the_csrf_token_with_a_session() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Edited", "Body", "198.51.100.1:1000").await;
+    let session = common::login(&app).await;
+    let uri = format!("/articles/{}/edit", id);
+
+    let req = common::multipart(&uri, &[("mode", "check")], None).cookie(session.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = common::post_multipart(&uri, &[("mode", "check")], None).cookie(session).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 200);
+    assert!(common::body(res).await.contains("Edited"));
+}
+
+async fn queued_unlinks(db: &common::TestDatabase) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM pending_unlinks").fetch_one(&db.pool).await.unwrap()
+}
+
+#[actix_web::test]
+async fn refused_edit_queues_its_upload_for_removal() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Edited", "Body", "198.51.100.1:1000").await;
+    let uri = format!("/articles/{}/edit", id);
+    let upload = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;";
+
+    let req = common::multipart(&uri, &[("mode", "check"), ("password", "not the password")], Some(("new.gif", upload)))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    assert_eq!(queued_unlinks(&db).await, 1);
+
+    // Without the token a session needs, the file is refused before it's stored
+    let session = common::login(&app).await;
+    let req = common::multipart(&uri, &[("mode", "check")], Some(("new.gif", upload)))
+        .cookie(session)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    assert_eq!(queued_unlinks(&db).await, 1);
+}
+
+// The value of the form input called `name` on `page`
+fn input_value(page: &str, name: &str) -> String {
+    let start = page.find(&format!(r#"name="{}" value=""#, name)).expect("no such input");
+    page[start..].split('"').nth(3).unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn saving_needs_the_edit_token_for_that_article() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Edited", "Body", "198.51.100.1:1000").await;
+    let other = common::submit_article(&app, &db, "Other", "Body", "198.51.100.2:1000").await;
+    let uri = format!("/articles/{}/edit", id);
+
+    // The password opens the form, which carries a token instead of it
+    let req = common::post_multipart(&uri, &[("mode", "check"), ("password", common::ADMIN_PASSWORD)], None).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    assert!(!form.contains(common::ADMIN_PASSWORD));
+    let token = input_value(&form, "edit_token");
+
+    let save = |uri: &str, token: &str| {
+        common::post_multipart(uri, &[("mode", "save"), ("edit_token", token), ("title", "Renamed"), ("body", "Body")], None)
+            .to_request()
+    };
+    let res = test::call_service(&app, save(&format!("/articles/{}/edit", other), &token)).await;
+    assert_eq!(res.status(), 403);
+    let res = test::call_service(&app, save(&uri, &format!("{}0", token))).await;
+    assert_eq!(res.status(), 403);
+    let res = test::call_service(&app, save(&uri, &token)).await;
+    assert_eq!(res.status(), 302);
+
+    let titles: Vec<String> = sqlx::query_scalar("SELECT title FROM articles WHERE id IN ($1, $2) ORDER BY id")
+        .bind(id)
+        .bind(other)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(titles, ["Renamed", "Other"]);
+}
+
+#[actix_web::test]
+async fn alt_text_and_caption_are_saved_and_escaped() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Captioned", "Body", "198.51.100.1:1000").await;
+    let (media_id, media_path): (i32, String) =
+        sqlx::query_as("SELECT id, media_path FROM article_media WHERE article_id = $1")
+            .bind(id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+    let filename = media_path.rsplit('/').next().unwrap().to_string();
+    let uri = format!("/articles/{}", id);
+    let edit = format!("{}/edit", uri);
+
+    // Without alt text the image is described by its filename
+    let page = common::body(test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await).await;
+    assert!(page.contains(&format!(r#"alt="Article image: {}""#, filename)));
+
+    let session = common::login(&app).await;
+    let req = common::post_multipart(&edit, &[("mode", "check")], None).cookie(session.clone()).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    let alt_field = format!("alt_{}", media_id);
+    let caption_field = format!("caption_{}", media_id);
+    let req = common::post_multipart(
+        &edit,
+        &[
+            ("mode", "save"),
+            ("edit_token", &input_value(&form, "edit_token")),
+            ("title", "Captioned"),
+            ("body", "Body"),
+            (&alt_field, r#"A "quoted" <pixel>"#),
+            (&caption_field, "Tiny & <b>bold</b>"),
+        ],
+        None,
+    )
+    .cookie(session.clone())
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let (alt, caption): (Option<String>, Option<String>) =
+        sqlx::query_as("SELECT alt_text, caption FROM article_media WHERE id = $1")
+            .bind(media_id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+    assert_eq!(alt.as_deref(), Some(r#"A "quoted" <pixel>"#));
+    assert_eq!(caption.as_deref(), Some("Tiny & <b>bold</b>"));
+
+    let page = common::body(test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await).await;
+    assert!(page.contains(r#"alt="A &quot;quoted&quot; &lt;pixel&gt;""#));
+    assert!(page.contains("<figcaption>Tiny &amp; &lt;b&gt;bold&lt;/b&gt; "));
+    assert!(!page.contains("<b>bold</b>"));
+
+    // The edit form shows what was saved, escaped for its attributes
+    let req = common::post_multipart(&edit, &[("mode", "check")], None).cookie(session).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    assert_eq!(input_value(&form, &caption_field), "Tiny &amp; &lt;b&gt;bold&lt;/b&gt;");
+}
+
+// A file staged by a preview is kept until the edit is saved, and let go when
+// the save brings a different one
+#[actix_web::test]
+async fn replaced_staged_upload_is_queued_for_removal() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Edited", "Body", "198.51.100.1:1000").await;
+    let uri = format!("/articles/{}/edit", id);
+
+    let req = common::post_multipart(&uri, &[("mode", "check"), ("password", common::ADMIN_PASSWORD)], None).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    let fields = [("mode", "save"), ("edit_token", &input_value(&form, "edit_token")), ("title", "Edited"), ("body", "Body")];
+
+    let mut preview_fields = fields.to_vec();
+    preview_fields.push(("preview", "1"));
+    let req = common::post_multipart(&uri, &preview_fields, Some(("first.gif", b"GIF89a\x01\x00\x01\x00\x00\x00\x00;")))
+        .to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    let staged: Vec<String> = sqlx::query_scalar("SELECT media_path FROM staged_uploads WHERE article_id = $1")
+        .bind(id)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(staged.len(), 1, "{}", page);
+
+    let token = input_value(&page, "edit_token");
+    // The token carries JSON, so the attribute escapes its quotes
+    let staged_media = html_escape::decode_html_entities(&input_value(&page, "staged_media")).to_string();
+    let save_fields = [
+        ("mode", "save"),
+        ("edit_token", token.as_str()),
+        ("title", "Edited"),
+        ("body", "Body"),
+        ("staged_media", staged_media.as_str()),
+    ];
+    let req = common::post_multipart(&uri, &save_fields, Some(("second.gif", b"GIF89a\x02\x00\x02\x00\x00\x00\x00;")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let staged_left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM staged_uploads").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(staged_left, 0);
+    let queued: Vec<String> = sqlx::query_scalar("SELECT media_path FROM pending_unlinks")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert!(queued.contains(&staged[0]), "{:?}", queued);
+    let current: String = sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_ne!(current, staged[0]);
+}
@@ -0,0 +1,47 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const TITLE: &str = r#"<img src=x onerror=alert(1)> "quoted""#;
+const ESCAPED_TITLE: &str = "&lt;img src=x onerror=alert(1)&gt;";
+
+#[actix_web::test]
+async fn user_content_is_escaped_everywhere_it_renders() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, TITLE, "<script>alert('body')</script>", "198.51.100.1:1000").await;
+    let res = test::call_service(
+        &app,
+        common::comment_request(id, "<script>alert('comment')</script>", "198.51.100.2:1000").to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), 302);
+    // Stored paths are generated, but rendering mustn't rely on that
+    sqlx::query(r#"UPDATE article_media SET media_path = '/uploads/a"onload="alert(1).png' WHERE article_id = $1"#)
+        .bind(id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let list = common::body(test::call_service(&app, TestRequest::get().uri("/articles").to_request()).await).await;
+    assert!(list.contains(ESCAPED_TITLE));
+    assert!(!list.contains("<img src=x"));
+
+    let page = common::body(test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await).await;
+    assert!(page.contains(ESCAPED_TITLE));
+    assert!(page.contains("&lt;script&gt;alert("));
+    assert!(!page.contains("<img src=x") && !page.contains("<script>alert"));
+    assert!(page.contains(r#"/uploads/a&quot;onload=&quot;alert(1).png""#));
+    assert!(!page.contains(r#"a"onload="alert"#));
+
+    // A double quote in the title doesn't break the edit form's value attribute
+    let admin = common::login(&app).await;
+    let req = common::post_multipart(&format!("/articles/{}/edit", id), &[("mode", "check")], None)
+        .cookie(admin)
+        .to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    assert!(form.contains(r#"name="title" value="&lt;img src=x onerror=alert(1)&gt; &quot;quoted&quot;""#));
+}
@@ -0,0 +1,45 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const IMPRINT: &str = r#"<a href="https://example.com/imprint">Imprint</a>"#;
+
+#[actix_web::test]
+async fn footer_change_shows_without_restart() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    common::submit_article(&app, &db, "Footed", "Body", "198.51.100.1:1000").await;
+
+    // Rendered once before the change, so a cached copy would show
+    let page = common::body(test::call_service(&app, TestRequest::get().uri("/articles").to_request()).await).await;
+    assert!(page.contains(r#"<footer class="site-footer">"#) && page.contains("Powered by <a"));
+    assert!(!page.contains(IMPRINT));
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(
+        "/admin/settings",
+        &[
+            ("footer_markdown", "[Imprint](https://example.com/imprint) <script>alert(1)</script>"),
+            ("footer_powered_by", "false"),
+        ],
+    )
+    .cookie(admin.clone())
+    .to_request();
+    assert!(common::body(test::call_service(&app, req).await).await.contains("Settings saved."));
+
+    let requests = [
+        TestRequest::get().uri("/articles").to_request(),
+        TestRequest::get().uri("/articles/999999").to_request(),
+        TestRequest::get().uri("/admin/settings").cookie(admin).to_request(),
+    ];
+    for req in requests {
+        let uri = req.path().to_string();
+        let page = common::body(test::call_service(&app, req).await).await;
+        assert!(page.contains(IMPRINT), "no footer link on {}", uri);
+        assert!(!page.contains("Powered by <a"), "{}", uri);
+        assert!(!page.contains("<script>alert(1)"), "{}", uri);
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn media_path(db: &common::TestDatabase, id: i32) -> String {
+    sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+// Images show up in the grid and the feed; other attachments don't
+#[actix_web::test]
+async fn gallery_lists_only_images() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let pictured = common::submit_article(&app, &db, "Pictured", "Body", "198.51.100.1:1000").await;
+    let req = common::post_multipart("/submit", &[("title", "Notes"), ("body", "Body")], Some(("notes.txt", b"Notes\n")))
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let notes: i32 = sqlx::query_scalar("SELECT id FROM articles WHERE title = 'Notes'").fetch_one(&db.pool).await.unwrap();
+    let (image, text) = (media_path(&db, pictured).await, media_path(&db, notes).await);
+
+    let page = common::body(test::call_service(&app, TestRequest::get().uri("/gallery").to_request()).await).await;
+    assert!(page.contains(&format!(r#"<a href="/articles/{}" class="gallery-item""#, pictured)), "{}", page);
+    assert!(page.contains(&format!(r#"<img src="{}""#, image)), "{}", page);
+    assert!(!page.contains(&text), "{}", page);
+
+    let feed = common::body(test::call_service(&app, TestRequest::get().uri("/gallery/feed.xml").to_request()).await).await;
+    assert_eq!(feed.matches("<item>").count(), 1, "{}", feed);
+    assert!(feed.contains(&format!(r#"{}" length="{}" type="image/png"/>"#, image, common::PNG.len())), "{}", feed);
+}
@@ -0,0 +1,55 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+// The trap renamed, as an admin rotating it would
+const FIELD: &str = "homepage";
+
+async fn count(db: &common::TestDatabase, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(&db.pool).await.unwrap()
+}
+
+#[actix_web::test]
+async fn filled_trap_drops_the_post_and_an_empty_one_lets_it_through() {
+    let db = match common::database_with(&[("HONEYPOT_FIELD", FIELD)]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Trapped", "Body", "198.51.100.1:1000").await;
+    let trap = format!(r#"name="{}""#, FIELD);
+    for uri in ["/".to_string(), format!("/articles/{}", id)] {
+        let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+        assert!(page.contains(&trap), "no trap on {}", uri);
+    }
+
+    // Filled in: the bot gets the usual redirect and nothing is stored
+    let req = common::post_multipart(
+        "/submit",
+        &[("title", "Spam"), ("body", "Buy now"), (FIELD, "http://spam.example")],
+        Some(("pixel.png", common::PNG)),
+    )
+    .peer_addr("198.51.100.2:1000".parse().unwrap())
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form(&format!("/articles/{}/comment", id), &[("comment", "Buy now"), (FIELD, "http://spam.example")])
+        .peer_addr("198.51.100.3:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert_eq!((count(&db, "articles").await, count(&db, "comments").await), (1, 0));
+
+    // Left empty, as a person leaves it
+    let req = common::post_multipart(
+        "/submit",
+        &[("title", "Real"), ("body", "Body"), (FIELD, "")],
+        Some(("pixel.png", common::PNG)),
+    )
+    .peer_addr("198.51.100.4:1000".parse().unwrap())
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form(&format!("/articles/{}/comment", id), &[("comment", "Real reply"), (FIELD, "")])
+        .peer_addr("198.51.100.5:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert_eq!((count(&db, "articles").await, count(&db, "comments").await), (2, 1));
+}
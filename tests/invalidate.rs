@@ -0,0 +1,71 @@
+use actix_web::test::{self, TestRequest};
+use serde_json::Value;
+
+mod common;
+
+async fn get<S, B>(app: &S, uri: &str) -> String
+where
+    S: actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    common::body(test::call_service(app, TestRequest::get().uri(uri).to_request()).await).await
+}
+
+#[actix_web::test]
+async fn invalidating_makes_the_next_render_read_the_database() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Before", "Body", "198.51.100.1:1000").await;
+    assert!(get(&app, "/gallery/feed.xml").await.contains("Before"));
+    assert!(!get(&app, "/articles").await.contains("Cached footer"));
+
+    // Changed behind the app's back, so only a fresh read sees it
+    sqlx::query("UPDATE articles SET title = 'After' WHERE id = $1").bind(id).execute(&db.pool).await.unwrap();
+    sqlx::query("INSERT INTO settings (key, value) VALUES ('footer_markdown', 'Cached footer')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    assert!(get(&app, "/gallery/feed.xml").await.contains("Before"));
+    assert!(!get(&app, "/articles").await.contains("Cached footer"));
+
+    let admin = common::login(&app).await;
+    let invalidate = |target: &str| common::post_form("/admin/invalidate", &[("target", target)]).cookie(admin.clone()).to_request();
+    let cleared: Value = test::call_and_read_body_json(&app, invalidate("listing")).await;
+    assert_eq!(cleared["target"], "listing");
+    assert!(cleared["invalidated"].as_array().unwrap().iter().any(|c| c == "1 cached feeds"), "{}", cleared);
+    // Feeds are marked stale rather than dropped: the next request still gets
+    // the old copy but sets off the rebuild from the database
+    assert!(get(&app, "/gallery/feed.xml").await.contains("Before"));
+    let mut feed = String::new();
+    for _ in 0..50 {
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+        feed = get(&app, "/gallery/feed.xml").await;
+        if feed.contains("After") {
+            break;
+        }
+    }
+    assert!(feed.contains("After") && !feed.contains("Before"), "{}", feed);
+    assert!(!get(&app, "/articles").await.contains("Cached footer"));
+
+    let cleared: Value = test::call_and_read_body_json(&app, invalidate("settings")).await;
+    assert_eq!(cleared["invalidated"], serde_json::json!(["settings"]));
+    assert!(get(&app, "/articles").await.contains("Cached footer"));
+
+    // `all` drops the feeds, so the very next request reads the database
+    sqlx::query("UPDATE articles SET title = 'Final' WHERE id = $1").bind(id).execute(&db.pool).await.unwrap();
+    let cleared: Value = test::call_and_read_body_json(&app, invalidate("all")).await;
+    assert!(cleared["invalidated"].as_array().unwrap().iter().any(|c| c == "1 cached feeds"), "{}", cleared);
+    assert!(get(&app, "/gallery/feed.xml").await.contains("Final"));
+
+    let res = test::call_service(&app, invalidate("everything")).await;
+    assert_eq!(res.status(), 400);
+    let audited: Vec<String> = sqlx::query_scalar("SELECT detail FROM admin_actions WHERE action = 'invalidate' ORDER BY id")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(audited.len(), 3);
+    assert!(audited[0].starts_with("listing: "));
+}
@@ -0,0 +1,40 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+fn delete_request(id: i32, password: &str, peer: &str) -> actix_http::Request {
+    TestRequest::post()
+        .uri(&format!("/articles/{}/delete", id))
+        .peer_addr(peer.parse().unwrap())
+        .set_form([("password", password)])
+        .to_request()
+}
+
+#[actix_web::test]
+async fn wrong_passwords_lock_out_the_client() {
+    let db = match common::database_with(&[("LOGIN_MAX_FAILURES", "3")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Guarded", "Body", "198.51.100.1:1000").await;
+
+    for _ in 0..3 {
+        let res = test::call_service(&app, delete_request(id, "guess", "203.0.113.5:1000")).await;
+        assert_eq!(res.status(), 401);
+    }
+    // Locked out now, even with the right password
+    let res = test::call_service(&app, delete_request(id, common::ADMIN_PASSWORD, "203.0.113.5:2000")).await;
+    assert_eq!(res.status(), 429);
+    assert!(res.headers().get("Retry-After").is_some());
+
+    // Another client isn't affected
+    let res = test::call_service(&app, delete_request(id, common::ADMIN_PASSWORD, "198.51.100.9:1000")).await;
+    assert_eq!(res.status(), 302);
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert!(!exists);
+}
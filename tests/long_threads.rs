@@ -0,0 +1,47 @@
+use actix_web::body::MessageBody;
+use actix_web::test::{self, TestRequest};
+use std::pin::pin;
+
+mod common;
+
+const COMMENTS: i32 = 10_000;
+
+// A long thread is sent in many small pieces rather than rendered whole, so
+// what a request holds doesn't grow with the number of comments
+#[actix_web::test]
+async fn long_thread_streams_in_bounded_chunks() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Long thread", "Body", "198.51.100.1:1000").await;
+    sqlx::query(
+        "INSERT INTO comments (article_id, comment, created_at, ip_representation)
+         SELECT $1, 'Seeded comment ' || n, 1700000000 + n, 'seed' FROM generate_series(1, $2) AS n",
+    )
+    .bind(id)
+    .bind(COMMENTS)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    assert_eq!(res.status(), 200);
+    let mut body = pin!(res.into_body());
+    let (mut total, mut largest, mut chunks) = (0, 0, 0);
+    let mut page = Vec::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+        let chunk = chunk.ok().expect("the page failed mid-stream");
+        total += chunk.len();
+        largest = largest.max(chunk.len());
+        chunks += 1;
+        page.extend_from_slice(&chunk);
+    }
+    let page = String::from_utf8(page).unwrap();
+
+    assert!(page.contains("Seeded comment 1<") && page.contains(&format!("Seeded comment {}<", COMMENTS)));
+    assert!(page.trim_end().ends_with("</html>"));
+    assert!(chunks > (COMMENTS / 300) as usize, "{} chunks", chunks);
+    assert!(largest * 10 < total, "largest chunk {} of {} bytes", largest, total);
+}
@@ -0,0 +1,84 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn media_path(db: &common::TestDatabase, id: i32) -> String {
+    sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+async fn flag(db: &common::TestDatabase, id: i32) -> Option<String> {
+    sqlx::query_scalar("SELECT media_integrity FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+// A removed file and one whose size no longer matches are flagged, and the
+// article page shows a placeholder instead of the missing file
+#[actix_web::test]
+async fn scan_flags_missing_and_changed_files() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    // Each with its own file: uploads are stored under their content hash
+    let mut ids = Vec::new();
+    for (title, peer) in [("Intact", "198.51.100.1:1000"), ("Missing", "198.51.100.2:1000"), ("Resized", "198.51.100.3:1000")] {
+        let notes = format!("{} notes\n", title);
+        let id = common::submit_article_with_file(&app, &db, title, "Body", peer, ("notes.txt", notes.as_bytes())).await;
+        sqlx::query("UPDATE article_media SET size_bytes = $1 WHERE article_id = $2")
+            .bind(notes.len() as i64)
+            .bind(id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        ids.push(id);
+    }
+    let (intact, missing, resized) = (ids[0], ids[1], ids[2]);
+    std::fs::remove_file(format!(".{}", media_path(&db, missing).await)).unwrap();
+    sqlx::query("UPDATE article_media SET size_bytes = size_bytes + 1 WHERE article_id = $1")
+        .bind(resized)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let admin = common::login(&app).await;
+    let req = common::post_form("/admin/media/integrity", &[]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let mut finished = false;
+    for _ in 0..100 {
+        finished = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM migration_state WHERE name = 'media_integrity' AND finished_at IS NOT NULL)",
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        if finished {
+            break;
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(finished, "the scan didn't finish");
+
+    assert_eq!(flag(&db, intact).await, None);
+    assert_eq!(flag(&db, missing).await.as_deref(), Some("missing"));
+    assert_eq!(flag(&db, resized).await.as_deref(), Some("size_mismatch"));
+
+    for (id, flagged) in [(missing, true), (intact, false)] {
+        let req = TestRequest::get().uri(&format!("/articles/{}", id)).to_request();
+        let page = common::body(test::call_service(&app, req).await).await;
+        assert_eq!(page.contains("Media unavailable"), flagged, "{}", page);
+    }
+
+    let req = TestRequest::get().uri("/admin/media/integrity").cookie(admin).to_request();
+    let report = common::body(test::call_service(&app, req).await).await;
+    for (id, listed) in [(missing, true), (resized, true), (intact, false)] {
+        assert_eq!(report.contains(&format!(r#"<a href="/articles/{}">"#, id)), listed, "{}", report);
+    }
+}
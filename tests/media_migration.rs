@@ -0,0 +1,19 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+// Failure rows and the unlink backlog are for staff
+#[actix_web::test]
+async fn migration_report_needs_staff() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let req = TestRequest::get().uri("/admin/migrations/media").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let admin = common::login(&app).await;
+    let req = TestRequest::get().uri("/admin/migrations/media").cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+}
@@ -0,0 +1,83 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const PDF: &[u8] = b"%PDF-1.4\n1 0 obj << /Type /Catalog >> endobj\ntrailer << /Root 1 0 R >>\n%%EOF\n";
+const NOTES: &[u8] = b"Notes <b>bold</b> & more\n";
+
+// Posts an article with `file` attached and returns its id and the media row's id
+async fn post_with<S, B>(app: &S, db: &common::TestDatabase, title: &str, file: (&str, &[u8])) -> (i32, i32)
+where
+    S: actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let req = common::post_multipart("/submit", &[("title", title), ("body", "Body")], Some(file))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(app, req).await.status(), 302);
+    sqlx::query_as("SELECT a.id, m.id FROM articles a JOIN article_media m ON m.article_id = a.id WHERE a.title = $1")
+        .bind(title)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn pdf_preview_is_sandboxed() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let (id, media_id) = post_with(&app, &db, "With a PDF", ("report.pdf", PDF)).await;
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let page = common::body(res).await;
+    assert!(page.contains(&format!(r#"<iframe sandbox="" src="/media/{}/inline""#, media_id)), "{}", page);
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/media/{}/inline", media_id)).to_request()).await;
+    assert_eq!(res.status(), 200);
+    let header = |name: &str| res.headers().get(name).unwrap().to_str().unwrap().to_string();
+    assert_eq!(header("Content-Type"), "application/pdf");
+    assert!(header("Content-Disposition").starts_with("inline"));
+    assert_eq!(header("X-Content-Type-Options"), "nosniff");
+    let csp = header("Content-Security-Policy");
+    for directive in ["default-src 'none'", "form-action 'none'", "sandbox"] {
+        assert!(csp.contains(directive), "{} lacks {}", csp, directive);
+    }
+    assert!(!csp.contains("script-src"), "{}", csp);
+}
+
+#[actix_web::test]
+async fn text_is_previewed_escaped() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let (id, media_id) = post_with(&app, &db, "With notes", ("notes.txt", NOTES)).await;
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let page = common::body(res).await;
+    assert!(page.contains("Notes &lt;b&gt;bold&lt;/b&gt; &amp; more"), "{}", page);
+    assert!(!page.contains("<b>bold</b>"));
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/media/{}/inline", media_id)).to_request()).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "text/plain; charset=utf-8");
+}
+
+// Images and other types aren't served through the preview route
+#[actix_web::test]
+async fn other_types_have_no_preview() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let (_, media_id) = post_with(&app, &db, "With a picture", ("pixel.png", common::PNG)).await;
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/media/{}/inline", media_id)).to_request()).await;
+    assert_eq!(res.status(), 404);
+    assert!(res.headers().get("Content-Disposition").is_none());
+}
@@ -0,0 +1,46 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+#[actix_web::test]
+async fn comment_on_a_missing_article_is_not_found() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let res = test::call_service(&app, common::comment_request(999999, "Hello?", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 404);
+    assert!(common::body(res).await.contains("<h2>Article Not Found</h2>"));
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM comments").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(stored, 0);
+}
+
+// A missing article is a 404 and a failing query a 500, on the page and the edit form
+#[actix_web::test]
+async fn missing_article_is_told_apart_from_a_database_error() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Present", "Body", "198.51.100.1:1000").await;
+    let session = common::login(&app).await;
+    let check = |uri: String| common::post_multipart(&uri, &[("mode", "check")], None).cookie(session.clone()).to_request();
+
+    let res = test::call_service(&app, TestRequest::get().uri("/articles/999999").to_request()).await;
+    assert_eq!(res.status(), 404);
+    assert_eq!(test::call_service(&app, check("/articles/999999/edit".to_string())).await.status(), 404);
+
+    // The connection is fine, the query behind both pages isn't
+    sqlx::query("ALTER TABLE articles RENAME COLUMN body_format TO body_format_gone")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    assert_eq!(res.status(), 500);
+    let page = common::body(res).await;
+    assert!(page.contains("Failed to load article"), "{}", page);
+    assert_eq!(test::call_service(&app, check(format!("/articles/{}/edit", id))).await.status(), 500);
+}
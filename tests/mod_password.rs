@@ -0,0 +1,47 @@
+use actix_web::test;
+
+mod common;
+
+// The moderation password removes comments but can't touch the article
+#[actix_web::test]
+async fn mod_password_deletes_comments_but_not_articles() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Guarded", "Body", "198.51.100.1:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "Spam", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let comment: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let req = common::post_form(&format!("/articles/{}/delete", id), &[("password", common::MOD_PASSWORD)]).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert!(exists);
+
+    let req = common::post_form(&format!("/comments/{}/delete", comment), &[("password", common::MOD_PASSWORD)])
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM comments WHERE article_id = $1 AND NOT deleted")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(left, 0);
+
+    // The activity timeline names the role that acted
+    let actor: String = sqlx::query_scalar("SELECT actor FROM article_events WHERE kind = 'comment_deleted'")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(actor, "mod-password");
+}
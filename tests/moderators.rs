@@ -0,0 +1,60 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+#[actix_web::test]
+async fn moderator_can_delete_comments_but_not_articles() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Moderated", "Body", "198.51.100.1:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "Off topic", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let comment_id: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let req = TestRequest::get().uri("/admin/moderators").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(
+        "/admin/moderators",
+        &[("username", "alice"), ("new_password", "alice's password"), ("perm_delete_comments", "1")],
+    )
+    .cookie(admin)
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let req = common::post_form("/admin/login", &[("username", "alice"), ("password", "alice's password")]).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 302);
+    let alice = res.response().cookies().find(|c| c.name() == "admin_session").unwrap().into_owned();
+
+    let req = TestRequest::get().uri("/admin/moderators").cookie(alice.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = common::post_form(&format!("/articles/{}/delete", id), &[]).cookie(alice.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = common::post_form(&format!("/comments/{}/delete", comment_id), &[]).cookie(alice).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    // The timeline names the moderator, not just "admin"
+    let actor: String = sqlx::query_scalar("SELECT actor FROM article_events WHERE article_id = $1 AND kind = 'comment_deleted'")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(actor, "alice");
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM articles WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert!(exists);
+}
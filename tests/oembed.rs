@@ -0,0 +1,68 @@
+use actix_web::test::{self, TestRequest};
+use serde_json::Value;
+
+mod common;
+
+// The fields oEmbed 1.0 requires of a JSON response of this type
+fn assert_valid(response: &Value) {
+    assert_eq!(response["version"], "1.0");
+    let required: &[&str] = match response["type"].as_str() {
+        Some("photo") => &["url", "width", "height"],
+        Some("video") | Some("rich") => &["html", "width", "height"],
+        Some("link") => &[],
+        other => panic!("not an oEmbed type: {:?}", other),
+    };
+    for field in required {
+        assert!(!response[field].is_null(), "missing {}", field);
+    }
+    for field in ["width", "height", "thumbnail_width", "thumbnail_height", "cache_age"] {
+        assert!(response[field].is_null() || response[field].is_u64(), "{} isn't a number", field);
+    }
+    // Thumbnails come with both dimensions or not at all
+    let thumbnail = ["thumbnail_url", "thumbnail_width", "thumbnail_height"].map(|f| response[f].is_null());
+    assert!(thumbnail.iter().all(|t| *t) || thumbnail.iter().all(|t| !*t), "{}", response);
+    for field in ["title", "provider_name", "provider_url"] {
+        assert!(response[field].is_null() || response[field].is_string(), "{} isn't a string", field);
+    }
+}
+
+#[actix_web::test]
+async fn discovery_link_leads_to_a_valid_response() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Embedded", "Body", "198.51.100.1:1000").await;
+
+    let req = TestRequest::get().uri(&format!("/articles/{}", id)).to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    let start = page.find(r#"type="application/json+oembed" href=""#).expect("no discovery link");
+    let href = &page[start..].split('"').nth(3).unwrap().replace("&amp;", "&");
+    let path = &href[href.find("/oembed").unwrap()..];
+
+    let res = test::call_service(&app, TestRequest::get().uri(path).to_request()).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get("content-type").unwrap(), "application/json");
+    let response: Value = serde_json::from_str(&common::body(res).await).unwrap();
+    assert_valid(&response);
+    assert_eq!(response["title"], "Embedded");
+}
+
+#[actix_web::test]
+async fn other_formats_and_foreign_urls_are_refused() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Embedded", "Body", "198.51.100.1:1000").await;
+    let url = format!("http%3A%2F%2Flocalhost%3A8080%2Farticles%2F{}", id);
+
+    let req = TestRequest::get().uri(&format!("/oembed?url={}&format=xml", url)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 501);
+    let req = TestRequest::get().uri("/oembed?url=https%3A%2F%2Fexample.com%2Farticles%2F1").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    let req = TestRequest::get().uri(&format!("/oembed?url={}", url)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+}
@@ -0,0 +1,105 @@
+use actix_web::test::{self, TestRequest};
+use serde_json::Value;
+
+mod common;
+
+#[actix_web::test]
+async fn only_the_admin_session_posts_official_comments() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Announcements", "Body", "198.51.100.1:1000").await;
+    let uri = format!("/articles/{}/comment", id);
+    let admin = common::login(&app).await;
+    let moderator = common::login_with(&app, common::MOD_PASSWORD).await;
+
+    // The public path can't set the flag, whatever it sends
+    let req = common::post_form(&uri, &[("comment", "Forged"), ("official", "1"), ("is_admin", "true")])
+        .peer_addr("198.51.100.2:1000".parse().unwrap())
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_client_error());
+    let req = common::post_form(&uri, &[("comment", "Forged"), ("official", "1")]).cookie(moderator).to_request();
+    assert!(test::call_service(&app, req).await.status().is_client_error());
+
+    let req = common::post_form(&uri, &[("comment", "Plain reply"), ("is_admin", "true")])
+        .peer_addr("198.51.100.3:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form(&uri, &[("comment", "Unticked staff reply")]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form(&uri, &[("comment", "Operator reply"), ("official", "1")]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let flags: Vec<(String, bool)> =
+        sqlx::query_as("SELECT comment, is_admin FROM comments WHERE article_id = $1 ORDER BY id")
+            .bind(id)
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        flags,
+        [
+            ("Plain reply".to_string(), false),
+            ("Unticked staff reply".to_string(), false),
+            ("Operator reply".to_string(), true)
+        ]
+    );
+
+    let page = common::body(test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await).await;
+    assert_eq!(page.matches(r#"class="comment official""#).count(), 1);
+    let official = &page[page.find(r#"class="comment official""#).unwrap()..];
+    assert!(official[..official.find("</div>").unwrap()].contains("Operator reply"));
+    assert!(official.contains(r#"<span class="official-badge">"#));
+
+    let api: Value = test::call_and_read_body_json(
+        &app,
+        TestRequest::get().uri(&format!("/api/articles/{}/comments", id)).to_request(),
+    )
+    .await;
+    let official: Vec<bool> = api["comments"].as_array().unwrap().iter().map(|c| c["official"].as_bool().unwrap()).collect();
+    assert_eq!(official, [false, false, true]);
+}
+
+#[actix_web::test]
+async fn official_reply_controls_need_the_admin_session() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Public thread", "Body", "198.51.100.1:1000").await;
+    let uri = format!("/articles/{}", id);
+
+    let page = common::body(test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await).await;
+    assert!(!page.contains(r#"name="official""#), "{}", page);
+    assert!(!page.contains(r#"type="password""#), "{}", page);
+
+    let admin = common::login(&app).await;
+    let req = TestRequest::get().uri(&uri).cookie(admin).to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    assert!(page.contains(r#"name="official""#), "{}", page);
+    assert!(!page.contains(r#"type="password""#), "{}", page);
+
+    // A moderator's reply can't be official, so they aren't offered the box
+    let moderator = common::login_with(&app, common::MOD_PASSWORD).await;
+    let req = TestRequest::get().uri(&uri).cookie(moderator).to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    assert!(!page.contains(r#"name="official""#), "{}", page);
+
+    // The admin password alone no longer makes a reply official
+    let req = common::post_form(
+        &format!("{}/comment", uri),
+        &[("comment", "Password reply"), ("official", "1"), ("password", common::ADMIN_PASSWORD)],
+    )
+    .peer_addr("198.51.100.2:1000".parse().unwrap())
+    .to_request();
+    assert!(test::call_service(&app, req).await.status().is_client_error());
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
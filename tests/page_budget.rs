@@ -0,0 +1,50 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const COMMENTS: i32 = 3_000;
+
+// The number of the last seeded comment on `page`
+fn last_comment(page: &str) -> i32 {
+    let (_, rest) = page.rsplit_once("Seeded comment ").unwrap();
+    rest.split('<').next().unwrap().parse().unwrap()
+}
+
+// A thread far over MAX_PAGE_BYTES ends early with a link that picks up
+// right after the last comment shown
+#[actix_web::test]
+async fn giant_thread_is_cut_at_the_budget_with_a_continuation_link() {
+    let db = match common::database_with(&[("MAX_PAGE_BYTES", "100000")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Giant thread", "Body", "198.51.100.1:1000").await;
+    sqlx::query(
+        "INSERT INTO comments (article_id, comment, created_at, ip_representation)
+         SELECT $1, 'Seeded comment ' || n, 1700000000 + n, 'seed' FROM generate_series(1, $2) AS n",
+    )
+    .bind(id)
+    .bind(COMMENTS)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let get = |uri: String| TestRequest::get().uri(&uri).to_request();
+    let page = common::body(test::call_service(&app, get(format!("/articles/{}", id))).await).await;
+    assert!(page.contains("Seeded comment 1<"));
+    assert!(page.contains(r#"class="page-truncated""#), "no truncation marker");
+    assert!(page.trim_end().ends_with("</html>"));
+    let shown = last_comment(&page);
+    assert!(shown < COMMENTS, "all {} comments were sent", shown);
+
+    let (_, link) = page.split_once(r#"class="page-truncated">Page truncated — <a href=""#).unwrap();
+    let link = link.split('"').next().unwrap();
+    assert!(link.starts_with(&format!("/articles/{}?comments=oldest&after=", id)), "{}", link);
+
+    let res = test::call_service(&app, get(link.to_string())).await;
+    assert_eq!(res.status(), 200);
+    let next = common::body(res).await;
+    assert!(!next.contains(&format!("Seeded comment {}<", shown)));
+    assert!(next.contains(&format!("Seeded comment {}<", shown + 1)));
+}
@@ -0,0 +1,60 @@
+use actix_web::test;
+
+mod common;
+
+#[actix_web::test]
+async fn second_post_from_one_address_waits_for_the_cooldown() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "First", "Body", "198.51.100.1:1000").await;
+
+    let req = common::post_multipart("/submit", &[("title", "Second"), ("body", "Body")], Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 429);
+    let wait: i64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+    assert!((1..=30).contains(&wait), "{}", wait);
+
+    // Staff don't wait
+    let admin = common::login(&app).await;
+    let req = common::post_multipart("/submit", &[("title", "Staff"), ("body", "Body")], Some(("pixel.png", common::PNG)))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .cookie(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let res = test::call_service(&app, common::comment_request(id, "Once", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let res = test::call_service(&app, common::comment_request(id, "Twice", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 429);
+    assert!(res.headers().contains_key("Retry-After"));
+    // The refused comment comes back in the form
+    assert!(common::body(res).await.contains("Twice"));
+    let res = test::call_service(&app, common::comment_request(id, "Elsewhere", "198.51.100.3:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+
+    let comments: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM comments").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(comments, 2);
+}
+
+#[actix_web::test]
+async fn cooldown_of_zero_is_off() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query("INSERT INTO settings (key, value) VALUES ('comment_cooldown_secs', '0')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Open", "Body", "198.51.100.1:1000").await;
+    for comment in ["Once", "Twice"] {
+        let res = test::call_service(&app, common::comment_request(id, comment, "198.51.100.2:1000").to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+}
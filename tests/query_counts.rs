@@ -0,0 +1,55 @@
+use actix_web::test::{self, TestRequest};
+use articles1::query_count;
+
+mod common;
+
+const ARTICLES: i32 = 30;
+
+// Statements run to serve `uri` in full, streamed batches included
+async fn queries_for<S, B>(app: &S, uri: &str) -> u32
+where
+    S: actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let (page, count) = query_count::counted(async {
+        common::body(test::call_service(app, TestRequest::get().uri(uri).to_request()).await).await
+    })
+    .await;
+    assert!(page.contains("</html>"), "{} didn't render", uri);
+    count
+}
+
+// Fails when a page starts running a query per article or per comment
+#[actix_web::test]
+async fn pages_run_a_fixed_number_of_queries() {
+    let db = match common::database_with(&[("QUERY_COUNT_WARN", "1000")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Busy", "Body", "198.51.100.1:1000").await;
+    sqlx::query(
+        "INSERT INTO articles (title, body, bump_time, created_at, updated_at)
+         SELECT 'Seeded ' || n, 'Body', 1600000000 + n, 1600000000 + n, 1600000000 + n
+         FROM generate_series(1, $1) AS n",
+    )
+    .bind(ARTICLES)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO comments (article_id, comment, created_at, ip_representation)
+         SELECT $1, 'Reply ' || n || ' to >>' || a.id, 1700000000 + n, 'seed'
+         FROM generate_series(1, $2) AS n JOIN articles a ON a.id = (n % $2) + 1",
+    )
+    .bind(id)
+    .bind(ARTICLES)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let list = queries_for(&app, "/articles").await;
+    assert!((1..=5).contains(&list), "/articles ran {} queries", list);
+    let article = queries_for(&app, &format!("/articles/{}", id)).await;
+    assert!((1..=10).contains(&article), "the article page ran {} queries", article);
+}
@@ -0,0 +1,57 @@
+use serde_json::Value;
+
+mod common;
+
+// Names of the plan nodes and the indexes they use, depth first
+fn nodes(plan: &Value, found: &mut Vec<(String, Option<String>)>) {
+    found.push((
+        plan["Node Type"].as_str().unwrap().to_string(),
+        plan["Index Name"].as_str().map(str::to_string),
+    ));
+    for child in plan["Plans"].as_array().into_iter().flatten() {
+        nodes(child, found);
+    }
+}
+
+// Fails if an edit to the article list query stops it using its index
+#[actix_web::test]
+async fn article_list_walks_its_index() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query(
+        "INSERT INTO articles (title, body, bump_time, created_at, updated_at)
+         SELECT 'Article ' || n, 'Body', 1700000000 + n, 1700000000 + n, 1700000000 + n
+         FROM generate_series(1, 20000) AS n",
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap();
+    sqlx::query("ANALYZE articles").execute(&db.pool).await.unwrap();
+
+    let client = format!("(NOT articles.shadow_hidden OR articles.shadow_viewer = '{}')", "ab".repeat(32));
+    for shown in ["NOT articles.shadow_hidden", client.as_str(), "(NOT articles.shadow_hidden OR TRUE)"] {
+        for cursor in [(i64::MAX, i32::MAX), (1700010000, 10000)] {
+            let (plan,): (Value,) =
+                sqlx::query_as(&format!("EXPLAIN (FORMAT JSON) {}", articles1::article_list_query(shown)))
+                    .bind(cursor.0)
+                    .bind(cursor.1)
+                    .bind(300i64)
+                    .fetch_one(&db.pool)
+                    .await
+                    .unwrap();
+            let mut found = Vec::new();
+            nodes(&plan[0]["Plan"], &mut found);
+            assert!(
+                found.iter().any(|(node, index)| node.starts_with("Index")
+                    && index.as_deref() == Some("articles_listing_idx")),
+                "{} from {:?}: {:?}",
+                shown,
+                cursor,
+                found
+            );
+            assert!(!found.iter().any(|(node, _)| node == "Seq Scan" || node == "Sort"), "{:?}", found);
+        }
+    }
+}
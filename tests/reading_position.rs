@@ -0,0 +1,79 @@
+use actix_web::cookie::Cookie;
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn comment_id(db: &common::TestDatabase, text: &str) -> i32 {
+    sqlx::query_scalar("SELECT id FROM comments WHERE comment = $1").bind(text).fetch_one(&db.pool).await.unwrap()
+}
+
+// Two visits with comments posted in between, and the listing's continue link
+// followed after each
+#[actix_web::test]
+async fn continue_link_follows_the_reader_between_visits() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Long thread", "Body", "198.51.100.1:1000").await;
+    let uri = format!("/articles/{}", id);
+    let resume = format!("{}?continue=1", uri);
+    let continue_to = |cookie: Option<Cookie<'static>>| {
+        let mut req = TestRequest::get().uri(&resume);
+        if let Some(cookie) = cookie {
+            req = req.cookie(cookie);
+        }
+        req.to_request()
+    };
+    for (n, text) in ["First", "Second"].iter().enumerate() {
+        let peer = format!("198.51.100.{}:1000", n + 2);
+        let res = test::call_service(&app, common::comment_request(id, text, &peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+    let second = comment_id(&db, "Second").await;
+
+    // First visit: watching marks everything so far as seen
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("{}/watch", uri)).to_request()).await;
+    let watched = res.response().cookies().find(|c| c.name() == "watched").unwrap().into_owned();
+    assert_eq!(watched.value(), format!("{}:{}", id, second));
+
+    // Meanwhile two more arrive, and the first of them is deleted
+    for (n, text) in ["Third", "Fourth"].iter().enumerate() {
+        let peer = format!("198.51.100.{}:1000", n + 4);
+        let res = test::call_service(&app, common::comment_request(id, text, &peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+    let (third, fourth) = (comment_id(&db, "Third").await, comment_id(&db, "Fourth").await);
+    let admin = common::login(&app).await;
+    let req = common::post_form(&format!("/comments/{}/delete", third), &[]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let req = TestRequest::get().uri("/articles").cookie(watched.clone()).to_request();
+    let list = common::body(test::call_service(&app, req).await).await;
+    assert!(list.contains(&resume), "{}", list);
+    let res = test::call_service(&app, continue_to(Some(watched.clone()))).await;
+    assert_eq!(res.status(), 302);
+    let first_unseen = format!("{}?after={}#c{}", uri, fourth - 1, fourth);
+    assert_eq!(res.headers().get("Location").unwrap(), first_unseen.as_str());
+
+    // Second visit: reading the page catches up, so nothing is new
+    let (page, _) = first_unseen.split_once('#').unwrap();
+    let res = test::call_service(&app, TestRequest::get().uri(page).cookie(watched).to_request()).await;
+    let watched = res.response().cookies().find(|c| c.name() == "watched").unwrap().into_owned();
+    assert_eq!(watched.value(), format!("{}:{}", id, fourth));
+    let res = test::call_service(&app, continue_to(Some(watched.clone()))).await;
+    assert_eq!(res.headers().get("Location").unwrap(), first_unseen.as_str());
+
+    // The last one seen is gone too: back to the one before it
+    let req = common::post_form(&format!("/comments/{}/delete", fourth), &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let res = test::call_service(&app, continue_to(Some(watched))).await;
+    let last_left = format!("{}?after={}#c{}", uri, second - 1, second);
+    assert_eq!(res.headers().get("Location").unwrap(), last_left.as_str());
+
+    // A cleared cookie gets the top of the article
+    let res = test::call_service(&app, continue_to(None)).await;
+    assert_eq!(res.status(), 302);
+    assert_eq!(res.headers().get("Location").unwrap(), uri.as_str());
+}
@@ -0,0 +1,47 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+fn report(id: i32, reason: &str, peer: &str) -> actix_http::Request {
+    common::post_form(&format!("/articles/{}/report", id), &[("reason", reason)])
+        .peer_addr(peer.parse().unwrap())
+        .to_request()
+}
+
+// Repeats from one address add up on its open report, and deleting the
+// article resolves every report of it
+#[actix_web::test]
+async fn repeat_reports_coalesce_and_deletion_resolves_them() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Spammy", "Body", "198.51.100.1:1000").await;
+
+    for (reason, peer) in [
+        ("Obvious spam", "198.51.100.2:1000"),
+        ("", "198.51.100.2:1000"),
+        ("Selling ads", "198.51.100.3:1000"),
+    ] {
+        assert_eq!(test::call_service(&app, report(id, reason, peer)).await.status(), 200);
+    }
+    let rows: Vec<(String, i32)> = sqlx::query_as("SELECT reason, report_count FROM reports ORDER BY id")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, [("Obvious spam".to_string(), 2), ("Selling ads".to_string(), 1)]);
+
+    let admin = common::login(&app).await;
+    let req = TestRequest::get().uri("/admin/reports").cookie(admin.clone()).to_request();
+    let queue = common::body(test::call_service(&app, req).await).await;
+    assert!(queue.contains("Obvious spam") && queue.contains("Selling ads"), "{}", queue);
+
+    let req = common::post_form(&format!("/articles/{}/delete", id), &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let open: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM reports WHERE resolved_at IS NULL")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(open, 0);
+}
@@ -0,0 +1,86 @@
+use actix_web::test;
+use serde_json::Value;
+use std::fs;
+
+mod common;
+
+// Name of the newest file in migrations/, e.g. "0039_reports.sql"
+fn newest_migration() -> (i64, String) {
+    let newest = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".sql"))
+        .max()
+        .unwrap();
+    let (version, _) = newest.split_once('_').unwrap();
+    (version.parse().unwrap(), newest)
+}
+
+#[actix_web::test]
+async fn ready_and_version_see_the_migrated_schema() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let ready: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/api/ready").to_request()).await;
+    assert_eq!(ready["database"], "ok");
+
+    let version: Value =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/api/version").to_request()).await;
+    let (latest, file) = newest_migration();
+    assert_eq!(version["latest_migration"]["version"], latest, "{}", file);
+    assert_eq!(version["unknown_migrations"], serde_json::json!([]));
+}
+
+#[actix_web::test]
+async fn migrations_can_run_again() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    articles1::version::migrate(&db.pool).await.unwrap();
+}
+
+// A database last migrated by a newer release is refused at startup
+#[actix_web::test]
+async fn unknown_migration_is_refused() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+         VALUES (99990101000000, 'from the future', TRUE, '\\x00', 0)",
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let refused = articles1::version::migrate(&db.pool).await.unwrap_err();
+    assert!(refused.contains("99990101000000"), "{}", refused);
+    assert!(refused.contains("ALLOW_SCHEMA_MISMATCH=1"), "{}", refused);
+}
+
+#[actix_web::test]
+async fn non_canonical_paths_redirect() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    for path in ["/articles/", "/ARTICLES", "//Articles"] {
+        let res = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        assert_eq!(res.status(), 308, "{}", path);
+        assert_eq!(res.headers().get("Location").unwrap(), "/articles", "{}", path);
+    }
+    let res = test::call_service(&app, test::TestRequest::get().uri("/Admin/Bans?page=2").to_request()).await;
+    assert_eq!(res.headers().get("Location").unwrap(), "/admin/bans?page=2");
+
+    // File names keep their case
+    let res = test::call_service(&app, test::TestRequest::get().uri("/uploads/Photo.PNG").to_request()).await;
+    assert_ne!(res.status(), 308);
+    assert!(res.headers().get("Location").is_none());
+}
@@ -0,0 +1,65 @@
+use actix_web::test;
+
+mod common;
+
+async fn count(db: &common::TestDatabase, table: &str, id: i32) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {} WHERE article_id = $1", table))
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn deleting_an_article_takes_its_comments_and_media() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Doomed", "Body", "198.51.100.1:1000").await;
+    let kept = common::submit_article(&app, &db, "Kept", "Body", "198.51.100.3:1000").await;
+    for (article, peer) in [(id, "198.51.100.2:1000"), (kept, "198.51.100.4:1000")] {
+        let res = test::call_service(&app, common::comment_request(article, "Reply", peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+    assert_eq!((count(&db, "comments", id).await, count(&db, "article_media", id).await), (1, 1));
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(&format!("/articles/{}/delete", id), &[]).cookie(admin.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert_eq!((count(&db, "comments", id).await, count(&db, "article_media", id).await), (0, 0));
+    assert_eq!((count(&db, "comments", kept).await, count(&db, "article_media", kept).await), (1, 1));
+
+    // Nothing can point at the deleted article any more
+    let orphan = sqlx::query("INSERT INTO comments (article_id, comment, created_at, ip_representation) VALUES ($1, 'x', 1, 'x')")
+        .bind(id)
+        .execute(&db.pool)
+        .await;
+    assert!(orphan.is_err());
+
+    let req = common::post_form("/comments/999999/delete", &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn timestamps_must_be_positive() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Dated", "Body", "198.51.100.1:1000").await;
+
+    for update in [
+        "UPDATE articles SET bump_time = 0 WHERE id = $1",
+        "UPDATE articles SET updated_at = -1 WHERE id = $1",
+    ] {
+        assert!(sqlx::query(update).bind(id).execute(&db.pool).await.is_err(), "{}", update);
+    }
+    let comment = sqlx::query("INSERT INTO comments (article_id, comment, created_at, ip_representation) VALUES ($1, 'x', 0, 'x')")
+        .bind(id)
+        .execute(&db.pool)
+        .await;
+    assert!(comment.is_err());
+}
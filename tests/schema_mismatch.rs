@@ -0,0 +1,19 @@
+mod common;
+
+// With ALLOW_SCHEMA_MISMATCH=1 the same database starts anyway
+#[actix_web::test]
+async fn unknown_migration_is_let_through_when_allowed() {
+    let db = match common::database_with(&[("ALLOW_SCHEMA_MISMATCH", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+         VALUES (99990101000000, 'from the future', TRUE, '\\x00', 0)",
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    articles1::version::migrate(&db.pool).await.unwrap();
+}
@@ -0,0 +1,26 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+#[actix_web::test]
+async fn title_match_outranks_body_match() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    // The body match is newer, so only the ranking can put the title match first
+    let titled = common::submit_article(&app, &db, "Zephyr sightings", "Nothing else here", "198.51.100.1:1000").await;
+    let mentioned =
+        common::submit_article(&app, &db, "Weather log", "A zephyr came through today", "198.51.100.2:1000").await;
+
+    let req = TestRequest::get().uri("/search?q=zephyr").to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    let first = page.find(&format!("/articles/{}\"", titled)).expect("title match missing");
+    let second = page.find(&format!("/articles/{}\"", mentioned)).expect("body match missing");
+    assert!(first < second);
+
+    let req = TestRequest::get().uri("/search?q=nowhere").to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    assert!(page.contains("No articles matched your search."));
+}
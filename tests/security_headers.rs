@@ -0,0 +1,35 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+#[actix_web::test]
+async fn pages_carry_the_security_headers() {
+    let db = match common::database_with(&[("BEHIND_TLS", "1")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Headed", "Body", "198.51.100.1:1000").await;
+
+    for uri in ["/".to_string(), "/articles".to_string(), format!("/articles/{}", id), "/admin/login".to_string()] {
+        let res = test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(res.status(), 200, "{}", uri);
+        let header = |name: &str| res.headers().get(name).map(|v| v.to_str().unwrap().to_string());
+        let csp = header("content-security-policy").unwrap_or_else(|| panic!("no CSP on {}", uri));
+        for directive in ["script-src 'self'", "style-src 'self'", "img-src 'self'", "frame-ancestors 'self'"] {
+            assert!(csp.contains(directive), "{} lacks {}: {}", uri, directive, csp);
+        }
+        assert_eq!(header("x-content-type-options").as_deref(), Some("nosniff"), "{}", uri);
+        assert_eq!(header("x-frame-options").as_deref(), Some("SAMEORIGIN"), "{}", uri);
+        assert_eq!(header("referrer-policy").as_deref(), Some("strict-origin-when-cross-origin"), "{}", uri);
+        assert_eq!(header("strict-transport-security").as_deref(), Some("max-age=31536000"), "{}", uri);
+    }
+
+    // Other responses keep nosniff but have no page policy to carry
+    let res = test::call_service(&app, TestRequest::get().uri("/api/version").to_request()).await;
+    assert_eq!(res.headers().get("x-content-type-options").unwrap(), "nosniff");
+    assert!(res.headers().get("content-security-policy").is_none());
+
+    // Behind TLS the session cookie is kept off plain HTTP
+    assert_eq!(common::login(&app).await.secure(), Some(true));
+}
@@ -0,0 +1,148 @@
+use actix_web::cookie::Cookie;
+use actix_web::dev::Service;
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const SHADOWED: &str = "203.0.113.9:4000";
+const OTHER: &str = "198.51.100.7:5000";
+
+async fn shadow_ban<S, B>(app: &S, admin: &Cookie<'static>)
+where
+    S: Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let req = common::post_form(
+        "/admin/bans",
+        &[("ip", "203.0.113.9"), ("reason", "Spam"), ("days", ""), ("shadow", "1")],
+    )
+    .cookie(admin.clone())
+    .to_request();
+    assert_eq!(test::call_service(app, req).await.status(), 302);
+}
+
+fn get(uri: &str, peer: &str) -> actix_http::Request {
+    TestRequest::get().uri(uri).peer_addr(peer.parse().unwrap()).to_request()
+}
+
+#[actix_web::test]
+async fn shadow_banned_comment_is_shown_only_to_its_poster_until_released() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Open thread", "Body", OTHER).await;
+    let admin = common::login(&app).await;
+    shadow_ban(&app, &admin).await;
+
+    // Looks like any other post to the poster
+    let res = test::call_service(&app, common::comment_request(id, "Quietly kept", SHADOWED).to_request()).await;
+    assert_eq!(res.status(), 302);
+
+    let page = common::body(test::call_service(&app, get(&format!("/articles/{}", id), SHADOWED)).await).await;
+    assert!(page.contains("Quietly kept"));
+    assert!(!page.contains("shadow-hidden"));
+    let page = common::body(test::call_service(&app, get(&format!("/articles/{}", id), OTHER)).await).await;
+    assert!(!page.contains("Quietly kept"));
+
+    let (count, bump_time, created_at): (i64, i64, i64) =
+        sqlx::query_as("SELECT comment_count, bump_time, created_at FROM articles WHERE id = $1")
+            .bind(id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+    assert_eq!(count, 0);
+    assert_eq!(bump_time, created_at);
+
+    let req = TestRequest::get().uri("/admin/comments?status=shadow").cookie(admin.clone()).to_request();
+    let browser = common::body(test::call_service(&app, req).await).await;
+    assert!(browser.contains("Quietly kept"));
+    assert!(browser.contains(r#"value="Release""#));
+
+    let comment_id: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    let req = common::post_form(&format!("/admin/comments/{}/release", comment_id), &[])
+        .cookie(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let page = common::body(test::call_service(&app, get(&format!("/articles/{}", id), OTHER)).await).await;
+    assert!(page.contains("Quietly kept"));
+    let count: i64 = sqlx::query_scalar("SELECT comment_count FROM articles WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_web::test]
+async fn shadow_banned_article_is_shown_only_to_its_poster_until_released() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let admin = common::login(&app).await;
+    shadow_ban(&app, &admin).await;
+
+    let id = common::submit_article(&app, &db, "Shadowed article", "Body", SHADOWED).await;
+
+    let list = common::body(test::call_service(&app, get("/articles", SHADOWED)).await).await;
+    assert!(list.contains("Shadowed article"));
+    let list = common::body(test::call_service(&app, get("/articles", OTHER)).await).await;
+    assert!(!list.contains("Shadowed article"));
+
+    let uri = format!("/articles/{}", id);
+    assert_eq!(test::call_service(&app, get(&uri, SHADOWED)).await.status(), 200);
+    assert_eq!(test::call_service(&app, get(&uri, OTHER)).await.status(), 404);
+    let res = test::call_service(&app, common::comment_request(id, "Can't see it", OTHER).to_request()).await;
+    assert_eq!(res.status(), 404);
+
+    let req = TestRequest::get().uri(&uri).cookie(admin.clone()).to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    assert!(page.contains("shadow-hidden"));
+
+    let req = common::post_form(&format!("/articles/{}/release", id), &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    assert_eq!(test::call_service(&app, get(&uri, OTHER)).await.status(), 200);
+    let list = common::body(test::call_service(&app, get("/articles", OTHER)).await).await;
+    assert!(list.contains("Shadowed article"));
+}
+
+#[actix_web::test]
+async fn removed_moderator_stops_seeing_shadow_hidden_articles() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let admin = common::login(&app).await;
+    shadow_ban(&app, &admin).await;
+    let id = common::submit_article(&app, &db, "Shadowed article", "Body", SHADOWED).await;
+
+    let req = common::post_form(
+        "/admin/moderators",
+        &[("username", "alice"), ("new_password", "alice's password"), ("perm_delete_comments", "1")],
+    )
+    .cookie(admin)
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form("/admin/login", &[("username", "alice"), ("password", "alice's password")]).to_request();
+    let res = test::call_service(&app, req).await;
+    let alice = res.response().cookies().find(|c| c.name() == "admin_session").unwrap().into_owned();
+
+    let uri = format!("/articles/{}", id);
+    let req = TestRequest::get().uri(&uri).cookie(alice.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // The session cookie is still validly signed
+    sqlx::query("DELETE FROM moderators WHERE username = 'alice'").execute(&db.pool).await.unwrap();
+    let req = TestRequest::get().uri(&uri).cookie(alice).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
@@ -0,0 +1,57 @@
+use actix_web::test::{self, TestRequest};
+use serde_json::Value;
+
+mod common;
+
+// The JSON-LD block on `page`, as a parser reading up to `</script>` gets it
+fn json_ld(page: &str) -> Value {
+    let (_, rest) = page.split_once(r#"<script type="application/ld+json">"#).expect("no JSON-LD block");
+    let (json, _) = rest.split_once("</script>").unwrap();
+    serde_json::from_str(json).unwrap()
+}
+
+#[actix_web::test]
+async fn article_page_carries_valid_json_ld() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let title = "Breaking</script><script>alert(1)</script>";
+    let id = common::submit_article(&app, &db, title, "Body", "198.51.100.1:1000").await;
+    for (comment, peer) in [("First <b>reply</b>", "198.51.100.2:1000"), ("Second reply", "198.51.100.3:1000")] {
+        let res = test::call_service(&app, common::comment_request(id, comment, peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+    let media_path: String = sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let page = common::body(res).await;
+    assert!(!page.contains("<script>alert(1)"));
+    let data = json_ld(&page);
+
+    assert_eq!(data["@context"], "https://schema.org");
+    assert_eq!(data["@type"], "Article");
+    assert_eq!(data["headline"], title);
+    let url = data["url"].as_str().unwrap();
+    assert!(url.starts_with("http") && url.ends_with(&format!("/articles/{}", id)), "{}", url);
+    assert_eq!(data["mainEntityOfPage"], url);
+    for date in ["datePublished", "dateModified"] {
+        let value = data[date].as_str().unwrap_or_else(|| panic!("no {}", date));
+        assert!(chrono::DateTime::parse_from_rfc3339(value).is_ok(), "{} = {}", date, value);
+    }
+    let image = data["image"][0].as_str().unwrap();
+    assert!(image.starts_with("http") && image.ends_with(&media_path), "{}", image);
+    assert_eq!(data["commentCount"], 2);
+
+    let comments = data["comment"].as_array().unwrap();
+    assert_eq!(comments.len(), 2);
+    assert_eq!(comments[0]["@type"], "Comment");
+    assert_eq!(comments[0]["text"], "First <b>reply</b>");
+    assert!(comments[0]["url"].as_str().unwrap().starts_with(url));
+    assert_eq!(comments[1]["author"]["name"], "Anonymous");
+}
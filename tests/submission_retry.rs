@@ -0,0 +1,112 @@
+use actix_web::test;
+
+mod common;
+
+const SUBMISSION: &str = "0123456789abcdef0123456789abcdef";
+const NOTES: &[u8] = b"Notes for the retried article\n";
+
+// One attempt at submission SUBMISSION from a single client
+fn attempt(title: &str, files: &[(&str, &[u8])]) -> actix_http::Request {
+    common::post_multipart_files(
+        "/submit",
+        &[("submission_id", SUBMISSION), ("title", title), ("body", "Body")],
+        files,
+    )
+    .peer_addr("198.51.100.1:1000".parse().unwrap())
+    .to_request()
+}
+
+// (original filename, created_at) of what attempts have stored so far
+async fn stored(db: &common::TestDatabase) -> Vec<(String, i64)> {
+    sqlx::query_as("SELECT original_filename, created_at FROM submission_uploads WHERE submission_id = $1 ORDER BY original_filename")
+        .bind(SUBMISSION)
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+}
+
+// A retry reuses the file an earlier attempt stored, stores only the one
+// that's new, and a final attempt sending no files publishes both at once
+#[actix_web::test]
+async fn retry_reuses_what_earlier_attempts_stored() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    // The last retry comes from the same client right after publishing
+    sqlx::query("INSERT INTO settings (key, value) VALUES ('article_cooldown_secs', '0')")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let app = common::app(&db).await;
+
+    // Refused after the picture was stored, as a dropped connection would leave it
+    let res = test::call_service(&app, attempt("", &[("pixel.png", common::PNG)])).await;
+    assert_eq!(res.status(), 400);
+    assert!(common::body(res).await.contains("Your file is kept"));
+    sqlx::query("UPDATE submission_uploads SET created_at = created_at - 600 WHERE submission_id = $1")
+        .bind(SUBMISSION)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let first = stored(&db).await;
+    assert_eq!(first.len(), 1);
+
+    // The picture again, matched by name and size, plus a file it didn't have
+    let res = test::call_service(&app, attempt("", &[("pixel.png", common::PNG), ("notes.txt", NOTES)])).await;
+    assert_eq!(res.status(), 400);
+    let second = stored(&db).await;
+    assert_eq!(second.len(), 2);
+    assert_eq!(second[1], first[0], "the picture was stored again");
+    assert_eq!(second[0].0, "notes.txt");
+
+    let res = test::call_service(&app, attempt("Retried", &[])).await;
+    assert_eq!(res.status(), 302);
+    let (id, media): (i32, i64) = sqlx::query_as(
+        "SELECT a.id, (SELECT COUNT(*) FROM article_media m WHERE m.article_id = a.id)
+         FROM articles a WHERE a.title = 'Retried'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    assert_eq!(media, 2);
+    // Claimed into the article in the same transaction
+    assert!(stored(&db).await.is_empty());
+    let published: i32 = sqlx::query_scalar("SELECT article_id FROM submissions WHERE submission_id = $1")
+        .bind(SUBMISSION)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(published, id);
+
+    // Once more after publishing goes to the article instead of posting it twice
+    let res = test::call_service(&app, attempt("Retried", &[("pixel.png", common::PNG)])).await;
+    assert_eq!(res.status(), 302);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    assert!(location.starts_with(&format!("/articles/{}", id)), "{}", location);
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM articles").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(count, 1);
+}
+
+// Files stored more than an hour ago aren't picked up again
+#[actix_web::test]
+async fn expired_uploads_are_not_reused() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let res = test::call_service(&app, attempt("", &[("pixel.png", common::PNG)])).await;
+    assert_eq!(res.status(), 400);
+    sqlx::query("UPDATE submission_uploads SET created_at = created_at - 3601 WHERE submission_id = $1")
+        .bind(SUBMISSION)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let res = test::call_service(&app, attempt("Too late", &[])).await;
+    assert_eq!(res.status(), 400);
+    let page = common::body(res).await;
+    assert!(page.contains("Media file is required"), "{}", page);
+}
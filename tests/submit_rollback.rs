@@ -0,0 +1,78 @@
+use actix_web::test;
+
+mod common;
+
+// A media insert that fails after the first one went in leaves nothing behind
+#[actix_web::test]
+async fn failed_media_insert_leaves_no_article() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    sqlx::query(
+        "CREATE FUNCTION refuse_second_file() RETURNS trigger AS $$
+         BEGIN
+             IF NEW.original_filename = 'second.txt' THEN
+                 RAISE EXCEPTION 'forced failure';
+             END IF;
+             RETURN NEW;
+         END
+         $$ LANGUAGE plpgsql",
+    )
+    .execute(&db.pool)
+    .await
+    .unwrap();
+    sqlx::query("CREATE TRIGGER refuse_second_file BEFORE INSERT ON article_media FOR EACH ROW EXECUTE FUNCTION refuse_second_file()")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let app = common::app(&db).await;
+
+    let req = common::post_multipart_files(
+        "/submit",
+        &[("title", "Half written"), ("body", "Body")],
+        &[("first.png", common::PNG), ("second.txt", "Second file\n".as_bytes())],
+    )
+    .peer_addr("198.51.100.1:1000".parse().unwrap())
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 500);
+
+    for table in ["articles", "article_media", "article_events"] {
+        let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(&db.pool).await.unwrap();
+        assert_eq!(rows, 0, "{}", table);
+    }
+    // Both stored files are queued for removal rather than left as orphans
+    let queued: Vec<String> = sqlx::query_scalar("SELECT media_path FROM pending_unlinks ORDER BY media_path")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 2, "{:?}", queued);
+    assert!(queued.iter().any(|p| p.ends_with(".png")) && queued.iter().any(|p| p.ends_with(".txt")), "{:?}", queued);
+}
+
+// A form refused partway through still queues the files it had stored
+#[actix_web::test]
+async fn refused_later_file_queues_the_earlier_ones() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let req = common::post_multipart_files(
+        "/submit",
+        &[("title", "Refused"), ("body", "Body")],
+        &[("first.png", common::PNG), ("tool.exe", b"MZ\x90\x00\x03\x00\x00\x00\x04\x00")],
+    )
+    .peer_addr("198.51.100.1:1000".parse().unwrap())
+    .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_client_error(), "{}", res.status());
+
+    let queued: Vec<String> = sqlx::query_scalar("SELECT media_path FROM pending_unlinks")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1, "{:?}", queued);
+    assert!(queued[0].ends_with(".png"), "{:?}", queued);
+}
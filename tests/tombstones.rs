@@ -0,0 +1,56 @@
+use actix_web::test;
+
+mod common;
+
+async fn comment_exists(db: &common::TestDatabase, id: i32) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM comments WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+// Tombstones left from when the setting was on stay protected after it's
+// turned off: only the admin can purge them
+#[actix_web::test]
+async fn tombstones_need_the_admin_with_the_setting_off() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let id = common::submit_article(&app, &db, "Tombstoned", "Body", "198.51.100.1:1000").await;
+    let res = test::call_service(&app, common::comment_request(id, "Removed earlier", "198.51.100.2:1000").to_request()).await;
+    assert_eq!(res.status(), 302);
+    let comment_id: i32 = sqlx::query_scalar("SELECT id FROM comments WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE comments SET comment = '', deleted = TRUE WHERE id = $1")
+        .bind(comment_id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    let admin = common::login(&app).await;
+    let req = common::post_form(
+        "/admin/moderators",
+        &[("username", "carol"), ("new_password", "carol's password"), ("perm_delete_comments", "1")],
+    )
+    .cookie(admin.clone())
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let req = common::post_form("/admin/login", &[("username", "carol"), ("password", "carol's password")]).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 302);
+    let carol = res.response().cookies().find(|c| c.name() == "admin_session").unwrap().into_owned();
+
+    let req = common::post_form(&format!("/comments/{}/delete", comment_id), &[]).cookie(carol).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    assert!(comment_exists(&db, comment_id).await);
+
+    let req = common::post_form(&format!("/comments/{}/delete", comment_id), &[]).cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    assert!(!comment_exists(&db, comment_id).await);
+}
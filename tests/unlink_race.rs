@@ -0,0 +1,102 @@
+use articles1::unlinks;
+use sqlx::{PgConnection, PgPool};
+use std::path::Path;
+use std::time::Duration;
+
+mod common;
+
+// A file written for an upload whose rows haven't committed yet, already
+// queued for removal by an earlier delete of the same content
+async fn queued_upload(pool: &PgPool, name: &str) -> String {
+    let media_path = format!("/uploads/{}-{}.png", name, std::process::id());
+    std::fs::write(format!(".{}", media_path), common::PNG).unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+    unlinks::enqueue(&mut conn, std::slice::from_ref(&media_path)).await.unwrap();
+    media_path
+}
+
+async fn reference(conn: &mut PgConnection, article_id: i32, media_path: &str) {
+    sqlx::query("INSERT INTO article_media (article_id, media_path, original_filename) VALUES ($1, $2, 'pixel.png')")
+        .bind(article_id)
+        .bind(media_path)
+        .execute(conn)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn drain_waits_for_an_upload_being_stored() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let article_id = common::submit_article(&app, &db, "Shared file", "Body", "198.51.100.1:1000").await;
+    let media_path = queued_upload(&db.pool, "storing").await;
+
+    // The upload's transaction holds the file before its row commits
+    let mut tx = db.pool.begin().await.unwrap();
+    unlinks::hold(&mut tx, &[media_path.as_str()]).await.unwrap();
+    reference(&mut tx, article_id, &media_path).await;
+
+    let pool = db.pool.clone();
+    let drain = actix_web::rt::spawn(async move { unlinks::drain(&pool).await });
+    actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+    assert!(!drain.is_finished(), "drain didn't wait for the upload");
+    assert!(Path::new(&format!(".{}", media_path)).exists());
+
+    tx.commit().await.unwrap();
+    assert_eq!(drain.await.unwrap(), Ok(1));
+    assert!(Path::new(&format!(".{}", media_path)).exists(), "drain removed a file that is now referenced");
+    std::fs::remove_file(format!(".{}", media_path)).ok();
+}
+
+#[actix_web::test]
+async fn upload_fails_when_a_drain_removed_its_file_first() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let article_id = common::submit_article(&app, &db, "Late upload", "Body", "198.51.100.2:1000").await;
+    let media_path = queued_upload(&db.pool, "late").await;
+
+    // The drain gets in between writing the file and storing its row
+    assert_eq!(unlinks::drain(&db.pool).await, Ok(1));
+    let mut tx = db.pool.begin().await.unwrap();
+    reference(&mut tx, article_id, &media_path).await;
+    assert!(unlinks::hold(&mut tx, &[media_path.as_str()]).await.is_err());
+    tx.rollback().await.unwrap();
+
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM article_media WHERE media_path = $1")
+        .bind(&media_path)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, 0);
+}
+
+// A path that can't be removed (here a directory) stays queued, and the rest
+// of the queue is drained past it
+#[actix_web::test]
+async fn unremovable_file_stays_queued_without_blocking_the_rest() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let stuck = format!("/uploads/stuck-{}.png", std::process::id());
+    std::fs::create_dir_all(format!(".{}", stuck)).unwrap();
+    let mut conn = db.pool.acquire().await.unwrap();
+    unlinks::enqueue(&mut conn, std::slice::from_ref(&stuck)).await.unwrap();
+    drop(conn);
+    let media_path = queued_upload(&db.pool, "behind").await;
+
+    assert_eq!(unlinks::drain(&db.pool).await, Ok(1));
+    assert!(!Path::new(&format!(".{}", media_path)).exists());
+    let queued: Vec<String> = sqlx::query_scalar("SELECT media_path FROM pending_unlinks")
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, [stuck.as_str()]);
+    std::fs::remove_dir(format!(".{}", stuck)).ok();
+}
@@ -0,0 +1,56 @@
+use actix_web::dev::ServiceResponse;
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+fn header<B>(res: &ServiceResponse<B>, name: &str) -> String {
+    res.headers().get(name).map(|v| v.to_str().unwrap().to_string()).unwrap_or_default()
+}
+
+#[actix_web::test]
+async fn uploads_are_served_with_safe_headers() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let image = common::submit_article(&app, &db, "Image", "Body", "198.51.100.1:1000").await;
+    let legacy = common::submit_article(&app, &db, "Legacy", "Body", "198.51.100.2:1000").await;
+    let image_path: String = sqlx::query_scalar("SELECT media_path FROM article_media WHERE article_id = $1")
+        .bind(image)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+
+    let res = test::call_service(&app, TestRequest::get().uri(&image_path).to_request()).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(header(&res, "content-type"), "image/png");
+    assert!(header(&res, "content-disposition").starts_with("inline"), "{}", header(&res, "content-disposition"));
+    assert_eq!(header(&res, "x-content-type-options"), "nosniff");
+    assert_eq!(header(&res, "cache-control"), "public, max-age=31536000, immutable");
+
+    // A row from before uploads were checked, with a type nobody recognizes
+    let name = format!("legacy-{}.xyz", std::process::id());
+    std::fs::write(format!("uploads/{}", name), "<html><script>alert(1)</script></html>").unwrap();
+    sqlx::query("UPDATE article_media SET media_path = $1, mime_type = NULL WHERE article_id = $2")
+        .bind(format!("/uploads/{}", name))
+        .bind(legacy)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/uploads/{}", name)).to_request()).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(header(&res, "content-type"), "application/octet-stream");
+    assert!(header(&res, "content-disposition").starts_with("attachment"), "{}", header(&res, "content-disposition"));
+    assert_eq!(header(&res, "x-content-type-options"), "nosniff");
+    assert_eq!(header(&res, "cache-control"), "public, max-age=31536000, immutable");
+
+    // On disk but not in article_media
+    let stray = format!("stray-{}.png", std::process::id());
+    std::fs::write(format!("uploads/{}", stray), common::PNG).unwrap();
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/uploads/{}", stray)).to_request()).await;
+    assert_eq!(res.status(), 404);
+    for file in [name, stray] {
+        std::fs::remove_file(format!("uploads/{}", file)).ok();
+    }
+}
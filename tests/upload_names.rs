@@ -0,0 +1,72 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+async fn media_of(db: &common::TestDatabase, id: i32) -> (String, Option<String>) {
+    sqlx::query_as("SELECT media_path, original_filename FROM article_media WHERE article_id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+}
+
+async fn fetch<S, B>(app: &S, media_path: &str) -> String
+where
+    S: actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let res = test::call_service(app, TestRequest::get().uri(media_path).to_request()).await;
+    assert_eq!(res.status(), 200, "{}", media_path);
+    common::body(res).await
+}
+
+// Two uploads with the same name, on new articles and through an edit,
+// each keep a file of their own
+#[actix_web::test]
+async fn same_filename_never_overwrites() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let mut ids = Vec::new();
+    // From two addresses, so the post cooldown doesn't refuse the second
+    for (title, contents, peer) in
+        [("First", "First notes\n", "198.51.100.1:1000"), ("Second", "Second notes\n", "198.51.100.2:1000")]
+    {
+        ids.push(common::submit_article_with_file(&app, &db, title, "Body", peer, ("notes.txt", contents.as_bytes())).await);
+    }
+
+    let (first, first_name) = media_of(&db, ids[0]).await;
+    let (second, second_name) = media_of(&db, ids[1]).await;
+    assert_ne!(first, second);
+    assert_eq!(first_name.as_deref(), Some("notes.txt"));
+    assert_eq!(second_name.as_deref(), Some("notes.txt"));
+    assert!(first.ends_with(".txt") && second.ends_with(".txt"));
+    for path in [&first, &second] {
+        assert!(std::path::Path::new(&format!(".{}", path)).is_file(), "{} is not on disk", path);
+    }
+    assert_eq!(fetch(&app, &first).await, "First notes\n");
+    assert_eq!(fetch(&app, &second).await, "Second notes\n");
+
+    // Replacing the second article's file under the same name leaves the first alone
+    let session = common::login(&app).await;
+    let edit = format!("/articles/{}/edit", ids[1]);
+    let req = common::post_multipart(&edit, &[("mode", "check")], None).cookie(session.clone()).to_request();
+    let form = common::body(test::call_service(&app, req).await).await;
+    let (_, token) = form.split_once(r#"name="edit_token" value=""#).expect("no edit token");
+    let token = token.split('"').next().unwrap();
+    let req = common::post_multipart(
+        &edit,
+        &[("mode", "save"), ("edit_token", token), ("title", "Second"), ("body", "Body")],
+        Some(("notes.txt", "Replaced notes\n".as_bytes())),
+    )
+    .cookie(session)
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+    let (replaced, _) = media_of(&db, ids[1]).await;
+    assert!(replaced != first && replaced != second, "{}", replaced);
+    assert_eq!(fetch(&app, &replaced).await, "Replaced notes\n");
+    assert_eq!(fetch(&app, &first).await, "First notes\n");
+}
@@ -0,0 +1,43 @@
+use actix_web::test;
+
+mod common;
+
+const LIMIT: usize = 4096;
+
+fn files_in(dir: &str) -> usize {
+    std::fs::read_dir(dir).map(|entries| entries.count()).unwrap_or(0)
+}
+
+#[actix_web::test]
+async fn file_over_max_upload_bytes_is_cut_off() {
+    let db = match common::database_with(&[("MAX_UPLOAD_BYTES", "4096")]).await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let stored = files_in("uploads") + files_in("quarantine");
+
+    let over = "a".repeat(LIMIT + 1);
+    let req = common::post_multipart("/submit", &[("title", "Too big"), ("body", "Body")], Some(("big.txt", over.as_bytes())))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), 413);
+    let page = common::body(res).await;
+    assert!(page.contains("Files can be at most 4 KB"), "{}", page);
+    assert_eq!(files_in("uploads") + files_in("quarantine"), stored);
+
+    let at_limit = "a".repeat(LIMIT);
+    let req = common::post_multipart("/submit", &[("title", "Just fits"), ("body", "Body")], Some(("fits.txt", at_limit.as_bytes())))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 302);
+    let media_path: String = sqlx::query_scalar(
+        "SELECT m.media_path FROM article_media m JOIN articles a ON a.id = m.article_id WHERE a.title = 'Just fits'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    let stored = std::fs::metadata(format!(".{}", media_path)).unwrap();
+    assert_eq!(stored.len(), LIMIT as u64);
+}
@@ -0,0 +1,56 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+fn submit(title: &str, file: (&str, &[u8])) -> actix_http::Request {
+    common::post_multipart("/submit", &[("title", title), ("body", "Body")], Some(file))
+        .peer_addr("198.51.100.1:1000".parse().unwrap())
+        .to_request()
+}
+
+// An executable or a page renamed to an image is refused by its contents
+#[actix_web::test]
+async fn renamed_files_are_refused() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    let exe: &[u8] = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff\x00\x00";
+    let page: &[u8] = b"<!DOCTYPE html><script>alert(1)</script>";
+    for (name, data) in [("setup.png", exe), ("page.jpg", page)] {
+        let res = test::call_service(&app, submit("Disguised", (name, data))).await;
+        assert_eq!(res.status(), 400, "{}", name);
+        let message = common::body(res).await;
+        assert!(message.contains(&format!("\"{}\" isn't a supported file type", name)), "{}", message);
+    }
+    let posted: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM articles").fetch_one(&db.pool).await.unwrap();
+    assert_eq!(posted, 0);
+}
+
+// The stored type and the rendering follow the contents, not the extension
+#[actix_web::test]
+async fn image_named_like_a_video_is_stored_as_an_image() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+
+    assert_eq!(test::call_service(&app, submit("Misnamed", ("clip.mp4", common::PNG))).await.status(), 302);
+    let (id, media_path, mime_type): (i32, String, Option<String>) = sqlx::query_as(
+        "SELECT a.id, m.media_path, m.mime_type FROM articles a JOIN article_media m ON m.article_id = a.id
+         WHERE a.title = 'Misnamed'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    assert_eq!(mime_type.as_deref(), Some("image/png"));
+    assert!(media_path.ends_with(".png"), "{}", media_path);
+
+    let res = test::call_service(&app, TestRequest::get().uri(&format!("/articles/{}", id)).to_request()).await;
+    let page = common::body(res).await;
+    assert!(page.contains(&format!(r#"<img src="{}""#, media_path)), "{}", page);
+    assert!(!page.contains("<video"));
+}
@@ -0,0 +1,76 @@
+use actix_web::test::{self, TestRequest};
+
+mod common;
+
+const VISITOR: &str = "203.0.113.9:1000";
+
+// Text of the posts nobody but staff (or their sender) should see
+const RESTRICTED: [&str; 3] = ["Shadowed article", "Hidden reply", "Shadowed reply"];
+
+// Every public page an article or comment can show up on. A new one goes here.
+fn surfaces(visible: i32, shadowed: i32) -> Vec<String> {
+    let today = chrono::Utc::now().date_naive().format("%Y-%m-%d");
+    let mut uris = vec![
+        "/articles".to_string(),
+        "/gallery".to_string(),
+        "/gallery/feed.xml".to_string(),
+        "/digest/feed.xml".to_string(),
+        format!("/digest/{}", today),
+        "/search?q=shadowed".to_string(),
+        "/search?q=reply".to_string(),
+    ];
+    for id in [visible, shadowed] {
+        uris.push(format!("/articles/{}", id));
+        uris.push(format!("/articles/{}/quote", id));
+        uris.push(format!("/api/articles/{}/comments", id));
+        uris.push(format!("/oembed?url=http%3A%2F%2Flocalhost%3A8080%2Farticles%2F{}", id));
+    }
+    uris
+}
+
+#[actix_web::test]
+async fn restricted_posts_stay_off_every_public_surface() {
+    let db = match common::database().await {
+        Some(db) => db,
+        None => return,
+    };
+    let app = common::app(&db).await;
+    let visible = common::submit_article(&app, &db, "Visible article", "Body", "198.51.100.1:1000").await;
+    let shadowed = common::submit_article(&app, &db, "Shadowed article", "Body", "198.51.100.2:1000").await;
+    for (comment, peer) in [
+        ("Shown reply", "198.51.100.3:1000"),
+        ("Hidden reply", "198.51.100.4:1000"),
+        ("Shadowed reply", "198.51.100.5:1000"),
+    ] {
+        let res = test::call_service(&app, common::comment_request(visible, comment, peer).to_request()).await;
+        assert_eq!(res.status(), 302);
+    }
+
+    // Shadowed for some other address than the visitor's
+    sqlx::query("UPDATE articles SET shadow_hidden = TRUE, shadow_viewer = 'abcdef' WHERE id = $1")
+        .bind(shadowed)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE comments SET hidden = TRUE WHERE comment = 'Hidden reply'").execute(&db.pool).await.unwrap();
+    sqlx::query("UPDATE comments SET shadow_hidden = TRUE, shadow_viewer = 'abcdef' WHERE comment = 'Shadowed reply'")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    for uri in surfaces(visible, shadowed) {
+        let req = TestRequest::get().uri(&uri).peer_addr(VISITOR.parse().unwrap()).to_request();
+        let page = common::body(test::call_service(&app, req).await).await;
+        for text in RESTRICTED {
+            assert!(!page.contains(text), "{:?} is shown on {}", text, uri);
+        }
+    }
+
+    // The surfaces do show posts, so the checks above aren't passing on empty pages
+    let req = TestRequest::get().uri(&format!("/articles/{}", visible)).peer_addr(VISITOR.parse().unwrap()).to_request();
+    let page = common::body(test::call_service(&app, req).await).await;
+    assert!(page.contains("Visible article") && page.contains("Shown reply"));
+    let admin = common::login(&app).await;
+    let req = TestRequest::get().uri(&format!("/articles/{}", shadowed)).cookie(admin).to_request();
+    assert!(common::body(test::call_service(&app, req).await).await.contains("Shadowed article"));
+}